@@ -0,0 +1,115 @@
+use crate::{Error, MmapCache};
+
+use fst::{IntoStreamer, Streamer};
+use std::ops::RangeBounds;
+
+/// How a [`MmapCache`] is about to be accessed, used to tune the operating system's readahead with
+/// [`MmapCache::advise`].
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum Access {
+    /// No special treatment (`MADV_NORMAL`).
+    #[default]
+    Normal,
+    /// Pages will be read in order, e.g. during a full scan, so read aggressively ahead (`MADV_SEQUENTIAL`).
+    Sequential,
+    /// Pages will be read in random order, e.g. by point lookups, so don't read ahead (`MADV_RANDOM`).
+    Random,
+}
+
+impl MmapCache {
+    /// Advises the operating system how both the index and value mappings will be accessed.
+    ///
+    /// This is only a hint, and it does nothing on platforms other than Unix.
+    pub fn advise(&self, access: Access) -> Result<(), Error> {
+        #[cfg(unix)]
+        {
+            let advice = match access {
+                Access::Normal => memmap2::Advice::Normal,
+                Access::Sequential => memmap2::Advice::Sequential,
+                Access::Random => memmap2::Advice::Random,
+            };
+            self.index().as_fst().as_inner().advise(advice)?;
+            self.raw_value_bytes().advise(advice)?;
+        }
+        #[cfg(not(unix))]
+        let _ = access;
+        Ok(())
+    }
+
+    /// Advises the operating system to drop the pages of both mappings from this process (`madvise(MADV_DONTNEED)`), e.g.
+    /// after a bulk scan, so they stop counting towards its resident set size and can be reclaimed for other processes.
+    ///
+    /// The cache stays usable: dropped pages are read back from the files (or the page cache) on their next access. It
+    /// fails if the mappings are locked (see [`CacheOptions::lock`](crate::CacheOptions::lock)), and does nothing on
+    /// platforms other than Unix.
+    pub fn evict(&self) -> Result<(), Error> {
+        #[cfg(unix)]
+        {
+            self.index()
+                .as_fst()
+                .as_inner()
+                .advise(memmap2::Advice::DontNeed)?;
+            self.raw_value_bytes().advise(memmap2::Advice::DontNeed)?;
+        }
+        Ok(())
+    }
+
+    /// Like [`evict`](Self::evict), but only for the pages holding the `len` value bytes at `offset`.
+    ///
+    /// Pages are whole, so neighboring values that share the first or last page are dropped as well.
+    pub fn evict_range(&self, offset: u64, len: u64) -> Result<(), Error> {
+        let available = self.value_bytes().len() as u64;
+        if offset.checked_add(len).is_none_or(|end| end > available) {
+            return Err(Error::OutOfBounds {
+                offset,
+                len,
+                available,
+            });
+        }
+        if len == 0 {
+            return Ok(());
+        }
+        #[cfg(unix)]
+        self.raw_value_bytes().advise_range(
+            memmap2::Advice::DontNeed,
+            offset as usize,
+            len as usize,
+        )?;
+        Ok(())
+    }
+
+    /// Like [`range`](crate::Cache::range), but applies [`Access::Sequential`] advice for as long as the returned stream
+    /// exists, and [`Access::Normal`] advice once it's dropped.
+    pub fn range_sequential<K, R>(&self, key_range: R) -> Result<SequentialStream<'_>, Error>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        self.advise(Access::Sequential)?;
+        Ok(SequentialStream {
+            cache: self,
+            stream: self.range(key_range).into_stream(),
+        })
+    }
+}
+
+/// A streaming iterator over (key, value offset) pairs, returned by [`MmapCache::range_sequential`].
+pub struct SequentialStream<'c> {
+    cache: &'c MmapCache,
+    stream: fst::map::Stream<'c>,
+}
+
+impl<'a, 'c> Streamer<'a> for SequentialStream<'c> {
+    type Item = (&'a [u8], u64);
+
+    fn next(&'a mut self) -> Option<Self::Item> {
+        self.stream.next()
+    }
+}
+
+impl Drop for SequentialStream<'_> {
+    fn drop(&mut self) {
+        // Advice is only a hint, so there's nothing useful to do with an error.
+        let _ = self.cache.advise(Access::Normal);
+    }
+}
@@ -0,0 +1,232 @@
+use crate::Cache;
+
+use fst::{IntoStreamer, Streamer};
+use std::future::Future;
+use std::ops::RangeBounds;
+use std::pin::Pin;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// An asynchronous facade over a [`Cache`], for use from async executors like `tokio`.
+///
+/// Reading a cold memory-mapped page blocks the reading thread until the page is loaded from storage, which would stall an
+/// executor thread. Instead, every lookup is performed by a dedicated pool of blocking threads, and its result is delivered
+/// through a [`Future`]. This works with any executor.
+///
+/// Since the lookups happen on other threads, keys are passed and values are returned as owned copies.
+pub struct AsyncCache<DK, DV> {
+    cache: Arc<Cache<DK, DV>>,
+    jobs: Mutex<mpsc::Sender<Job>>,
+}
+
+impl<DK, DV> AsyncCache<DK, DV>
+where
+    DK: AsRef<[u8]> + Send + Sync + 'static,
+    DV: AsRef<[u8]> + Send + Sync + 'static,
+{
+    /// Spawns `threads` blocking threads to perform lookups in `cache`. The threads exit once this facade is dropped.
+    ///
+    /// The number of threads bounds the number of concurrent IOs.
+    ///
+    /// # Panics
+    ///
+    /// If `threads` is zero.
+    pub fn new(cache: Cache<DK, DV>, threads: usize) -> std::io::Result<Self> {
+        assert!(threads > 0);
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for i in 0..threads {
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("mmap-cache-io-{i}"))
+                .spawn(move || loop {
+                    let job = receiver.lock().unwrap().recv();
+                    match job {
+                        Ok(job) => job(),
+                        Err(mpsc::RecvError) => return,
+                    }
+                })?;
+        }
+        Ok(Self {
+            cache: Arc::new(cache),
+            jobs: Mutex::new(sender),
+        })
+    }
+
+    pub fn cache(&self) -> &Arc<Cache<DK, DV>> {
+        &self.cache
+    }
+
+    /// Runs `f` with the cache on a blocking thread.
+    ///
+    /// If `f` panics, the returned [`Lookup`] never completes and the thread that ran it exits.
+    pub fn spawn<T, F>(&self, f: F) -> Lookup<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Cache<DK, DV>) -> T + Send + 'static,
+    {
+        let shared = Arc::new(Mutex::new(LookupState {
+            result: None,
+            waker: None,
+        }));
+        let job_shared = shared.clone();
+        let cache = self.cache.clone();
+        let job: Job = Box::new(move || {
+            let result = f(&cache);
+            let mut state = job_shared.lock().unwrap();
+            state.result = Some(result);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        });
+        // The worker threads only exit once the sender is dropped, so this can't fail.
+        self.jobs.lock().unwrap().send(job).unwrap();
+        Lookup { shared }
+    }
+
+    /// Returns a copy of the value for `key`, if it exists. See [`Cache::get_value`].
+    pub fn get_value(&self, key: impl Into<Vec<u8>>) -> Lookup<Option<Vec<u8>>> {
+        let key = key.into();
+        self.spawn(move |cache| cache.get_value(&key).map(<[u8]>::to_vec))
+    }
+
+    /// Returns copies of the values for all `keys`, in the same order, with a single blocking task.
+    pub fn get_many(&self, keys: Vec<Vec<u8>>) -> Lookup<Vec<Option<Vec<u8>>>> {
+        self.spawn(move |cache| {
+            cache
+                .get_many(&keys)
+                .into_iter()
+                .zip(&keys)
+                .map(|(offset, key)| {
+                    let value = cache.resolve_value(key, offset?)?;
+                    Some(value.to_vec())
+                })
+                .collect()
+        })
+    }
+
+    /// Returns copies of up to `limit` (key, value) pairs in `key_range`. See [`Cache::range_values`].
+    pub fn range_values<R>(&self, key_range: R, limit: usize) -> Lookup<Vec<(Vec<u8>, Vec<u8>)>>
+    where
+        R: RangeBounds<Vec<u8>> + Send + 'static,
+    {
+        self.spawn(move |cache| {
+            let mut pairs = Vec::new();
+            let mut stream = cache.range_values(key_range);
+            while pairs.len() < limit {
+                match stream.next() {
+                    Some((key, value)) => pairs.push((key.to_vec(), value.to_vec())),
+                    None => break,
+                }
+            }
+            pairs
+        })
+    }
+
+    /// Returns up to `limit` (key, value offset) pairs in `key_range`. See [`Cache::range`].
+    pub fn range<R>(&self, key_range: R, limit: usize) -> Lookup<Vec<(Vec<u8>, u64)>>
+    where
+        R: RangeBounds<Vec<u8>> + Send + 'static,
+    {
+        self.spawn(move |cache| {
+            let mut pairs = Vec::new();
+            let mut stream = cache.range(key_range).into_stream();
+            while pairs.len() < limit {
+                match stream.next() {
+                    Some((key, offset)) => pairs.push((key.to_vec(), offset)),
+                    None => break,
+                }
+            }
+            pairs
+        })
+    }
+}
+
+/// The [`Future`] result of a lookup running on an [`AsyncCache`] thread.
+pub struct Lookup<T> {
+    shared: Arc<Mutex<LookupState<T>>>,
+}
+
+struct LookupState<T> {
+    result: Option<T>,
+    waker: Option<Waker>,
+}
+
+impl<T> Future for Lookup<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut state = self.shared.lock().unwrap();
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mmap"))]
+mod tests {
+    use super::*;
+    use crate::{FileBuilder, MmapCache};
+
+    use std::task::Wake;
+
+    struct ThreadWaker(thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn async_lookups() {
+        let dir = std::env::temp_dir();
+        let index_path = dir.join("mmap_cache_async_lookups_index");
+        let values_path = dir.join("mmap_cache_async_lookups_values");
+        let mut builder = FileBuilder::create_files(&index_path, &values_path).unwrap();
+        builder.insert(b"a", b"1").unwrap();
+        builder.insert(b"b", b"22").unwrap();
+        builder.insert(b"c", b"333").unwrap();
+        builder.finish().unwrap();
+        let cache = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+        let cache = AsyncCache::new(cache, 2).unwrap();
+
+        assert_eq!(
+            block_on(cache.get_value(b"b".as_slice())),
+            Some(b"22".to_vec())
+        );
+        assert_eq!(
+            block_on(cache.get_many(vec![b"c".to_vec(), b"z".to_vec(), b"a".to_vec()])),
+            [Some(b"333".to_vec()), None, Some(b"1".to_vec())]
+        );
+        assert_eq!(
+            block_on(cache.range_values(b"b".to_vec().., 10)),
+            [
+                (b"b".to_vec(), b"22".to_vec()),
+                (b"c".to_vec(), b"333".to_vec())
+            ]
+        );
+        assert_eq!(block_on(cache.range(.., 1)), [(b"a".to_vec(), 0)]);
+        assert_eq!(block_on(cache.spawn(|cache| cache.len())), 3);
+    }
+}
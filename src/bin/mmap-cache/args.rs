@@ -0,0 +1,128 @@
+use crate::Result;
+
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// Command line arguments, split into `--name value` (or `--name=value`) options, boolean `--name` flags, and positional
+/// arguments. Each accessor removes what it returns, so [`finish`](Self::finish) can reject anything left over.
+#[derive(Debug, Default)]
+pub struct Args {
+    options: Vec<(String, String)>,
+    flags: Vec<String>,
+    positionals: VecDeque<String>,
+}
+
+impl Args {
+    /// `flag_names` are the options that don't take a value. Everything after `--` is positional.
+    pub fn parse(args: impl IntoIterator<Item = String>, flag_names: &[&str]) -> Result<Self> {
+        let mut parsed = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                parsed.positionals.extend(args);
+                break;
+            }
+            let Some(name) = arg.strip_prefix("--") else {
+                parsed.positionals.push_back(arg);
+                continue;
+            };
+            if let Some((name, value)) = name.split_once('=') {
+                parsed.options.push((name.to_owned(), value.to_owned()));
+            } else if flag_names.contains(&name) {
+                parsed.flags.push(name.to_owned());
+            } else {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("missing value for --{name}"))?;
+                parsed.options.push((name.to_owned(), value));
+            }
+        }
+        Ok(parsed)
+    }
+
+    pub fn flag(&mut self, name: &str) -> bool {
+        let len = self.flags.len();
+        self.flags.retain(|flag| flag != name);
+        self.flags.len() < len
+    }
+
+    /// Returns the last value given for the option `name`.
+    pub fn option(&mut self, name: &str) -> Option<String> {
+        self.options_all(name).pop()
+    }
+
+    /// Returns every value given for the option `name`, in order.
+    pub fn options_all(&mut self, name: &str) -> Vec<String> {
+        let (matching, rest) = std::mem::take(&mut self.options)
+            .into_iter()
+            .partition(|(n, _)| n == name);
+        self.options = rest;
+        matching.into_iter().map(|(_, value)| value).collect()
+    }
+
+    pub fn parse_option<T>(&mut self, name: &str) -> Result<Option<T>>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.option(name)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|e| format!("invalid value `{value}` for --{name}: {e}").into())
+            })
+            .transpose()
+    }
+
+    /// Removes the next positional argument, which is described by `what` if it's missing.
+    pub fn positional(&mut self, what: &str) -> Result<String> {
+        self.positionals
+            .pop_front()
+            .ok_or_else(|| format!("missing {what}").into())
+    }
+
+    /// Removes all the remaining positional arguments.
+    pub fn positionals_all(&mut self) -> Vec<String> {
+        self.positionals.drain(..).collect()
+    }
+
+    /// Fails if any arguments weren't consumed.
+    pub fn finish(self) -> Result<()> {
+        if let Some((name, _)) = self.options.first() {
+            return Err(format!("unexpected option --{name}").into());
+        }
+        if let Some(name) = self.flags.first() {
+            return Err(format!("unexpected flag --{name}").into());
+        }
+        if let Some(arg) = self.positionals.front() {
+            return Err(format!("unexpected argument `{arg}`").into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn options_flags_and_positionals() {
+        let args = [
+            "in.tsv", "--key=1", "--header", "--value", "3", "--", "--out",
+        ];
+        let mut args = Args::parse(args.map(String::from), &["header"]).unwrap();
+        assert!(args.flag("header"));
+        assert!(!args.flag("sorted"));
+        assert_eq!(args.parse_option::<usize>("value").unwrap(), Some(3));
+        assert!(args.parse_option::<usize>("missing").unwrap().is_none());
+        assert_eq!(args.positional("input").unwrap(), "in.tsv");
+        assert_eq!(args.positionals_all(), ["--out"]);
+        assert!(args.positional("more").is_err());
+        assert!(Args::default().finish().is_ok());
+        // `--key` was never consumed.
+        assert!(args.finish().is_err());
+
+        assert!(Args::parse(["--value".to_owned()], &[]).is_err());
+    }
+}
@@ -0,0 +1,151 @@
+use crate::args::Args;
+use crate::encoding::Encoding;
+use crate::input::{self, Column, Format};
+use crate::Result;
+
+use mmap_cache::{ExternalSortBuilder, FileBuilder};
+use std::fs;
+use std::io::{self, BufRead};
+
+const USAGE: &str = "\
+usage: mmap-cache build [options] <input> (--output <file> | --index <file> --values <file>)
+
+Builds a cache from a TSV, CSV, or JSON Lines file, or from stdin if <input> is `-`. The output files are only replaced
+once the build succeeds.
+
+options:
+  --output <file>           Write a single-file container
+  --index <file>            Write the index to a separate file (requires --values)
+  --values <file>           Write the values to a separate file (requires --index)
+  --format <format>         tsv, csv, or jsonl [default: from the input file extension]
+  --header                  The first row of a TSV or CSV file names the columns
+  --key <column>            Key column, by zero-based position or name [default: 0, or `key` for jsonl]
+  --value <column>          Value column, by zero-based position or name [default: 1, or `value` for jsonl]
+  --key-encoding <enc>      How key fields are converted to bytes [default: utf8]
+  --value-encoding <enc>    How value fields are converted to bytes [default: utf8]
+  --sorted                  The input is already sorted by encoded key, so it's streamed without sorting
+  --sort-memory <bytes>     Memory for sorting before spilling to temporary files [default: 268435456]
+  --hash-index              Also write a hash index for fast lookups of missing keys
+  --align <bytes>           Align every value to a power of two
+  --dedup                   Store identical values only once
+  --metadata <key=value>    Attach metadata to the cache (repeatable)
+
+Encodings: utf8, hex, u8, u16, u32, u64, i8, i16, i32, i64, f32, f64. Numeric keys are encoded so that they sort
+numerically, and numeric values are stored as native-endian bytes.";
+
+pub fn run(args: impl Iterator<Item = String>) -> Result<()> {
+    let mut args = Args::parse(args, &["help", "header", "sorted", "hash-index", "dedup"])?;
+    if args.flag("help") {
+        println!("{USAGE}");
+        return Ok(());
+    }
+
+    let input_path = args.positional("<input>")?;
+    let format = match args.parse_option::<Format>("format")? {
+        Some(format) => format,
+        None => {
+            Format::from_path(&input_path).ok_or("can't guess the input format; use --format")?
+        }
+    };
+    let header = args.flag("header");
+    let (default_key, default_value) = match format {
+        Format::Jsonl => (
+            Column::Name("key".to_owned()),
+            Column::Name("value".to_owned()),
+        ),
+        _ => (Column::Index(0), Column::Index(1)),
+    };
+    let key_column = args.parse_option("key")?.unwrap_or(default_key);
+    let value_column = args.parse_option("value")?.unwrap_or(default_value);
+    let key_encoding: Encoding = args.parse_option("key-encoding")?.unwrap_or_default();
+    let value_encoding: Encoding = args.parse_option("value-encoding")?.unwrap_or_default();
+    let sorted = args.flag("sorted");
+    let sort_memory = args.parse_option("sort-memory")?.unwrap_or(256 << 20);
+
+    let mut builder = match (
+        args.option("output"),
+        args.option("index"),
+        args.option("values"),
+    ) {
+        (Some(output), None, None) => FileBuilder::create_file_atomic(output)?,
+        (None, Some(index), Some(values)) => FileBuilder::create_files_atomic(index, values)?,
+        _ => {
+            return Err(format!(
+                "expected either --output, or both --index and --values\n\n{USAGE}"
+            )
+            .into())
+        }
+    };
+    if args.flag("hash-index") {
+        builder = builder.with_hash_index();
+    }
+    if let Some(alignment) = args.parse_option::<usize>("align")? {
+        if !alignment.is_power_of_two() {
+            return Err(format!("--align must be a power of two, not {alignment}").into());
+        }
+        builder = builder.with_value_alignment(alignment);
+    }
+    if args.flag("dedup") {
+        builder = builder.with_value_dedup();
+    }
+    for entry in args.options_all("metadata") {
+        let (key, value) = entry
+            .split_once('=')
+            .ok_or_else(|| format!("expected --metadata <key=value>, not `{entry}`"))?;
+        builder.set_metadata(key, value);
+    }
+    args.finish()?;
+
+    let reader: Box<dyn BufRead> = if input_path == "-" {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(io::BufReader::new(
+            fs::File::open(&input_path).map_err(|e| format!("can't open {input_path}: {e}"))?,
+        ))
+    };
+    let mut sink = if sorted {
+        Sink::Sorted(builder)
+    } else {
+        Sink::Unsorted(ExternalSortBuilder::new(sort_memory), builder)
+    };
+    let count = input::for_each_record(
+        reader,
+        format,
+        header,
+        &key_column,
+        &value_column,
+        |key, value| {
+            sink.insert(
+                &key_encoding.encode_key(key)?,
+                &value_encoding.encode_value(value)?,
+            )
+        },
+    )?;
+    sink.finish()?;
+    eprintln!("wrote {count} entries");
+    Ok(())
+}
+
+/// Where encoded records go: straight into the builder, or through a sort first.
+enum Sink {
+    Sorted(FileBuilder),
+    Unsorted(ExternalSortBuilder, FileBuilder),
+}
+
+impl Sink {
+    fn insert(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        match self {
+            Self::Sorted(builder) => builder.insert(key, value)?,
+            Self::Unsorted(sorter, _) => sorter.insert(key, value)?,
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<()> {
+        match self {
+            Self::Sorted(builder) => builder.finish()?,
+            Self::Unsorted(sorter, builder) => sorter.finish(builder)?,
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,244 @@
+use crate::Result;
+
+use mmap_cache::KeyEncode;
+use std::fmt;
+use std::str::FromStr;
+
+/// How text fields are converted to key or value bytes.
+///
+/// Numeric keys use the order-preserving [`KeyEncode`] encoding, so they sort numerically. Numeric values use native-endian
+/// bytes, like [`PodCodec`](mmap_cache::PodCodec), so they can be read with a [`TypedCache`](mmap_cache::TypedCache).
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    Hex,
+    U8,
+    U16,
+    U32,
+    U64,
+    I8,
+    I16,
+    I32,
+    I64,
+    F32,
+    F64,
+}
+
+const NAMES: [(&str, Encoding); 12] = [
+    ("utf8", Encoding::Utf8),
+    ("hex", Encoding::Hex),
+    ("u8", Encoding::U8),
+    ("u16", Encoding::U16),
+    ("u32", Encoding::U32),
+    ("u64", Encoding::U64),
+    ("i8", Encoding::I8),
+    ("i16", Encoding::I16),
+    ("i32", Encoding::I32),
+    ("i64", Encoding::I64),
+    ("f32", Encoding::F32),
+    ("f64", Encoding::F64),
+];
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        NAMES
+            .iter()
+            .find(|(name, _)| *name == s)
+            .map(|&(_, encoding)| encoding)
+            .ok_or_else(|| {
+                let names: Vec<_> = NAMES.iter().map(|(name, _)| *name).collect();
+                format!("expected one of {}", names.join(", "))
+            })
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (name, _) = NAMES.iter().find(|(_, e)| e == self).unwrap();
+        f.write_str(name)
+    }
+}
+
+macro_rules! encode_number {
+    ($self:ident, $text:ident, $method:ident, $($variant:ident => $t:ty),*) => {
+        match $self {
+            Encoding::Utf8 => Ok($text.as_bytes().to_vec()),
+            Encoding::Hex => decode_hex($text),
+            $(Encoding::$variant => {
+                let n: $t = $text
+                    .trim()
+                    .parse()
+                    .map_err(|e| format!("invalid {} `{}`: {e}", $self, $text))?;
+                Ok(encode_number!(@$method n))
+            })*
+        }
+    };
+    (@key $n:ident) => { $n.to_key_bytes() };
+    (@value $n:ident) => { $n.to_ne_bytes().to_vec() };
+}
+
+macro_rules! decode_number {
+    ($self:ident, $bytes:ident, $method:ident, $($variant:ident => $t:ty),*) => {
+        match $self {
+            Encoding::Utf8 => std::str::from_utf8($bytes)
+                .map(str::to_owned)
+                .map_err(|_| format!("{} is not valid UTF-8", encode_hex($bytes)).into()),
+            Encoding::Hex => Ok(encode_hex($bytes)),
+            $(Encoding::$variant => decode_number!(@$method $t, $bytes)
+                .map(|n| n.to_string())
+                .ok_or_else(|| format!("{} is not a valid {}", encode_hex($bytes), $self).into()),)*
+        }
+    };
+    (@key $t:ty, $bytes:ident) => { <$t>::from_key_bytes($bytes) };
+    (@value $t:ty, $bytes:ident) => { $bytes.try_into().ok().map(<$t>::from_ne_bytes) };
+}
+
+impl Encoding {
+    pub fn encode_key(self, text: &str) -> Result<Vec<u8>> {
+        encode_number!(
+            self, text, key,
+            U8 => u8, U16 => u16, U32 => u32, U64 => u64,
+            I8 => i8, I16 => i16, I32 => i32, I64 => i64,
+            F32 => f32, F64 => f64
+        )
+    }
+
+    pub fn encode_value(self, text: &str) -> Result<Vec<u8>> {
+        encode_number!(
+            self, text, value,
+            U8 => u8, U16 => u16, U32 => u32, U64 => u64,
+            I8 => i8, I16 => i16, I32 => i32, I64 => i64,
+            F32 => f32, F64 => f64
+        )
+    }
+
+    /// The inverse of [`encode_key`](Self::encode_key).
+    pub fn decode_key(self, bytes: &[u8]) -> Result<String> {
+        decode_number!(
+            self, bytes, key,
+            U8 => u8, U16 => u16, U32 => u32, U64 => u64,
+            I8 => i8, I16 => i16, I32 => i32, I64 => i64,
+            F32 => f32, F64 => f64
+        )
+    }
+
+    /// The inverse of [`encode_value`](Self::encode_value).
+    pub fn decode_value(self, bytes: &[u8]) -> Result<String> {
+        decode_number!(
+            self, bytes, value,
+            U8 => u8, U16 => u16, U32 => u32, U64 => u64,
+            I8 => i8, I16 => i16, I32 => i32, I64 => i64,
+            F32 => f32, F64 => f64
+        )
+    }
+}
+
+/// Formats `bytes` for display with `encoding`, or if it's `None`, as UTF-8 when that's printable and hex otherwise.
+pub fn display_bytes(bytes: &[u8], encoding: Option<Encoding>, decode: Decode) -> Result<String> {
+    match encoding {
+        Some(encoding) => match decode {
+            Decode::Key => encoding.decode_key(bytes),
+            Decode::Value => encoding.decode_value(bytes),
+        },
+        None => Ok(match std::str::from_utf8(bytes) {
+            Ok(text) if !text.chars().any(char::is_control) => text.to_owned(),
+            _ => encode_hex(bytes),
+        }),
+    }
+}
+
+/// Whether bytes being displayed are a key or a value, since numbers are encoded differently in each.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Decode {
+    Key,
+    Value,
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(2 + 2 * bytes.len());
+    hex.push_str("0x");
+    for byte in bytes {
+        hex.push_str(&format!("{byte:02x}"));
+    }
+    hex
+}
+
+fn decode_hex(text: &str) -> Result<Vec<u8>> {
+    let text = text.trim();
+    let text = text.strip_prefix("0x").unwrap_or(text);
+    if !text.len().is_multiple_of(2) {
+        return Err(format!("hex string `{text}` has an odd number of digits").into());
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&text[i..i + 2], 16)
+                .map_err(|_| format!("invalid hex string `{text}`").into())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_keys_and_values() {
+        for (name, encoding) in NAMES {
+            assert_eq!(name.parse::<Encoding>().unwrap(), encoding);
+            assert_eq!(encoding.to_string(), name);
+        }
+        assert!("u128".parse::<Encoding>().is_err());
+
+        assert_eq!(Encoding::Utf8.encode_key("abc").unwrap(), b"abc");
+        assert_eq!(Encoding::Hex.encode_value("0x00ff").unwrap(), [0, 0xff]);
+        assert!(Encoding::Hex.encode_value("abc").is_err());
+        assert!(Encoding::Hex.encode_value("zz").is_err());
+
+        let keys: Vec<_> = ["-2", "-1", "0", "10"]
+            .map(|n| Encoding::I32.encode_key(n).unwrap())
+            .to_vec();
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(
+            Encoding::U16.encode_value(" 258 ").unwrap(),
+            258u16.to_ne_bytes()
+        );
+        assert!(Encoding::U8.encode_value("256").is_err());
+        assert_eq!(
+            Encoding::F64.encode_value("1.5").unwrap(),
+            1.5f64.to_ne_bytes()
+        );
+    }
+
+    #[test]
+    fn decode_keys_and_values() {
+        for (encoding, text) in [
+            (Encoding::Utf8, "abc"),
+            (Encoding::Hex, "0x00ff"),
+            (Encoding::I32, "-7"),
+            (Encoding::U64, "258"),
+            (Encoding::F64, "1.5"),
+        ] {
+            let key = encoding.encode_key(text).unwrap();
+            assert_eq!(encoding.decode_key(&key).unwrap(), text);
+            let value = encoding.encode_value(text).unwrap();
+            assert_eq!(encoding.decode_value(&value).unwrap(), text);
+        }
+        assert!(Encoding::Utf8.decode_value(&[0xff]).is_err());
+        assert!(Encoding::U32.decode_value(&[1, 2]).is_err());
+        assert!(Encoding::U32.decode_key(&[1, 2, 3, 4, 5]).is_err());
+
+        assert_eq!(display_bytes(b"abc", None, Decode::Value).unwrap(), "abc");
+        assert_eq!(
+            display_bytes(b"a\tb", None, Decode::Key).unwrap(),
+            "0x610962"
+        );
+        assert_eq!(
+            display_bytes(&[0, 0, 0, 2], Some(Encoding::U32), Decode::Key).unwrap(),
+            "2"
+        );
+    }
+}
@@ -0,0 +1,268 @@
+use crate::json;
+use crate::Result;
+
+use std::io::BufRead;
+use std::str::FromStr;
+
+/// The format of a text input file, with one record per line (except for quoted line breaks in CSV).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    /// Tab-separated fields, without quoting.
+    Tsv,
+    /// Comma-separated fields, where fields may be quoted with `"` and quotes are escaped by doubling them.
+    Csv,
+    /// One JSON object per line.
+    Jsonl,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "tsv" => Ok(Self::Tsv),
+            "csv" => Ok(Self::Csv),
+            "jsonl" => Ok(Self::Jsonl),
+            _ => Err("expected one of tsv, csv, jsonl".to_owned()),
+        }
+    }
+}
+
+impl Format {
+    /// Guesses the format from the extension of `path`.
+    pub fn from_path(path: &str) -> Option<Self> {
+        let (_, extension) = path.rsplit_once('.')?;
+        match extension {
+            "json" | "ndjson" => Some(Self::Jsonl),
+            other => other.parse().ok(),
+        }
+    }
+}
+
+/// Selects a field of each record, either by its zero-based position or by name. Names are resolved with the header row of
+/// a TSV or CSV file, or the field names of a JSON object.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Column {
+    Index(usize),
+    Name(String),
+}
+
+impl FromStr for Column {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(s.parse()
+            .map_or_else(|_| Self::Name(s.to_owned()), Self::Index))
+    }
+}
+
+/// Reads records from `reader` and calls `f` with the key and value fields of each one.
+///
+/// If `header` is set, the first row of a TSV or CSV file names the columns instead of being a record. Returns the number
+/// of records read.
+pub fn for_each_record(
+    mut reader: impl BufRead,
+    format: Format,
+    header: bool,
+    key: &Column,
+    value: &Column,
+    mut f: impl FnMut(&str, &str) -> Result<()>,
+) -> Result<u64> {
+    let mut line_number = 0;
+    let mut names: Option<Vec<String>> = None;
+    let mut count = 0;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(count);
+        }
+        line_number += 1;
+        let record_line = line_number;
+        let fields = match format {
+            Format::Jsonl => {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let fields = json::parse_object(&line).map_err(|e| at_line(record_line, e))?;
+                let pick = |column: &Column| match column {
+                    Column::Name(name) => fields
+                        .iter()
+                        .find(|(n, _)| n == name)
+                        .map(|(_, field)| field.as_str())
+                        .ok_or_else(|| format!("missing field `{name}`")),
+                    Column::Index(i) => fields
+                        .get(*i)
+                        .map(|(_, field)| field.as_str())
+                        .ok_or_else(|| format!("missing field {i}")),
+                };
+                let (key, value) = pick(key)
+                    .and_then(|key| Ok((key, pick(value)?)))
+                    .map_err(|e| at_line(record_line, e.into()))?;
+                f(key, value).map_err(|e| at_line(record_line, e))?;
+                count += 1;
+                continue;
+            }
+            Format::Tsv => trim_newline(&line).split('\t').map(str::to_owned).collect(),
+            Format::Csv => {
+                // Quoted fields may span lines, so keep reading until the record is complete.
+                while line.matches('"').count() % 2 == 1 {
+                    if reader.read_line(&mut line)? == 0 {
+                        return Err(at_line(record_line, "unterminated quoted field".into()));
+                    }
+                    line_number += 1;
+                }
+                parse_csv(trim_newline(&line)).map_err(|e| at_line(record_line, e))?
+            }
+        };
+        if header && names.is_none() {
+            names = Some(fields);
+            continue;
+        }
+        let pick = |column: &Column| {
+            let i = match column {
+                Column::Index(i) => *i,
+                Column::Name(name) => names
+                    .as_ref()
+                    .and_then(|names| names.iter().position(|n| n == name))
+                    .ok_or_else(|| format!("no column named `{name}` (is --header missing?)"))?,
+            };
+            fields
+                .get(i)
+                .map(String::as_str)
+                .ok_or_else(|| format!("missing column {i}"))
+        };
+        let (key, value) = pick(key)
+            .and_then(|key| Ok((key, pick(value)?)))
+            .map_err(|e| at_line(record_line, e.into()))?;
+        f(key, value).map_err(|e| at_line(record_line, e))?;
+        count += 1;
+    }
+}
+
+fn at_line(line: u64, e: Box<dyn std::error::Error>) -> Box<dyn std::error::Error> {
+    format!("line {line}: {e}").into()
+}
+
+fn trim_newline(line: &str) -> &str {
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    line.strip_suffix('\r').unwrap_or(line)
+}
+
+fn parse_csv(line: &str) -> Result<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        let mut field = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    Some('"') => break,
+                    Some(c) => field.push(c),
+                    None => return Err("unterminated quoted field".into()),
+                }
+            }
+            if !matches!(chars.peek(), None | Some(',')) {
+                return Err("unexpected characters after a quoted field".into());
+            }
+        } else {
+            while let Some(&c) = chars.peek() {
+                if c == ',' {
+                    break;
+                }
+                field.push(c);
+                chars.next();
+            }
+        }
+        fields.push(field);
+        if chars.next().is_none() {
+            return Ok(fields);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(
+        input: &str,
+        format: Format,
+        header: bool,
+        key: &str,
+        value: &str,
+    ) -> Result<Vec<(String, String)>> {
+        let mut out = Vec::new();
+        for_each_record(
+            input.as_bytes(),
+            format,
+            header,
+            &key.parse().unwrap(),
+            &value.parse().unwrap(),
+            |k, v| {
+                out.push((k.to_owned(), v.to_owned()));
+                Ok(())
+            },
+        )?;
+        Ok(out)
+    }
+
+    fn pairs(expected: &[(&str, &str)]) -> Vec<(String, String)> {
+        expected
+            .iter()
+            .map(|&(k, v)| (k.to_owned(), v.to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn delimited_records() {
+        let tsv = "id\tname\tcount\nb\tbee\t2\r\na\tant\t1\n";
+        assert_eq!(
+            records(tsv, Format::Tsv, true, "id", "count").unwrap(),
+            pairs(&[("b", "2"), ("a", "1")])
+        );
+        assert_eq!(
+            records(tsv, Format::Tsv, false, "1", "0").unwrap(),
+            pairs(&[("name", "id"), ("bee", "b"), ("ant", "a")])
+        );
+        let err = records(tsv, Format::Tsv, false, "id", "count").unwrap_err();
+        assert!(err.to_string().starts_with("line 1: no column named `id`"));
+
+        let csv = "k,v\n\"a,1\",\"say \"\"hi\"\"\"\nb,\"two\nlines\"\nc,\n";
+        assert_eq!(
+            records(csv, Format::Csv, true, "k", "v").unwrap(),
+            pairs(&[("a,1", "say \"hi\""), ("b", "two\nlines"), ("c", "")])
+        );
+        let err = records("a,\"b\"x\n", Format::Csv, false, "0", "1").unwrap_err();
+        assert!(err.to_string().starts_with("line 1:"));
+        assert!(records("a\n", Format::Csv, false, "0", "1").is_err());
+    }
+
+    #[test]
+    fn json_lines_records() {
+        let jsonl = "{\"key\": \"a\", \"value\": {\"n\": 1}}\n\n{\"value\": 2.5, \"key\": \"b\"}\n";
+        assert_eq!(
+            records(jsonl, Format::Jsonl, false, "key", "value").unwrap(),
+            pairs(&[("a", "{\"n\": 1}"), ("b", "2.5")])
+        );
+        let err = records(jsonl, Format::Jsonl, false, "id", "value").unwrap_err();
+        assert_eq!(err.to_string(), "line 1: missing field `id`");
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(Format::from_path("data.csv"), Some(Format::Csv));
+        assert_eq!(Format::from_path("data.ndjson"), Some(Format::Jsonl));
+        assert_eq!(Format::from_path("data"), None);
+        assert_eq!("3".parse::<Column>().unwrap(), Column::Index(3));
+        assert_eq!(
+            "id".parse::<Column>().unwrap(),
+            Column::Name("id".to_owned())
+        );
+    }
+}
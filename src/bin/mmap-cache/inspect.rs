@@ -0,0 +1,125 @@
+use crate::args::Args;
+use crate::encoding::{display_bytes, Decode, Encoding};
+use crate::Result;
+
+use mmap_cache::MmapCache;
+use std::io::{self, Write};
+
+const USAGE: &str = "\
+usage: mmap-cache inspect [options] (<cache> | --index <file> --values <file>)
+
+Prints the number of entries, the sizes of the index, values, and auxiliary sections, the first and last keys, a histogram
+of value sizes, and any metadata.
+
+options:
+  --index <file>          Read the index from a separate file (requires --values)
+  --values <file>         Read the values from a separate file (requires --index)
+  --key-encoding <enc>    How to display keys [default: UTF-8 if printable, otherwise hex]";
+
+pub fn run(args: impl Iterator<Item = String>) -> Result<()> {
+    let mut args = Args::parse(args, &["help"])?;
+    if args.flag("help") {
+        println!("{USAGE}");
+        return Ok(());
+    }
+    let key_encoding = args.parse_option("key-encoding")?;
+    let cache = crate::open_cache(&mut args)?;
+    args.finish()?;
+    print_summary(&cache, key_encoding, &mut io::stdout().lock())
+}
+
+fn print_summary(
+    cache: &MmapCache,
+    key_encoding: Option<Encoding>,
+    out: &mut impl Write,
+) -> Result<()> {
+    writeln!(out, "entries:      {}", cache.len())?;
+    writeln!(out, "index bytes:  {}", cache.index().as_fst().size())?;
+    writeln!(out, "value bytes:  {}", cache.value_bytes().len())?;
+    let sections = cache.sections();
+    if sections.is_empty() {
+        writeln!(out, "sections:     none (raw values)")?;
+    } else {
+        writeln!(out, "sections:")?;
+        for (name, len) in sections {
+            writeln!(out, "  {name:<14}{len} bytes")?;
+        }
+    }
+
+    let key = |(key, _): (Vec<u8>, u64)| display_bytes(&key, key_encoding, Decode::Key);
+    if let Some(first) = cache.first_key_value().map(key).transpose()? {
+        writeln!(out, "first key:    {first}")?;
+    }
+    if let Some(last) = cache.last_key_value().map(key).transpose()? {
+        writeln!(out, "last key:     {last}")?;
+    }
+
+    let histogram = cache.value_size_histogram();
+    if let (Some(min), Some(max), Some(mean)) = (histogram.min, histogram.max, histogram.mean()) {
+        writeln!(
+            out,
+            "value sizes:  min {min}, max {max}, mean {mean:.1}, total {}",
+            histogram.total_bytes
+        )?;
+        for (range, count) in histogram.buckets().filter(|&(_, count)| count > 0) {
+            let range = format!("{}..{}", range.start, range.end);
+            writeln!(out, "  {range:<14}{count}")?;
+        }
+    }
+
+    if let Some(schema) = cache.value_schema()? {
+        writeln!(out, "value schema: {schema}")?;
+    }
+    let metadata = cache.metadata()?;
+    if !metadata.is_empty() {
+        writeln!(out, "metadata:")?;
+        for (key, value) in metadata.iter() {
+            writeln!(
+                out,
+                "  {key} = {}",
+                display_bytes(value, None, Decode::Value)?
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use mmap_cache::FileBuilder;
+
+    #[test]
+    fn summary() {
+        let path = "/tmp/mmap_cache_cli_test_inspect";
+        let mut builder = FileBuilder::create_file(path).unwrap();
+        builder.set_metadata("source", "test");
+        for (key, value) in [(1u32, "a"), (2, "bcd"), (3, "")] {
+            builder
+                .insert(
+                    &Encoding::U32.encode_key(&key.to_string()).unwrap(),
+                    value.as_bytes(),
+                )
+                .unwrap();
+        }
+        builder.finish().unwrap();
+
+        let cache = unsafe { MmapCache::map_path(path) }.unwrap();
+        let mut out = Vec::new();
+        print_summary(&cache, Some(Encoding::U32), &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        for expected in [
+            "entries:      3\n",
+            "  metadata      ",
+            "first key:    1\n",
+            "last key:     3\n",
+            "value sizes:  min 0, max 3, mean 1.3, total 4\n",
+            "  0..1          1\n",
+            "  2..4          1\n",
+            "  source = test\n",
+        ] {
+            assert!(out.contains(expected), "{expected:?} not in\n{out}");
+        }
+    }
+}
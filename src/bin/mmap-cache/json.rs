@@ -0,0 +1,209 @@
+//! Just enough JSON to pull fields out of the objects in a JSON Lines file.
+
+use crate::Result;
+
+/// Parses a JSON object into its (name, field) pairs, in order.
+///
+/// A string field is unescaped, and any other field (a number, `true`, an array, etc.) is kept as its JSON text.
+pub fn parse_object(text: &str) -> Result<Vec<(String, String)>> {
+    let mut parser = Parser { text, pos: 0 };
+    parser.skip_whitespace();
+    parser.expect(b'{')?;
+    let mut fields = Vec::new();
+    parser.skip_whitespace();
+    if !parser.eat(b'}') {
+        loop {
+            parser.skip_whitespace();
+            let name = parser.string()?;
+            parser.skip_whitespace();
+            parser.expect(b':')?;
+            parser.skip_whitespace();
+            let value = if parser.peek() == Some(b'"') {
+                parser.string()?
+            } else {
+                let start = parser.pos;
+                parser.skip_value()?;
+                parser.text[start..parser.pos].to_owned()
+            };
+            fields.push((name, value));
+            parser.skip_whitespace();
+            if parser.eat(b'}') {
+                break;
+            }
+            parser.expect(b',')?;
+        }
+    }
+    parser.skip_whitespace();
+    if parser.pos != text.len() {
+        return Err(parser.error("trailing characters"));
+    }
+    Ok(fields)
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        let matches = self.peek() == Some(byte);
+        self.pos += matches as usize;
+        matches
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<()> {
+        if self.eat(byte) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{}`", byte as char)))
+        }
+    }
+
+    fn error(&self, message: &str) -> Box<dyn std::error::Error> {
+        format!("invalid JSON at column {}: {message}", self.pos + 1).into()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.expect(b'"')?;
+        let mut out = String::new();
+        loop {
+            let rest = &self.text[self.pos..];
+            let end = rest
+                .find(['"', '\\'])
+                .ok_or_else(|| self.error("unterminated string"))?;
+            out.push_str(&rest[..end]);
+            self.pos += end + 1;
+            if rest.as_bytes()[end] == b'"' {
+                return Ok(out);
+            }
+            let escape = self
+                .peek()
+                .ok_or_else(|| self.error("unterminated string"))?;
+            self.pos += 1;
+            match escape {
+                b'"' => out.push('"'),
+                b'\\' => out.push('\\'),
+                b'/' => out.push('/'),
+                b'b' => out.push('\u{8}'),
+                b'f' => out.push('\u{c}'),
+                b'n' => out.push('\n'),
+                b'r' => out.push('\r'),
+                b't' => out.push('\t'),
+                b'u' => {
+                    let mut c = self.hex4()?;
+                    if (0xd800..0xdc00).contains(&c) && self.text[self.pos..].starts_with("\\u") {
+                        self.pos += 2;
+                        let low = self.hex4()?;
+                        c = 0x10000 + ((c - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff);
+                    }
+                    out.push(
+                        char::from_u32(c).ok_or_else(|| self.error("invalid unicode escape"))?,
+                    );
+                }
+                _ => return Err(self.error("invalid escape")),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32> {
+        let digits = self
+            .text
+            .get(self.pos..self.pos + 4)
+            .ok_or_else(|| self.error("truncated unicode escape"))?;
+        let c =
+            u32::from_str_radix(digits, 16).map_err(|_| self.error("invalid unicode escape"))?;
+        self.pos += 4;
+        Ok(c)
+    }
+
+    /// Skips over any JSON value.
+    fn skip_value(&mut self) -> Result<()> {
+        match self.peek() {
+            Some(b'"') => self.string().map(drop),
+            Some(open @ (b'{' | b'[')) => {
+                let close = if open == b'{' { b'}' } else { b']' };
+                self.pos += 1;
+                self.skip_whitespace();
+                if self.eat(close) {
+                    return Ok(());
+                }
+                loop {
+                    self.skip_whitespace();
+                    if open == b'{' {
+                        self.string()?;
+                        self.skip_whitespace();
+                        self.expect(b':')?;
+                        self.skip_whitespace();
+                    }
+                    self.skip_value()?;
+                    self.skip_whitespace();
+                    if self.eat(close) {
+                        return Ok(());
+                    }
+                    self.expect(b',')?;
+                }
+            }
+            _ => {
+                let len = self.text[self.pos..]
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '+' | '.')))
+                    .unwrap_or(self.text.len() - self.pos);
+                let token = &self.text[self.pos..self.pos + len];
+                if token.is_empty()
+                    || !(matches!(token, "true" | "false" | "null") || token.parse::<f64>().is_ok())
+                {
+                    return Err(self.error("expected a value"));
+                }
+                self.pos += len;
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_objects() {
+        let fields =
+            parse_object(r#" {"id": 7, "name": "a\"bé😀", "tags": [1, {"x": null}], "ok": true} "#)
+                .unwrap();
+        let expected = [
+            ("id", "7"),
+            ("name", "a\"bé😀"),
+            ("tags", r#"[1, {"x": null}]"#),
+            ("ok", "true"),
+        ];
+        assert_eq!(fields.len(), expected.len());
+        for ((name, value), (expected_name, expected_value)) in fields.iter().zip(expected) {
+            assert_eq!(
+                (name.as_str(), value.as_str()),
+                (expected_name, expected_value)
+            );
+        }
+        assert!(parse_object("{}").unwrap().is_empty());
+
+        for invalid in [
+            r#"{"a": }"#,
+            r#"{"a": 1"#,
+            r#"{"a": 1} x"#,
+            r#"["a"]"#,
+            r#"{"a": tru}"#,
+            r#"{"a": "\q"}"#,
+        ] {
+            assert!(parse_object(invalid).is_err(), "{invalid}");
+        }
+    }
+}
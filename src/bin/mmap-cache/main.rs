@@ -0,0 +1,72 @@
+//! The `mmap-cache` command line tool, for building caches from text files without writing Rust.
+
+mod args;
+mod build;
+mod encoding;
+mod input;
+mod inspect;
+mod json;
+mod merge;
+mod query;
+mod verify;
+
+use args::Args;
+
+use mmap_cache::MmapCache;
+use std::process::ExitCode;
+
+pub type Result<T, E = Box<dyn std::error::Error>> = std::result::Result<T, E>;
+
+const USAGE: &str = "\
+usage: mmap-cache <command> [options]
+
+commands:
+  build      Build a cache from TSV, CSV, or JSON Lines input
+  inspect    Print the size, sections, and metadata of a cache
+  get        Print the value for a key
+  scan       Print the entries in a key range
+  verify     Check a cache for corruption
+  merge      Merge several caches into one
+
+Run `mmap-cache <command> --help` for the options of each command.";
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+    let result = match args.next().as_deref() {
+        Some("build") => build::run(args),
+        Some("inspect") => inspect::run(args),
+        Some("get") => query::run_get(args),
+        Some("scan") => query::run_scan(args),
+        Some("verify") => verify::run(args),
+        Some("merge") => merge::run(args),
+        None | Some("help" | "--help" | "-h") => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        Some(command) => Err(format!("unknown command `{command}`\n\n{USAGE}").into()),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Maps the cache given by the `--index` and `--values` options, or otherwise by the next positional argument, which names
+/// a single-file container.
+pub fn open_cache(args: &mut Args) -> Result<MmapCache> {
+    let index = args.option("index");
+    let values = args.option("values");
+    // SAFETY: The files are only read. Like any reader of a cache, this assumes that nobody modifies them while it runs.
+    let cache = match (index, values) {
+        (Some(index), Some(values)) => unsafe { MmapCache::map_paths(&index, &values)? },
+        (None, None) => {
+            let path = args.positional("<cache>")?;
+            unsafe { MmapCache::map_path(&path)? }
+        }
+        _ => return Err("--index and --values must be given together".into()),
+    };
+    Ok(cache)
+}
@@ -0,0 +1,148 @@
+use crate::args::Args;
+use crate::Result;
+
+use mmap_cache::{merge, DuplicatePolicy, FileBuilder, MergeOptions, MmapCache};
+
+const USAGE: &str = "\
+usage: mmap-cache merge [options] <out-index> <out-values> (<index> <values>)...
+       mmap-cache merge [options] --output <file> <cache>...
+
+Merges caches into one, in constant memory. The inputs are either pairs of index and value files, or single-file
+containers with --output. The output files are only replaced once the merge succeeds.
+
+options:
+  --output <file>          Write a single-file container, reading single-file containers as input
+  --duplicates <policy>    Which value to keep for a key found in several inputs: first, last, or error [default: last]
+  --drop-tombstones        Leave deleted keys out of the output, instead of writing their tombstones";
+
+pub fn run(args: impl Iterator<Item = String>) -> Result<()> {
+    let mut args = Args::parse(args, &["help", "drop-tombstones"])?;
+    if args.flag("help") {
+        println!("{USAGE}");
+        return Ok(());
+    }
+    let options = MergeOptions {
+        duplicates: args
+            .option("duplicates")
+            .as_deref()
+            .map_or(Ok(DuplicatePolicy::default()), parse_policy)?,
+        drop_tombstones: args.flag("drop-tombstones"),
+    };
+    let output = args.option("output");
+    let paths = args.positionals_all();
+    args.finish()?;
+
+    // SAFETY: The inputs are only read. Like any reader of a cache, this assumes that nobody modifies them while it runs.
+    let (caches, builder) = match output {
+        Some(output) => {
+            let caches = paths
+                .iter()
+                .map(|path| unsafe { MmapCache::map_path(path) })
+                .collect::<Result<Vec<_>, _>>()?;
+            (caches, FileBuilder::create_file_atomic(output)?)
+        }
+        None => {
+            if paths.len() < 2 || !paths.len().is_multiple_of(2) {
+                return Err(format!(
+                    "expected an output index and value file, then pairs of input files\n\n{USAGE}"
+                )
+                .into());
+            }
+            let caches = paths[2..]
+                .chunks(2)
+                .map(|pair| unsafe { MmapCache::map_paths(&pair[0], &pair[1]) })
+                .collect::<Result<Vec<_>, _>>()?;
+            (
+                caches,
+                FileBuilder::create_files_atomic(&paths[0], &paths[1])?,
+            )
+        }
+    };
+    if caches.is_empty() {
+        return Err(format!("no input caches\n\n{USAGE}").into());
+    }
+    merge(&caches, options, builder)?;
+    eprintln!("merged {} caches", caches.len());
+    Ok(())
+}
+
+fn parse_policy(policy: &str) -> Result<DuplicatePolicy> {
+    match policy {
+        "first" => Ok(DuplicatePolicy::KeepFirst),
+        "last" => Ok(DuplicatePolicy::KeepLast),
+        "error" => Ok(DuplicatePolicy::Error),
+        _ => Err(format!(
+            "invalid value `{policy}` for --duplicates: expected one of first, last, error"
+        )
+        .into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build(path: &str, entries: &[(&str, Option<&str>)]) {
+        let mut builder = FileBuilder::create_file(path).unwrap();
+        for &(key, value) in entries {
+            match value {
+                Some(value) => builder.insert(key.as_bytes(), value.as_bytes()).unwrap(),
+                None => builder.insert_tombstone(key.as_bytes()).unwrap(),
+            }
+        }
+        builder.finish().unwrap();
+    }
+
+    fn merged(options: &[&str]) -> Result<Vec<(String, String)>> {
+        let (a, b, out) = (
+            "/tmp/mmap_cache_cli_test_merge_a",
+            "/tmp/mmap_cache_cli_test_merge_b",
+            "/tmp/mmap_cache_cli_test_merge_out",
+        );
+        build(
+            a,
+            &[("k1", Some("a1")), ("k2", Some("a2")), ("k3", Some("a3"))],
+        );
+        build(b, &[("k2", Some("b2")), ("k3", None), ("k4", Some("b4"))]);
+        let args = options.iter().copied().chain(["--output", out, a, b]);
+        run(args.map(String::from))?;
+
+        let cache = unsafe { MmapCache::map_path(out) }.unwrap();
+        let mut entries = Vec::new();
+        let mut keys = cache.keys();
+        while let Some(key) = fst::Streamer::next(&mut keys) {
+            let value = if cache.is_tombstone(key) {
+                "deleted"
+            } else {
+                std::str::from_utf8(cache.get_value(key).unwrap()).unwrap()
+            };
+            entries.push((String::from_utf8(key.to_vec()).unwrap(), value.to_owned()));
+        }
+        Ok(entries)
+    }
+
+    fn entries(expected: &[(&str, &str)]) -> Vec<(String, String)> {
+        expected
+            .iter()
+            .map(|&(k, v)| (k.to_owned(), v.to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn merge_containers() {
+        assert_eq!(
+            merged(&[]).unwrap(),
+            entries(&[("k1", "a1"), ("k2", "b2"), ("k3", "deleted"), ("k4", "b4")])
+        );
+        assert_eq!(
+            merged(&["--duplicates", "first", "--drop-tombstones"]).unwrap(),
+            entries(&[("k1", "a1"), ("k2", "a2"), ("k3", "a3"), ("k4", "b4")])
+        );
+        assert_eq!(
+            merged(&["--drop-tombstones"]).unwrap(),
+            entries(&[("k1", "a1"), ("k2", "b2"), ("k4", "b4")])
+        );
+        assert!(merged(&["--duplicates", "error"]).is_err());
+        assert!(merged(&["--duplicates", "middle"]).is_err());
+    }
+}
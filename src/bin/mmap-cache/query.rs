@@ -0,0 +1,219 @@
+use crate::args::Args;
+use crate::encoding::{display_bytes, Decode, Encoding};
+use crate::Result;
+
+use fst::Streamer;
+use mmap_cache::MmapCache;
+use std::io::{self, Write};
+use std::ops::Bound;
+
+const GET_USAGE: &str = "\
+usage: mmap-cache get [options] (<cache> | --index <file> --values <file>) <key>
+
+Prints the value for <key>.
+
+options:
+  --index <file>            Read the index from a separate file (requires --values)
+  --values <file>           Read the values from a separate file (requires --index)
+  --key-encoding <enc>      How <key> is converted to bytes [default: utf8]
+  --value-encoding <enc>    How to display the value [default: UTF-8 if printable, otherwise hex]
+  --raw                     Write the value bytes to stdout exactly, without a trailing newline
+
+Encodings: utf8, hex, u8, u16, u32, u64, i8, i16, i32, i64, f32, f64.";
+
+const SCAN_USAGE: &str = "\
+usage: mmap-cache scan [options] (<cache> | --index <file> --values <file>) [<start> [<end>]]
+
+Prints the key and value of every entry with a key from <start> (inclusive) to <end> (exclusive), separated by a tab,
+one entry per line. Either bound may be omitted, or given as `..` to leave it unbounded.
+
+options:
+  --index <file>            Read the index from a separate file (requires --values)
+  --values <file>           Read the values from a separate file (requires --index)
+  --key-encoding <enc>      How <start> and <end> are converted to bytes, and how keys are displayed [default: utf8]
+  --value-encoding <enc>    How to display values [default: UTF-8 if printable, otherwise hex]
+  --limit <n>               Stop after <n> entries
+  --keys-only               Print only the keys
+
+Keys that were given no --key-encoding are displayed as UTF-8 if printable, and hex otherwise.";
+
+pub fn run_get(args: impl Iterator<Item = String>) -> Result<()> {
+    let mut args = Args::parse(args, &["help", "raw"])?;
+    if args.flag("help") {
+        println!("{GET_USAGE}");
+        return Ok(());
+    }
+    let key_encoding: Encoding = args.parse_option("key-encoding")?.unwrap_or_default();
+    let value_encoding = args.parse_option("value-encoding")?;
+    let raw = args.flag("raw");
+    let cache = crate::open_cache(&mut args)?;
+    let key_text = args.positional("<key>")?;
+    args.finish()?;
+
+    let value = lookup(&cache, &key_encoding.encode_key(&key_text)?)
+        .map_err(|e| format!("key `{key_text}` {e}"))?;
+    let mut out = io::stdout().lock();
+    if raw {
+        out.write_all(value)?;
+    } else {
+        writeln!(
+            out,
+            "{}",
+            display_bytes(value, value_encoding, Decode::Value)?
+        )?;
+    }
+    Ok(())
+}
+
+fn lookup<'c>(cache: &'c MmapCache, key: &[u8]) -> Result<&'c [u8], &'static str> {
+    if cache.is_tombstone(key) {
+        return Err("was deleted");
+    }
+    cache.get_value(key).ok_or("not found")
+}
+
+pub fn run_scan(args: impl Iterator<Item = String>) -> Result<()> {
+    let mut args = Args::parse(args, &["help", "keys-only"])?;
+    if args.flag("help") {
+        println!("{SCAN_USAGE}");
+        return Ok(());
+    }
+    let key_encoding: Option<Encoding> = args.parse_option("key-encoding")?;
+    let value_encoding = args.parse_option("value-encoding")?;
+    let limit = args.parse_option("limit")?.unwrap_or(usize::MAX);
+    let keys_only = args.flag("keys-only");
+    let cache = crate::open_cache(&mut args)?;
+    let bound =
+        |args: &mut Args, bounded: fn(Vec<u8>) -> Bound<Vec<u8>>| match args.positional("bound") {
+            Ok(text) if text != ".." => key_encoding
+                .unwrap_or_default()
+                .encode_key(&text)
+                .map(bounded),
+            _ => Ok(Bound::Unbounded),
+        };
+    let start = bound(&mut args, Bound::Included)?;
+    let end = bound(&mut args, Bound::Excluded)?;
+    args.finish()?;
+
+    let scan = Scan {
+        key_encoding,
+        value_encoding: (!keys_only).then_some(value_encoding),
+        limit,
+    };
+    scan.print(
+        &cache,
+        (start, end),
+        &mut io::BufWriter::new(io::stdout().lock()),
+    )
+}
+
+struct Scan {
+    key_encoding: Option<Encoding>,
+    /// `None` if only keys are printed.
+    value_encoding: Option<Option<Encoding>>,
+    limit: usize,
+}
+
+impl Scan {
+    fn print(
+        &self,
+        cache: &MmapCache,
+        range: (Bound<Vec<u8>>, Bound<Vec<u8>>),
+        out: &mut impl Write,
+    ) -> Result<()> {
+        let mut entries = cache.range_values(range);
+        let mut count = 0;
+        while let Some((key, value)) = entries.next() {
+            if count == self.limit {
+                break;
+            }
+            count += 1;
+            let key = display_bytes(key, self.key_encoding, Decode::Key)?;
+            match self.value_encoding {
+                Some(encoding) => {
+                    let value = display_bytes(value, encoding, Decode::Value)
+                        .map_err(|e| format!("value of key `{key}`: {e}"))?;
+                    writeln!(out, "{key}\t{value}")?;
+                }
+                None => writeln!(out, "{key}")?,
+            }
+        }
+        out.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use mmap_cache::FileBuilder;
+
+    #[test]
+    fn get_and_scan() {
+        let path = "/tmp/mmap_cache_cli_test_query";
+        let mut builder = FileBuilder::create_file(path).unwrap();
+        for key in 1u16..=5 {
+            let key = key.to_string();
+            builder
+                .insert(
+                    &Encoding::U16.encode_key(&key).unwrap(),
+                    &Encoding::F32.encode_value(&key).unwrap(),
+                )
+                .unwrap();
+        }
+        builder
+            .insert_tombstone(&Encoding::U16.encode_key("6").unwrap())
+            .unwrap();
+        builder.finish().unwrap();
+        let cache = unsafe { MmapCache::map_path(path) }.unwrap();
+
+        let key = |n: &str| Encoding::U16.encode_key(n).unwrap();
+        assert_eq!(lookup(&cache, &key("2")).unwrap(), 2f32.to_ne_bytes());
+        assert_eq!(lookup(&cache, &key("6")), Err("was deleted"));
+        assert_eq!(lookup(&cache, &key("7")), Err("not found"));
+
+        let scan = |scan: Scan, start, end| {
+            let mut out = Vec::new();
+            scan.print(&cache, (start, end), &mut out)
+                .map(|()| String::from_utf8(out).unwrap())
+        };
+        let typed = || Scan {
+            key_encoding: Some(Encoding::U16),
+            value_encoding: Some(Some(Encoding::F32)),
+            limit: usize::MAX,
+        };
+        assert_eq!(
+            scan(
+                typed(),
+                Bound::Included(key("2")),
+                Bound::Excluded(key("4"))
+            )
+            .unwrap(),
+            "2\t2\n3\t3\n"
+        );
+        let limited = Scan {
+            limit: 2,
+            ..typed()
+        };
+        assert_eq!(
+            scan(limited, Bound::Included(key("4")), Bound::Unbounded).unwrap(),
+            "4\t4\n5\t5\n"
+        );
+        let keys_only = Scan {
+            value_encoding: None,
+            ..typed()
+        };
+        assert_eq!(
+            scan(keys_only, Bound::Unbounded, Bound::Excluded(key("3"))).unwrap(),
+            "1\n2\n"
+        );
+        let wrong_type = Scan {
+            value_encoding: Some(Some(Encoding::U16)),
+            ..typed()
+        };
+        let err = scan(wrong_type, Bound::Unbounded, Bound::Unbounded).unwrap_err();
+        assert!(err.to_string().starts_with("value of key `1`: 0x"));
+        assert!(err.to_string().ends_with("is not a valid u16"));
+    }
+}
@@ -0,0 +1,117 @@
+use crate::args::Args;
+use crate::Result;
+
+use mmap_cache::MmapCache;
+use std::io::{self, Write};
+
+const USAGE: &str = "\
+usage: mmap-cache verify [options] (<cache> | <index> <values>)
+
+Checks that a cache is intact: that the files have a supported format, that their checksums match, and that every value
+offset in the index lies within the value file (and, without a length table, that offsets are in key order). Exits with
+a non-zero status if any check fails, so it can gate deployments.
+
+options:
+  --allow-missing-checksums    Pass value files written without checksums, like raw value files";
+
+pub fn run(args: impl Iterator<Item = String>) -> Result<()> {
+    let mut args = Args::parse(args, &["help", "allow-missing-checksums"])?;
+    if args.flag("help") {
+        println!("{USAGE}");
+        return Ok(());
+    }
+    let allow_missing_checksums = args.flag("allow-missing-checksums");
+    let first = args.positional("<cache>")?;
+    let second = args.positional("<values>").ok();
+    args.finish()?;
+
+    // SAFETY: The files are only read. Like any reader of a cache, this assumes that nobody modifies them while it runs.
+    let cache = match &second {
+        Some(values) => unsafe { MmapCache::map_paths(&first, values) },
+        None => unsafe { MmapCache::map_path(&first) },
+    }
+    .map_err(|e| format!("format: {e}"))?;
+    if check(&cache, allow_missing_checksums, &mut io::stdout().lock())? {
+        Ok(())
+    } else {
+        Err("cache is corrupt".into())
+    }
+}
+
+/// Runs every check on `cache`, reporting each one to `out`. Returns `true` if they all pass.
+fn check(cache: &MmapCache, allow_missing_checksums: bool, out: &mut impl Write) -> Result<bool> {
+    let has_checksums = cache
+        .sections()
+        .iter()
+        .any(|&(name, _)| name == "checksums");
+    let checks = [
+        ("format", Ok(())),
+        (
+            "checksums",
+            if has_checksums || !allow_missing_checksums {
+                cache.verify()
+            } else {
+                Ok(())
+            },
+        ),
+        ("offsets", cache.verify_offsets()),
+        ("metadata", cache.metadata().map(drop)),
+    ];
+    let mut passed = true;
+    for (name, result) in checks {
+        match result {
+            Ok(()) => writeln!(out, "{:<11}ok", format!("{name}:"))?,
+            Err(e) => {
+                passed = false;
+                writeln!(out, "{:<11}FAILED: {e}", format!("{name}:"))?;
+            }
+        }
+    }
+    Ok(passed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use mmap_cache::FileBuilder;
+    use std::fs;
+
+    #[test]
+    fn detect_corruption() {
+        let (index_path, values_path) = (
+            "/tmp/mmap_cache_cli_test_verify_index",
+            "/tmp/mmap_cache_cli_test_verify_values",
+        );
+        let mut builder = FileBuilder::create_files(index_path, values_path).unwrap();
+        builder.insert(b"a", b"first").unwrap();
+        builder.insert(b"b", b"second").unwrap();
+        builder.finish().unwrap();
+
+        let report = |allow_missing_checksums| {
+            let cache = unsafe { MmapCache::map_paths(index_path, values_path) }.unwrap();
+            let mut out = Vec::new();
+            let passed = check(&cache, allow_missing_checksums, &mut out).unwrap();
+            (passed, String::from_utf8(out).unwrap())
+        };
+        assert_eq!(
+            report(false),
+            (
+                true,
+                "format:    ok\nchecksums: ok\noffsets:   ok\nmetadata:  ok\n".to_owned()
+            )
+        );
+
+        let mut values = fs::read(values_path).unwrap();
+        values[0] ^= 1;
+        fs::write(values_path, &values).unwrap();
+        let (passed, out) = report(false);
+        assert!(!passed);
+        assert!(out.contains("checksums: FAILED: values checksum mismatch"));
+
+        // A raw value file has no checksums.
+        fs::write(values_path, b"firstsecond").unwrap();
+        assert!(!report(false).0);
+        assert!(report(true).0);
+    }
+}
@@ -0,0 +1,334 @@
+use crate::error::PathContext;
+use crate::{Compressor, Error};
+
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+// The block file is a sequence of blocks, each stored as `(decompressed_len: u64, compressed_len: u64, compressed bytes)`.
+// A decompressed block is `(count: u64, value_ends: [u64; count], value bytes)`, where `value_ends` are relative to the
+// start of the value bytes.
+//
+// The index maps each key to `(block_offset << SLOT_BITS) | slot`, where `block_offset` is the offset of the key's block in
+// the block file and `slot` is the position of the value within that block.
+
+const SLOT_BITS: u32 = 16;
+const MAX_SLOTS: usize = 1 << SLOT_BITS;
+const BLOCK_HEADER_LEN: usize = 16;
+
+/// Serializes a sorted stream of key-value pairs, packing consecutive values into blocks that are compressed as a unit.
+///
+/// Compressing many small values together achieves a much better ratio than compressing each one separately (see
+/// [`CompressedBuilder`](crate::CompressedBuilder)). The resulting files are read with [`BlockCache`].
+pub struct BlockBuilder<C> {
+    map_builder: fst::MapBuilder<io::BufWriter<fs::File>>,
+    block_writer: io::BufWriter<fs::File>,
+    block_cursor: u64,
+    block_size: usize,
+    compressor: C,
+    value_ends: Vec<u64>,
+    values: Vec<u8>,
+    scratch: Vec<u8>,
+}
+
+impl<C: Compressor> BlockBuilder<C> {
+    pub fn new(
+        index_writer: io::BufWriter<fs::File>,
+        block_writer: io::BufWriter<fs::File>,
+        compressor: C,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            map_builder: fst::MapBuilder::new(index_writer)?,
+            block_writer,
+            block_cursor: 0,
+            block_size: 4096,
+            compressor,
+            value_ends: Vec::new(),
+            values: Vec::new(),
+            scratch: Vec::new(),
+        })
+    }
+
+    /// Creates a new [`BlockBuilder`] that overwrites the files at `index_path` and `block_path`.
+    pub fn create_files(
+        index_path: impl AsRef<Path>,
+        block_path: impl AsRef<Path>,
+        compressor: C,
+    ) -> Result<Self, Error> {
+        Self::new(
+            io::BufWriter::new(fs::File::create(index_path)?),
+            io::BufWriter::new(fs::File::create(block_path)?),
+            compressor,
+        )
+    }
+
+    /// A block is compressed and written once its values reach `block_size` bytes. Defaults to 4 KiB.
+    ///
+    /// Larger blocks compress better, but every lookup must decompress a whole block.
+    pub fn with_block_size(mut self, block_size: usize) -> Self {
+        self.block_size = block_size;
+        self
+    }
+
+    /// Associates `value` with `key`. Keys must be inserted in sorted order.
+    pub fn insert(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        let slot = self.value_ends.len() as u64;
+        self.map_builder
+            .insert(key, (self.block_cursor << SLOT_BITS) | slot)?;
+        self.values.extend_from_slice(value);
+        self.value_ends.push(self.values.len() as u64);
+        if self.values.len() >= self.block_size || self.value_ends.len() == MAX_SLOTS {
+            self.write_block()?;
+        }
+        Ok(())
+    }
+
+    /// Completes the serialization and flushes any outstanding IO.
+    pub fn finish(mut self) -> Result<(), Error> {
+        if !self.value_ends.is_empty() {
+            self.write_block()?;
+        }
+        self.map_builder.into_inner()?.flush()?;
+        self.block_writer.flush()?;
+        Ok(())
+    }
+
+    fn write_block(&mut self) -> Result<(), Error> {
+        let mut block = Vec::with_capacity(8 * (self.value_ends.len() + 1) + self.values.len());
+        block.extend_from_slice(&(self.value_ends.len() as u64).to_le_bytes());
+        for end in &self.value_ends {
+            block.extend_from_slice(&end.to_le_bytes());
+        }
+        block.extend_from_slice(&self.values);
+
+        self.scratch.clear();
+        self.compressor.compress(&block, &mut self.scratch);
+        self.block_writer
+            .write_all(&(block.len() as u64).to_le_bytes())?;
+        self.block_writer
+            .write_all(&(self.scratch.len() as u64).to_le_bytes())?;
+        self.block_writer.write_all(&self.scratch)?;
+
+        self.block_cursor += (BLOCK_HEADER_LEN + self.scratch.len()) as u64;
+        if self.block_cursor >> (64 - SLOT_BITS) != 0 {
+            return Err(Error::InvalidFormat("block file is too large"));
+        }
+        self.value_ends.clear();
+        self.values.clear();
+        Ok(())
+    }
+}
+
+/// Reads the files written by a [`BlockBuilder`].
+///
+/// Recently decompressed blocks are kept in memory, so that lookups of nearby keys only decompress one block.
+pub struct BlockCache<C, DK, DV> {
+    index: fst::Map<DK>,
+    blocks: DV,
+    compressor: C,
+    block_cache: Mutex<BlockLru>,
+}
+
+/// The least recently used block is at the front.
+struct BlockLru {
+    capacity: usize,
+    blocks: VecDeque<(u64, Arc<[u8]>)>,
+}
+
+impl<C, DK, DV> BlockCache<C, DK, DV>
+where
+    C: Compressor,
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    pub fn new(index_bytes: DK, blocks: DV, compressor: C) -> Result<Self, Error> {
+        Ok(Self {
+            index: fst::Map::new(index_bytes)?,
+            blocks,
+            compressor,
+            block_cache: Mutex::new(BlockLru {
+                capacity: 16,
+                blocks: VecDeque::new(),
+            }),
+        })
+    }
+
+    /// Keeps up to `capacity` decompressed blocks in memory. Defaults to 16.
+    pub fn with_block_cache_capacity(self, capacity: usize) -> Self {
+        self.block_cache.lock().unwrap().capacity = capacity;
+        self
+    }
+
+    pub fn index(&self) -> &fst::Map<DK> {
+        &self.index
+    }
+
+    /// Returns a copy of the value for `key`, if it exists.
+    pub fn get_value(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.with_value(key, <[u8]>::to_vec)
+    }
+
+    /// Calls `f` with the value for `key`, if it exists, without copying the value out of its decompressed block.
+    pub fn with_value<T>(
+        &self,
+        key: &[u8],
+        f: impl FnOnce(&[u8]) -> T,
+    ) -> Result<Option<T>, Error> {
+        let Some(handle) = self.index.get(key) else {
+            return Ok(None);
+        };
+        let block = self.block(handle >> SLOT_BITS)?;
+        let slot = (handle & (MAX_SLOTS as u64 - 1)) as usize;
+        Ok(Some(f(slot_value(&block, slot)?)))
+    }
+
+    fn block(&self, offset: u64) -> Result<Arc<[u8]>, Error> {
+        if let Some(block) = self.block_cache.lock().unwrap().get(offset) {
+            return Ok(block);
+        }
+
+        const TRUNCATED: Error = Error::InvalidFormat("block is truncated");
+        let bytes = self.blocks.as_ref();
+        let start = usize::try_from(offset).map_err(|_| TRUNCATED)?;
+        let header = bytes
+            .get(start..start + BLOCK_HEADER_LEN)
+            .ok_or(TRUNCATED)?;
+        let decompressed_len = u64::from_le_bytes(header[..8].try_into().unwrap());
+        let compressed_len = u64::from_le_bytes(header[8..].try_into().unwrap());
+        let compressed = usize::try_from(compressed_len)
+            .ok()
+            .and_then(|len| bytes.get(start + BLOCK_HEADER_LEN..)?.get(..len))
+            .ok_or(TRUNCATED)?;
+        // Check the length against what `compressed` could possibly decompress to before allocating for it.
+        let decompressed_len = usize::try_from(decompressed_len)
+            .ok()
+            .filter(|&len| len <= self.compressor.max_decompressed_len(compressed.len()))
+            .ok_or(Error::InvalidFormat("block is too large"))?;
+        let mut block = Vec::with_capacity(decompressed_len);
+        self.compressor
+            .decompress(compressed, decompressed_len, &mut block)?;
+
+        let block: Arc<[u8]> = block.into();
+        self.block_cache
+            .lock()
+            .unwrap()
+            .insert(offset, block.clone());
+        Ok(block)
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl<C: Compressor> BlockCache<C, Mmap, Mmap> {
+    /// Maps the files written by a [`BlockBuilder`] to read-only virtual memory ranges.
+    ///
+    /// # Safety
+    ///
+    /// See [`Mmap`].
+    pub unsafe fn map_paths(
+        index_path: impl AsRef<Path>,
+        block_path: impl AsRef<Path>,
+        compressor: C,
+    ) -> Result<Self, Error> {
+        let (index_path, block_path) = (index_path.as_ref(), block_path.as_ref());
+        let index = fs::File::open(index_path)
+            .and_then(|file| Mmap::map(&file))
+            .at_path("map", index_path)?;
+        let blocks = fs::File::open(block_path)
+            .and_then(|file| Mmap::map(&file))
+            .at_path("map", block_path)?;
+        Self::new(index, blocks, compressor).map_err(|e| e.at_cache_paths(index_path, block_path))
+    }
+}
+
+impl BlockLru {
+    fn get(&mut self, offset: u64) -> Option<Arc<[u8]>> {
+        let i = self.blocks.iter().position(|(o, _)| *o == offset)?;
+        let entry = self.blocks.remove(i).unwrap();
+        let block = entry.1.clone();
+        self.blocks.push_back(entry);
+        Some(block)
+    }
+
+    fn insert(&mut self, offset: u64, block: Arc<[u8]>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.blocks.len() == self.capacity {
+            self.blocks.pop_front();
+        }
+        self.blocks.push_back((offset, block));
+    }
+}
+
+fn slot_value(block: &[u8], slot: usize) -> Result<&[u8], Error> {
+    const CORRUPT: Error = Error::InvalidFormat("corrupt block");
+    let read = |at: usize| -> Result<usize, Error> {
+        let bytes = block.get(at..at + 8).ok_or(CORRUPT)?;
+        usize::try_from(u64::from_le_bytes(bytes.try_into().unwrap())).map_err(|_| CORRUPT)
+    };
+    let count = read(0)?;
+    if slot >= count {
+        return Err(CORRUPT);
+    }
+    let values_start = count
+        .checked_add(1)
+        .and_then(|n| n.checked_mul(8))
+        .ok_or(CORRUPT)?;
+    let start = if slot == 0 { 0 } else { read(8 * slot)? };
+    let end = read(8 * (slot + 1))?;
+    block
+        .get(values_start..)
+        .and_then(|values| values.get(start..end))
+        .ok_or(CORRUPT)
+}
+
+#[cfg(all(test, feature = "mmap"))]
+mod tests {
+    use super::*;
+    use crate::Lz4;
+
+    #[test]
+    fn block_compressed_values() {
+        let dir = std::env::temp_dir();
+        let index_path = dir.join("mmap_cache_block_compressed_index");
+        let block_path = dir.join("mmap_cache_block_compressed_blocks");
+
+        let mut builder = BlockBuilder::create_files(&index_path, &block_path, Lz4)
+            .unwrap()
+            .with_block_size(256);
+        let keys: Vec<_> = (0..1000u32).map(|i| format!("key{i:04}")).collect();
+        for (i, key) in keys.iter().enumerate() {
+            builder
+                .insert(key.as_bytes(), format!("value {i}").as_bytes())
+                .unwrap();
+        }
+        builder.insert(b"zzz", b"").unwrap();
+        builder.finish().unwrap();
+
+        let cache = unsafe { BlockCache::map_paths(&index_path, &block_path, Lz4) }
+            .unwrap()
+            .with_block_cache_capacity(2);
+        for (i, key) in keys.iter().enumerate().rev() {
+            assert_eq!(
+                cache.get_value(key.as_bytes()).unwrap(),
+                Some(format!("value {i}").into_bytes())
+            );
+        }
+        assert_eq!(cache.get_value(b"zzz").unwrap(), Some(Vec::new()));
+        assert_eq!(cache.get_value(b"missing").unwrap(), None);
+        assert_eq!(cache.with_value(b"key0500", |v| v.len()).unwrap(), Some(9));
+
+        let index = std::fs::read(&index_path).unwrap();
+        let mut blocks = std::fs::read(&block_path).unwrap();
+        blocks[..8].copy_from_slice(&(u64::MAX / 2).to_le_bytes());
+        let cache = BlockCache::new(index, blocks, Lz4).unwrap();
+        assert!(matches!(
+            cache.get_value(b"key0000"),
+            Err(Error::InvalidFormat("block is too large"))
+        ));
+    }
+}
@@ -1,13 +1,28 @@
-use crate::Error;
+use crate::checkpoint::{CheckpointState, Journal};
+use crate::checksum::ChecksumWriter;
+use crate::error::PathContext;
+use crate::format::{self, Section};
+use crate::temp::TempFile;
+use crate::{Cache, CancellationToken, Error};
 
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
+use std::hash::BuildHasher;
 use std::io;
-use std::io::Write;
-use std::path::Path;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 /// Serializes an arbitrarily large sorted stream of `([u8], [u8])` key-value pairs.
 ///
-/// Duplicate keys are not supported.
+/// Duplicate keys are not supported: a key that doesn't sort after the previous key fails with [`Error::KeyOrder`].
+///
+/// Along with the values, the value stream records the length of every committed value, so readers can recover exact value
+/// slices with [`Cache::get_value`](crate::Cache::get_value). Empty values still occupy one byte of padding so that every
+/// entry has a distinct offset. CRC-32 checksums of both the index and the values are also recorded, to be checked with
+/// [`Cache::verify`](crate::Cache::verify). Every 1024th key is kept in memory and recorded as well, to speed up
+/// [`Cache::nth_key`](crate::Cache::nth_key) and [`Cache::rank`](crate::Cache::rank).
 ///
 /// Serialization happens by writing key-value pairs in sorted order. A value is always written before its corresponding key,
 /// because the index will map that key to the starting byte offset of the value that was written.
@@ -16,6 +31,7 @@ use std::path::Path;
 ///
 /// ```
 /// # use mmap_cache::Error;
+/// # #[cfg(feature = "mmap")]
 /// # fn example() -> Result<(), Error> {
 /// use mmap_cache::{FileBuilder, MmapCache};
 ///
@@ -41,96 +57,878 @@ use std::path::Path;
 /// assert_eq!(unsafe { cache.get_transmuted_value(b"lots_of_garbage") }, Some(&buf));
 /// # Ok(())
 /// # }
+/// # #[cfg(feature = "mmap")]
 /// # example().unwrap();
 /// ```
-pub struct FileBuilder {
-    map_builder: fst::MapBuilder<io::BufWriter<fs::File>>,
-    value_writer: io::BufWriter<fs::File>,
+pub struct FileBuilder<WI = io::BufWriter<fs::File>, WV = io::BufWriter<fs::File>> {
+    map_builder: fst::MapBuilder<ChecksumWriter<WI>>,
+    value_writer: ChecksumWriter<WV>,
+    length_writer: LengthBuffer,
     value_cursor: usize,
     committed_value_cursor: usize,
+    value_alignment: usize,
+    /// Holds the index until it's appended to the value file, when building a single-file container.
+    container_index: Option<TempFile>,
+    /// Temporary files to rename into place when building atomically.
+    pending_renames: Vec<(TempFile, PathBuf)>,
+    /// Handles to the files being written, if known, for syncing them to storage.
+    sync_files: Vec<fs::File>,
+    /// Final locations of the files being written, if known.
+    output_paths: Vec<PathBuf>,
+    durability: Durability,
+    dedup: Option<Dedup>,
+    key_count: u64,
+    /// The last committed key, to check that keys are inserted in order.
+    last_key: Vec<u8>,
+    /// Every [`format::RANK_SAMPLE_INTERVAL`]th key, concatenated, for ordinal lookups like
+    /// [`Cache::nth_key`](crate::Cache::nth_key).
+    rank_samples: Vec<u8>,
+    rank_sample_ends: Vec<u64>,
+    /// `(key_hash, offset)` for every key, if writing a hash index.
+    hash_entries: Option<Vec<(u64, u64)>>,
+    metadata: BTreeMap<String, Vec<u8>>,
+    /// `(offset, expires_at)` for every entry that expires, in offset order.
+    expiries: Vec<(u64, u64)>,
+    /// Records committed entries, if the build is resumable.
+    journal: Option<Journal>,
+    progress: Option<Progress>,
+    cancellation: Option<CancellationToken>,
 }
 
-impl FileBuilder {
+/// A callback registered with [`FileBuilder::with_progress`].
+struct Progress {
+    interval: u64,
+    callback: Box<dyn FnMut(BuildProgress<'_>) + Send>,
+}
+
+/// How far a build has progressed, as reported to the callback registered with [`FileBuilder::with_progress`].
+#[derive(Clone, Copy, Debug)]
+pub struct BuildProgress<'a> {
+    /// The number of entries committed so far, including tombstones.
+    pub entries: u64,
+    /// The number of bytes written to the value stream so far, including padding.
+    pub value_bytes: u64,
+    /// The number of bytes of the index written so far.
+    pub index_bytes: u64,
+    /// The key that was just committed.
+    pub key: &'a [u8],
+}
+
+/// State for deduplicating identical values.
+struct Dedup {
+    /// The current value, buffered until it's committed so it can be compared with previous values.
+    pending: Vec<u8>,
+    /// Maps the 128-bit hash of every distinct value to its offset.
+    offsets: HashMap<u128, u64>,
+    hashers: [RandomState; 2],
+}
+
+impl Dedup {
+    fn hash(&self, value: &[u8]) -> u128 {
+        let [a, b] = &self.hashers;
+        ((a.hash_one(value) as u128) << 64) | b.hash_one(value) as u128
+    }
+}
+
+/// Holds the length table until it's appended after the values.
+enum LengthBuffer {
+    /// The table has one entry per key, so it's spilled to a temporary file by default.
+    File(io::BufWriter<TempFile>),
+    Memory(Vec<u8>),
+}
+
+impl LengthBuffer {
+    fn copy_to(self, writer: &mut impl Write) -> Result<u64, Error> {
+        match self {
+            Self::File(buffer) => {
+                let mut file = buffer.into_inner().map_err(|e| e.into_error())?;
+                file.seek(SeekFrom::Start(0))?;
+                Ok(io::copy(&mut file, writer)?)
+            }
+            Self::Memory(bytes) => {
+                writer.write_all(&bytes)?;
+                Ok(bytes.len() as u64)
+            }
+        }
+    }
+}
+
+impl Write for LengthBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::File(writer) => writer.write(buf),
+            Self::Memory(bytes) => bytes.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::File(writer) => writer.flush(),
+            Self::Memory(_) => Ok(()),
+        }
+    }
+}
+
+/// How hard [`FileBuilder::finish`] tries to make the written files survive a crash or power loss.
+#[derive(Clone, Copy, Debug, Default, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Durability {
+    /// Only flush buffered writes to the operating system.
+    #[default]
+    Flush,
+    /// Also wait for the contents of the written files to reach the storage device.
+    SyncFiles,
+    /// Also sync the directories containing the written files, so that newly created or renamed files are durable.
+    ///
+    /// Directories can only be synced on Unix, and only for builders that know their output paths (i.e. not those created
+    /// with [`FileBuilder::new`]).
+    SyncFilesAndDirectories,
+}
+
+impl<WI: Write, WV: Write> FileBuilder<WI, WV> {
     /// Creates a new [`FileBuilder`] for serializing a collection of key-value pairs.
     ///
     /// - `index_writer`: Writes the serialized [`fst::Map`] which stores the value offsets.
     /// - `value_writer`: Writes the values pointed to by the byte offsets stored in the [`fst::Map`].
     ///
+    /// Any writers can be used, e.g. sockets, compression encoders, or in-memory buffers. Since the builder doesn't know
+    /// which files they write to, if any, [`Durability`] beyond [`Durability::Flush`] only applies to builders created with
+    /// the `create_*` constructors.
+    ///
     /// ## Warning
     ///
     /// This crate has no control over the alignment guarantees provided by the given writers. Be careful to preserve alignment
-    /// when using [`memmap2`].
-    pub fn new(
-        index_writer: io::BufWriter<fs::File>,
-        value_writer: io::BufWriter<fs::File>,
+    /// when using [`memmap2`](https://docs.rs/memmap2).
+    pub fn new(index_writer: WI, value_writer: WV) -> Result<Self, Error> {
+        let length_writer = LengthBuffer::File(io::BufWriter::new(TempFile::new()?));
+        Self::with_length_buffer(index_writer, value_writer, length_writer)
+    }
+
+    fn with_length_buffer(
+        index_writer: WI,
+        value_writer: WV,
+        length_writer: LengthBuffer,
     ) -> Result<Self, Error> {
         Ok(Self {
-            map_builder: fst::MapBuilder::new(index_writer)?,
-            value_writer,
+            map_builder: fst::MapBuilder::new(ChecksumWriter::new(index_writer))?,
+            value_writer: ChecksumWriter::new(value_writer),
+            length_writer,
             committed_value_cursor: 0,
             value_cursor: 0,
+            value_alignment: 1,
+            container_index: None,
+            pending_renames: Vec::new(),
+            sync_files: Vec::new(),
+            output_paths: Vec::new(),
+            durability: Durability::default(),
+            dedup: None,
+            key_count: 0,
+            last_key: Vec::new(),
+            rank_samples: Vec::new(),
+            rank_sample_ends: Vec::new(),
+            hash_entries: None,
+            metadata: BTreeMap::new(),
+            expiries: Vec::new(),
+            journal: None,
+            progress: None,
+            cancellation: None,
         })
     }
 
-    /// Creates a new [`FileBuilder`], using the file at `index_path` for an index writer and the file at `value_path` as a
-    /// value writer.
+    /// Reuses the offset of an identical, previously committed value instead of writing the value again.
     ///
-    /// This always overwrites the given files.
+    /// Values are compared by a randomly keyed 128-bit hash, which must be kept in memory for every distinct value. While
+    /// enabled, each value is buffered in memory until it's committed.
+    pub fn with_value_dedup(mut self) -> Self {
+        self.dedup = Some(Dedup {
+            pending: Vec::new(),
+            offsets: HashMap::new(),
+            hashers: [RandomState::new(), RandomState::new()],
+        });
+        self
+    }
+
+    /// Also writes a hash table from key to value offset, which [`Cache::get_value_offset`](crate::Cache::get_value_offset)
+    /// probes before the index, so that lookups of missing keys usually finish in constant time without traversing it.
     ///
-    /// After calling `finish`, these same files can be used with `Cache::map_files`.
-    pub fn create_files(
-        index_path: impl AsRef<Path>,
-        value_path: impl AsRef<Path>,
-    ) -> Result<Self, Error> {
-        let index_writer = io::BufWriter::new(fs::File::create(index_path)?);
-        let value_writer = io::BufWriter::new(fs::File::create(value_path)?);
-        FileBuilder::new(index_writer, value_writer)
+    /// The table stores a 64-bit hash of each key rather than the key itself. Since such hashes can be made to collide on
+    /// purpose, a key found in the table is always confirmed against the index, so lookups of present keys cost the same
+    /// as without the table. While building, 16 bytes per key are kept in memory.
+    pub fn with_hash_index(mut self) -> Self {
+        self.hash_entries = Some(Vec::new());
+        self
+    }
+
+    /// Attaches `value` to the finished file under `key`, e.g. a schema version, source dataset ID, or build timestamp,
+    /// replacing any previous value for `key`. Read it back with [`Cache::metadata`].
+    ///
+    /// Metadata can be set at any time before `finish`, and is kept in memory until then.
+    pub fn set_metadata(&mut self, key: &str, value: impl AsRef<[u8]>) {
+        self.metadata
+            .insert(key.to_owned(), value.as_ref().to_vec());
+    }
+
+    /// Like [`set_metadata`](Self::set_metadata), but keeps any value already set for `key`.
+    pub(crate) fn set_metadata_if_unset(&mut self, key: &str, value: &[u8]) {
+        self.metadata
+            .entry(key.to_owned())
+            .or_insert_with(|| value.to_vec());
+    }
+
+    /// Pads between committed values so that the offset of every entry is a multiple of `alignment`.
+    ///
+    /// This is useful when values will be transmuted or cast to types with alignment requirements. Padding is not counted as
+    /// part of any value.
+    ///
+    /// # Panics
+    ///
+    /// If `alignment` is not a power of two.
+    pub fn with_value_alignment(mut self, alignment: usize) -> Self {
+        assert!(alignment.is_power_of_two());
+        self.value_alignment = alignment;
+        self
+    }
+
+    /// Sets how `finish` makes the written files durable. Defaults to [`Durability::Flush`].
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    /// Calls `callback` after every `interval` committed entries, e.g. to drive a progress bar or to detect a stalled build.
+    ///
+    /// The callback runs on the thread that commits the entry, so it should return quickly.
+    ///
+    /// # Panics
+    ///
+    /// If `interval` is zero.
+    pub fn with_progress(
+        mut self,
+        interval: u64,
+        callback: impl FnMut(BuildProgress<'_>) + Send + 'static,
+    ) -> Self {
+        assert!(interval > 0);
+        self.progress = Some(Progress {
+            interval,
+            callback: Box::new(callback),
+        });
+        self
+    }
+
+    /// Fails every commit with [`Error::Cancelled`] once `token` is cancelled, as well as `finish`.
+    ///
+    /// Builders that write to temporary files (e.g. [`create_files_atomic`](FileBuilder::create_files_atomic)) remove them
+    /// when dropped, but [`create_files`](FileBuilder::create_files) leaves partially written files behind.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Returns [`Error::Cancelled`] if the build was cancelled.
+    pub(crate) fn check_cancelled(&self) -> Result<(), Error> {
+        self.cancellation
+            .as_ref()
+            .map_or(Ok(()), CancellationToken::check)
     }
 
     /// Writes `value` into the value stream and commits the entry, storing the value's [`u64`] byte offset along with the `key`
     /// in the [`fst::Map`].
+    ///
+    /// Fails with [`Error::KeyOrder`] if `key` doesn't sort after the previous key, before writing anything.
     pub fn insert(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        self.check_key_order(key)?;
         self.append_value_bytes(value)?;
         self.commit_entry(key)
     }
 
     /// Finishes writing the current value, associating the starting byte offset of the value with `key`.
+    ///
+    /// Fails with [`Error::KeyOrder`] if `key` doesn't sort after the previous key, in which case the current value is left
+    /// uncommitted.
     pub fn commit_entry(&mut self, key: &[u8]) -> Result<(), Error> {
-        self.map_builder
-            .insert(key, u64::try_from(self.committed_value_cursor).unwrap())?;
+        self.check_key_order(key)?;
+        if let Some(mut dedup) = self.dedup.take() {
+            let result = self.commit_dedup(key, &mut dedup);
+            dedup.pending.clear();
+            self.dedup = Some(dedup);
+            return result;
+        }
+        let len = u64::try_from(self.value_cursor - self.committed_value_cursor).unwrap();
+        self.commit(key, len)
+    }
+
+    fn commit_dedup(&mut self, key: &[u8], dedup: &mut Dedup) -> Result<(), Error> {
+        let hash = dedup.hash(&dedup.pending);
+        if let Some(&offset) = dedup.offsets.get(&hash) {
+            return self.insert_key(key, offset);
+        }
+        dedup
+            .offsets
+            .insert(hash, u64::try_from(self.committed_value_cursor).unwrap());
+        self.write_value_bytes(&dedup.pending)?;
+        self.commit(key, dedup.pending.len() as u64)
+    }
+
+    /// Like [`insert`](Self::insert), but the entry expires at `expires_at`, with a resolution of one second.
+    ///
+    /// Expired entries are still present in the cache, but an [`ExpiringCache`](crate::ExpiringCache) treats them as missing,
+    /// and [`compact_expired`](crate::compact_expired) leaves them out. The expiry of each entry is kept in memory (16 bytes)
+    /// until `finish`. Fails if value deduplication is enabled, since deduplicated entries share their value.
+    pub fn insert_with_expiry(
+        &mut self,
+        key: &[u8],
+        value: &[u8],
+        expires_at: SystemTime,
+    ) -> Result<(), Error> {
+        if self.dedup.is_some() {
+            return Err(Error::Unsupported("expiry with value deduplication"));
+        }
+        let offset = u64::try_from(self.committed_value_cursor).unwrap();
+        self.insert(key, value)?;
+        self.expiries
+            .push((offset, format::unix_seconds(expires_at)));
+        Ok(())
+    }
+
+    /// Commits a tombstone for `key`, marking it as deleted.
+    ///
+    /// A tombstone has no value. When this cache is a layer of a [`LayeredCache`](crate::LayeredCache), or an input to
+    /// [`merge`](crate::merge), the tombstone hides any value for `key` in older layers. Any value bytes appended since the
+    /// last commit are left as unreachable padding.
+    pub fn insert_tombstone(&mut self, key: &[u8]) -> Result<(), Error> {
+        self.check_key_order(key)?;
+        if let Some(dedup) = &mut self.dedup {
+            dedup.pending.clear();
+        }
+        self.committed_value_cursor = self.value_cursor;
+        self.commit(key, format::TOMBSTONE_LEN)
+    }
+
+    fn commit(&mut self, key: &[u8], recorded_len: u64) -> Result<(), Error> {
+        let offset = u64::try_from(self.committed_value_cursor).unwrap();
+        self.insert_key(key, offset)?;
+        format::write_length_entry(&mut self.length_writer, offset, recorded_len)?;
+        if let Some(journal) = &mut self.journal {
+            journal.write_entry(key, offset, recorded_len)?;
+        }
+        if self.value_cursor == self.committed_value_cursor {
+            // Keep offsets unique so the length table can be searched by offset.
+            self.write_value_bytes(&[0])?;
+        }
+        self.write_padding(self.value_alignment)?;
         self.committed_value_cursor = self.value_cursor;
         Ok(())
     }
 
+    /// Fails unless `key` sorts after the last committed key.
+    pub(crate) fn check_key_order(&self, key: &[u8]) -> Result<(), Error> {
+        if self.key_count > 0 && key <= self.last_key.as_slice() {
+            return Err(Error::KeyOrder {
+                key: key.to_vec(),
+                previous: self.last_key.clone(),
+                index: self.key_count,
+            });
+        }
+        Ok(())
+    }
+
+    fn insert_key(&mut self, key: &[u8], offset: u64) -> Result<(), Error> {
+        self.check_cancelled()?;
+        self.map_builder.insert(key, offset)?;
+        if let Some(entries) = &mut self.hash_entries {
+            entries.push((format::key_hash(key), offset));
+        }
+        if self.key_count.is_multiple_of(format::RANK_SAMPLE_INTERVAL) {
+            self.rank_samples.extend_from_slice(key);
+            self.rank_sample_ends.push(self.rank_samples.len() as u64);
+        }
+        self.key_count += 1;
+        self.last_key.clear();
+        self.last_key.extend_from_slice(key);
+        if let Some(progress) = &mut self.progress {
+            if self.key_count.is_multiple_of(progress.interval) {
+                (progress.callback)(BuildProgress {
+                    entries: self.key_count,
+                    value_bytes: self.value_cursor as u64,
+                    index_bytes: self.map_builder.bytes_written(),
+                    key,
+                });
+            }
+        }
+        Ok(())
+    }
+
     /// Writes `value` into the value stream.
     ///
     /// The caller may continue appending more value bytes as needed before calling `commit_entry` to finish the current entry
     /// and start a new one.
     pub fn append_value_bytes(&mut self, value: &[u8]) -> Result<(), Error> {
-        self.value_writer.write_all(value)?;
-        self.value_cursor += value.len();
+        match &mut self.dedup {
+            Some(dedup) => dedup.pending.extend_from_slice(value),
+            None => self.write_value_bytes(value)?,
+        }
+        Ok(())
+    }
+
+    fn write_value_bytes(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.value_writer.write_all(bytes)?;
+        self.value_cursor += bytes.len();
         Ok(())
     }
 
     /// Writes zero padding until the cursor is aligned to `alignment`.
+    ///
+    /// If no bytes have been appended to the current value yet, the padding is skipped over and the value will start at the
+    /// aligned offset. Otherwise the padding becomes part of the current value.
     pub fn align_value_cursor(&mut self, alignment: usize) -> Result<(), Error> {
         debug_assert!(alignment.is_power_of_two());
-        debug_assert!(alignment <= 16);
+        if let Some(dedup) = self.dedup.as_mut().filter(|d| !d.pending.is_empty()) {
+            let cursor = self.value_cursor + dedup.pending.len();
+            let pad_size = next_multiple(cursor, alignment) - cursor;
+            dedup.pending.resize(dedup.pending.len() + pad_size, 0);
+            return Ok(());
+        }
+        let value_started = self.value_cursor != self.committed_value_cursor;
+        self.write_padding(alignment)?;
+        if !value_started {
+            self.committed_value_cursor = self.value_cursor;
+        }
+        Ok(())
+    }
+
+    fn write_padding(&mut self, alignment: usize) -> Result<(), Error> {
         const ZERO_PAD: [u8; 16] = [0; 16];
-        let pad_size = next_multiple(self.value_cursor, alignment) - self.value_cursor;
-        self.value_writer.write_all(&ZERO_PAD[0..pad_size])?;
-        self.value_cursor += pad_size;
+        let mut pad_size = next_multiple(self.value_cursor, alignment) - self.value_cursor;
+        while pad_size > 0 {
+            let n = pad_size.min(ZERO_PAD.len());
+            self.value_writer.write_all(&ZERO_PAD[0..n])?;
+            self.value_cursor += n;
+            pad_size -= n;
+        }
         debug_assert_eq!(self.value_cursor % alignment, 0);
         Ok(())
     }
 
+    /// Records the progress of a resumable build (see [`create_files_resumable`](Self::create_files_resumable)), so that if
+    /// the build is interrupted, [`resume_files`](Self::resume_files) can continue from the last committed entry.
+    ///
+    /// This syncs the value file and the checkpoint file to storage, so it's best called periodically rather than after
+    /// every entry. It fails if the build isn't resumable, if value deduplication is enabled, or if a value has been
+    /// appended but not committed yet.
+    pub fn checkpoint(&mut self) -> Result<(), Error> {
+        if self.journal.is_none() {
+            return Err(Error::Unsupported(
+                "checkpointing a builder without a checkpoint file",
+            ));
+        }
+        if self.dedup.is_some() {
+            return Err(Error::Unsupported("checkpointing with value deduplication"));
+        }
+        if self.value_cursor != self.committed_value_cursor {
+            return Err(Error::Unsupported("checkpointing in the middle of a value"));
+        }
+        if !self.expiries.is_empty() {
+            return Err(Error::Unsupported("checkpointing with expiring entries"));
+        }
+        self.value_writer.flush()?;
+        for file in &self.sync_files {
+            file.sync_data()?;
+        }
+        let state = CheckpointState {
+            values_len: u64::try_from(self.value_cursor).unwrap(),
+            value_alignment: self.value_alignment,
+            hash_index: self.hash_entries.is_some(),
+            metadata: self.metadata.clone(),
+        };
+        self.journal.as_mut().unwrap().write_checkpoint(&state)?;
+        Ok(())
+    }
+
+    /// The last key committed to a resumable build, including the entries replayed by
+    /// [`resume_files`](Self::resume_files). Inserting continues with the keys after it.
+    ///
+    /// Returns `None` if the build isn't resumable or has no entries.
+    pub fn last_committed_key(&self) -> Option<&[u8]> {
+        self.journal.as_ref()?.last_key()
+    }
+
     /// Completes the serialization and flushes any outstanding IO.
-    pub fn finish(mut self) -> Result<(), Error> {
+    ///
+    /// This appends the length table, rank samples, hash index (if enabled), metadata (if set), expiry table (if any entries
+    /// expire), checksums, and footer to the value stream. For a single-file container, the index is then appended as well.
+    /// When building atomically, the finished files are then renamed into place.
+    ///
+    /// See [`with_durability`](Self::with_durability) for syncing the files to storage.
+    pub fn finish(self) -> Result<(), Error> {
+        self.finish_into_inner().map(drop)
+    }
+
+    /// Like [`finish`](Self::finish), but returns the flushed index and value writers, e.g. to take back in-memory buffers.
+    ///
+    /// For a single-file container, the returned index writer held only the temporary copy of the index.
+    pub fn finish_into_inner(mut self) -> Result<(WI, WV), Error> {
+        self.check_cancelled()?;
+        let mut index_writer = self.map_builder.into_inner()?;
+        index_writer.flush()?;
+        let index_checksum = index_writer.checksum();
+
+        let values_len = u64::try_from(self.value_cursor).unwrap();
+        let lengths_len = self.length_writer.copy_to(&mut self.value_writer)?;
+        let rank_samples_offset = values_len + lengths_len;
+        let rank_samples_len = format::write_rank_samples(
+            &mut self.value_writer,
+            format::RANK_SAMPLE_INTERVAL,
+            &self.rank_sample_ends,
+            &self.rank_samples,
+        )?;
+        let hash_index_offset = rank_samples_offset + rank_samples_len;
+        let hash_index_len = match &self.hash_entries {
+            Some(entries) => format::write_hash_index(&mut self.value_writer, entries)?,
+            None => 0,
+        };
+        let metadata_offset = hash_index_offset + hash_index_len;
+        let metadata_len = if self.metadata.is_empty() {
+            0
+        } else {
+            format::write_metadata(&mut self.value_writer, &self.metadata)?
+        };
+        let expiry_offset = metadata_offset + metadata_len;
+        let mut expiry_len = 0;
+        for &(offset, expires_at) in &self.expiries {
+            format::write_length_entry(&mut self.value_writer, offset, expires_at)?;
+            expiry_len += format::LENGTH_ENTRY_LEN as u64;
+        }
+        let checksums_offset = expiry_offset + expiry_len;
+        let values_checksum = self.value_writer.checksum();
+        let checksums_len =
+            format::write_checksums(&mut self.value_writer, values_checksum, index_checksum)?;
+        let mut sections = vec![
+            Section {
+                kind: format::SECTION_LENGTHS,
+                offset: values_len,
+                len: lengths_len,
+            },
+            Section {
+                kind: format::SECTION_RANK_SAMPLES,
+                offset: rank_samples_offset,
+                len: rank_samples_len,
+            },
+            Section {
+                kind: format::SECTION_CHECKSUMS,
+                offset: checksums_offset,
+                len: checksums_len,
+            },
+        ];
+        if self.hash_entries.is_some() {
+            sections.push(Section {
+                kind: format::SECTION_HASH_INDEX,
+                offset: hash_index_offset,
+                len: hash_index_len,
+            });
+        }
+        if !self.metadata.is_empty() {
+            sections.push(Section {
+                kind: format::SECTION_METADATA,
+                offset: metadata_offset,
+                len: metadata_len,
+            });
+        }
+        if !self.expiries.is_empty() {
+            sections.push(Section {
+                kind: format::SECTION_EXPIRY,
+                offset: expiry_offset,
+                len: expiry_len,
+            });
+        }
+        let footer_len = format::write_footer(&mut self.value_writer, values_len, &sections)?;
+
+        let index_writer = index_writer.into_inner();
+        if let Some(index_file) = &self.container_index {
+            let value_section_len = checksums_offset + checksums_len + footer_len;
+            // The index writer was flushed above, so the temporary file is complete.
+            let mut index = index_file.try_clone_file()?;
+            index.seek(SeekFrom::Start(0))?;
+            let index_len = io::copy(&mut index, &mut self.value_writer)?;
+            format::write_container_footer(&mut self.value_writer, value_section_len, index_len)?;
+        }
         self.value_writer.flush()?;
-        Ok(self.map_builder.finish()?)
+        if self.durability >= Durability::SyncFiles {
+            for file in &self.sync_files {
+                file.sync_all()?;
+            }
+        }
+        let value_writer = self.value_writer.into_inner();
+
+        for (file, path) in self.pending_renames {
+            file.persist(&path).at_path("replace", &path)?;
+        }
+        if self.durability >= Durability::SyncFilesAndDirectories {
+            for path in &self.output_paths {
+                sync_parent_dir(path)?;
+            }
+        }
+        if let Some(journal) = self.journal {
+            journal.remove()?;
+        }
+        Ok((index_writer, value_writer))
+    }
+}
+
+impl FileBuilder {
+    /// Creates a new [`FileBuilder`], using the file at `index_path` for an index writer and the file at `value_path` as a
+    /// value writer.
+    ///
+    /// This always overwrites the given files.
+    ///
+    /// After calling `finish`, these same files can be used with `Cache::map_files`.
+    pub fn create_files(
+        index_path: impl AsRef<Path>,
+        value_path: impl AsRef<Path>,
+    ) -> Result<Self, Error> {
+        let index_file = fs::File::create(&index_path).at_path("create", &index_path)?;
+        let value_file = fs::File::create(&value_path).at_path("create", &value_path)?;
+        Self::from_files(index_file, value_file, index_path, value_path)
+    }
+
+    fn from_files(
+        index_file: fs::File,
+        value_file: fs::File,
+        index_path: impl AsRef<Path>,
+        value_path: impl AsRef<Path>,
+    ) -> Result<Self, Error> {
+        let sync_files = vec![index_file.try_clone()?, value_file.try_clone()?];
+        let mut builder = FileBuilder::new(
+            io::BufWriter::new(index_file),
+            io::BufWriter::new(value_file),
+        )?;
+        builder.sync_files = sync_files;
+        builder.output_paths = vec![
+            index_path.as_ref().to_owned(),
+            value_path.as_ref().to_owned(),
+        ];
+        Ok(builder)
+    }
+
+    /// Like [`create_files`](Self::create_files), but every committed entry is also recorded in a checkpoint file at
+    /// `checkpoint_path`, so an interrupted build can be continued with [`resume_files`](Self::resume_files) instead of
+    /// starting over.
+    ///
+    /// Call [`checkpoint`](Self::checkpoint) periodically to mark how far the build has durably progressed. The checkpoint
+    /// file is removed once `finish` succeeds.
+    pub fn create_files_resumable(
+        index_path: impl AsRef<Path>,
+        value_path: impl AsRef<Path>,
+        checkpoint_path: impl AsRef<Path>,
+    ) -> Result<Self, Error> {
+        let mut builder = Self::create_files(index_path, value_path)?;
+        builder.journal = Some(Journal::create(checkpoint_path)?);
+        Ok(builder)
+    }
+
+    /// Reopens a build started with [`create_files_resumable`](Self::create_files_resumable), as of its last
+    /// [`checkpoint`](Self::checkpoint).
+    ///
+    /// Values written after the checkpoint are truncated from the value file, and the index is rebuilt from the entries in
+    /// the checkpoint file, which is much faster than rewriting the values. The value alignment, hash index, and metadata
+    /// of the original builder are restored. Continue inserting with the key after
+    /// [`last_committed_key`](Self::last_committed_key).
+    pub fn resume_files(
+        index_path: impl AsRef<Path>,
+        value_path: impl AsRef<Path>,
+        checkpoint_path: impl AsRef<Path>,
+    ) -> Result<Self, Error> {
+        let (mut journal, state) = Journal::recover(checkpoint_path)?;
+        let mut value_file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&value_path)
+            .at_path("open", &value_path)?;
+        if value_file.metadata()?.len() < state.values_len {
+            return Err(Error::InvalidFormat(
+                "value file is shorter than its last checkpoint",
+            ));
+        }
+        value_file.set_len(state.values_len)?;
+        let index_file = fs::File::create(&index_path).at_path("create", &index_path)?;
+        let mut builder =
+            Self::from_files(index_file, value_file.try_clone()?, index_path, value_path)?;
+
+        // The checksum covers every value byte, including those written before the interruption.
+        let mut buf = vec![0; 64 * 1024];
+        value_file.seek(SeekFrom::Start(0))?;
+        loop {
+            let n = value_file.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            builder.value_writer.include_existing(&buf[..n]);
+        }
+        builder.value_cursor = usize::try_from(state.values_len).unwrap();
+        builder.committed_value_cursor = builder.value_cursor;
+        builder.value_alignment = state.value_alignment;
+        if state.hash_index {
+            builder.hash_entries = Some(Vec::new());
+        }
+        builder.metadata = state.metadata;
+        journal.replay(|key, offset, len| {
+            builder.insert_key(key, offset)?;
+            format::write_length_entry(&mut builder.length_writer, offset, len)?;
+            Ok(())
+        })?;
+        builder.journal = Some(journal);
+        Ok(builder)
+    }
+
+    /// Like [`create_files`](Self::create_files), but the existing files are only replaced once `finish` succeeds.
+    ///
+    /// The index and values are written to temporary files in the same directories as `index_path` and `value_path`, which
+    /// are atomically renamed into place by `finish`. If the builder is dropped or `finish` fails, the temporary files are
+    /// removed and the existing files are left untouched.
+    ///
+    /// Each file is replaced atomically, but the two renames are not atomic with respect to each other. Use
+    /// [`create_file_atomic`](Self::create_file_atomic) if readers must never observe a mismatched pair.
+    pub fn create_files_atomic(
+        index_path: impl AsRef<Path>,
+        value_path: impl AsRef<Path>,
+    ) -> Result<Self, Error> {
+        let index_file =
+            TempFile::new_beside(&index_path).at_path("create a file beside", &index_path)?;
+        let value_file =
+            TempFile::new_beside(&value_path).at_path("create a file beside", &value_path)?;
+        let index_writer = io::BufWriter::new(index_file.try_clone_file()?);
+        let value_writer = io::BufWriter::new(value_file.try_clone_file()?);
+        let mut builder = FileBuilder::new(index_writer, value_writer)?;
+        builder.sync_files = vec![index_file.try_clone_file()?, value_file.try_clone_file()?];
+        builder.output_paths = vec![
+            index_path.as_ref().to_owned(),
+            value_path.as_ref().to_owned(),
+        ];
+        builder.pending_renames = vec![
+            (index_file, builder.output_paths[0].clone()),
+            (value_file, builder.output_paths[1].clone()),
+        ];
+        Ok(builder)
+    }
+
+    /// Writes (key, value) pairs to the files at `index_path` and `value_path`, like [`create_files`](Self::create_files)
+    /// followed by an [`insert`](Self::insert) for each pair and [`finish`](Self::finish).
+    ///
+    /// The pairs must be sorted by key and have no duplicate keys.
+    pub fn build_from_sorted_iter<K, V>(
+        index_path: impl AsRef<Path>,
+        value_path: impl AsRef<Path>,
+        pairs: impl IntoIterator<Item = (K, V)>,
+    ) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        let mut builder = Self::create_files(index_path, value_path)?;
+        for (key, value) in pairs {
+            builder.insert(key.as_ref(), value.as_ref())?;
+        }
+        builder.finish()
+    }
+
+    /// Writes the entries of `map` to the files at `index_path` and `value_path`.
+    ///
+    /// The keys of a [`BTreeMap`] are already sorted and unique, so this can't fail with an ordering error.
+    pub fn from_btree_map<V: AsRef<[u8]>>(
+        map: &BTreeMap<Vec<u8>, V>,
+        index_path: impl AsRef<Path>,
+        value_path: impl AsRef<Path>,
+    ) -> Result<(), Error> {
+        Self::build_from_sorted_iter(index_path, value_path, map)
+    }
+
+    /// Creates a new [`FileBuilder`] that writes both the index and the values into a single container file at `path`.
+    ///
+    /// This always overwrites the given file. Until `finish` is called, the index is written to a temporary file in the same
+    /// directory as `path`.
+    ///
+    /// After calling `finish`, the file can be used with `MmapCache::map_path`.
+    pub fn create_file(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let value_writer = io::BufWriter::new(fs::File::create(&path).at_path("create", &path)?);
+        Self::new_container(path, value_writer)
+    }
+
+    /// Like [`create_file`](Self::create_file), but the existing file is only replaced once `finish` succeeds.
+    ///
+    /// The container is written to a temporary file in the same directory as `path`, which is atomically renamed into place
+    /// by `finish`. If the builder is dropped or `finish` fails, the temporary file is removed and the existing file is left
+    /// untouched.
+    pub fn create_file_atomic(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let container_file = TempFile::new_beside(&path).at_path("create a file beside", &path)?;
+        let value_writer = io::BufWriter::new(container_file.try_clone_file()?);
+        let mut builder = Self::new_container(&path, value_writer)?;
+        builder.pending_renames = vec![(container_file, path.as_ref().to_owned())];
+        Ok(builder)
+    }
+
+    fn new_container(
+        path: impl AsRef<Path>,
+        value_writer: io::BufWriter<fs::File>,
+    ) -> Result<Self, Error> {
+        let index_file = TempFile::new_beside(&path).at_path("create a file beside", &path)?;
+        let index_writer = io::BufWriter::new(index_file.try_clone_file()?);
+        let sync_file = value_writer.get_ref().try_clone()?;
+        let mut builder = FileBuilder::new(index_writer, value_writer)?;
+        builder.sync_files = vec![sync_file];
+        builder.container_index = Some(index_file);
+        builder.output_paths = vec![path.as_ref().to_owned()];
+        Ok(builder)
     }
 }
 
+/// A [`FileBuilder`] that writes the index and values into byte vectors, without touching the file system.
+///
+/// Useful for tests and small, ephemeral caches.
+///
+/// ```
+/// # use mmap_cache::Error;
+/// # fn example() -> Result<(), Error> {
+/// use mmap_cache::{Cache, MemoryBuilder};
+///
+/// let mut builder = MemoryBuilder::in_memory()?;
+/// builder.insert(b"abc", b"123")?;
+/// builder.insert(b"xyz", b"789")?;
+/// let (index, values) = builder.finish_into_inner()?;
+///
+/// let cache = Cache::new(index, values)?;
+/// assert_eq!(cache.get_value(b"xyz"), Some(&b"789"[..]));
+/// # Ok(())
+/// # }
+/// # example().unwrap();
+/// ```
+pub type MemoryBuilder = FileBuilder<Vec<u8>, Vec<u8>>;
+
+impl MemoryBuilder {
+    /// Creates a new [`MemoryBuilder`]. Unlike [`FileBuilder::new`], the length table is also kept in memory instead of a
+    /// temporary file.
+    pub fn in_memory() -> Result<Self, Error> {
+        Self::with_length_buffer(Vec::new(), Vec::new(), LengthBuffer::Memory(Vec::new()))
+    }
+
+    /// Finishes the serialization and loads the result into a [`Cache`].
+    pub fn finish_into_cache(self) -> Result<Cache<Vec<u8>, Vec<u8>>, Error> {
+        let (index, values) = self.finish_into_inner()?;
+        Cache::new(index, values)
+    }
+}
+
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    fs::File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
 fn next_multiple(n: usize, k: usize) -> usize {
-    k * ((n + k - 1) / k)
+    k * n.div_ceil(k)
 }
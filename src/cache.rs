@@ -1,22 +1,35 @@
-use crate::Error;
-
-use fst::raw::Node;
-use fst::raw::Transition;
-use fst::Streamer;
-use memmap2::Mmap;
-use std::cmp::Ordering;
-use std::fs;
+use crate::checksum::crc32;
+#[cfg(feature = "mmap")]
+use crate::error::PathContext;
+use crate::format::{self, ContainerLayout, HashLookup, ValueLayout};
+use crate::{CacheObserver, Cursor, CursorPosition, Error, MemoryBuilder, RevStream, ValueStore};
+
+#[cfg(feature = "mmap")]
+use crate::CacheOptions;
+use bytemuck::{AnyBitPattern, PodCastError};
+use fst::{Automaton, IntoStreamer, Streamer};
+#[cfg(feature = "mmap")]
+use memmap2::{Mmap, MmapOptions};
+use std::borrow::Cow;
+use std::fmt;
 use std::ops::{Bound, RangeBounds};
-use std::path::Path;
+use std::sync::Arc;
+#[cfg(feature = "mmap")]
+use std::{fs, ops::Range, path::Path};
 
 /// A cache, mapping `[u8]` keys to `[u8]` values.
 ///
 /// This cache wraps generic byte storage that implements `AsRef<[u8]>`. This is most commonly a memory-mapped file, [`Mmap`].
+/// Value storage that isn't addressable in memory can instead implement [`ValueStore`]; see
+/// [`from_store`](Self::from_store).
 ///
 /// For serializing a stream of (key, value) pairs, see [`FileBuilder`](crate::FileBuilder).
+#[derive(Clone)]
 pub struct Cache<DK, DV> {
     index: fst::Map<DK>,
     value_bytes: DV,
+    value_layout: ValueLayout,
+    observer: Option<Arc<dyn CacheObserver>>,
 }
 
 impl<DK, DV> Cache<DK, DV>
@@ -24,32 +37,275 @@ where
     DK: AsRef<[u8]>,
     DV: AsRef<[u8]>,
 {
+    /// Wraps the serialized index and value storage.
+    ///
+    /// `value_bytes` may either be the complete output of a [`FileBuilder`](crate::FileBuilder), or raw value bytes without
+    /// a length table, in which case value lengths are inferred from the offsets of neighboring keys.
     pub fn new(index_bytes: DK, value_bytes: DV) -> Result<Self, Error> {
+        let value_layout = ValueLayout::parse(value_bytes.as_ref())?;
         Ok(Self {
             index: fst::Map::new(index_bytes)?,
             value_bytes,
+            value_layout,
+            observer: None,
         })
     }
 
-    /// Access the internal [`fst::Map`] used for mapping keys to value offsets.
-    pub fn index(&self) -> &fst::Map<DK> {
-        &self.index
-    }
-
     /// The entire byte slice storing all values.
+    ///
+    /// This excludes the length table and footer written by the [`FileBuilder`](crate::FileBuilder).
     pub fn value_bytes(&self) -> &[u8] {
-        self.value_bytes.as_ref()
+        &self.value_bytes.as_ref()[..self.value_layout.values_len]
+    }
+
+    /// The value storage, including any sections after the values.
+    pub(crate) fn raw_value_bytes(&self) -> &DV {
+        &self.value_bytes
+    }
+
+    /// Lists the auxiliary sections that the [`FileBuilder`](crate::FileBuilder) wrote after the values, as (name, length in
+    /// bytes) pairs in file order. Sections written by a newer version of this crate are named `"unknown"`.
+    pub fn sections(&self) -> Vec<(&'static str, u64)> {
+        let mut sections = self.value_layout.sections.clone();
+        sections.sort_by_key(|section| section.offset);
+        sections
+            .iter()
+            .map(|section| (format::section_name(section.kind), section.len))
+            .collect()
+    }
+
+    /// Replaces the index and value storage with `map_index(index storage)` and `map_values(value storage)`, which must hold
+    /// the same bytes.
+    #[cfg(feature = "mmap")]
+    pub(crate) fn map_storage<NK, NV>(
+        self,
+        map_index: impl FnMut(DK) -> NK,
+        map_values: impl FnOnce(DV) -> NV,
+    ) -> Cache<NK, NV>
+    where
+        NK: AsRef<[u8]>,
+        NV: AsRef<[u8]>,
+    {
+        Cache {
+            index: self
+                .index
+                .map_data(map_index)
+                .expect("index bytes were already validated"),
+            value_bytes: map_values(self.value_bytes),
+            value_layout: self.value_layout,
+            observer: self.observer,
+        }
+    }
+
+    #[cfg(feature = "mmap")]
+    pub(crate) fn raw_value_bytes_mut(&mut self) -> &mut DV {
+        &mut self.value_bytes
+    }
+
+    /// The bytes of the first auxiliary section of `kind`, if any.
+    pub(crate) fn section_bytes(&self, kind: u64) -> Option<&[u8]> {
+        Some(&self.value_bytes.as_ref()[self.value_layout.section(kind)?])
+    }
+
+    /// Returns the bytes of the value starting at `offset`, if the value file has a length table with an entry for `offset`.
+    ///
+    /// Tombstones have no value, so this returns `None` for them.
+    pub fn value_at_offset(&self, offset: u64) -> Option<&[u8]> {
+        let len = self.recorded_len(offset)?;
+        if len == format::TOMBSTONE_LEN {
+            return None;
+        }
+        let start = usize::try_from(offset).ok()?;
+        let end = start.checked_add(usize::try_from(len).ok()?)?;
+        self.value_bytes().get(start..end)
+    }
+
+    pub(crate) fn recorded_len(&self, offset: u64) -> Option<u64> {
+        let table =
+            &self.value_bytes.as_ref()[self.value_layout.section(format::SECTION_LENGTHS)?];
+        format::lookup_length(table, offset)
+    }
+
+    /// Returns `true` if `key` was committed as a tombstone with
+    /// [`FileBuilder::insert_tombstone`](crate::FileBuilder::insert_tombstone).
+    pub fn is_tombstone(&self, key: &[u8]) -> bool {
+        self.find_value_offset(key)
+            .is_some_and(|offset| self.is_tombstone_at(offset))
+    }
+
+    pub(crate) fn is_tombstone_at(&self, offset: u64) -> bool {
+        self.recorded_len(offset) == Some(format::TOMBSTONE_LEN)
+    }
+
+    /// Recomputes the checksums of the index and values and compares them with the checksums recorded by the
+    /// [`FileBuilder`](crate::FileBuilder).
+    ///
+    /// This reads every byte of both the index and the value file, so it can be slow for large caches.
+    pub fn verify(&self) -> Result<(), Error> {
+        let section = self
+            .value_layout
+            .section(format::SECTION_CHECKSUMS)
+            .ok_or(Error::InvalidFormat("value file has no checksums"))?;
+        let bytes = self.value_bytes.as_ref();
+        let (values_crc, index_crc) = format::read_checksums(&bytes[section.clone()])?;
+        let actual = crc32(&bytes[..section.start]);
+        if actual != values_crc {
+            return Err(Error::ChecksumMismatch {
+                section: "values",
+                expected: values_crc,
+                actual,
+            });
+        }
+        let actual = crc32(self.index.as_fst().as_bytes());
+        if actual != index_crc {
+            return Err(Error::ChecksumMismatch {
+                section: "index",
+                expected: index_crc,
+                actual,
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks that every value offset in the index is consistent with the value file.
+    ///
+    /// With a length table, every offset must have an entry, and every value must fit within
+    /// [`value_bytes`](Self::value_bytes). Without one, value lengths are inferred from the offset of the next key, so offsets
+    /// must not decrease in key order and must not exceed the length of the value bytes.
+    pub fn verify_offsets(&self) -> Result<(), Error> {
+        let available = self.value_bytes().len() as u64;
+        let has_lengths = self.value_layout.section(format::SECTION_LENGTHS).is_some();
+        let mut previous = 0;
+        let mut stream = self.index.stream();
+        while let Some((_, offset)) = stream.next() {
+            let len = if has_lengths {
+                let len = self.recorded_len(offset).ok_or(Error::InvalidFormat(
+                    "value offset is missing from the length table",
+                ))?;
+                if len == format::TOMBSTONE_LEN {
+                    continue;
+                }
+                len
+            } else {
+                if offset < previous {
+                    return Err(Error::InvalidFormat("value offsets are not in key order"));
+                }
+                previous = offset;
+                0
+            };
+            if offset.checked_add(len).is_none_or(|end| end > available) {
+                return Err(Error::OutOfBounds {
+                    offset,
+                    len,
+                    available,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the checksum that the [`fst`] crate stores in the index, then walks every key of the index and checks its value
+    /// offset with [`verify_offsets`](Self::verify_offsets).
+    ///
+    /// [`fst::Map::new`] only checks the header and footer of the index, so a corrupted index may otherwise go unnoticed
+    /// until a lookup returns a wrong offset. Like [`verify`](Self::verify), this reads every byte of the index.
+    pub fn verify_index(&self) -> Result<(), Error> {
+        self.index.as_fst().verify()?;
+        self.verify_offsets()
     }
 
     /// Returns the byte offset of the value for `key`, if it exists.
     ///
     /// The returned offset can be used with the `value_at_offset` method.
+    ///
+    /// If the value file has a hash index (see [`FileBuilder::with_hash_index`](crate::FileBuilder::with_hash_index)), it's
+    /// probed first, so that most missing keys are rejected without traversing the [`fst::Map`].
     pub fn get_value_offset(&self, key: &[u8]) -> Option<u64> {
+        let offset = self.find_value_offset(key);
+        self.observe_get(key, offset.is_some());
+        offset
+    }
+
+    pub(crate) fn find_value_offset(&self, key: &[u8]) -> Option<u64> {
+        if let Some(section) = self.value_layout.section(format::SECTION_HASH_INDEX) {
+            let table = &self.value_bytes.as_ref()[section];
+            match format::lookup_hash_index(table, format::key_hash(key)) {
+                // The table only stores hashes, which can be made to collide on purpose, so a hit is confirmed against
+                // the index.
+                Ok(HashLookup::Found(offset)) => {
+                    return self.index.get(key).filter(|&found| found == offset)
+                }
+                Ok(HashLookup::Missing) => return None,
+                Ok(HashLookup::Colliding) | Err(_) => {}
+            }
+        }
         self.index.get(key)
     }
 
+    /// Looks up the value offsets of many keys at once. The results are in the same order as `keys`.
+    ///
+    /// The keys are probed in sorted order, so that consecutive lookups share the same paths through the index, which is
+    /// friendlier to the page cache than probing in arbitrary order.
+    pub fn get_many<K: AsRef<[u8]>>(&self, keys: &[K]) -> Vec<Option<u64>> {
+        let mut order: Vec<usize> = (0..keys.len()).collect();
+        order.sort_unstable_by_key(|&i| keys[i].as_ref());
+        let mut offsets = vec![None; keys.len()];
+        for i in order {
+            offsets[i] = self.get_value_offset(keys[i].as_ref());
+        }
+        offsets
+    }
+
+    /// Returns the bytes of the value for `key`, if it exists.
+    ///
+    /// If the value file has a length table, the exact value is returned. Otherwise, values are assumed to be written in key
+    /// order, so the value ends where the value of the next (lexicographical) key begins, or at the end of `value_bytes` for
+    /// the last key. In that case, any padding written after the value is included.
+    pub fn get_value(&self, key: &[u8]) -> Option<&[u8]> {
+        let offset = self.get_value_offset(key)?;
+        let value = self.resolve_value(key, offset)?;
+        self.observe_bytes_read(value.len());
+        Some(value)
+    }
+
+    pub(crate) fn resolve_value(&self, key: &[u8], offset: u64) -> Option<&[u8]> {
+        if self.value_layout.section(format::SECTION_LENGTHS).is_some() {
+            return self.value_at_offset(offset);
+        }
+
+        let end = self
+            .index
+            .range()
+            .gt(key)
+            .into_stream()
+            .next()
+            .map(|(_, offset)| offset)
+            .unwrap_or(self.value_bytes().len() as u64);
+        self.value_bytes()
+            .get(usize::try_from(offset).ok()?..usize::try_from(end).ok()?)
+    }
+
+    /// Returns the `len` bytes starting at `offset` in [`value_bytes`](Self::value_bytes), or [`Error::OutOfBounds`] if they
+    /// aren't all within it.
+    ///
+    /// Unlike [`offset_transmuted_value`](Self::offset_transmuted_value), this never panics or reads outside of the values,
+    /// so it's safe to use with offsets and lengths from untrusted sources.
+    pub fn try_value_at(&self, offset: u64, len: u64) -> Result<&[u8], Error> {
+        let bytes = self.value_bytes();
+        offset
+            .checked_add(len)
+            .and_then(|end| bytes.get(usize::try_from(offset).ok()?..usize::try_from(end).ok()?))
+            .ok_or(Error::OutOfBounds {
+                offset,
+                len,
+                available: bytes.len() as u64,
+            })
+    }
+
     /// Transmutes the bytes starting at `offset` into a `T` reference.
     ///
+    /// See [`get_pod`](Self::get_pod) or [`try_transmuted_value`](Self::try_transmuted_value) for checked alternatives.
+    ///
     /// # Safety
     ///
     /// `offset` must point to a valid representation of `T` in the `value_bytes` region of memory.
@@ -57,6 +313,33 @@ where
         std::mem::transmute(&self.value_bytes()[offset])
     }
 
+    /// Like [`offset_transmuted_value`](Self::offset_transmuted_value), but first checks that the bytes starting at `offset`
+    /// are aligned for `T` and long enough to hold a `T`.
+    ///
+    /// # Safety
+    ///
+    /// If the checks pass, the bytes must still be a valid representation of `T`.
+    pub unsafe fn try_transmuted_value<T>(&self, offset: usize) -> Result<&T, Error> {
+        let bytes = self.value_bytes();
+        let size = std::mem::size_of::<T>();
+        if offset.checked_add(size).is_none_or(|end| end > bytes.len()) {
+            return Err(Error::OutOfBounds {
+                offset: offset as u64,
+                len: size as u64,
+                available: bytes.len() as u64,
+            });
+        }
+        let alignment = std::mem::align_of::<T>();
+        let ptr = bytes.as_ptr().wrapping_add(offset);
+        if !(ptr as usize).is_multiple_of(alignment) {
+            return Err(Error::Misaligned {
+                offset: offset as u64,
+                alignment,
+            });
+        }
+        Ok(&*(ptr as *const T))
+    }
+
     /// Transmutes the bytes pointed to by `key` (if any) into a `T` reference.
     ///
     /// # Safety
@@ -67,32 +350,300 @@ where
             .map(|offset| self.offset_transmuted_value(offset.try_into().unwrap()))
     }
 
+    /// Casts the value for `key` (if any) into a `T` reference with [`bytemuck`], which requires no `unsafe`.
+    ///
+    /// Fails with [`Error::ValueSize`] if the value isn't exactly `size_of::<T>()` bytes, or with [`Error::Misaligned`] if
+    /// it isn't aligned for `T` (see [`FileBuilder::with_value_alignment`](crate::FileBuilder::with_value_alignment)).
+    /// Without a length table, value lengths are inferred and include any padding, so the size check may fail.
+    pub fn get_pod<T: AnyBitPattern>(&self, key: &[u8]) -> Result<Option<&T>, Error> {
+        let Some(offset) = self.get_value_offset(key) else {
+            return Ok(None);
+        };
+        let Some(value) = self.resolve_value(key, offset) else {
+            return Ok(None);
+        };
+        self.observe_bytes_read(value.len());
+        bytemuck::try_from_bytes(value)
+            .map(Some)
+            .map_err(|e| match e {
+                PodCastError::SizeMismatch => Error::ValueSize {
+                    offset,
+                    expected: std::mem::size_of::<T>() as u64,
+                    actual: value.len() as u64,
+                },
+                _ => Error::Misaligned {
+                    offset,
+                    alignment: std::mem::align_of::<T>(),
+                },
+            })
+    }
+
+    /// Returns the key with rank `i` (i.e. the `i`th key in sorted order, counting from zero) and its value offset.
+    ///
+    /// Files written by [`FileBuilder`](crate::FileBuilder) record a sample of keys, so this only needs to scan past a
+    /// bounded number of keys. Otherwise, the index is scanned from the start.
+    pub fn nth_key(&self, i: usize) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        let (start, skip) = match self.rank_samples()? {
+            Some(samples) if samples.len() > 0 => {
+                let interval = samples.interval as usize;
+                let sample = (i / interval).min(samples.len() - 1);
+                (samples.key(sample)?, i - sample * interval)
+            }
+            _ => (&[][..], i),
+        };
+        let mut stream = self.index.range().ge(start).into_stream();
+        for _ in 0..skip {
+            if stream.next().is_none() {
+                return Ok(None);
+            }
+        }
+        Ok(stream.next().map(|(key, offset)| (key.to_vec(), offset)))
+    }
+
+    /// Returns the number of keys less than `key`. If `key` exists, this is its index in sorted order.
+    ///
+    /// Like [`nth_key`](Self::nth_key), this uses the recorded key samples when available.
+    pub fn rank(&self, key: &[u8]) -> Result<usize, Error> {
+        let (start, base_rank) = match self.rank_samples()? {
+            Some(samples) => {
+                // Binary search for the number of samples <= key.
+                let (mut lower, mut upper) = (0, samples.len());
+                while lower != upper {
+                    let mid = (lower + upper) / 2;
+                    if samples.key(mid)? <= key {
+                        lower = mid + 1;
+                    } else {
+                        upper = mid;
+                    }
+                }
+                match lower.checked_sub(1) {
+                    Some(sample) => (samples.key(sample)?, sample * samples.interval as usize),
+                    None => return Ok(0),
+                }
+            }
+            None => (&[][..], 0),
+        };
+        let mut stream = self.index.range().ge(start).lt(key).into_stream();
+        let mut rank = base_rank;
+        while stream.next().is_some() {
+            rank += 1;
+        }
+        Ok(rank)
+    }
+
+    /// Counts the keys in `key_range`, without copying any keys.
+    ///
+    /// This is computed from the [`rank`](Self::rank) of each bound, so for files with recorded key samples it's much faster
+    /// than iterating over a large range.
+    pub fn count_range<K, R>(&self, key_range: R) -> Result<usize, Error>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        let start = match key_range.start_bound() {
+            Bound::Unbounded => 0,
+            Bound::Included(k) => self.rank(k.as_ref())?,
+            Bound::Excluded(k) => {
+                self.rank(k.as_ref())? + usize::from(self.contains_key(k.as_ref()))
+            }
+        };
+        let end = match key_range.end_bound() {
+            Bound::Unbounded => self.len(),
+            Bound::Included(k) => {
+                self.rank(k.as_ref())? + usize::from(self.contains_key(k.as_ref()))
+            }
+            Bound::Excluded(k) => self.rank(k.as_ref())?,
+        };
+        Ok(end.saturating_sub(start))
+    }
+
+    /// Picks `n` keys uniformly at random (with replacement), returning them with their value offsets in sorted order.
+    ///
+    /// `random` must return uniformly distributed [`u64`]s, e.g. `|| rng.gen()` with the `rand` crate. Each sample costs a
+    /// call to [`nth_key`](Self::nth_key).
+    pub fn sample(
+        &self,
+        mut random: impl FnMut() -> u64,
+        n: usize,
+    ) -> Result<Vec<(Vec<u8>, u64)>, Error> {
+        let len = self.len() as u128;
+        if len == 0 {
+            return Ok(Vec::new());
+        }
+        let mut ranks: Vec<usize> = (0..n)
+            .map(|_| ((random() as u128 * len) >> 64) as usize)
+            .collect();
+        ranks.sort_unstable();
+        let mut samples = Vec::with_capacity(n);
+        for rank in ranks {
+            samples.extend(self.nth_key(rank)?);
+        }
+        Ok(samples)
+    }
+
+    fn rank_samples(&self) -> Result<Option<format::RankSamples<'_>>, Error> {
+        self.value_layout
+            .section(format::SECTION_RANK_SAMPLES)
+            .map(|section| format::RankSamples::parse(&self.value_bytes.as_ref()[section]))
+            .transpose()
+    }
+
+    /// Returns a streaming iterator over (key, value) pairs, where each value is the byte slice found by
+    /// [`get_value`](Self::get_value).
+    ///
+    /// Values are borrowed directly from the value storage, so no value bytes are copied. Tombstones are skipped.
+    pub fn range_values<K, R>(&self, key_range: R) -> ValueStream<'_, DK, DV>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        ValueStream {
+            cache: self,
+            stream: self.range(key_range).into_stream(),
+            key: Vec::new(),
+        }
+    }
+}
+
+impl<DK, DV> Cache<DK, DV> {
+    /// Reports lookups, scans, and bytes read to `observer`; see [`CacheObserver`].
+    pub fn with_observer(mut self, observer: Arc<dyn CacheObserver>) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    fn observe_get(&self, key: &[u8], hit: bool) {
+        if let Some(observer) = &self.observer {
+            if hit {
+                observer.on_get_hit(key);
+            } else {
+                observer.on_get_miss(key);
+            }
+        }
+    }
+
+    fn observe_bytes_read(&self, bytes: usize) {
+        if let Some(observer) = &self.observer {
+            observer.on_bytes_read(bytes as u64);
+        }
+    }
+}
+
+impl<DK, DV> Cache<DK, DV>
+where
+    DK: AsRef<[u8]>,
+{
+    /// Access the internal [`fst::Map`] used for mapping keys to value offsets.
+    pub fn index(&self) -> &fst::Map<DK> {
+        &self.index
+    }
+
+    /// The number of keys in the index, including any tombstones.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Returns `true` if the index contains `key`, even if it's a tombstone.
+    ///
+    /// This is slightly cheaper than [`get_value_offset`](Self::get_value_offset), since the offset is not computed.
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.index.contains_key(key)
+    }
+
     /// Returns a streaming iterator over (key, value offset) pairs.
     ///
     /// The offset is a byte offset pointing to the start of the value for that key.
-    pub fn range<K, R>(&self, key_range: R) -> fst::map::StreamBuilder
+    pub fn range<K, R>(&self, key_range: R) -> fst::map::StreamBuilder<'_>
     where
         K: AsRef<[u8]>,
         R: RangeBounds<K>,
     {
-        let builder = self.index.range();
-        let builder = match key_range.start_bound() {
-            Bound::Unbounded => builder,
-            Bound::Excluded(b) => builder.gt(b),
-            Bound::Included(b) => builder.ge(b),
-        };
-        match key_range.end_bound() {
-            Bound::Unbounded => builder,
-            Bound::Excluded(b) => builder.lt(b),
-            Bound::Included(b) => builder.le(b),
+        if let Some(observer) = &self.observer {
+            observer.on_range_start();
+        }
+        bound_stream(self.index.range(), key_range)
+    }
+
+    /// Returns a streaming iterator over (key, value offset) pairs for all keys matched by `automaton`.
+    ///
+    /// Any [`Automaton`] can be used, e.g. [`fst::automaton::Subsequence`] or [`fst::automaton::Str::starts_with`]. The
+    /// returned builder can be further restricted to a key range with `ge`, `lt`, etc., or see
+    /// [`search_range`](Self::search_range).
+    pub fn search<A: Automaton>(&self, automaton: A) -> fst::map::StreamBuilder<'_, A> {
+        self.index.search(automaton)
+    }
+
+    /// Like [`search`](Self::search), but only for keys in `key_range`.
+    pub fn search_range<A, K, R>(
+        &self,
+        automaton: A,
+        key_range: R,
+    ) -> fst::map::StreamBuilder<'_, A>
+    where
+        A: Automaton,
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        bound_stream(self.index.search(automaton), key_range)
+    }
+
+    /// Returns a streaming iterator over all keys, in sorted order.
+    pub fn keys(&self) -> fst::map::Keys<'_> {
+        self.index.keys()
+    }
+
+    /// Returns a streaming iterator over the value offsets of all keys, in key order.
+    pub fn value_offsets(&self) -> fst::map::Values<'_> {
+        self.index.values()
+    }
+
+    /// Returns a streaming iterator over (key, value offset) pairs for all keys that start with `prefix`.
+    pub fn prefix(&self, prefix: &[u8]) -> fst::map::StreamBuilder<'_> {
+        let builder = self.index.range().ge(prefix);
+        match prefix_successor(prefix) {
+            Some(end) => builder.lt(end),
+            None => builder,
         }
     }
 
+    /// Returns a [`Cursor`] positioned before the first key.
+    pub fn cursor(&self) -> Cursor<'_, DK> {
+        self.cursor_at(CursorPosition::start())
+    }
+
+    /// Returns a [`Cursor`] resumed from a `position` saved by [`Cursor::position`].
+    pub fn cursor_at(&self, position: CursorPosition) -> Cursor<'_, DK> {
+        Cursor::new(&self.index, position)
+    }
+
+    /// Like [`range`](Self::range), but streams (key, value offset) pairs in descending key order.
+    pub fn range_rev<K, R>(&self, key_range: R) -> RevStream<'_, DK>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        let lower = match key_range.start_bound() {
+            Bound::Unbounded => Bound::Unbounded,
+            Bound::Excluded(b) => Bound::Excluded(b.as_ref().to_vec()),
+            Bound::Included(b) => Bound::Included(b.as_ref().to_vec()),
+        };
+        let upper = match key_range.end_bound() {
+            Bound::Unbounded => Bound::Unbounded,
+            Bound::Excluded(b) => Bound::Excluded(b.as_ref()),
+            Bound::Included(b) => Bound::Included(b.as_ref()),
+        };
+        RevStream::new(self.index.as_fst(), lower, upper)
+    }
+
     /// Returns the (lexicographical) first (key, value) pair.
     ///
     /// # Panics
     ///
-    /// If the actual first key is longer than `N`.
+    /// If the actual first key is longer than `N`; see [`try_first`](Self::try_first) for a fallible variant.
     pub fn first<const N: usize>(&self) -> Option<([u8; N], u64)> {
         self.index.stream().next().map(|(k, offset)| {
             let mut key = [0; N];
@@ -105,7 +656,7 @@ where
     ///
     /// # Panics
     ///
-    /// If the actual last key is longer than `N`.
+    /// If the actual last key is longer than `N`; see [`try_last`](Self::try_last) for a fallible variant.
     pub fn last<const N: usize>(&self) -> Option<([u8; N], u64)> {
         let raw = self.index.as_fst();
         let mut key = [0; N];
@@ -119,161 +670,397 @@ where
             i += 1;
             offset += last.out.value();
         }
-        (i == N).then(|| (key, offset))
+        (i == N).then_some((key, offset))
+    }
+
+    /// Returns the (lexicographical) first (key, value offset) pair, for keys of any length.
+    pub fn first_key_value(&self) -> Option<(Vec<u8>, u64)> {
+        self.index
+            .stream()
+            .next()
+            .map(|(key, offset)| (key.to_vec(), offset))
+    }
+
+    /// Returns the (lexicographical) last (key, value offset) pair, for keys of any length.
+    pub fn last_key_value(&self) -> Option<(Vec<u8>, u64)> {
+        if self.index.is_empty() {
+            return None;
+        }
+        let raw = self.index.as_fst();
+        let mut key = Vec::new();
+        let mut n = raw.root();
+        let mut offset = 0;
+        while !n.is_empty() {
+            let last = n.transition(n.len() - 1);
+            key.push(last.inp);
+            n = raw.node(last.addr);
+            offset += last.out.value();
+        }
+        Some((key, offset + n.final_output().value()))
     }
 
     /// Finds the (lexicographical) greatest key `k` such that `k <= upper_bound`.
     ///
     /// # Panics
     ///
-    /// If the found key is longer than `N`.
+    /// If the found key is longer than `N`; see [`try_last_le`](Self::try_last_le) for a fallible variant.
     pub fn last_le<const N: usize>(&self, upper_bound: &[u8]) -> Option<([u8; N], u64)> {
-        let raw = self.index.as_fst();
-        let mut key = [0; N];
-        let offset = self.last_le_recursive(raw, upper_bound, LastLeSearch::initial(raw), &mut key);
-        offset.map(|o| (key, o))
+        let mut stream = RevStream::new(
+            self.index.as_fst(),
+            Bound::Unbounded,
+            Bound::Included(upper_bound),
+        );
+        stream.next().map(|(k, offset)| (key_array(k), offset))
     }
 
-    fn last_le_recursive<const N: usize>(
+    /// Finds the (lexicographical) greatest key `k` such that `k < upper_bound`.
+    ///
+    /// # Panics
+    ///
+    /// If the found key is longer than `N`.
+    pub fn last_lt<const N: usize>(&self, upper_bound: &[u8]) -> Option<([u8; N], u64)> {
+        let mut stream = RevStream::new(
+            self.index.as_fst(),
+            Bound::Unbounded,
+            Bound::Excluded(upper_bound),
+        );
+        stream.next().map(|(k, offset)| (key_array(k), offset))
+    }
+
+    /// Finds the (lexicographical) least key `k` such that `k >= lower_bound`.
+    ///
+    /// # Panics
+    ///
+    /// If the found key is longer than `N`.
+    pub fn first_ge<const N: usize>(&self, lower_bound: &[u8]) -> Option<([u8; N], u64)> {
+        let mut stream = self.index.range().ge(lower_bound).into_stream();
+        stream.next().map(|(k, offset)| (key_array(k), offset))
+    }
+
+    /// Finds the (lexicographical) least key `k` such that `k > lower_bound`.
+    ///
+    /// # Panics
+    ///
+    /// If the found key is longer than `N`.
+    pub fn first_gt<const N: usize>(&self, lower_bound: &[u8]) -> Option<([u8; N], u64)> {
+        let mut stream = self.index.range().gt(lower_bound).into_stream();
+        stream.next().map(|(k, offset)| (key_array(k), offset))
+    }
+
+    /// Like [`first`](Self::first), but fails with [`Error::KeyTooLong`] instead of panicking if the first key is longer than
+    /// `N`. A shorter key is padded with zeros.
+    pub fn try_first<const N: usize>(&self) -> Result<Option<([u8; N], u64)>, Error> {
+        self.first_key_value()
+            .map(|(key, offset)| Ok((try_key_array(&key)?, offset)))
+            .transpose()
+    }
+
+    /// Like [`last`](Self::last), but fails with [`Error::KeyTooLong`] instead of panicking if the last key is longer than
+    /// `N`. A shorter key is padded with zeros.
+    pub fn try_last<const N: usize>(&self) -> Result<Option<([u8; N], u64)>, Error> {
+        self.last_key_value()
+            .map(|(key, offset)| Ok((try_key_array(&key)?, offset)))
+            .transpose()
+    }
+
+    /// Like [`last_le`](Self::last_le), but fails with [`Error::KeyTooLong`] instead of panicking if the found key is longer
+    /// than `N`.
+    pub fn try_last_le<const N: usize>(
         &self,
-        raw: &fst::raw::Fst<DK>,
         upper_bound: &[u8],
-        state: LastLeSearch,
-        key: &mut [u8; N],
-    ) -> Option<u64> {
-        if let Ordering::Greater = state.parent_ordering {
-            return None;
+    ) -> Result<Option<([u8; N], u64)>, Error> {
+        let mut stream = RevStream::new(
+            self.index.as_fst(),
+            Bound::Unbounded,
+            Bound::Included(upper_bound),
+        );
+        stream
+            .next()
+            .map(|(k, offset)| Ok((try_key_array(k)?, offset)))
+            .transpose()
+    }
+}
+
+impl<DK, DV> Cache<DK, DV>
+where
+    DK: AsRef<[u8]>,
+    DV: ValueStore,
+{
+    /// Wraps the serialized index and a [`ValueStore`] holding the value file, which doesn't need to be addressable in
+    /// memory.
+    ///
+    /// Methods that borrow values directly, like [`get_value`](Self::get_value), require `DV: AsRef<[u8]>`, but values can
+    /// always be read with [`read_value`](Self::read_value).
+    pub fn from_store(index_bytes: DK, store: DV) -> Result<Self, Error> {
+        let value_layout =
+            ValueLayout::read_with(store.size(), |buf, offset| store.read_into(offset, buf))?;
+        Ok(Self {
+            index: fst::Map::new(index_bytes)?,
+            value_bytes: store,
+            value_layout,
+            observer: None,
+        })
+    }
+
+    pub fn store(&self) -> &DV {
+        &self.value_bytes
+    }
+
+    /// Reads the value for `key` from the store, if it exists and isn't a tombstone.
+    ///
+    /// Like [`get_value`](Self::get_value), value lengths are inferred from neighboring keys if there is no length table.
+    /// The hash index is not used, since probing it could take several reads from the store.
+    pub fn read_value(&self, key: &[u8]) -> Result<Option<Cow<'_, [u8]>>, Error> {
+        let offset = self.index.get(key);
+        self.observe_get(key, offset.is_some());
+        let Some(offset) = offset else {
+            return Ok(None);
+        };
+        match self.read_value_len(key, offset)? {
+            Some(len) => self.read_store_values(offset, len).map(Some),
+            None => Ok(None),
         }
+    }
 
-        let le_found = if !state.node.is_empty() {
-            match state.parent_ordering {
-                Ordering::Greater => unreachable!(),
-                Ordering::Equal => {
-                    if state.byte_i < upper_bound.len() {
-                        // We need to backtrack if the least terminal key is GREATER than upper_bound.
-                        find_last_le_transition(state.node, upper_bound[state.byte_i]).and_then(
-                            |(t_i, t)| {
-                                key[state.byte_i] = t.inp;
-                                let next_state = state.next(raw, upper_bound, t);
-                                self.last_le_recursive(raw, upper_bound, next_state, key)
-                                    .or_else(|| {
-                                        // Backtrack. We should only need to move to the next greatest key.
-                                        if t_i > 0 {
-                                            let t = state.node.transition(t_i - 1);
-                                            key[state.byte_i] = t.inp;
-                                            let next_state =
-                                                state.next_with_ordering(raw, t, Ordering::Less);
-                                            self.last_le_recursive(
-                                                raw,
-                                                upper_bound,
-                                                next_state,
-                                                key,
-                                            )
-                                        } else {
-                                            None
-                                        }
-                                    })
-                            },
-                        )
-                    } else {
-                        None
-                    }
-                }
-                Ordering::Less => {
-                    // We're already LESS, so just take the greatest key we can find.
-                    let t = state.node.transition(state.node.len() - 1);
-                    key[state.byte_i] = t.inp;
-                    let next_state = state.next_with_ordering(raw, t, Ordering::Less);
-                    self.last_le_recursive(raw, upper_bound, next_state, key)
+    /// Reads the value starting at `offset` from the store, if the value file has a length table with an entry for
+    /// `offset` that isn't a tombstone.
+    pub fn read_value_at(&self, offset: u64) -> Result<Option<Cow<'_, [u8]>>, Error> {
+        match self.read_recorded_len(offset)? {
+            Some(len) => self.read_store_values(offset, len).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// The length of the value of `key`, which starts at `offset`, read from the length table in the store if there is
+    /// one. Otherwise the value ends where the value of the next key begins. Returns `None` for tombstones and offsets
+    /// missing from the length table.
+    pub(crate) fn read_value_len(&self, key: &[u8], offset: u64) -> Result<Option<u64>, Error> {
+        if self.value_layout.section(format::SECTION_LENGTHS).is_some() {
+            return self.read_recorded_len(offset);
+        }
+        let end = self
+            .index
+            .range()
+            .gt(key)
+            .into_stream()
+            .next()
+            .map(|(_, offset)| offset)
+            .unwrap_or(self.value_layout.values_len as u64);
+        Ok(Some(end.saturating_sub(offset)))
+    }
+
+    /// Returns [`Error::OutOfBounds`] unless the `len` bytes at `offset` are in the values region of the store, excluding
+    /// the sections after it.
+    pub(crate) fn check_store_values(&self, offset: u64, len: u64) -> Result<(), Error> {
+        let available = self.value_layout.values_len as u64;
+        if offset.checked_add(len).is_none_or(|end| end > available) {
+            return Err(Error::OutOfBounds {
+                offset,
+                len,
+                available,
+            });
+        }
+        Ok(())
+    }
+
+    /// Reads from the values region of the store, excluding the sections after it.
+    fn read_store_values(&self, offset: u64, len: u64) -> Result<Cow<'_, [u8]>, Error> {
+        self.check_store_values(offset, len)?;
+        let bytes = self.value_bytes.read(offset, len)?;
+        self.observe_bytes_read(bytes.len());
+        Ok(bytes)
+    }
+
+    /// Binary searches the length table with one small read per probe. Returns `None` for tombstones and offsets missing
+    /// from the table.
+    pub(crate) fn read_recorded_len(&self, offset: u64) -> Result<Option<u64>, Error> {
+        let Some(table) = self.value_layout.section(format::SECTION_LENGTHS) else {
+            return Ok(None);
+        };
+        let mut entry = [0; format::LENGTH_ENTRY_LEN];
+        let (mut lower, mut upper) = (0, table.len() / format::LENGTH_ENTRY_LEN);
+        while lower != upper {
+            let mid = (lower + upper) / 2;
+            self.value_bytes.read_into(
+                (table.start + mid * format::LENGTH_ENTRY_LEN) as u64,
+                &mut entry,
+            )?;
+            match format::read_u64(&entry, 0).cmp(&offset) {
+                std::cmp::Ordering::Less => lower = mid + 1,
+                std::cmp::Ordering::Greater => upper = mid,
+                std::cmp::Ordering::Equal => {
+                    let len = format::read_u64(&entry, 8);
+                    return Ok((len != format::TOMBSTONE_LEN).then_some(len));
                 }
             }
-        } else {
-            None
+        }
+        Ok(None)
+    }
+}
+
+/// A streaming iterator over (key, value bytes) pairs, returned by [`Cache::range_values`].
+///
+/// If a value can't be resolved (e.g. the length table is missing an entry), it is yielded as an empty slice.
+pub struct ValueStream<'c, DK, DV> {
+    cache: &'c Cache<DK, DV>,
+    stream: fst::map::Stream<'c>,
+    key: Vec<u8>,
+}
+
+impl<'a, 'c: 'a, DK, DV> Streamer<'a> for ValueStream<'c, DK, DV>
+where
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    type Item = (&'a [u8], &'c [u8]);
+
+    fn next(&'a mut self) -> Option<Self::Item> {
+        let value = loop {
+            let (key, offset) = self.stream.next()?;
+            if !self.cache.is_tombstone_at(offset) {
+                self.key.clear();
+                self.key.extend_from_slice(key);
+                break self.cache.resolve_value(key, offset).unwrap_or(&[]);
+            }
         };
-        le_found.or_else(|| state.node.is_final().then(|| state.offset_sum))
+        self.cache.observe_bytes_read(value.len());
+        Some((&self.key, value))
     }
 }
 
-struct LastLeSearch<'a> {
-    parent_ordering: Ordering,
-    byte_i: usize,
-    offset_sum: u64,
-    node: Node<'a>,
+/// Summarizes the cache, with its size and its first and last keys, without dumping its contents.
+impl<DK, DV> fmt::Debug for Cache<DK, DV>
+where
+    DK: AsRef<[u8]>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cache")
+            .field("len", &self.len())
+            .field("index_bytes", &self.index.as_fst().as_bytes().len())
+            .field("value_bytes", &self.value_layout.values_len)
+            .field(
+                "first_key",
+                &self.first_key_value().map(|(key, _)| KeyPreview(key)),
+            )
+            .field(
+                "last_key",
+                &self.last_key_value().map(|(key, _)| KeyPreview(key)),
+            )
+            .finish_non_exhaustive()
+    }
 }
 
-impl<'a> LastLeSearch<'a> {
-    fn initial<B>(raw: &'a fst::raw::Fst<B>) -> Self
-    where
-        B: AsRef<[u8]>,
-    {
-        Self {
-            parent_ordering: Ordering::Equal,
-            byte_i: 0,
-            offset_sum: 0,
-            node: raw.root(),
+/// Shows a key as an escaped string, truncated to [`KEY_PREVIEW_LEN`] bytes.
+struct KeyPreview(Vec<u8>);
+
+const KEY_PREVIEW_LEN: usize = 32;
+
+impl fmt::Debug for KeyPreview {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let shown = &self.0[..self.0.len().min(KEY_PREVIEW_LEN)];
+        write!(f, "\"{}\"", shown.escape_ascii())?;
+        if shown.len() < self.0.len() {
+            write!(f, "...")?;
         }
+        Ok(())
     }
+}
 
-    fn next<B>(&self, raw: &'a fst::raw::Fst<B>, upper_bound: &[u8], t: Transition) -> Self
-    where
-        B: AsRef<[u8]>,
-    {
-        self.next_with_ordering(raw, t, t.inp.cmp(&upper_bound[self.byte_i]))
+/// Copies `key` into the start of a zeroed array.
+///
+/// # Panics
+///
+/// If `key` is longer than `N`.
+fn key_array<const N: usize>(key: &[u8]) -> [u8; N] {
+    let mut array = [0; N];
+    array[..key.len()].copy_from_slice(key);
+    array
+}
+
+/// Like [`key_array`], but fails if `key` is longer than `N`.
+fn try_key_array<const N: usize>(key: &[u8]) -> Result<[u8; N], Error> {
+    if key.len() > N {
+        return Err(Error::KeyTooLong {
+            actual: key.len(),
+            capacity: N,
+        });
     }
+    Ok(key_array(key))
+}
 
-    fn next_with_ordering<B>(
-        &self,
-        raw: &'a fst::raw::Fst<B>,
-        t: Transition,
-        ordering: Ordering,
-    ) -> Self
-    where
-        B: AsRef<[u8]>,
-    {
-        Self {
-            parent_ordering: ordering,
-            byte_i: self.byte_i + 1,
-            node: raw.node(t.addr),
-            offset_sum: self.offset_sum + t.out.value(),
-        }
+pub(crate) fn bound_stream<'m, A, K, R>(
+    builder: fst::map::StreamBuilder<'m, A>,
+    key_range: R,
+) -> fst::map::StreamBuilder<'m, A>
+where
+    A: Automaton,
+    K: AsRef<[u8]>,
+    R: RangeBounds<K>,
+{
+    let builder = match key_range.start_bound() {
+        Bound::Unbounded => builder,
+        Bound::Excluded(b) => builder.gt(b),
+        Bound::Included(b) => builder.ge(b),
+    };
+    match key_range.end_bound() {
+        Bound::Unbounded => builder,
+        Bound::Excluded(b) => builder.lt(b),
+        Bound::Included(b) => builder.le(b),
     }
 }
 
-/// If there are any transitions from `node` whose input byte is LE `upper_bound`, then one of them will be returned. If there
-/// are multiple such transitions, the one with the greatest input byte is returned.
-fn find_last_le_transition(node: Node, upper_bound: u8) -> Option<(usize, Transition)> {
-    // Binary search over the transitions.
-    let mut lower = 0;
-    let mut upper = node.len();
-    while lower != upper {
-        let mid = (lower + upper) / 2;
-
-        let t = node.transition(mid);
-        if t.inp <= upper_bound {
-            if mid == node.len() - 1 {
-                // Transition byte is LE our upper_bound, and we're at the last transition, so this is the *last* LE transition.
-                return Some((mid, t));
-            }
+/// The smallest key that is greater than every key starting with `prefix`, or `None` if there is no such key (i.e. `prefix`
+/// is empty or all `0xFF` bytes).
+fn prefix_successor(prefix: &[u8]) -> Option<Vec<u8>> {
+    let last = prefix.iter().rposition(|&b| b != u8::MAX)?;
+    let mut successor = prefix[..=last].to_vec();
+    successor[last] += 1;
+    Some(successor)
+}
 
-            let next_t = node.transition(mid + 1);
-            if next_t.inp > upper_bound {
-                // Transition byte is LE our upper_bound, and the next transition byte is *not*, so this is the *last* LE
-                // transition.
-                return Some((mid, t));
-            }
+impl Cache<Vec<u8>, Vec<u8>> {
+    /// Splits the bytes of a single-file container, as written by [`FileBuilder::create_file`](crate::FileBuilder::create_file),
+    /// into an in-memory cache.
+    ///
+    /// This is meant for platforms without memory mapping, like `wasm32`, where the whole container is fetched into a
+    /// buffer. Only the index is copied.
+    pub fn from_container_bytes(mut bytes: Vec<u8>) -> Result<Self, Error> {
+        let layout = ContainerLayout::parse(&bytes)?;
+        let index = bytes[layout.index.start as usize..layout.index.end as usize].to_vec();
+        bytes.truncate(layout.values.end as usize);
+        Self::new(index, bytes)
+    }
 
-            // Not the last LE transition, so we need to search higher than mid.
-            lower = mid + 1;
-        } else {
-            // Transition too large, search lower than mid.
-            upper = mid;
+    /// Builds an in-memory cache from (key, value) pairs, which must be sorted by key and have no duplicate keys.
+    ///
+    /// ```
+    /// # use mmap_cache::Error;
+    /// # fn example() -> Result<(), Error> {
+    /// use mmap_cache::Cache;
+    ///
+    /// let cache = Cache::from_sorted_iter([("apple", "red"), ("banana", "yellow")])?;
+    /// assert_eq!(cache.get_value(b"banana"), Some(&b"yellow"[..]));
+    /// # Ok(())
+    /// # }
+    /// # example().unwrap();
+    /// ```
+    pub fn from_sorted_iter<K, V>(pairs: impl IntoIterator<Item = (K, V)>) -> Result<Self, Error>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        let mut builder = MemoryBuilder::in_memory()?;
+        for (key, value) in pairs {
+            builder.insert(key.as_ref(), value.as_ref())?;
         }
+        builder.finish_into_cache()
     }
-    None
 }
 
+#[cfg(feature = "mmap")]
 pub type MmapCache = Cache<Mmap, Mmap>;
 
+#[cfg(feature = "mmap")]
 impl MmapCache {
     /// Maps the files at `index_path` and `value_path` to read-only virtual memory ranges.
     ///
@@ -284,9 +1071,82 @@ impl MmapCache {
         index_path: impl AsRef<Path>,
         value_path: impl AsRef<Path>,
     ) -> Result<Self, Error> {
-        let index_file = fs::File::open(index_path)?;
-        let value_file = fs::File::open(value_path)?;
-        Self::map_files(&index_file, &value_file)
+        let (index_path, value_path) = (index_path.as_ref(), value_path.as_ref());
+        let index_mmap = fs::File::open(index_path)
+            .and_then(|file| Mmap::map(&file))
+            .at_path("map", index_path)?;
+        let value_mmap = fs::File::open(value_path)
+            .and_then(|file| Mmap::map(&file))
+            .at_path("map", value_path)?;
+        Self::new(index_mmap, value_mmap).map_err(|e| e.at_cache_paths(index_path, value_path))
+    }
+
+    /// Like [`map_paths`](Self::map_paths), but fully validates the cache before returning it, for deployments that would
+    /// rather fail at startup than serve corrupted data.
+    ///
+    /// The index is checked with [`verify_index`](Self::verify_index) and both files with [`verify`](Self::verify), so the
+    /// files must have been written with checksums, and every byte of both is read.
+    ///
+    /// # Safety
+    ///
+    /// See [`Mmap`].
+    pub unsafe fn open_verified(
+        index_path: impl AsRef<Path>,
+        value_path: impl AsRef<Path>,
+    ) -> Result<Self, Error> {
+        CacheOptions::new()
+            .verify_index(true)
+            .verify_checksums(true)
+            .open(index_path, value_path)
+    }
+
+    /// Like [`open_verified`](Self::open_verified), but for the single-file container at `path`.
+    ///
+    /// # Safety
+    ///
+    /// See [`Mmap`].
+    pub unsafe fn open_verified_path(path: impl AsRef<Path>) -> Result<Self, Error> {
+        CacheOptions::new()
+            .verify_index(true)
+            .verify_checksums(true)
+            .open_path(path)
+    }
+
+    /// Maps the index and value sections of the single-file container at `path` to read-only virtual memory ranges.
+    ///
+    /// # Safety
+    ///
+    /// See [`Mmap`].
+    pub unsafe fn map_path(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let file = fs::File::open(&path).at_path("map", &path)?;
+        Self::map_file(&file).at_path("read", path)
+    }
+
+    /// Maps the index and value sections of the single-file container `file` to read-only virtual memory ranges.
+    ///
+    /// # Safety
+    ///
+    /// See [`Mmap`].
+    pub unsafe fn map_file(file: &fs::File) -> Result<Self, Error> {
+        Self::map_file_with_options(file, &MmapOptions::new())
+    }
+
+    /// Like [`map_file`](Self::map_file), but each section is mapped with a copy of `options`, e.g. to request
+    /// [`populate`](MmapOptions::populate).
+    ///
+    /// The offset and length of `options` are ignored.
+    ///
+    /// # Safety
+    ///
+    /// See [`Mmap`].
+    pub unsafe fn map_file_with_options(
+        file: &fs::File,
+        options: &MmapOptions,
+    ) -> Result<Self, Error> {
+        let layout = ContainerLayout::read(file)?;
+        let index_mmap = map_section(file, layout.index, options)?;
+        let value_mmap = map_section(file, layout.values, options)?;
+        Self::new(index_mmap, value_mmap)
     }
 
     /// Maps`index_file` and `value_file` to read-only virtual memory ranges.
@@ -299,4 +1159,66 @@ impl MmapCache {
         let value_mmap = Mmap::map(value_file)?;
         Self::new(index_mmap, value_mmap)
     }
+
+    /// Like [`map_files`](Self::map_files), but each file is mapped with a copy of `options`, e.g. to request
+    /// [`populate`](MmapOptions::populate).
+    ///
+    /// The offset and length of `options` are ignored; whole files are always mapped.
+    ///
+    /// # Safety
+    ///
+    /// See [`Mmap`].
+    pub unsafe fn map_files_with_options(
+        index_file: &fs::File,
+        value_file: &fs::File,
+        options: &MmapOptions,
+    ) -> Result<Self, Error> {
+        let index_mmap = map_section(index_file, 0..index_file.metadata()?.len(), options)?;
+        let value_mmap = map_section(value_file, 0..value_file.metadata()?.len(), options)?;
+        Self::new(index_mmap, value_mmap)
+    }
+
+    /// Advises the operating system that the `len` value bytes at `offset` will be read soon, so it can start reading them
+    /// into the page cache in the background (`madvise(MADV_WILLNEED)`).
+    ///
+    /// This is only a hint, and it does nothing on platforms other than Unix.
+    pub fn prefetch_value(&self, offset: u64, len: u64) -> Result<(), Error> {
+        let available = self.value_bytes().len() as u64;
+        if offset.checked_add(len).is_none_or(|end| end > available) {
+            return Err(Error::OutOfBounds {
+                offset,
+                len,
+                available,
+            });
+        }
+        if len == 0 {
+            return Ok(());
+        }
+        #[cfg(unix)]
+        self.value_bytes
+            .advise_range(memmap2::Advice::WillNeed, offset as usize, len as usize)?;
+        Ok(())
+    }
+
+    /// Calls [`prefetch_value`](Self::prefetch_value) for each `(offset, len)` pair.
+    pub fn prefetch_values(
+        &self,
+        values: impl IntoIterator<Item = (u64, u64)>,
+    ) -> Result<(), Error> {
+        for (offset, len) in values {
+            self.prefetch_value(offset, len)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "mmap")]
+pub(crate) unsafe fn map_section(
+    file: &fs::File,
+    section: Range<u64>,
+    options: &MmapOptions,
+) -> Result<Mmap, Error> {
+    let len = usize::try_from(section.end - section.start)
+        .map_err(|_| Error::InvalidFormat("section is too large to map"))?;
+    Ok(options.clone().offset(section.start).len(len).map(file)?)
 }
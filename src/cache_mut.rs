@@ -0,0 +1,124 @@
+use crate::error::PathContext;
+use crate::format::ContainerLayout;
+use crate::{Cache, Error};
+
+use bytemuck::Pod;
+use memmap2::{Mmap, MmapMut, MmapOptions};
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+/// A [`Cache`] whose value file is mapped read-write, so values can be updated in place without a rebuild, e.g. to bump
+/// fixed-size counters.
+///
+/// Keys and value lengths are immutable; only the bytes of existing values can change. Changes are written back to the file
+/// by the operating system, or explicitly with [`flush`](Self::flush). Since the checksums are not updated,
+/// [`verify`](Cache::verify) fails once a value has changed.
+pub type CacheMut = Cache<Mmap, MmapMut>;
+
+impl CacheMut {
+    /// Maps the file at `index_path` read-only and the file at `value_path` read-write.
+    ///
+    /// # Safety
+    ///
+    /// See [`MmapMut`]. No other process may modify or truncate the files while they are mapped.
+    pub unsafe fn map_paths(
+        index_path: impl AsRef<Path>,
+        value_path: impl AsRef<Path>,
+    ) -> Result<Self, Error> {
+        let (index_path, value_path) = (index_path.as_ref(), value_path.as_ref());
+        let index_mmap = fs::File::open(index_path)
+            .and_then(|file| Mmap::map(&file))
+            .at_path("map", index_path)?;
+        let value_mmap = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(value_path)
+            .and_then(|file| MmapMut::map_mut(&file))
+            .at_path("map", value_path)?;
+        Self::new(index_mmap, value_mmap).map_err(|e| e.at_cache_paths(index_path, value_path))
+    }
+
+    /// Maps the index section of the single-file container at `path` read-only and its value section read-write.
+    ///
+    /// # Safety
+    ///
+    /// See [`MmapMut`]. No other process may modify or truncate the file while it's mapped.
+    pub unsafe fn map_path(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::map_container(path.as_ref()).at_path("map", path)
+    }
+
+    unsafe fn map_container(path: &Path) -> Result<Self, Error> {
+        let file = fs::OpenOptions::new().read(true).write(true).open(path)?;
+        let layout = ContainerLayout::read(&file)?;
+        let section_len = |len: u64| {
+            usize::try_from(len).map_err(|_| Error::InvalidFormat("section is too large to map"))
+        };
+        let index_mmap = MmapOptions::new()
+            .offset(layout.index.start)
+            .len(section_len(layout.index.end - layout.index.start)?)
+            .map(&file)?;
+        let value_mmap = MmapOptions::new()
+            .offset(layout.values.start)
+            .len(section_len(layout.values.end - layout.values.start)?)
+            .map_mut(&file)?;
+        Self::new(index_mmap, value_mmap)
+    }
+
+    /// Synchronously writes any modified values back to the file.
+    pub fn flush(&self) -> Result<(), Error> {
+        Ok(self.raw_value_bytes().flush()?)
+    }
+}
+
+impl<DK, DV> Cache<DK, DV>
+where
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]> + AsMut<[u8]>,
+{
+    /// Mutable access to the bytes of all values; see [`value_bytes`](Self::value_bytes).
+    pub fn value_bytes_mut(&mut self) -> &mut [u8] {
+        let len = self.value_bytes().len();
+        &mut self.raw_value_bytes_mut().as_mut()[..len]
+    }
+
+    /// Returns the bytes of the value for `key` mutably, if it exists; see [`get_value`](Self::get_value).
+    pub fn get_value_mut(&mut self, key: &[u8]) -> Option<&mut [u8]> {
+        let range = self.value_range(key)?;
+        Some(&mut self.value_bytes_mut()[range])
+    }
+
+    /// Returns the value for `key` as a mutable `T`, if it exists.
+    ///
+    /// Fails if the value is not exactly the size of `T`, or is not aligned for `T` (see
+    /// [`FileBuilder::with_value_alignment`](crate::FileBuilder::with_value_alignment)).
+    pub fn get_pod_mut<T: Pod>(&mut self, key: &[u8]) -> Result<Option<&mut T>, Error> {
+        let Some(range) = self.value_range(key) else {
+            return Ok(None);
+        };
+        let size = std::mem::size_of::<T>();
+        if range.len() != size {
+            return Err(Error::Decode(
+                format!(
+                    "value is {} bytes, but the type is {size} bytes",
+                    range.len()
+                )
+                .into(),
+            ));
+        }
+        let offset = range.start as u64;
+        bytemuck::try_from_bytes_mut(&mut self.value_bytes_mut()[range])
+            .map(Some)
+            .map_err(|_| Error::Misaligned {
+                offset,
+                alignment: std::mem::align_of::<T>(),
+            })
+    }
+
+    fn value_range(&self, key: &[u8]) -> Option<Range<usize>> {
+        let offset = self.get_value_offset(key)?;
+        let len = self.resolve_value(key, offset)?.len();
+        let start = usize::try_from(offset).ok()?;
+        Some(start..start + len)
+    }
+}
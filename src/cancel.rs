@@ -0,0 +1,52 @@
+use crate::Error;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A flag for aborting a long build from another thread, e.g. when an operator stops a job.
+///
+/// Clones share the same flag. Pass a clone to [`FileBuilder::with_cancellation`](crate::FileBuilder::with_cancellation)
+/// or [`ExternalSortBuilder::with_cancellation`](crate::ExternalSortBuilder::with_cancellation), and the next entry they
+/// process after [`cancel`](Self::cancel) is called fails with [`Error::Cancelled`]. Dropping the failed builder removes its
+/// temporary files, including the output files of an atomic build.
+///
+/// ```
+/// # use mmap_cache::Error;
+/// # fn example() -> Result<(), Error> {
+/// use mmap_cache::{CancellationToken, FileBuilder};
+///
+/// let token = CancellationToken::new();
+/// let mut builder = FileBuilder::in_memory()?.with_cancellation(token.clone());
+/// builder.insert(b"apple", b"red")?;
+///
+/// token.cancel();
+/// assert!(matches!(builder.insert(b"peach", b"pink"), Err(Error::Cancelled)));
+/// # Ok(())
+/// # }
+/// # example().unwrap();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests cancellation from every holder of a clone of this token.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Returns [`Error::Cancelled`] if this token was cancelled.
+    pub(crate) fn check(&self) -> Result<(), Error> {
+        if self.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        Ok(())
+    }
+}
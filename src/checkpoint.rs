@@ -0,0 +1,229 @@
+use crate::{format, Error};
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+// A checkpoint file is a journal of the entries committed by a resumable `FileBuilder`:
+//
+// [magic][version: u32][record]*
+//
+// Each record starts with a tag byte:
+//
+// - `ENTRY`: `(key_len: u64, key bytes, offset: u64, recorded_len: u64)`
+// - `CHECKPOINT`: `(values_len: u64, value_alignment: u64, hash_index: u8, metadata)`, where the metadata is laid out like
+//   the metadata section of a value file.
+//
+// A checkpoint is only written once the value file has been synced up to `values_len`, so the entries before the last
+// checkpoint are all backed by durable values. Anything after it is discarded when resuming.
+//
+// All integers are little-endian.
+
+const MAGIC: [u8; 8] = *b"MMAPCKPT";
+const VERSION: u32 = 1;
+const HEADER_LEN: u64 = 12;
+
+const ENTRY: u8 = 1;
+const CHECKPOINT: u8 = 2;
+
+/// The builder state recorded by a checkpoint, besides the entries.
+pub(crate) struct CheckpointState {
+    pub values_len: u64,
+    pub value_alignment: usize,
+    pub hash_index: bool,
+    pub metadata: BTreeMap<String, Vec<u8>>,
+}
+
+/// The open checkpoint file of a resumable build.
+pub(crate) struct Journal {
+    path: PathBuf,
+    writer: io::BufWriter<fs::File>,
+    /// The length of the file up to the end of the last checkpoint.
+    checkpoint_len: u64,
+    last_key: Option<Vec<u8>>,
+}
+
+impl Journal {
+    /// Creates an empty checkpoint file at `path`, replacing any existing file.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut writer = io::BufWriter::new(fs::File::create(&path)?);
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        Ok(Self {
+            path: path.as_ref().to_owned(),
+            writer,
+            checkpoint_len: 0,
+            last_key: None,
+        })
+    }
+
+    /// Opens the checkpoint file at `path` and finds its last checkpoint. Any records after it are truncated, so new
+    /// records follow the checkpoint.
+    ///
+    /// The entries before the checkpoint must then be read with [`replay`](Self::replay).
+    pub fn recover(path: impl AsRef<Path>) -> Result<(Self, CheckpointState), Error> {
+        let mut file = fs::OpenOptions::new().read(true).write(true).open(&path)?;
+        let mut reader = io::BufReader::new(&mut file);
+        let mut header = [0; HEADER_LEN as usize];
+        reader.read_exact(&mut header)?;
+        if header[..8] != MAGIC || format::read_u32(&header, 8) != VERSION {
+            return Err(Error::InvalidFormat("not a checkpoint file"));
+        }
+        let mut position = HEADER_LEN;
+        let mut last_checkpoint = None;
+        // A record cut off by a crash ends the journal.
+        while let Ok(Some((record, len))) = read_record(&mut reader) {
+            position += len;
+            if let Record::Checkpoint(state) = record {
+                last_checkpoint = Some((state, position));
+            }
+        }
+        let (state, checkpoint_len) =
+            last_checkpoint.ok_or(Error::InvalidFormat("checkpoint file has no checkpoint"))?;
+        file.set_len(checkpoint_len)?;
+        file.seek(SeekFrom::End(0))?;
+        let journal = Self {
+            path: path.as_ref().to_owned(),
+            writer: io::BufWriter::new(file),
+            checkpoint_len,
+            last_key: None,
+        };
+        Ok((journal, state))
+    }
+
+    /// Calls `f` with the `(key, offset, recorded_len)` of every entry before the last checkpoint, in order.
+    pub fn replay(
+        &mut self,
+        mut f: impl FnMut(&[u8], u64, u64) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let mut file = fs::File::open(&self.path)?;
+        file.seek(SeekFrom::Start(HEADER_LEN))?;
+        let mut reader = io::BufReader::new(file).take(self.checkpoint_len - HEADER_LEN);
+        while let Some((record, _)) = read_record(&mut reader)? {
+            if let Record::Entry { key, offset, len } = record {
+                f(&key, offset, len)?;
+                self.last_key = Some(key);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write_entry(&mut self, key: &[u8], offset: u64, len: u64) -> io::Result<()> {
+        self.writer.write_all(&[ENTRY])?;
+        self.writer.write_all(&(key.len() as u64).to_le_bytes())?;
+        self.writer.write_all(key)?;
+        format::write_length_entry(&mut self.writer, offset, len)?;
+        match &mut self.last_key {
+            Some(last_key) => {
+                last_key.clear();
+                last_key.extend_from_slice(key);
+            }
+            None => self.last_key = Some(key.to_vec()),
+        }
+        Ok(())
+    }
+
+    /// Appends a checkpoint and syncs the file, so the checkpoint survives a crash.
+    pub fn write_checkpoint(&mut self, state: &CheckpointState) -> io::Result<()> {
+        self.writer.write_all(&[CHECKPOINT])?;
+        self.writer.write_all(&state.values_len.to_le_bytes())?;
+        self.writer
+            .write_all(&(state.value_alignment as u64).to_le_bytes())?;
+        self.writer.write_all(&[state.hash_index as u8])?;
+        format::write_metadata(&mut self.writer, &state.metadata)?;
+        self.writer.flush()?;
+        self.writer.get_ref().sync_data()
+    }
+
+    /// The key of the last entry written or replayed.
+    pub fn last_key(&self) -> Option<&[u8]> {
+        self.last_key.as_deref()
+    }
+
+    /// Removes the checkpoint file, once the build it records has finished.
+    pub fn remove(self) -> io::Result<()> {
+        drop(self.writer);
+        fs::remove_file(&self.path)
+    }
+}
+
+enum Record {
+    Entry { key: Vec<u8>, offset: u64, len: u64 },
+    Checkpoint(CheckpointState),
+}
+
+/// Reads the next record and its length in bytes, or returns `None` at the end of `reader`.
+fn read_record(reader: &mut impl BufRead) -> io::Result<Option<(Record, u64)>> {
+    if reader.fill_buf()?.is_empty() {
+        return Ok(None);
+    }
+    let mut tag = [0];
+    reader.read_exact(&mut tag)?;
+    match tag[0] {
+        ENTRY => {
+            let key = read_bytes(reader)?;
+            let offset = read_u64(reader)?;
+            let len = read_u64(reader)?;
+            let record_len = 1 + 8 + key.len() as u64 + 16;
+            Ok(Some((Record::Entry { key, offset, len }, record_len)))
+        }
+        CHECKPOINT => {
+            let values_len = read_u64(reader)?;
+            let value_alignment = usize::try_from(read_u64(reader)?)
+                .ok()
+                .filter(|alignment| alignment.is_power_of_two())
+                .ok_or_else(|| corrupt("invalid value alignment"))?;
+            let mut hash_index = [0];
+            reader.read_exact(&mut hash_index)?;
+            let count = read_u64(reader)?;
+            let mut metadata = BTreeMap::new();
+            let mut record_len = 1 + 8 + 8 + 1 + 8;
+            for _ in 0..count {
+                let key_len = read_u64(reader)?;
+                let value_len = read_u64(reader)?;
+                let key = read_exact_vec(reader, key_len)?;
+                let value = read_exact_vec(reader, value_len)?;
+                let key =
+                    String::from_utf8(key).map_err(|_| corrupt("metadata key is not UTF-8"))?;
+                record_len += 16 + key_len + value_len;
+                metadata.insert(key, value);
+            }
+            let state = CheckpointState {
+                values_len,
+                value_alignment,
+                hash_index: hash_index[0] != 0,
+                metadata,
+            };
+            Ok(Some((Record::Checkpoint(state), record_len)))
+        }
+        _ => Err(corrupt("unknown record")),
+    }
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_bytes(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let len = read_u64(reader)?;
+    read_exact_vec(reader, len)
+}
+
+fn read_exact_vec(reader: &mut impl Read, len: u64) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(bytes)
+}
+
+fn corrupt(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("corrupt checkpoint file: {message}"),
+    )
+}
@@ -0,0 +1,160 @@
+use crate::error::PathContext;
+use crate::{Cache, Error, ValueStore};
+
+use memmap2::{Mmap, MmapOptions};
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Window sizes must be a multiple of this, so that every window starts on a page boundary (and on an allocation
+/// granularity boundary on Windows).
+pub const CHUNK_ALIGNMENT: u64 = 64 * 1024;
+
+/// A [`ValueStore`] that maps fixed-size windows of a file on demand, instead of mapping the whole file at once.
+///
+/// This lets files that are larger than the address space, e.g. > 2 GB files on 32-bit targets, be read with the same
+/// offsets. Only a bounded number of windows are mapped at a time; the least recently used window is unmapped to make
+/// room for a new one. Since a window can be unmapped at any time, reads return copies of the value bytes.
+pub struct ChunkedMmap {
+    file: fs::File,
+    len: u64,
+    window_size: u64,
+    windows: Mutex<MappedWindows>,
+}
+
+struct MappedWindows {
+    capacity: usize,
+    /// Least recently used first.
+    windows: VecDeque<(u64, Arc<Mmap>)>,
+}
+
+impl ChunkedMmap {
+    /// Reads `file` with windows of 64 MiB, keeping up to 8 windows mapped.
+    ///
+    /// # Safety
+    ///
+    /// See [`Mmap`].
+    pub unsafe fn new(file: fs::File) -> Result<Self, Error> {
+        let len = file.metadata()?.len();
+        Ok(Self {
+            file,
+            len,
+            window_size: 64 * 1024 * 1024,
+            windows: Mutex::new(MappedWindows {
+                capacity: 8,
+                windows: VecDeque::new(),
+            }),
+        })
+    }
+
+    /// # Safety
+    ///
+    /// See [`Mmap`].
+    pub unsafe fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::new(fs::File::open(path)?)
+    }
+
+    /// Sets the size of each mapped window.
+    ///
+    /// # Panics
+    ///
+    /// If `window_size` is zero or not a multiple of [`CHUNK_ALIGNMENT`], or doesn't fit in the address space.
+    pub fn with_window_size(mut self, window_size: u64) -> Self {
+        assert!(window_size > 0 && window_size.is_multiple_of(CHUNK_ALIGNMENT));
+        assert!(usize::try_from(window_size).is_ok());
+        self.window_size = window_size;
+        self.windows.get_mut().unwrap().windows.clear();
+        self
+    }
+
+    /// Sets the maximum number of windows that are mapped at once.
+    ///
+    /// # Panics
+    ///
+    /// If `capacity` is zero.
+    pub fn with_window_capacity(mut self, capacity: usize) -> Self {
+        assert!(capacity > 0);
+        let windows = self.windows.get_mut().unwrap();
+        windows.capacity = capacity;
+        windows.windows.clear();
+        self
+    }
+
+    fn window(&self, index: u64) -> Result<Arc<Mmap>, Error> {
+        let mut windows = self.windows.lock().unwrap();
+        if let Some(i) = windows.windows.iter().position(|(w, _)| *w == index) {
+            let entry = windows.windows.remove(i).unwrap();
+            let window = entry.1.clone();
+            windows.windows.push_back(entry);
+            return Ok(window);
+        }
+
+        let offset = index * self.window_size;
+        let len = self.window_size.min(self.len - offset);
+        // SAFETY: The caller of `new` upheld the `Mmap` contract for the whole file.
+        let window = Arc::new(unsafe {
+            MmapOptions::new()
+                .offset(offset)
+                .len(len as usize)
+                .map(&self.file)?
+        });
+        if windows.windows.len() == windows.capacity {
+            windows.windows.pop_front();
+        }
+        windows.windows.push_back((index, window.clone()));
+        Ok(window)
+    }
+}
+
+impl ValueStore for ChunkedMmap {
+    fn size(&self) -> u64 {
+        self.len
+    }
+
+    fn read(&self, offset: u64, len: u64) -> Result<Cow<'_, [u8]>, Error> {
+        let end = offset
+            .checked_add(len)
+            .filter(|&end| end <= self.len)
+            .ok_or(Error::OutOfBounds {
+                offset,
+                len,
+                available: self.len,
+            })?;
+        let mut bytes = Vec::with_capacity(
+            usize::try_from(len).map_err(|_| Error::InvalidFormat("value is too large"))?,
+        );
+        let mut position = offset;
+        while position < end {
+            let window = self.window(position / self.window_size)?;
+            let start = (position % self.window_size) as usize;
+            let take = (window.len() - start).min((end - position) as usize);
+            bytes.extend_from_slice(&window[start..start + take]);
+            position += take as u64;
+        }
+        Ok(Cow::Owned(bytes))
+    }
+}
+
+/// A [`Cache`] with a fully mapped index and a value file mapped in windows by [`ChunkedMmap`].
+pub type ChunkedMmapCache = Cache<Mmap, ChunkedMmap>;
+
+impl ChunkedMmapCache {
+    /// Maps the index at `index_path`, and reads the value file at `value_path` in windows.
+    ///
+    /// # Safety
+    ///
+    /// See [`Mmap`].
+    pub unsafe fn map_paths(
+        index_path: impl AsRef<Path>,
+        value_path: impl AsRef<Path>,
+    ) -> Result<Self, Error> {
+        let (index_path, value_path) = (index_path.as_ref(), value_path.as_ref());
+        let index = fs::File::open(index_path)
+            .and_then(|file| Mmap::map(&file))
+            .at_path("map", index_path)?;
+        let values = ChunkedMmap::open(value_path).at_path("map", value_path)?;
+        Cache::from_store(index, values).map_err(|e| e.at_cache_paths(index_path, value_path))
+    }
+}
@@ -0,0 +1,207 @@
+use crate::{Cache, Error, FileBuilder, ValueStream};
+
+use bytemuck::Pod;
+use fst::Streamer;
+use std::io;
+use std::marker::PhantomData;
+use std::ops::RangeBounds;
+
+/// A serialization format for values, used to write them with [`FileBuilder::insert_encoded`] and read them with a
+/// [`CodecCache`].
+///
+/// Swapping the codec changes the format of every value without touching any call sites. Besides the built-in codecs, it can
+/// be implemented for any serialization format, e.g. `bincode` or `postcard` with `serde`.
+pub trait ValueCodec {
+    /// The type of values that are encoded.
+    type Value: ?Sized;
+
+    /// The decoded form of a value, which may borrow from the encoded bytes.
+    type View<'a>;
+
+    fn encode<W: io::Write>(&self, value: &Self::Value, writer: &mut W) -> io::Result<()>;
+
+    /// Decodes exactly the bytes of one value.
+    fn decode<'a>(&self, bytes: &'a [u8]) -> Result<Self::View<'a>, Error>;
+}
+
+/// Values are raw byte slices, and views borrow them directly.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RawCodec;
+
+impl ValueCodec for RawCodec {
+    type Value = [u8];
+    type View<'a> = &'a [u8];
+
+    fn encode<W: io::Write>(&self, value: &[u8], writer: &mut W) -> io::Result<()> {
+        writer.write_all(value)
+    }
+
+    fn decode<'a>(&self, bytes: &'a [u8]) -> Result<&'a [u8], Error> {
+        Ok(bytes)
+    }
+}
+
+/// Values are UTF-8 strings, and views borrow them directly.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Utf8Codec;
+
+impl ValueCodec for Utf8Codec {
+    type Value = str;
+    type View<'a> = &'a str;
+
+    fn encode<W: io::Write>(&self, value: &str, writer: &mut W) -> io::Result<()> {
+        writer.write_all(value.as_bytes())
+    }
+
+    fn decode<'a>(&self, bytes: &'a [u8]) -> Result<&'a str, Error> {
+        std::str::from_utf8(bytes).map_err(|e| Error::Decode(e.into()))
+    }
+}
+
+/// Values are a [`Pod`] type `T`, stored as its native bytes. Views are copies, so values don't need to be aligned; for
+/// zero-copy references to aligned values, see [`TypedCache`](crate::TypedCache).
+pub struct PodCodec<T>(PhantomData<fn() -> T>);
+
+impl<T> PodCodec<T> {
+    pub fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T> Default for PodCodec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for PodCodec<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for PodCodec<T> {}
+
+impl<T: Pod> ValueCodec for PodCodec<T> {
+    type Value = T;
+    type View<'a> = T;
+
+    fn encode<W: io::Write>(&self, value: &T, writer: &mut W) -> io::Result<()> {
+        writer.write_all(bytemuck::bytes_of(value))
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<T, Error> {
+        bytemuck::try_pod_read_unaligned(bytes).map_err(|e| Error::Decode(e.to_string().into()))
+    }
+}
+
+/// An [`io::Write`] adapter that appends to the current value of a [`FileBuilder`], returned by
+/// [`FileBuilder::value_writer`].
+pub struct ValueWriter<'b, WI = io::BufWriter<std::fs::File>, WV = io::BufWriter<std::fs::File>> {
+    builder: &'b mut FileBuilder<WI, WV>,
+}
+
+impl<WI: io::Write, WV: io::Write> io::Write for ValueWriter<'_, WI, WV> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.builder.append_value_bytes(buf) {
+            Ok(()) => Ok(buf.len()),
+            Err(Error::IO(e)) => Err(e),
+            Err(e) => Err(io::Error::other(e)),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<WI: io::Write, WV: io::Write> FileBuilder<WI, WV> {
+    /// Returns an [`io::Write`] that appends to the current value, like
+    /// [`append_value_bytes`](Self::append_value_bytes).
+    pub fn value_writer(&mut self) -> ValueWriter<'_, WI, WV> {
+        ValueWriter { builder: self }
+    }
+
+    /// Encodes `value` with `codec` straight into the value stream and commits the entry for `key`.
+    pub fn insert_encoded<C: ValueCodec>(
+        &mut self,
+        key: &[u8],
+        codec: &C,
+        value: &C::Value,
+    ) -> Result<(), Error> {
+        // Check before encoding, so a rejected key doesn't leave its value pending.
+        self.check_key_order(key)?;
+        codec.encode(value, &mut self.value_writer())?;
+        self.commit_entry(key)
+    }
+}
+
+/// A [`Cache`] whose values are decoded with a [`ValueCodec`].
+pub struct CodecCache<C, DK, DV> {
+    cache: Cache<DK, DV>,
+    codec: C,
+}
+
+impl<C, DK, DV> CodecCache<C, DK, DV>
+where
+    C: ValueCodec,
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    pub fn new(cache: Cache<DK, DV>, codec: C) -> Self {
+        Self { cache, codec }
+    }
+
+    /// Access the untyped [`Cache`].
+    pub fn untyped(&self) -> &Cache<DK, DV> {
+        &self.cache
+    }
+
+    pub fn into_untyped(self) -> Cache<DK, DV> {
+        self.cache
+    }
+
+    pub fn codec(&self) -> &C {
+        &self.codec
+    }
+
+    /// Returns the decoded value for `key`, if it exists.
+    pub fn get(&self, key: &[u8]) -> Result<Option<C::View<'_>>, Error> {
+        self.cache
+            .get_value(key)
+            .map(|bytes| self.codec.decode(bytes))
+            .transpose()
+    }
+
+    /// Returns a streaming iterator over (key, decoded value) pairs. Tombstones are skipped.
+    pub fn range<K, R>(&self, key_range: R) -> CodecStream<'_, C, DK, DV>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        CodecStream {
+            stream: self.cache.range_values(key_range),
+            codec: &self.codec,
+        }
+    }
+}
+
+/// A streaming iterator over (key, decoded value) pairs, returned by [`CodecCache::range`].
+pub struct CodecStream<'c, C, DK, DV> {
+    stream: ValueStream<'c, DK, DV>,
+    codec: &'c C,
+}
+
+impl<'a, 'c: 'a, C, DK, DV> Streamer<'a> for CodecStream<'c, C, DK, DV>
+where
+    C: ValueCodec,
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    type Item = (&'a [u8], Result<C::View<'c>, Error>);
+
+    fn next(&'a mut self) -> Option<Self::Item> {
+        let (key, value) = self.stream.next()?;
+        Some((key, self.codec.decode(value)))
+    }
+}
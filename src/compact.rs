@@ -0,0 +1,104 @@
+use crate::{Cache, Error, FileBuilder};
+
+use fst::Streamer;
+use std::time::SystemTime;
+
+/// What [`compact`] copied.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CompactionStats {
+    /// The number of keys written to the output, including tombstones.
+    pub entries: u64,
+    /// The number of tombstones left out of the output.
+    pub dropped_tombstones: u64,
+    /// The number of expired entries left out of the output (see [`compact_expired`]).
+    pub dropped_expired: u64,
+    /// The length of the value bytes of the input, including unreachable bytes and padding.
+    pub input_value_bytes: u64,
+    /// The total length of the values that were copied.
+    pub live_value_bytes: u64,
+}
+
+/// Rewrites `cache` into `builder`, copying only the values that are reachable from its keys, then finishes `builder`.
+///
+/// Values that were replaced while merging, or that were left behind by [`FileBuilder::insert_tombstone`], are unreachable,
+/// but stay in the value file until it's rewritten. The output has the same keys and values as `cache`, with new offsets.
+/// If `drop_tombstones` is set, keys with tombstones are left out entirely, which is only correct if the output won't be
+/// layered on top of older caches.
+///
+/// Every value is copied once per key, so values shared by several keys (see
+/// [`FileBuilder::with_value_dedup`](crate::FileBuilder::with_value_dedup)) are only shared in the output if `builder`
+/// deduplicates values as well. The expiry of each entry (see [`FileBuilder::insert_with_expiry`]) is kept, which fails if
+/// `builder` deduplicates values.
+pub fn compact<DK, DV>(
+    cache: &Cache<DK, DV>,
+    drop_tombstones: bool,
+    builder: FileBuilder,
+) -> Result<CompactionStats, Error>
+where
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    compact_impl(cache, drop_tombstones, None, builder)
+}
+
+/// Like [`compact`], but also leaves out the entries that expire at or before `now`.
+pub fn compact_expired<DK, DV>(
+    cache: &Cache<DK, DV>,
+    now: SystemTime,
+    drop_tombstones: bool,
+    builder: FileBuilder,
+) -> Result<CompactionStats, Error>
+where
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    compact_impl(cache, drop_tombstones, Some(now), builder)
+}
+
+fn compact_impl<DK, DV>(
+    cache: &Cache<DK, DV>,
+    drop_tombstones: bool,
+    now: Option<SystemTime>,
+    mut builder: FileBuilder,
+) -> Result<CompactionStats, Error>
+where
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    let mut stats = CompactionStats {
+        input_value_bytes: cache.value_bytes().len() as u64,
+        ..Default::default()
+    };
+    let mut stream = cache.index().stream();
+    while let Some((key, offset)) = stream.next() {
+        if cache.is_tombstone_at(offset) {
+            if drop_tombstones {
+                stats.dropped_tombstones += 1;
+            } else {
+                builder.insert_tombstone(key)?;
+                stats.entries += 1;
+            }
+            continue;
+        }
+        let expires_at = cache.expiry_at(offset);
+        if let (Some(expires_at), Some(now)) = (expires_at, now) {
+            if expires_at <= now {
+                stats.dropped_expired += 1;
+                continue;
+            }
+        }
+        let value = cache
+            .resolve_value(key, offset)
+            .ok_or(Error::InvalidFormat(
+                "value offset is missing from the length table",
+            ))?;
+        match expires_at {
+            Some(expires_at) => builder.insert_with_expiry(key, value, expires_at)?,
+            None => builder.insert(key, value)?,
+        }
+        stats.entries += 1;
+        stats.live_value_bytes += value.len() as u64;
+    }
+    builder.finish()?;
+    Ok(stats)
+}
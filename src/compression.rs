@@ -0,0 +1,341 @@
+use crate::{Cache, Error, FileBuilder};
+
+// Each compressed value is stored as `(uncompressed_len: u64, compressed bytes)`, with a little-endian length.
+
+/// A per-value compression algorithm, used by [`CompressedBuilder`] and [`CompressedCache`].
+///
+/// The same compressor must be used to write and read a cache.
+pub trait Compressor {
+    /// Appends the compressed form of `input` to `output`.
+    fn compress(&self, input: &[u8], output: &mut Vec<u8>);
+
+    /// Appends the decompressed form of `input` to `output`, which is expected to grow by exactly `decompressed_len` bytes.
+    fn decompress(
+        &self,
+        input: &[u8],
+        decompressed_len: usize,
+        output: &mut Vec<u8>,
+    ) -> Result<(), Error>;
+
+    /// An upper bound on the decompressed length of `compressed_len` bytes, so that a corrupt length can be rejected before
+    /// allocating for it. The default is no bound.
+    fn max_decompressed_len(&self, compressed_len: usize) -> usize {
+        let _ = compressed_len;
+        usize::MAX
+    }
+}
+
+/// The [LZ4 block format](https://github.com/lz4/lz4/blob/dev/doc/lz4_Block_format.md), which favors speed over
+/// compression ratio.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Lz4;
+
+/// Compresses each value with `C` before writing it with a [`FileBuilder`].
+pub struct CompressedBuilder<C> {
+    builder: FileBuilder,
+    compressor: C,
+    buffer: Vec<u8>,
+}
+
+impl<C: Compressor> CompressedBuilder<C> {
+    pub fn new(builder: FileBuilder, compressor: C) -> Self {
+        Self {
+            builder,
+            compressor,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Compresses `value` and associates it with `key`.
+    pub fn insert(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        self.buffer.clear();
+        self.buffer
+            .extend_from_slice(&(value.len() as u64).to_le_bytes());
+        self.compressor.compress(value, &mut self.buffer);
+        self.builder.insert(key, &self.buffer)
+    }
+
+    /// Completes the serialization. See [`FileBuilder::finish`].
+    pub fn finish(self) -> Result<(), Error> {
+        self.builder.finish()
+    }
+}
+
+/// A [`Cache`] whose values were compressed by a [`CompressedBuilder`], and are transparently decompressed when read.
+pub struct CompressedCache<C, DK, DV> {
+    cache: Cache<DK, DV>,
+    compressor: C,
+}
+
+impl<C, DK, DV> CompressedCache<C, DK, DV>
+where
+    C: Compressor,
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    pub fn new(cache: Cache<DK, DV>, compressor: C) -> Self {
+        Self { cache, compressor }
+    }
+
+    /// Access the underlying [`Cache`], whose values are compressed.
+    pub fn compressed(&self) -> &Cache<DK, DV> {
+        &self.cache
+    }
+
+    pub fn into_compressed(self) -> Cache<DK, DV> {
+        self.cache
+    }
+
+    /// Returns the decompressed value for `key`, if it exists.
+    pub fn get_value(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let mut value = Vec::new();
+        Ok(self.get_value_into(key, &mut value)?.then_some(value))
+    }
+
+    /// Decompresses the value for `key` into `output`, replacing its contents. Returns `false` if `key` doesn't exist.
+    ///
+    /// This allows reusing the allocation of `output` across many lookups.
+    pub fn get_value_into(&self, key: &[u8], output: &mut Vec<u8>) -> Result<bool, Error> {
+        let Some(stored) = self.cache.get_value(key) else {
+            return Ok(false);
+        };
+        let (len, compressed) = stored
+            .split_first_chunk::<8>()
+            .ok_or(Error::InvalidFormat("compressed value is truncated"))?;
+        let len = usize::try_from(u64::from_le_bytes(*len))
+            .ok()
+            .filter(|&len| len <= self.compressor.max_decompressed_len(compressed.len()))
+            .ok_or(Error::InvalidFormat("compressed value is too large"))?;
+        output.clear();
+        output.reserve(len);
+        self.compressor.decompress(compressed, len, output)?;
+        Ok(true)
+    }
+}
+
+const MIN_MATCH: usize = 4;
+const LAST_LITERALS: usize = 5;
+/// Matches must start at least this many bytes before the end of the input.
+const MF_LIMIT: usize = 12;
+const MAX_OFFSET: usize = u16::MAX as usize;
+const HASH_BITS: u32 = 12;
+/// Each byte of a compressed block decompresses to at most this many bytes, since a match length grows by at most 255 per
+/// extension byte.
+const MAX_EXPANSION: usize = 255;
+
+impl Compressor for Lz4 {
+    fn compress(&self, input: &[u8], output: &mut Vec<u8>) {
+        // Greedy matching against the most recent position with the same 4-byte prefix.
+        let mut table = vec![0u32; 1 << HASH_BITS];
+        let mut anchor = 0;
+        let mut i = 0;
+        if input.len() > MF_LIMIT {
+            let match_limit = input.len() - MF_LIMIT;
+            let match_end_limit = input.len() - LAST_LITERALS;
+            while i < match_limit {
+                let prefix = u32::from_le_bytes(input[i..i + 4].try_into().unwrap());
+                let h = (prefix.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize;
+                let candidate = table[h] as usize;
+                table[h] = i as u32 + 1;
+                if candidate > 0 {
+                    let candidate = candidate - 1;
+                    if i - candidate <= MAX_OFFSET
+                        && input[candidate..candidate + 4] == input[i..i + 4]
+                    {
+                        let mut end = i + MIN_MATCH;
+                        while end < match_end_limit && input[end] == input[end - (i - candidate)] {
+                            end += 1;
+                        }
+                        write_sequence(output, &input[anchor..i], Some((i - candidate, end - i)));
+                        i = end;
+                        anchor = end;
+                        continue;
+                    }
+                }
+                i += 1;
+            }
+        }
+        write_sequence(output, &input[anchor..], None);
+    }
+
+    fn decompress(
+        &self,
+        input: &[u8],
+        decompressed_len: usize,
+        output: &mut Vec<u8>,
+    ) -> Result<(), Error> {
+        const CORRUPT: Error = Error::InvalidFormat("corrupt LZ4 block");
+        let start = output.len();
+        let mut i = 0;
+        loop {
+            let token = *input.get(i).ok_or(CORRUPT)?;
+            i += 1;
+
+            let literals_len = read_length(input, &mut i, (token >> 4) as usize).ok_or(CORRUPT)?;
+            let literals = input.get(i..i + literals_len).ok_or(CORRUPT)?;
+            output.extend_from_slice(literals);
+            i += literals_len;
+            if i == input.len() {
+                break;
+            }
+
+            let offset = input.get(i..i + 2).ok_or(CORRUPT)?;
+            let offset = u16::from_le_bytes([offset[0], offset[1]]) as usize;
+            i += 2;
+            let match_len =
+                read_length(input, &mut i, (token & 0xF) as usize).ok_or(CORRUPT)? + MIN_MATCH;
+            if offset == 0
+                || offset > output.len() - start
+                || output.len() - start + match_len > decompressed_len
+            {
+                return Err(CORRUPT);
+            }
+            // The match may overlap the bytes it produces, so copy one byte at a time.
+            let match_start = output.len() - offset;
+            for j in match_start..match_start + match_len {
+                output.push(output[j]);
+            }
+        }
+        if output.len() - start != decompressed_len {
+            return Err(CORRUPT);
+        }
+        Ok(())
+    }
+
+    fn max_decompressed_len(&self, compressed_len: usize) -> usize {
+        compressed_len.saturating_mul(MAX_EXPANSION)
+    }
+}
+
+fn write_sequence(output: &mut Vec<u8>, literals: &[u8], matched: Option<(usize, usize)>) {
+    let match_len = matched.map_or(0, |(_, len)| len - MIN_MATCH);
+    output.push(((literals.len().min(15) as u8) << 4) | match_len.min(15) as u8);
+    write_length(output, literals.len());
+    output.extend_from_slice(literals);
+    if let Some((offset, _)) = matched {
+        output.extend_from_slice(&(offset as u16).to_le_bytes());
+        write_length(output, match_len);
+    }
+}
+
+/// Writes the extension bytes of a length whose first 4 bits were written to a token.
+fn write_length(output: &mut Vec<u8>, len: usize) {
+    if len < 15 {
+        return;
+    }
+    let mut rest = len - 15;
+    while rest >= 255 {
+        output.push(255);
+        rest -= 255;
+    }
+    output.push(rest as u8);
+}
+
+fn read_length(input: &[u8], i: &mut usize, token_len: usize) -> Option<usize> {
+    let mut len = token_len;
+    if token_len == 15 {
+        loop {
+            let b = *input.get(*i)?;
+            *i += 1;
+            len = len.checked_add(b as usize)?;
+            if b != 255 {
+                break;
+            }
+        }
+    }
+    Some(len)
+}
+
+#[cfg(all(test, feature = "mmap"))]
+mod tests {
+    use super::*;
+
+    fn round_trip(input: &[u8]) -> Vec<u8> {
+        let mut compressed = Vec::new();
+        Lz4.compress(input, &mut compressed);
+        let mut output = Vec::new();
+        Lz4.decompress(&compressed, input.len(), &mut output)
+            .unwrap();
+        assert_eq!(output, input);
+        assert!(input.len() <= Lz4.max_decompressed_len(compressed.len()));
+        compressed
+    }
+
+    #[test]
+    fn lz4_round_trips() {
+        round_trip(b"");
+        round_trip(b"short");
+        round_trip(b"abcdefghijklmnopqrstuvwxyz0123456789");
+
+        let repetitive: Vec<u8> = b"{\"key\": \"value\", \"n\": 12345}, "
+            .iter()
+            .copied()
+            .cycle()
+            .take(10_000)
+            .collect();
+        assert!(round_trip(&repetitive).len() < repetitive.len() / 10);
+
+        let mut state = 1u32;
+        let noise: Vec<u8> = (0..5000)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect();
+        round_trip(&noise);
+        round_trip(&vec![0; 1 << 20]);
+    }
+
+    #[test]
+    fn lz4_rejects_corrupt_blocks() {
+        let mut compressed = Vec::new();
+        Lz4.compress(&[7; 100], &mut compressed);
+        let mut output = Vec::new();
+        assert!(Lz4.decompress(&compressed, 99, &mut output).is_err());
+        assert!(Lz4
+            .decompress(&compressed[..compressed.len() - 1], 100, &mut output)
+            .is_err());
+    }
+
+    #[test]
+    fn compressed_values() {
+        let dir = std::env::temp_dir();
+        let index_path = dir.join("mmap_cache_compressed_values_index");
+        let values_path = dir.join("mmap_cache_compressed_values_values");
+        let big = vec![b'x'; 1000];
+        let mut builder = CompressedBuilder::new(
+            FileBuilder::create_files(&index_path, &values_path).unwrap(),
+            Lz4,
+        );
+        builder.insert(b"big", &big).unwrap();
+        builder.insert(b"small", b"abc").unwrap();
+        builder.finish().unwrap();
+
+        let cache = CompressedCache::new(
+            unsafe { crate::MmapCache::map_paths(&index_path, &values_path) }.unwrap(),
+            Lz4,
+        );
+        assert!(cache.compressed().value_bytes().len() < 100);
+        assert_eq!(cache.get_value(b"big").unwrap(), Some(big));
+        assert_eq!(cache.get_value(b"small").unwrap(), Some(b"abc".to_vec()));
+        assert_eq!(cache.get_value(b"none").unwrap(), None);
+    }
+
+    #[test]
+    fn compressed_value_length_is_bounded() {
+        let mut stored = u64::MAX.to_le_bytes().to_vec();
+        stored.extend_from_slice(&[0x10, b'x']);
+        let mut too_long = (256 * 2u64).to_le_bytes().to_vec();
+        too_long.extend_from_slice(&[0x10, b'x']);
+        let cache = CompressedCache::new(
+            Cache::from_sorted_iter([(&b"huge"[..], stored), (&b"long"[..], too_long)]).unwrap(),
+            Lz4,
+        );
+        for key in [b"huge", b"long"] {
+            assert!(matches!(
+                cache.get_value(key),
+                Err(Error::InvalidFormat("compressed value is too large"))
+            ));
+        }
+    }
+}
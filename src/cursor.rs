@@ -0,0 +1,101 @@
+use crate::Error;
+
+use fst::{IntoStreamer, Streamer};
+
+/// A resumable, forward-only position in the key space of a [`Cache`](crate::Cache), created by
+/// [`Cache::cursor`](crate::Cache::cursor).
+///
+/// A cursor streams (key, value offset) pairs like [`Cache::range`](crate::Cache::range), but it can be moved with
+/// [`seek_ge`](Self::seek_ge) and its [`position`](Self::position) can be saved and later restored with
+/// [`Cache::cursor_at`](crate::Cache::cursor_at), even against a different [`Cache`](crate::Cache) instance. This makes it
+/// suitable for paginating over the cache across requests.
+pub struct Cursor<'c, DK> {
+    index: &'c fst::Map<DK>,
+    stream: fst::map::Stream<'c>,
+    position: CursorPosition,
+}
+
+/// A saved [`Cursor`] position.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum CursorPosition {
+    /// The next key will be the least key greater than or equal to this one.
+    AtOrAfter(Vec<u8>),
+    /// The next key will be the least key strictly greater than this one.
+    After(Vec<u8>),
+}
+
+impl<'c, DK: AsRef<[u8]>> Cursor<'c, DK> {
+    pub(crate) fn new(index: &'c fst::Map<DK>, position: CursorPosition) -> Self {
+        let builder = match &position {
+            CursorPosition::AtOrAfter(key) => index.range().ge(key),
+            CursorPosition::After(key) => index.range().gt(key),
+        };
+        Self {
+            index,
+            stream: builder.into_stream(),
+            position,
+        }
+    }
+
+    /// Moves the cursor so that the next key is the least key greater than or equal to `key`.
+    ///
+    /// Seeking backwards is allowed.
+    pub fn seek_ge(&mut self, key: &[u8]) {
+        *self = Self::new(self.index, CursorPosition::AtOrAfter(key.to_vec()));
+    }
+
+    /// The current position, which can be passed to [`Cache::cursor_at`](crate::Cache::cursor_at) to resume from here.
+    pub fn position(&self) -> &CursorPosition {
+        &self.position
+    }
+
+    pub fn into_position(self) -> CursorPosition {
+        self.position
+    }
+}
+
+impl<'a, 'c: 'a, DK: AsRef<[u8]>> Streamer<'a> for Cursor<'c, DK> {
+    type Item = (&'a [u8], u64);
+
+    fn next(&'a mut self) -> Option<Self::Item> {
+        let (key, offset) = self.stream.next()?;
+        // Reuse the position's buffer once the cursor has yielded a key.
+        if let CursorPosition::AtOrAfter(_) = self.position {
+            self.position = CursorPosition::After(Vec::new());
+        }
+        let CursorPosition::After(last_key) = &mut self.position else {
+            unreachable!()
+        };
+        last_key.clear();
+        last_key.extend_from_slice(key);
+        Some((last_key, offset))
+    }
+}
+
+impl CursorPosition {
+    /// The position before all keys.
+    pub fn start() -> Self {
+        Self::AtOrAfter(Vec::new())
+    }
+
+    /// Encodes the position as bytes, e.g. to use as an opaque pagination token.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let (tag, key) = match self {
+            Self::AtOrAfter(key) => (0, key),
+            Self::After(key) => (1, key),
+        };
+        let mut bytes = Vec::with_capacity(1 + key.len());
+        bytes.push(tag);
+        bytes.extend_from_slice(key);
+        bytes
+    }
+
+    /// Decodes a position encoded by [`to_bytes`](Self::to_bytes).
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        match bytes.split_first() {
+            Some((0, key)) => Ok(Self::AtOrAfter(key.to_vec())),
+            Some((1, key)) => Ok(Self::After(key.to_vec())),
+            _ => Err(Error::InvalidFormat("invalid cursor position")),
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use crate::Cache;
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+/// Wraps a [`Cache`] with an in-memory LRU cache of decoded values, so that hot keys are only decoded (e.g. decompressed or
+/// deserialized) once.
+///
+/// Decoded values are kept until their total weight exceeds a byte budget. Each entry weighs its key length plus the weight
+/// of its value, which defaults to `size_of::<T>()`; see [`with_weigher`](Self::with_weigher) for values that own heap
+/// memory.
+pub struct DecodedCache<T, F, DK, DV> {
+    cache: Cache<DK, DV>,
+    decode: F,
+    weigh: fn(&T) -> usize,
+    lru: Mutex<Lru<T>>,
+}
+
+struct Lru<T> {
+    budget: usize,
+    weight: usize,
+    next_tick: u64,
+    entries: HashMap<Vec<u8>, LruEntry<T>>,
+    /// Keys ordered from least to most recently used.
+    recency: BTreeMap<u64, Vec<u8>>,
+}
+
+struct LruEntry<T> {
+    value: Arc<T>,
+    weight: usize,
+    tick: u64,
+}
+
+impl<T, E, F, DK, DV> DecodedCache<T, F, DK, DV>
+where
+    F: Fn(&[u8]) -> Result<T, E>,
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    /// Decodes values from `cache` with `decode`, keeping up to `byte_budget` bytes of decoded values in memory.
+    pub fn new(cache: Cache<DK, DV>, byte_budget: usize, decode: F) -> Self {
+        Self {
+            cache,
+            decode,
+            weigh: |_| std::mem::size_of::<T>(),
+            lru: Mutex::new(Lru {
+                budget: byte_budget,
+                weight: 0,
+                next_tick: 0,
+                entries: HashMap::new(),
+                recency: BTreeMap::new(),
+            }),
+        }
+    }
+
+    /// Sets the function that estimates how many bytes a decoded value occupies.
+    pub fn with_weigher(mut self, weigh: fn(&T) -> usize) -> Self {
+        self.weigh = weigh;
+        self
+    }
+
+    pub fn cache(&self) -> &Cache<DK, DV> {
+        &self.cache
+    }
+
+    pub fn into_cache(self) -> Cache<DK, DV> {
+        self.cache
+    }
+
+    /// Returns the decoded value for `key`, decoding it only if it isn't already cached.
+    pub fn get(&self, key: &[u8]) -> Result<Option<Arc<T>>, E> {
+        if let Some(value) = self.lru.lock().unwrap().get(key) {
+            return Ok(Some(value));
+        }
+        let Some(bytes) = self.cache.get_value(key) else {
+            return Ok(None);
+        };
+        // Decode without holding the lock, so other keys can be read concurrently.
+        let value = Arc::new((self.decode)(bytes)?);
+        let weight = key.len() + (self.weigh)(&value);
+        self.lru.lock().unwrap().insert(key, value.clone(), weight);
+        Ok(Some(value))
+    }
+
+    /// Removes all decoded values from memory.
+    pub fn clear(&self) {
+        let mut lru = self.lru.lock().unwrap();
+        lru.entries.clear();
+        lru.recency.clear();
+        lru.weight = 0;
+    }
+
+    /// The total weight of the decoded values currently in memory.
+    pub fn weight(&self) -> usize {
+        self.lru.lock().unwrap().weight
+    }
+}
+
+impl<T> Lru<T> {
+    fn get(&mut self, key: &[u8]) -> Option<Arc<T>> {
+        let tick = self.next_tick;
+        let entry = self.entries.get_mut(key)?;
+        let key = self.recency.remove(&entry.tick).unwrap();
+        entry.tick = tick;
+        self.recency.insert(tick, key);
+        self.next_tick += 1;
+        Some(entry.value.clone())
+    }
+
+    fn insert(&mut self, key: &[u8], value: Arc<T>, weight: usize) {
+        if weight > self.budget {
+            return;
+        }
+        if let Some(old) = self.entries.remove(key) {
+            self.recency.remove(&old.tick);
+            self.weight -= old.weight;
+        }
+        while self.weight + weight > self.budget {
+            let (_, evicted) = self.recency.pop_first().unwrap();
+            self.weight -= self.entries.remove(&evicted).unwrap().weight;
+        }
+        let tick = self.next_tick;
+        self.next_tick += 1;
+        self.entries.insert(
+            key.to_vec(),
+            LruEntry {
+                value,
+                weight,
+                tick,
+            },
+        );
+        self.recency.insert(tick, key.to_vec());
+        self.weight += weight;
+    }
+}
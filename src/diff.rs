@@ -0,0 +1,119 @@
+use crate::Cache;
+
+use fst::Streamer;
+
+/// A key whose value differs between two versions of a cache, yielded by [`diff`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DiffEntry<'a> {
+    /// The key only has a value in the new cache.
+    Added(&'a [u8]),
+    /// The key only has a value in the old cache.
+    Removed(&'a [u8]),
+    /// The key has different values in the old and new cache.
+    Changed(&'a [u8]),
+}
+
+impl<'a> DiffEntry<'a> {
+    pub fn key(&self) -> &'a [u8] {
+        match *self {
+            Self::Added(key) | Self::Removed(key) | Self::Changed(key) => key,
+        }
+    }
+}
+
+/// Returns a streaming iterator over the keys that were added, removed or changed between `old` and `new`, in key order.
+///
+/// Both indexes are walked in lockstep, so only constant memory is required. A tombstone counts as a missing key. Values
+/// are only read when a key is in both caches, and not even then if the length tables show that their lengths differ.
+///
+/// ```
+/// # use mmap_cache::{Cache, Error};
+/// # fn example() -> Result<(), Error> {
+/// use mmap_cache::fst::Streamer;
+/// use mmap_cache::{diff, DiffEntry};
+///
+/// let old = Cache::from_sorted_iter([("apple", "red"), ("peach", "pink")])?;
+/// let new = Cache::from_sorted_iter([("apple", "green"), ("plumb", "purple")])?;
+///
+/// let mut changes = diff(&old, &new);
+/// assert_eq!(changes.next(), Some(DiffEntry::Changed(&b"apple"[..])));
+/// assert_eq!(changes.next(), Some(DiffEntry::Removed(&b"peach"[..])));
+/// assert_eq!(changes.next(), Some(DiffEntry::Added(&b"plumb"[..])));
+/// assert_eq!(changes.next(), None);
+/// # Ok(())
+/// # }
+/// # example().unwrap();
+/// ```
+pub fn diff<'c, DK, DV>(old: &'c Cache<DK, DV>, new: &'c Cache<DK, DV>) -> DiffStream<'c, DK, DV>
+where
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    DiffStream {
+        caches: [old, new],
+        union: fst::map::OpBuilder::new()
+            .add(old.index())
+            .add(new.index())
+            .union(),
+        key: Vec::new(),
+    }
+}
+
+/// A streaming iterator over the differences between two caches, returned by [`diff`].
+pub struct DiffStream<'c, DK, DV> {
+    caches: [&'c Cache<DK, DV>; 2],
+    union: fst::map::Union<'c>,
+    key: Vec<u8>,
+}
+
+impl<'a, 'c: 'a, DK, DV> Streamer<'a> for DiffStream<'c, DK, DV>
+where
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    type Item = DiffEntry<'a>;
+
+    fn next(&'a mut self) -> Option<Self::Item> {
+        let change = loop {
+            let (key, sources) = self.union.next()?;
+            // The offset of the value in each cache, if the key has one.
+            let mut offsets = [None; 2];
+            for source in sources {
+                if !self.caches[source.index].is_tombstone_at(source.value) {
+                    offsets[source.index] = Some(source.value);
+                }
+            }
+            let change = match offsets {
+                [None, None] => None,
+                [None, Some(_)] => Some(Change::Added),
+                [Some(_), None] => Some(Change::Removed),
+                [Some(old), Some(new)] => {
+                    let [old_cache, new_cache] = self.caches;
+                    let lengths_differ = matches!(
+                        (old_cache.recorded_len(old), new_cache.recorded_len(new)),
+                        (Some(old_len), Some(new_len)) if old_len != new_len
+                    );
+                    (lengths_differ
+                        || old_cache.resolve_value(key, old) != new_cache.resolve_value(key, new))
+                    .then_some(Change::Changed)
+                }
+            };
+            if let Some(change) = change {
+                self.key.clear();
+                self.key.extend_from_slice(key);
+                break change;
+            }
+        };
+        Some(match change {
+            Change::Added => DiffEntry::Added(&self.key),
+            Change::Removed => DiffEntry::Removed(&self.key),
+            Change::Changed => DiffEntry::Changed(&self.key),
+        })
+    }
+}
+
+enum Change {
+    Added,
+    Removed,
+    Changed,
+}
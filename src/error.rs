@@ -1,4 +1,5 @@
 use std::io;
+use std::path::{Path, PathBuf};
 
 use thiserror::Error;
 
@@ -8,4 +9,115 @@ pub enum Error {
     Fst(#[from] fst::Error),
     #[error(transparent)]
     IO(#[from] io::Error),
+    #[error("invalid cache file: {0}")]
+    InvalidFormat(&'static str),
+    #[error("{section} checksum mismatch: expected {expected:#010x}, found {actual:#010x}")]
+    ChecksumMismatch {
+        section: &'static str,
+        expected: u32,
+        actual: u32,
+    },
+    #[error("duplicate key {key:?}")]
+    DuplicateKey { key: Vec<u8> },
+    #[error(
+        "entry {index} has key {key:?}, which doesn't sort after the previous key {previous:?}"
+    )]
+    KeyOrder {
+        key: Vec<u8>,
+        previous: Vec<u8>,
+        index: u64,
+    },
+    #[error("version {version} of key {key:?} doesn't follow the previous version {previous}")]
+    VersionOrder {
+        key: Vec<u8>,
+        version: u64,
+        previous: u64,
+    },
+    #[error("value at offset {offset} is not aligned to {alignment} bytes")]
+    Misaligned { offset: u64, alignment: usize },
+    #[error("value at offset {offset} has {actual} bytes, but {expected} were expected")]
+    ValueSize {
+        offset: u64,
+        expected: u64,
+        actual: u64,
+    },
+    #[error("{len} bytes at offset {offset} exceed the {available} available value bytes")]
+    OutOfBounds {
+        offset: u64,
+        len: u64,
+        available: u64,
+    },
+    #[error("value schema mismatch: expected {expected}, found {found}")]
+    SchemaMismatch { expected: String, found: String },
+    #[error("key of {actual} bytes doesn't fit in {capacity} bytes")]
+    KeyTooLong { actual: usize, capacity: usize },
+    #[error("{0} is not supported")]
+    Unsupported(&'static str),
+    #[error("the operation was cancelled")]
+    Cancelled,
+    #[error("failed to decode value: {0}")]
+    Decode(Box<dyn std::error::Error + Send + Sync>),
+    /// `source` happened while performing `operation` (e.g. "map" or "create") on the file at `path`.
+    #[error("failed to {operation} {}: {source}", path.display())]
+    Path {
+        operation: &'static str,
+        path: PathBuf,
+        source: Box<Error>,
+    },
+}
+
+impl Error {
+    /// The underlying error, without the context added by [`Error::Path`].
+    ///
+    /// ```
+    /// # #[cfg(feature = "mmap")]
+    /// # {
+    /// # use mmap_cache::{Error, MmapCache};
+    /// let error = unsafe { MmapCache::map_path("/tmp/mmap_cache_missing_file") }.err().unwrap();
+    /// assert_eq!(error.path(), Some("/tmp/mmap_cache_missing_file".as_ref()));
+    /// assert!(matches!(error.root_cause(), Error::IO(e) if e.kind() == std::io::ErrorKind::NotFound));
+    /// # }
+    /// ```
+    pub fn root_cause(&self) -> &Error {
+        match self {
+            Self::Path { source, .. } => source.root_cause(),
+            error => error,
+        }
+    }
+
+    /// The path of the file that the error is about, if known.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::Path { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn at_path(self, operation: &'static str, path: impl AsRef<Path>) -> Self {
+        Self::Path {
+            operation,
+            path: path.as_ref().to_owned(),
+            source: Box::new(self),
+        }
+    }
+
+    /// Adds the path of the file that a failure to read a cache from `index_path` and `value_path` is most likely about.
+    pub(crate) fn at_cache_paths(self, index_path: &Path, value_path: &Path) -> Self {
+        let path = match self {
+            Self::Fst(_) => index_path,
+            _ => value_path,
+        };
+        self.at_path("read", path)
+    }
+}
+
+/// Adds the file that an operation failed on to its error.
+pub(crate) trait PathContext<T> {
+    fn at_path(self, operation: &'static str, path: impl AsRef<Path>) -> Result<T, Error>;
+}
+
+impl<T, E: Into<Error>> PathContext<T> for Result<T, E> {
+    fn at_path(self, operation: &'static str, path: impl AsRef<Path>) -> Result<T, Error> {
+        self.map_err(|e| e.into().at_path(operation, path))
+    }
 }
@@ -0,0 +1,135 @@
+use crate::{format, Cache};
+
+use fst::{IntoStreamer, Streamer};
+use std::ops::RangeBounds;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+impl<DK, DV> Cache<DK, DV>
+where
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    /// Returns when the entry for `key` expires, if it was inserted with
+    /// [`FileBuilder::insert_with_expiry`](crate::FileBuilder::insert_with_expiry).
+    ///
+    /// This only reports the expiry; [`get_value`](Self::get_value) still returns expired values. Use an
+    /// [`ExpiringCache`] to treat them as missing.
+    pub fn expiry(&self, key: &[u8]) -> Option<SystemTime> {
+        self.expiry_at(self.find_value_offset(key)?)
+    }
+
+    pub(crate) fn expiry_at(&self, offset: u64) -> Option<SystemTime> {
+        let table = self.section_bytes(format::SECTION_EXPIRY)?;
+        let seconds = format::lookup_length(table, offset)?;
+        Some(UNIX_EPOCH + Duration::from_secs(seconds))
+    }
+
+    pub(crate) fn is_expired_at(&self, offset: u64, now: SystemTime) -> bool {
+        self.expiry_at(offset)
+            .is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+/// A [`Cache`] that treats expired entries (see [`FileBuilder::insert_with_expiry`](crate::FileBuilder::insert_with_expiry))
+/// like missing keys.
+///
+/// By default, entries are checked against the system clock on every read. Use [`at_time`](Self::at_time) to read the
+/// cache as of a fixed time instead, e.g. for reproducible results.
+pub struct ExpiringCache<DK, DV> {
+    cache: Cache<DK, DV>,
+    now: Option<SystemTime>,
+}
+
+impl<DK, DV> ExpiringCache<DK, DV>
+where
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    pub fn new(cache: Cache<DK, DV>) -> Self {
+        Self { cache, now: None }
+    }
+
+    /// Treats the entries that expire at or before `now` as expired, regardless of the system clock.
+    pub fn at_time(mut self, now: SystemTime) -> Self {
+        self.now = Some(now);
+        self
+    }
+
+    /// Access the underlying [`Cache`], which includes expired entries.
+    pub fn cache(&self) -> &Cache<DK, DV> {
+        &self.cache
+    }
+
+    pub fn into_cache(self) -> Cache<DK, DV> {
+        self.cache
+    }
+
+    fn now(&self) -> SystemTime {
+        self.now.unwrap_or_else(SystemTime::now)
+    }
+
+    /// Returns the byte offset of the value for `key`, unless it's missing or expired.
+    pub fn get_value_offset(&self, key: &[u8]) -> Option<u64> {
+        let offset = self.cache.get_value_offset(key)?;
+        (!self.cache.is_expired_at(offset, self.now())).then_some(offset)
+    }
+
+    /// Returns the bytes of the value for `key`, unless it's missing, deleted or expired.
+    pub fn get_value(&self, key: &[u8]) -> Option<&[u8]> {
+        let offset = self.get_value_offset(key)?;
+        self.cache.resolve_value(key, offset)
+    }
+
+    /// Returns `true` if `key` exists and hasn't expired. Like [`Cache::contains_key`], this includes tombstones.
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.cache
+            .find_value_offset(key)
+            .is_some_and(|offset| !self.cache.is_expired_at(offset, self.now()))
+    }
+
+    /// Returns a streaming iterator over the (key, value) pairs in `key_range` that haven't expired.
+    ///
+    /// Tombstones are skipped. Expiry is checked against the time when the stream was created.
+    pub fn range<K, R>(&self, key_range: R) -> ExpiringStream<'_, DK, DV>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        ExpiringStream {
+            cache: &self.cache,
+            stream: self.cache.range(key_range).into_stream(),
+            now: self.now(),
+            key: Vec::new(),
+        }
+    }
+}
+
+/// A streaming iterator over (key, value bytes) pairs, returned by [`ExpiringCache::range`].
+///
+/// If a value can't be resolved (e.g. the length table is missing an entry), it is yielded as an empty slice.
+pub struct ExpiringStream<'c, DK, DV> {
+    cache: &'c Cache<DK, DV>,
+    stream: fst::map::Stream<'c>,
+    now: SystemTime,
+    key: Vec<u8>,
+}
+
+impl<'a, 'c: 'a, DK, DV> Streamer<'a> for ExpiringStream<'c, DK, DV>
+where
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    type Item = (&'a [u8], &'c [u8]);
+
+    fn next(&'a mut self) -> Option<Self::Item> {
+        let value = loop {
+            let (key, offset) = self.stream.next()?;
+            if !self.cache.is_tombstone_at(offset) && !self.cache.is_expired_at(offset, self.now) {
+                self.key.clear();
+                self.key.extend_from_slice(key);
+                break self.cache.resolve_value(key, offset).unwrap_or(&[]);
+            }
+        };
+        Some((&self.key, value))
+    }
+}
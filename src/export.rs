@@ -0,0 +1,50 @@
+use crate::temp::TempFile;
+use crate::{Cache, Error};
+
+use std::io::Write;
+use std::path::Path;
+
+impl<DK, DV> Cache<DK, DV>
+where
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    /// Copies the index and value file of this cache to new files at `index_path` and `value_path`, which can be mapped
+    /// with [`MmapCache::map_paths`](crate::MmapCache::map_paths).
+    ///
+    /// The copy is taken from the bytes this cache has mapped, so it's consistent even if the original files are replaced
+    /// while it runs. This makes it useful for backing up a cache that's about to be replaced. Each output file is written
+    /// to a temporary file beside it, synced to storage, and then renamed into place, so existing files are only replaced
+    /// by complete copies.
+    ///
+    /// The value file of a single-file container is a complete value file, so containers are exported as a pair of files.
+    pub fn export_to(
+        &self,
+        index_path: impl AsRef<Path>,
+        value_path: impl AsRef<Path>,
+    ) -> Result<(), Error> {
+        let index_file = write_synced(&index_path, self.index().as_fst().as_bytes())?;
+        let value_file = write_synced(&value_path, self.raw_value_bytes().as_ref())?;
+        index_file.persist(index_path)?;
+        value_file.persist(value_path)?;
+        Ok(())
+    }
+
+    /// Like [`export_to`](Self::export_to), but first checks the cache with [`verify`](Self::verify), so a corrupt cache
+    /// is never exported.
+    pub fn export_verified_to(
+        &self,
+        index_path: impl AsRef<Path>,
+        value_path: impl AsRef<Path>,
+    ) -> Result<(), Error> {
+        self.verify()?;
+        self.export_to(index_path, value_path)
+    }
+}
+
+fn write_synced(path: impl AsRef<Path>, bytes: &[u8]) -> Result<TempFile, Error> {
+    let mut file = TempFile::new_beside(path)?;
+    file.write_all(bytes)?;
+    file.try_clone_file()?.sync_all()?;
+    Ok(file)
+}
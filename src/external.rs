@@ -0,0 +1,171 @@
+use crate::error::PathContext;
+use crate::temp::TempFile;
+use crate::{CancellationToken, Error, FileBuilder, UnsortedBuilder};
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Serializes key-value pairs that arrive in any order, using only a bounded amount of memory.
+///
+/// Entries are buffered in memory until they exceed the run size, at which point they are sorted and spilled to a temporary
+/// "run" file. When finishing, all runs are merged in key order into a [`FileBuilder`]. Like [`FileBuilder`], duplicate keys
+/// are not supported.
+///
+/// If all entries fit in a single run, nothing is spilled and this behaves like an [`UnsortedBuilder`].
+pub struct ExternalSortBuilder {
+    buffer: UnsortedBuilder,
+    run_size: usize,
+    spill_dir: PathBuf,
+    runs: Vec<TempFile>,
+    cancellation: Option<CancellationToken>,
+}
+
+impl ExternalSortBuilder {
+    /// Creates a builder that spills a sorted run whenever the buffered entries use more than `run_size` bytes of memory.
+    ///
+    /// Runs are spilled to [`std::env::temp_dir`].
+    pub fn new(run_size: usize) -> Self {
+        Self::from_buffer(UnsortedBuilder::new(), run_size)
+    }
+
+    /// Continues from the entries already in `buffer`.
+    pub(crate) fn from_buffer(buffer: UnsortedBuilder, run_size: usize) -> Self {
+        Self {
+            buffer,
+            run_size,
+            spill_dir: std::env::temp_dir(),
+            runs: Vec::new(),
+            cancellation: None,
+        }
+    }
+
+    /// Spills runs to files in `dir` instead of [`std::env::temp_dir`].
+    pub fn with_spill_dir(mut self, dir: impl AsRef<Path>) -> Self {
+        self.spill_dir = dir.as_ref().to_owned();
+        self
+    }
+
+    /// Fails [`insert`](Self::insert) and [`finish`](Self::finish) with [`Error::Cancelled`] once `token` is cancelled. The
+    /// spilled runs are removed when the builder is dropped.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    fn check_cancelled(&self) -> Result<(), Error> {
+        self.cancellation
+            .as_ref()
+            .map_or(Ok(()), CancellationToken::check)
+    }
+
+    /// The number of runs spilled so far.
+    pub fn num_runs(&self) -> usize {
+        self.runs.len()
+    }
+
+    /// Buffers a copy of `key` and `value`, spilling a sorted run if the buffer is full.
+    pub fn insert(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        self.check_cancelled()?;
+        self.buffer.insert(key, value);
+        if self.buffer.memory_usage() > self.run_size {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    fn spill(&mut self) -> Result<(), Error> {
+        let run =
+            TempFile::new_in(&self.spill_dir).at_path("create a run file in", &self.spill_dir)?;
+        let mut writer = io::BufWriter::new(run);
+        for (key, value) in self.buffer.sorted() {
+            write_record(&mut writer, key, value)?;
+        }
+        let mut run = writer.into_inner().map_err(|e| e.into_error())?;
+        run.seek(SeekFrom::Start(0))?;
+        self.runs.push(run);
+        self.buffer.clear();
+        Ok(())
+    }
+
+    /// Merges all runs in key order and serializes them with `builder`, then finishes `builder`.
+    pub fn finish(mut self, mut builder: FileBuilder) -> Result<(), Error> {
+        self.check_cancelled()?;
+        if self.runs.is_empty() {
+            return self.buffer.finish(builder);
+        }
+        if !self.buffer.is_empty() {
+            self.spill()?;
+        }
+
+        let mut readers = Vec::with_capacity(self.runs.len());
+        let mut heap = BinaryHeap::with_capacity(self.runs.len());
+        for (i, run) in self.runs.into_iter().enumerate() {
+            let mut reader = RunReader::new(run);
+            if reader.advance()? {
+                heap.push(Reverse((std::mem::take(&mut reader.key), i)));
+            }
+            readers.push(reader);
+        }
+        while let Some(Reverse((key, i))) = heap.pop() {
+            if let Some(token) = &self.cancellation {
+                token.check()?;
+            }
+            let reader = &mut readers[i];
+            builder.insert(&key, &reader.value)?;
+            if reader.advance()? {
+                heap.push(Reverse((std::mem::take(&mut reader.key), i)));
+            }
+        }
+        builder.finish()
+    }
+}
+
+// Runs are sequences of `(key_len: u64, value_len: u64, key, value)` records.
+
+fn write_record(writer: &mut impl Write, key: &[u8], value: &[u8]) -> io::Result<()> {
+    writer.write_all(&(key.len() as u64).to_le_bytes())?;
+    writer.write_all(&(value.len() as u64).to_le_bytes())?;
+    writer.write_all(key)?;
+    writer.write_all(value)
+}
+
+struct RunReader {
+    reader: io::BufReader<TempFile>,
+    key: Vec<u8>,
+    value: Vec<u8>,
+}
+
+impl RunReader {
+    fn new(run: TempFile) -> Self {
+        Self {
+            reader: io::BufReader::new(run),
+            key: Vec::new(),
+            value: Vec::new(),
+        }
+    }
+
+    /// Reads the next record into `key` and `value`, returning `false` at the end of the run.
+    fn advance(&mut self) -> io::Result<bool> {
+        if self.reader.fill_buf()?.is_empty() {
+            return Ok(false);
+        }
+        let mut lens = [0; 16];
+        self.reader.read_exact(&mut lens)?;
+        let key_len = u64::from_le_bytes(lens[..8].try_into().unwrap());
+        let value_len = u64::from_le_bytes(lens[8..].try_into().unwrap());
+        read_exact_vec(&mut self.reader, &mut self.key, key_len)?;
+        read_exact_vec(&mut self.reader, &mut self.value, value_len)?;
+        Ok(true)
+    }
+}
+
+fn read_exact_vec(reader: &mut impl Read, buf: &mut Vec<u8>, len: u64) -> io::Result<()> {
+    buf.clear();
+    let n = reader.take(len).read_to_end(buf)?;
+    if (n as u64) < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(())
+}
@@ -0,0 +1,535 @@
+use crate::Error;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// The value file is laid out as:
+//
+// [values][sections][section directory][footer]
+//
+// The footer is always the last `FOOTER_LEN` bytes of the file. It is preceded by `section_count` directory entries of
+// `(kind, offset, len)`, each describing an auxiliary section stored between the values and the directory. Files without
+// the footer magic are treated as raw value bytes with no auxiliary sections.
+//
+// All integers are little-endian.
+
+pub(crate) const MAGIC: [u8; 8] = *b"MMAPCACH";
+pub(crate) const VERSION: u32 = 1;
+
+const FOOTER_LEN: usize = 24;
+const SECTION_ENTRY_LEN: usize = 24;
+
+/// Sorted table of `(offset: u64, len: u64)` pairs, one per committed value. Tombstones have a length of `TOMBSTONE_LEN`.
+pub(crate) const SECTION_LENGTHS: u64 = 1;
+
+pub(crate) const TOMBSTONE_LEN: u64 = u64::MAX;
+
+pub(crate) const LENGTH_ENTRY_LEN: usize = 16;
+
+/// `(values_crc: u32, index_crc: u32)`, where `values_crc` covers every byte of the value file before this section.
+pub(crate) const SECTION_CHECKSUMS: u64 = 2;
+
+const CHECKSUMS_LEN: usize = 8;
+
+/// `(interval: u64, count: u64, key_ends: [u64; count], key bytes)`, where sample `i` is the key with rank `i * interval`,
+/// stored at `key bytes[key_ends[i - 1]..key_ends[i]]`.
+pub(crate) const SECTION_RANK_SAMPLES: u64 = 3;
+
+/// Every key whose rank is a multiple of this is sampled.
+pub(crate) const RANK_SAMPLE_INTERVAL: u64 = 1024;
+
+/// `(bucket_count: u64, buckets: [(key_hash: u64, offset: u64); bucket_count])`, an open-addressing hash table with linear
+/// probing, where `bucket_count` is a power of two. Empty buckets have an offset of `EMPTY_BUCKET`. A hash shared by several
+/// keys has a single bucket with an offset of `COLLIDING_BUCKET`, so those keys are looked up in the index instead.
+pub(crate) const SECTION_HASH_INDEX: u64 = 4;
+
+const EMPTY_BUCKET: u64 = u64::MAX;
+const COLLIDING_BUCKET: u64 = u64::MAX - 1;
+
+/// `(count: u64, entries: [(key_len: u64, value_len: u64, key bytes, value bytes); count])`, sorted by key, where every
+/// key is UTF-8.
+pub(crate) const SECTION_METADATA: u64 = 5;
+
+/// Sorted table of `(offset: u64, expires_at: u64)` pairs for the values that expire, laid out like the length table, where
+/// `expires_at` is in seconds since the Unix epoch.
+pub(crate) const SECTION_EXPIRY: u64 = 6;
+
+/// A human-readable name for the section `kind`, for diagnostics.
+pub(crate) fn section_name(kind: u64) -> &'static str {
+    match kind {
+        SECTION_LENGTHS => "lengths",
+        SECTION_CHECKSUMS => "checksums",
+        SECTION_RANK_SAMPLES => "rank samples",
+        SECTION_HASH_INDEX => "hash index",
+        SECTION_METADATA => "metadata",
+        SECTION_EXPIRY => "expiry",
+        _ => "unknown",
+    }
+}
+
+// A single-file container is laid out as:
+//
+// [value section][index section][container footer]
+//
+// The value section is a complete value file (including its own footer) starting at offset 0, so it keeps whatever
+// alignment the builder gave the values. The container footer is `(values_len: u64, index_len: u64, version: u32,
+// reserved: u32, magic)`.
+
+pub(crate) const CONTAINER_MAGIC: [u8; 8] = *b"MMAPCONT";
+
+const CONTAINER_FOOTER_LEN: usize = 32;
+
+/// An auxiliary section of the value file, addressed by absolute byte offset.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct Section {
+    pub kind: u64,
+    pub offset: u64,
+    pub len: u64,
+}
+
+/// The parsed layout of a value file.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ValueLayout {
+    pub values_len: usize,
+    pub sections: Vec<Section>,
+}
+
+impl ValueLayout {
+    /// Parses the footer of `bytes`, or treats all of `bytes` as raw values if there is no footer.
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        Self::parse_tail(bytes, bytes.len())
+    }
+
+    /// Reads the footer and section directory from the end of a value file that is `file_len` bytes long, filling buffers
+    /// at absolute offsets with `read_at`.
+    pub fn read_with(
+        file_len: u64,
+        mut read_at: impl FnMut(&mut [u8], u64) -> Result<(), Error>,
+    ) -> Result<Self, Error> {
+        let file_len = usize::try_from(file_len)
+            .map_err(|_| Error::InvalidFormat("value file is too large"))?;
+        let footer_len = FOOTER_LEN.min(file_len);
+        let mut footer = vec![0; footer_len];
+        read_at(&mut footer, (file_len - footer_len) as u64)?;
+        if footer.len() < FOOTER_LEN || footer[FOOTER_LEN - MAGIC.len()..] != MAGIC {
+            return Self::parse_tail(&footer, file_len);
+        }
+        let tail_len = (read_u32(&footer, 8) as usize)
+            .checked_mul(SECTION_ENTRY_LEN)
+            .and_then(|len| len.checked_add(FOOTER_LEN))
+            .filter(|&len| len <= file_len)
+            .ok_or(Error::InvalidFormat("section directory out of bounds"))?;
+        let mut tail = vec![0; tail_len];
+        read_at(&mut tail, (file_len - tail_len) as u64)?;
+        Self::parse_tail(&tail, file_len)
+    }
+
+    /// Parses the last `tail.len()` bytes of a value file that is `file_len` bytes long.
+    fn parse_tail(tail: &[u8], file_len: usize) -> Result<Self, Error> {
+        if tail.len() < FOOTER_LEN || tail[tail.len() - MAGIC.len()..] != MAGIC {
+            return Ok(Self {
+                values_len: file_len,
+                sections: Vec::new(),
+            });
+        }
+
+        let footer = &tail[tail.len() - FOOTER_LEN..];
+        let values_len = read_u64(footer, 0);
+        let section_count = read_u32(footer, 8) as usize;
+        let version = read_u32(footer, 12);
+        if version != VERSION {
+            return Err(Error::InvalidFormat("unsupported format version"));
+        }
+
+        let directory_len = section_count
+            .checked_mul(SECTION_ENTRY_LEN)
+            .ok_or(Error::InvalidFormat("section directory too large"))?;
+        let directory_end = tail.len() - FOOTER_LEN;
+        let directory_start = directory_end
+            .checked_sub(directory_len)
+            .ok_or(Error::InvalidFormat("section directory out of bounds"))?;
+        // Absolute offset of the directory within the file.
+        let directory_offset = file_len - tail.len() + directory_start;
+        let values_len = usize::try_from(values_len)
+            .ok()
+            .filter(|&l| l <= directory_offset)
+            .ok_or(Error::InvalidFormat("values out of bounds"))?;
+
+        let directory = &tail[directory_start..directory_end];
+        let mut sections = Vec::with_capacity(section_count);
+        for entry in directory.chunks_exact(SECTION_ENTRY_LEN) {
+            let section = Section {
+                kind: read_u64(entry, 0),
+                offset: read_u64(entry, 8),
+                len: read_u64(entry, 16),
+            };
+            let end = section.offset.checked_add(section.len);
+            if section.offset < values_len as u64 || end.is_none_or(|e| e > directory_offset as u64)
+            {
+                return Err(Error::InvalidFormat("section out of bounds"));
+            }
+            sections.push(section);
+        }
+
+        Ok(Self {
+            values_len,
+            sections,
+        })
+    }
+
+    /// The byte range of the first section of `kind`, if any.
+    pub fn section(&self, kind: u64) -> Option<Range<usize>> {
+        self.sections
+            .iter()
+            .find(|s| s.kind == kind)
+            .map(|s| s.offset as usize..(s.offset + s.len) as usize)
+    }
+}
+
+/// The byte ranges of the value and index sections within a single-file container.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct ContainerLayout {
+    pub values: Range<u64>,
+    pub index: Range<u64>,
+}
+
+impl ContainerLayout {
+    /// Reads the container footer at the end of `file`.
+    pub fn read(mut file: &fs::File) -> Result<Self, Error> {
+        let file_len = file.metadata()?.len();
+        if file_len < CONTAINER_FOOTER_LEN as u64 {
+            return Err(Error::InvalidFormat("container footer is missing"));
+        }
+        let mut footer = [0; CONTAINER_FOOTER_LEN];
+        file.seek(SeekFrom::Start(file_len - CONTAINER_FOOTER_LEN as u64))?;
+        file.read_exact(&mut footer)?;
+        Self::parse_footer(&footer, file_len)
+    }
+
+    /// Parses the container footer at the end of `bytes`.
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        let footer = bytes
+            .len()
+            .checked_sub(CONTAINER_FOOTER_LEN)
+            .map(|start| bytes[start..].try_into().unwrap())
+            .ok_or(Error::InvalidFormat("container footer is missing"))?;
+        Self::parse_footer(footer, bytes.len() as u64)
+    }
+
+    fn parse_footer(footer: &[u8; CONTAINER_FOOTER_LEN], file_len: u64) -> Result<Self, Error> {
+        if footer[CONTAINER_FOOTER_LEN - CONTAINER_MAGIC.len()..] != CONTAINER_MAGIC {
+            return Err(Error::InvalidFormat("not a container file"));
+        }
+        if read_u32(footer, 16) != VERSION {
+            return Err(Error::InvalidFormat("unsupported format version"));
+        }
+        let values_len = read_u64(footer, 0);
+        let index_len = read_u64(footer, 8);
+        let index_end = values_len
+            .checked_add(index_len)
+            .filter(|&end| end <= file_len - CONTAINER_FOOTER_LEN as u64)
+            .ok_or(Error::InvalidFormat("container sections out of bounds"))?;
+        Ok(Self {
+            values: 0..values_len,
+            index: values_len..index_end,
+        })
+    }
+}
+
+pub(crate) fn write_container_footer(
+    writer: &mut impl io::Write,
+    values_len: u64,
+    index_len: u64,
+) -> io::Result<()> {
+    writer.write_all(&values_len.to_le_bytes())?;
+    writer.write_all(&index_len.to_le_bytes())?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&0u32.to_le_bytes())?;
+    writer.write_all(&CONTAINER_MAGIC)
+}
+
+/// Writes the section directory and footer. Must be called after all values and sections have been written.
+///
+/// Returns the number of bytes written.
+pub(crate) fn write_footer(
+    writer: &mut impl io::Write,
+    values_len: u64,
+    sections: &[Section],
+) -> io::Result<u64> {
+    for section in sections {
+        writer.write_all(&section.kind.to_le_bytes())?;
+        writer.write_all(&section.offset.to_le_bytes())?;
+        writer.write_all(&section.len.to_le_bytes())?;
+    }
+    writer.write_all(&values_len.to_le_bytes())?;
+    writer.write_all(&u32::try_from(sections.len()).unwrap().to_le_bytes())?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&MAGIC)?;
+    Ok((sections.len() * SECTION_ENTRY_LEN + FOOTER_LEN) as u64)
+}
+
+/// Returns the number of bytes written.
+pub(crate) fn write_checksums(
+    writer: &mut impl io::Write,
+    values_crc: u32,
+    index_crc: u32,
+) -> io::Result<u64> {
+    writer.write_all(&values_crc.to_le_bytes())?;
+    writer.write_all(&index_crc.to_le_bytes())?;
+    Ok(CHECKSUMS_LEN as u64)
+}
+
+/// Reads `(values_crc, index_crc)` from a checksum section.
+pub(crate) fn read_checksums(section: &[u8]) -> Result<(u32, u32), Error> {
+    if section.len() < CHECKSUMS_LEN {
+        return Err(Error::InvalidFormat("checksum section is truncated"));
+    }
+    Ok((read_u32(section, 0), read_u32(section, 4)))
+}
+
+/// Returns the number of bytes written.
+pub(crate) fn write_rank_samples(
+    writer: &mut impl io::Write,
+    interval: u64,
+    key_ends: &[u64],
+    keys: &[u8],
+) -> io::Result<u64> {
+    writer.write_all(&interval.to_le_bytes())?;
+    writer.write_all(&(key_ends.len() as u64).to_le_bytes())?;
+    for end in key_ends {
+        writer.write_all(&end.to_le_bytes())?;
+    }
+    writer.write_all(keys)?;
+    Ok((16 + 8 * key_ends.len() + keys.len()) as u64)
+}
+
+/// A parsed rank sample section.
+pub(crate) struct RankSamples<'a> {
+    pub interval: u64,
+    key_ends: &'a [u8],
+    keys: &'a [u8],
+}
+
+impl<'a> RankSamples<'a> {
+    pub fn parse(section: &'a [u8]) -> Result<Self, Error> {
+        const TRUNCATED: Error = Error::InvalidFormat("rank sample section is truncated");
+        if section.len() < 16 {
+            return Err(TRUNCATED);
+        }
+        let interval = read_u64(section, 0);
+        let ends_len = usize::try_from(read_u64(section, 8))
+            .ok()
+            .and_then(|count| count.checked_mul(8))
+            .filter(|&len| len <= section.len() - 16)
+            .ok_or(TRUNCATED)?;
+        if interval == 0 {
+            return Err(Error::InvalidFormat("rank sample interval is zero"));
+        }
+        let (key_ends, keys) = section[16..].split_at(ends_len);
+        Ok(Self {
+            interval,
+            key_ends,
+            keys,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.key_ends.len() / 8
+    }
+
+    pub fn key(&self, i: usize) -> Result<&'a [u8], Error> {
+        let start = if i == 0 {
+            0
+        } else {
+            read_u64(self.key_ends, 8 * (i - 1))
+        };
+        let end = read_u64(self.key_ends, 8 * i);
+        usize::try_from(start)
+            .ok()
+            .zip(usize::try_from(end).ok())
+            .and_then(|(start, end)| self.keys.get(start..end))
+            .ok_or(Error::InvalidFormat("rank sample out of bounds"))
+    }
+}
+
+/// A stable 64-bit hash of `key` (FNV-1a, followed by a finalizer so that the low bits are well mixed).
+pub(crate) fn key_hash(key: &[u8]) -> u64 {
+    let mut h = 0xcbf2_9ce4_8422_2325_u64;
+    for &b in key {
+        h = (h ^ b as u64).wrapping_mul(0x100_0000_01b3);
+    }
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    h ^ (h >> 33)
+}
+
+/// Writes a hash table of `(key_hash, offset)` entries, with at most 50% of buckets occupied.
+///
+/// Returns the number of bytes written.
+pub(crate) fn write_hash_index(
+    writer: &mut impl io::Write,
+    entries: &[(u64, u64)],
+) -> io::Result<u64> {
+    let bucket_count = (2 * entries.len()).next_power_of_two();
+    let mask = bucket_count - 1;
+    let mut buckets = vec![(0, EMPTY_BUCKET); bucket_count];
+    for &(hash, offset) in entries {
+        let mut i = hash as usize & mask;
+        while buckets[i].1 != EMPTY_BUCKET && buckets[i].0 != hash {
+            i = (i + 1) & mask;
+        }
+        buckets[i] = if buckets[i].1 == EMPTY_BUCKET {
+            (hash, offset)
+        } else {
+            (hash, COLLIDING_BUCKET)
+        };
+    }
+    writer.write_all(&(bucket_count as u64).to_le_bytes())?;
+    for (hash, offset) in buckets {
+        writer.write_all(&hash.to_le_bytes())?;
+        writer.write_all(&offset.to_le_bytes())?;
+    }
+    Ok(8 + 16 * bucket_count as u64)
+}
+
+/// The result of probing a hash index for a key hash.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum HashLookup {
+    /// No key has this hash.
+    Missing,
+    /// Exactly one key has this hash, and its value is at this offset.
+    Found(u64),
+    /// Several keys have this hash, so the index must be consulted.
+    Colliding,
+}
+
+/// Returns what the table written by [`write_hash_index`] records for `hash`.
+pub(crate) fn lookup_hash_index(table: &[u8], hash: u64) -> Result<HashLookup, Error> {
+    const CORRUPT: Error = Error::InvalidFormat("corrupt hash index");
+    if table.len() < 8 {
+        return Err(CORRUPT);
+    }
+    let bucket_count = read_u64(table, 0);
+    let buckets = &table[8..];
+    if !bucket_count.is_power_of_two() || (buckets.len() as u64) / 16 < bucket_count {
+        return Err(CORRUPT);
+    }
+    let mask = bucket_count as usize - 1;
+    let mut i = hash as usize & mask;
+    for _ in 0..bucket_count {
+        let offset = read_u64(buckets, 16 * i + 8);
+        if offset == EMPTY_BUCKET {
+            return Ok(HashLookup::Missing);
+        }
+        if read_u64(buckets, 16 * i) == hash {
+            return Ok(match offset {
+                COLLIDING_BUCKET => HashLookup::Colliding,
+                offset => HashLookup::Found(offset),
+            });
+        }
+        i = (i + 1) & mask;
+    }
+    Ok(HashLookup::Missing)
+}
+
+/// Returns the number of bytes written.
+pub(crate) fn write_metadata(
+    writer: &mut impl io::Write,
+    entries: &BTreeMap<String, Vec<u8>>,
+) -> io::Result<u64> {
+    let mut len = 8;
+    writer.write_all(&(entries.len() as u64).to_le_bytes())?;
+    for (key, value) in entries {
+        writer.write_all(&(key.len() as u64).to_le_bytes())?;
+        writer.write_all(&(value.len() as u64).to_le_bytes())?;
+        writer.write_all(key.as_bytes())?;
+        writer.write_all(value)?;
+        len += 16 + key.len() + value.len();
+    }
+    Ok(len as u64)
+}
+
+/// Parses the `(key, value)` entries written by [`write_metadata`].
+pub(crate) fn parse_metadata(section: &[u8]) -> Result<Vec<(&str, &[u8])>, Error> {
+    const CORRUPT: Error = Error::InvalidFormat("corrupt metadata section");
+    fn take<'a>(rest: &mut &'a [u8], len: u64) -> Result<&'a [u8], Error> {
+        let len = usize::try_from(len)
+            .ok()
+            .filter(|&len| len <= rest.len())
+            .ok_or(CORRUPT)?;
+        let (head, tail) = rest.split_at(len);
+        *rest = tail;
+        Ok(head)
+    }
+    let mut rest = section;
+    let count = read_u64(take(&mut rest, 8)?, 0);
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let lens = take(&mut rest, 16)?;
+        let key = take(&mut rest, read_u64(lens, 0))?;
+        let value = take(&mut rest, read_u64(lens, 8))?;
+        entries.push((std::str::from_utf8(key).map_err(|_| CORRUPT)?, value));
+    }
+    Ok(entries)
+}
+
+pub(crate) fn write_length_entry(
+    writer: &mut impl io::Write,
+    offset: u64,
+    len: u64,
+) -> io::Result<()> {
+    writer.write_all(&offset.to_le_bytes())?;
+    writer.write_all(&len.to_le_bytes())
+}
+
+/// Binary searches the length table for the value starting at `offset`.
+///
+/// This also searches the expiry table, which has the same layout.
+pub(crate) fn lookup_length(table: &[u8], offset: u64) -> Option<u64> {
+    let n = table.len() / LENGTH_ENTRY_LEN;
+    let (mut lower, mut upper) = (0, n);
+    while lower != upper {
+        let mid = (lower + upper) / 2;
+        let entry = &table[mid * LENGTH_ENTRY_LEN..];
+        match read_u64(entry, 0).cmp(&offset) {
+            std::cmp::Ordering::Less => lower = mid + 1,
+            std::cmp::Ordering::Greater => upper = mid,
+            std::cmp::Ordering::Equal => return Some(read_u64(entry, 8)),
+        }
+    }
+    None
+}
+
+/// `time` in whole seconds since the Unix epoch, or 0 if it's earlier.
+pub(crate) fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// Fills `buf` with the bytes of `file` starting at `offset`, without moving the file cursor on Unix.
+pub(crate) fn read_exact_at(file: &fs::File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::FileExt::read_exact_at(file, buf, offset)
+    }
+    #[cfg(not(unix))]
+    {
+        let mut file = file;
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(buf)
+    }
+}
+
+pub(crate) fn read_u64(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+pub(crate) fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
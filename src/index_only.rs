@@ -0,0 +1,137 @@
+use crate::cache::bound_stream;
+use crate::Error;
+
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+use std::fs;
+use std::io::{self, Write};
+use std::ops::RangeBounds;
+use std::path::Path;
+
+/// Serializes a sorted stream of keys, each with a single `u64` value, into an [`fst::Map`] alone.
+///
+/// Unlike a [`FileBuilder`](crate::FileBuilder), there is no value file; every value is stored directly as the output of
+/// its key in the index. Read the result with an [`IndexOnlyCache`].
+///
+/// ```
+/// # use mmap_cache::Error;
+/// # #[cfg(feature = "mmap")]
+/// # fn example() -> Result<(), Error> {
+/// use mmap_cache::{IndexOnlyBuilder, MmapIndexOnlyCache};
+///
+/// let mut builder = IndexOnlyBuilder::create_file("/tmp/mmap_cache_counts")?;
+/// builder.insert(b"apple", 3)?;
+/// builder.insert(b"banana", 12)?;
+/// builder.finish()?;
+///
+/// let counts = unsafe { MmapIndexOnlyCache::map_path("/tmp/mmap_cache_counts")? };
+/// assert_eq!(counts.get(b"banana"), Some(12));
+/// # Ok(())
+/// # }
+/// # #[cfg(feature = "mmap")]
+/// # example().unwrap();
+/// ```
+pub struct IndexOnlyBuilder<W = io::BufWriter<fs::File>> {
+    map_builder: fst::MapBuilder<W>,
+}
+
+impl<W: Write> IndexOnlyBuilder<W> {
+    pub fn new(index_writer: W) -> Result<Self, Error> {
+        Ok(Self {
+            map_builder: fst::MapBuilder::new(index_writer)?,
+        })
+    }
+
+    /// Inserts `key` with `value`. Keys must be inserted in order.
+    pub fn insert(&mut self, key: &[u8], value: u64) -> Result<(), Error> {
+        Ok(self.map_builder.insert(key, value)?)
+    }
+
+    /// Completes the serialization and flushes any outstanding IO.
+    pub fn finish(self) -> Result<(), Error> {
+        self.finish_into_inner().map(drop)
+    }
+
+    /// Like [`finish`](Self::finish), but returns the flushed writer.
+    pub fn finish_into_inner(self) -> Result<W, Error> {
+        let mut index_writer = self.map_builder.into_inner()?;
+        index_writer.flush()?;
+        Ok(index_writer)
+    }
+}
+
+impl IndexOnlyBuilder {
+    /// Creates a new [`IndexOnlyBuilder`] writing to the file at `path`. This always overwrites the given file.
+    pub fn create_file(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::new(io::BufWriter::new(fs::File::create(path)?))
+    }
+}
+
+/// Maps `[u8]` keys to `u64` values stored directly in an [`fst::Map`], as written by an [`IndexOnlyBuilder`].
+pub struct IndexOnlyCache<D> {
+    index: fst::Map<D>,
+}
+
+impl<D: AsRef<[u8]>> IndexOnlyCache<D> {
+    pub fn new(index_bytes: D) -> Result<Self, Error> {
+        Ok(Self {
+            index: fst::Map::new(index_bytes)?,
+        })
+    }
+
+    /// Access the underlying [`fst::Map`].
+    pub fn index(&self) -> &fst::Map<D> {
+        &self.index
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.index.contains_key(key)
+    }
+
+    /// Returns the value for `key`, if it exists.
+    pub fn get(&self, key: &[u8]) -> Option<u64> {
+        self.index.get(key)
+    }
+
+    /// Returns a streaming iterator over (key, value) pairs in `key_range`, in key order.
+    pub fn range<K, R>(&self, key_range: R) -> fst::map::StreamBuilder<'_>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        bound_stream(self.index.range(), key_range)
+    }
+}
+
+#[cfg(feature = "mmap")]
+pub type MmapIndexOnlyCache = IndexOnlyCache<Mmap>;
+
+#[cfg(feature = "mmap")]
+impl MmapIndexOnlyCache {
+    /// Maps the file at `path` to a read-only virtual memory range.
+    ///
+    /// # Safety
+    ///
+    /// See [`Mmap`].
+    pub unsafe fn map_path(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let file = fs::File::open(path)?;
+        Self::map_file(&file)
+    }
+
+    /// Maps `file` to a read-only virtual memory range.
+    ///
+    /// # Safety
+    ///
+    /// See [`Mmap`].
+    pub unsafe fn map_file(file: &fs::File) -> Result<Self, Error> {
+        Self::new(Mmap::map(file)?)
+    }
+}
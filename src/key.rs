@@ -0,0 +1,127 @@
+/// Encodes keys as byte strings whose lexicographical order matches the natural order of the keys, so that range queries
+/// over encoded keys (e.g. with [`Cache::range`](crate::Cache::range)) work as expected.
+///
+/// Integers are encoded in big-endian order, with the sign bit flipped for signed integers. Floats are ordered like
+/// [`f64::total_cmp`], so `-0.0 < 0.0` and NaNs sort after infinity (or before negative infinity, for negative NaNs).
+/// Every encoding has a fixed width, so tuples are encoded by concatenating their fields, and compare field by field.
+///
+/// ```
+/// use mmap_cache::KeyEncode;
+///
+/// assert!((-5i64).to_key_bytes() < 3i64.to_key_bytes());
+/// assert!((1u32, -0.5f64).to_key_bytes() < (1u32, 0.25f64).to_key_bytes());
+/// assert_eq!(<(u32, i64)>::from_key_bytes(&(7u32, -9i64).to_key_bytes()), Some((7, -9)));
+/// ```
+pub trait KeyEncode: Sized {
+    /// Appends the encoding of `self` to `out`.
+    fn encode_key(&self, out: &mut Vec<u8>);
+
+    /// Decodes a key from the front of `bytes`, advancing `bytes` past it.
+    fn decode_key(bytes: &mut &[u8]) -> Option<Self>;
+
+    fn to_key_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode_key(&mut out);
+        out
+    }
+
+    /// Decodes a key that must span all of `bytes`.
+    fn from_key_bytes(mut bytes: &[u8]) -> Option<Self> {
+        let key = Self::decode_key(&mut bytes)?;
+        bytes.is_empty().then_some(key)
+    }
+}
+
+fn take<const N: usize>(bytes: &mut &[u8]) -> Option<[u8; N]> {
+    let (head, rest) = bytes.split_first_chunk()?;
+    *bytes = rest;
+    Some(*head)
+}
+
+macro_rules! impl_unsigned {
+    ($($t:ty),*) => {$(
+        impl KeyEncode for $t {
+            fn encode_key(&self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_be_bytes());
+            }
+
+            fn decode_key(bytes: &mut &[u8]) -> Option<Self> {
+                take(bytes).map(<$t>::from_be_bytes)
+            }
+        }
+    )*};
+}
+
+impl_unsigned!(u8, u16, u32, u64, u128);
+
+macro_rules! impl_signed {
+    ($($t:ty => $u:ty),*) => {$(
+        impl KeyEncode for $t {
+            fn encode_key(&self, out: &mut Vec<u8>) {
+                let flipped = (*self as $u) ^ (1 << (<$u>::BITS - 1));
+                out.extend_from_slice(&flipped.to_be_bytes());
+            }
+
+            fn decode_key(bytes: &mut &[u8]) -> Option<Self> {
+                let flipped = take(bytes).map(<$u>::from_be_bytes)?;
+                Some((flipped ^ (1 << (<$u>::BITS - 1))) as $t)
+            }
+        }
+    )*};
+}
+
+impl_signed!(i8 => u8, i16 => u16, i32 => u32, i64 => u64, i128 => u128);
+
+macro_rules! impl_float {
+    ($($t:ty => $u:ty),*) => {$(
+        impl KeyEncode for $t {
+            fn encode_key(&self, out: &mut Vec<u8>) {
+                let bits = self.to_bits();
+                let sign = 1 << (<$u>::BITS - 1);
+                // Negative floats are ordered in reverse by magnitude, so flip all of their bits.
+                let ordered = if bits & sign != 0 { !bits } else { bits ^ sign };
+                out.extend_from_slice(&ordered.to_be_bytes());
+            }
+
+            fn decode_key(bytes: &mut &[u8]) -> Option<Self> {
+                let ordered = take(bytes).map(<$u>::from_be_bytes)?;
+                let sign = 1 << (<$u>::BITS - 1);
+                let bits = if ordered & sign != 0 { ordered ^ sign } else { !ordered };
+                Some(<$t>::from_bits(bits))
+            }
+        }
+    )*};
+}
+
+impl_float!(f32 => u32, f64 => u64);
+
+impl<const N: usize> KeyEncode for [u8; N] {
+    fn encode_key(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self);
+    }
+
+    fn decode_key(bytes: &mut &[u8]) -> Option<Self> {
+        take(bytes)
+    }
+}
+
+macro_rules! impl_tuple {
+    ($($name:ident),+) => {
+        impl<$($name: KeyEncode),+> KeyEncode for ($($name,)+) {
+            fn encode_key(&self, out: &mut Vec<u8>) {
+                #[allow(non_snake_case)]
+                let ($($name,)+) = self;
+                $($name.encode_key(out);)+
+            }
+
+            fn decode_key(bytes: &mut &[u8]) -> Option<Self> {
+                Some(($($name::decode_key(bytes)?,)+))
+            }
+        }
+    };
+}
+
+impl_tuple!(A);
+impl_tuple!(A, B);
+impl_tuple!(A, B, C);
+impl_tuple!(A, B, C, D);
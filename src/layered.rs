@@ -0,0 +1,131 @@
+use crate::{merge, Cache, DuplicatePolicy, Error, FileBuilder, MergeOptions};
+
+use fst::Streamer;
+use std::ops::{Range, RangeBounds};
+
+/// A stack of [`Cache`] layers that reads like a single cache, where newer layers shadow older ones.
+///
+/// This allows shipping small "delta" caches on top of a large base cache without rebuilding the base. Layers are ordered
+/// from oldest (the base, at index 0) to newest. Lookups consult the newest layer first, and a tombstone (see
+/// [`FileBuilder::insert_tombstone`](crate::FileBuilder::insert_tombstone)) hides the key in all older layers.
+pub struct LayeredCache<DK, DV> {
+    layers: Vec<Cache<DK, DV>>,
+}
+
+impl<DK, DV> LayeredCache<DK, DV>
+where
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    /// Creates a layered cache from `layers`, ordered from oldest to newest.
+    pub fn new(layers: Vec<Cache<DK, DV>>) -> Self {
+        Self { layers }
+    }
+
+    /// Adds `layer` on top of all existing layers.
+    pub fn push(&mut self, layer: Cache<DK, DV>) {
+        self.layers.push(layer);
+    }
+
+    /// The layers, ordered from oldest to newest.
+    pub fn layers(&self) -> &[Cache<DK, DV>] {
+        &self.layers
+    }
+
+    pub fn into_layers(self) -> Vec<Cache<DK, DV>> {
+        self.layers
+    }
+
+    /// Merges all layers into a single fresh cache written by `builder`, then finishes `builder`.
+    ///
+    /// Only the newest value of each key is kept, and tombstones are dropped along with the values they hide, so the output
+    /// can replace this entire stack.
+    pub fn compact(&self, builder: FileBuilder) -> Result<(), Error> {
+        self.compact_layers(0..self.layers.len(), builder)
+    }
+
+    /// Merges the contiguous `layers` into a single fresh cache written by `builder`, then finishes `builder`.
+    ///
+    /// The output can replace those layers in the stack. Only the newest value of each key is kept. Tombstones are only
+    /// dropped if `layers` includes the base layer, since otherwise they may still need to hide keys in older layers.
+    pub fn compact_layers(&self, layers: Range<usize>, builder: FileBuilder) -> Result<(), Error> {
+        let options = MergeOptions {
+            duplicates: DuplicatePolicy::KeepLast,
+            drop_tombstones: layers.start == 0,
+        };
+        merge(&self.layers[layers], options, builder)
+    }
+
+    /// Returns the index of the newest layer containing `key`, along with the byte offset of the value in that layer.
+    ///
+    /// Returns `None` if the newest layer containing `key` has a tombstone for it.
+    pub fn get_value_offset(&self, key: &[u8]) -> Option<(usize, u64)> {
+        let (i, offset) = self
+            .layers
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(i, layer)| layer.get_value_offset(key).map(|offset| (i, offset)))?;
+        (!self.layers[i].is_tombstone_at(offset)).then_some((i, offset))
+    }
+
+    /// Returns the bytes of the value for `key` in the newest layer containing `key`.
+    pub fn get_value(&self, key: &[u8]) -> Option<&[u8]> {
+        let (i, offset) = self.get_value_offset(key)?;
+        self.layers[i].resolve_value(key, offset)
+    }
+
+    /// Returns a streaming iterator over (key, value) pairs from all layers, in key order.
+    ///
+    /// Each key is yielded once, with the value from the newest layer containing it. Keys whose newest entry is a tombstone
+    /// are skipped.
+    pub fn range<K, R>(&self, key_range: R) -> LayeredStream<'_, DK, DV>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K> + Clone,
+    {
+        let union = self
+            .layers
+            .iter()
+            .fold(fst::map::OpBuilder::new(), |op, layer| {
+                op.add(layer.range(key_range.clone()))
+            })
+            .union();
+        LayeredStream {
+            layers: &self.layers,
+            union,
+            key: Vec::new(),
+        }
+    }
+}
+
+/// A streaming iterator over (key, value bytes) pairs, returned by [`LayeredCache::range`].
+///
+/// If a value can't be resolved (e.g. the length table is missing an entry), it is yielded as an empty slice.
+pub struct LayeredStream<'c, DK, DV> {
+    layers: &'c [Cache<DK, DV>],
+    union: fst::map::Union<'c>,
+    key: Vec<u8>,
+}
+
+impl<'a, 'c: 'a, DK, DV> Streamer<'a> for LayeredStream<'c, DK, DV>
+where
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    type Item = (&'a [u8], &'c [u8]);
+
+    fn next(&'a mut self) -> Option<Self::Item> {
+        let value = loop {
+            let (key, sources) = self.union.next()?;
+            let newest = sources.iter().max_by_key(|s| s.index).unwrap();
+            let layer = &self.layers[newest.index];
+            if !layer.is_tombstone_at(newest.value) {
+                self.key.clear();
+                self.key.extend_from_slice(key);
+                break layer.resolve_value(key, newest.value).unwrap_or(&[]);
+            }
+        };
+        Some((&self.key, value))
+    }
+}
@@ -13,6 +13,7 @@
 //!
 //! ```
 //! # use mmap_cache::Error;
+//! # #[cfg(feature = "mmap")]
 //! # fn example() -> Result<(), Error> {
 //! use mmap_cache::{FileBuilder, MmapCache};
 //!
@@ -28,10 +29,11 @@
 //!
 //! // Map the files into memory.
 //! let cache = unsafe { MmapCache::map_paths(INDEX_PATH, VALUES_PATH) }?;
-//! let value = unsafe { cache.get_transmuted_value(b"foo") };
+//! let value = cache.get_pod::<[u8; 3]>(b"foo")?;
 //! assert_eq!(value, Some(b"bar"));
 //! # Ok(())
 //! # }
+//! # #[cfg(feature = "mmap")]
 //! # example().unwrap();
 //! ```
 //!
@@ -40,24 +42,200 @@
 //! When using [`memmap2`] on a large file, it's likely that accessing values from the cache will cause the thread to block in
 //! the operating system scheduler while the page cache is filled from the file system. To achieve IO concurrency up to some
 //! maximum concurrency N, you could dispatch your IOs in a thread pool of N threads.
+//!
+//! ## Thread Safety
+//!
+//! A [`Cache`] is immutable once built, so it is [`Send`] and [`Sync`] whenever its storage is, as is the case for
+//! [`MmapCache`] and for in-memory buffers. All readers can share one cache by reference, e.g. from scoped threads, or
+//! through an [`Arc`](std::sync::Arc) (see [`SharedCache`]) when the threads outlive the current scope:
+//!
+//! ```
+//! # use mmap_cache::{Cache, Error};
+//! # fn example() -> Result<(), Error> {
+//! let cache = Cache::from_sorted_iter([("apple", "red"), ("peach", "pink")])?;
+//! let keys = [&b"apple"[..], b"peach", b"plumb"];
+//! let found = std::thread::scope(|scope| {
+//!     let workers: Vec<_> = keys
+//!         .chunks(2)
+//!         .map(|keys| scope.spawn(|| keys.iter().filter(|key| cache.contains_key(key)).count()))
+//!         .collect();
+//!     workers.into_iter().map(|worker| worker.join().unwrap()).sum::<usize>()
+//! });
+//! assert_eq!(found, 2);
+//! # Ok(())
+//! # }
+//! # example().unwrap();
+//! ```
+//!
+//! With the `parallel` feature, `Cache::par_get_many` and `Cache::par_range` do this splitting for you.
+//!
+//! ## Platforms Without Memory Mapping
+//!
+//! Memory mapping is behind the default `mmap` feature. Without it, e.g. on `wasm32`, a [`Cache`] can wrap in-memory
+//! buffers like `Vec<u8>` or `&[u8]`, and [`Cache::from_container_bytes`] reads a fetched single-file container.
+//!
+//! ## Command Line Tool
+//!
+//! With the `cli` feature, the `mmap-cache` binary builds caches from TSV, CSV, or JSON Lines files, e.g.
+//! `mmap-cache build data.csv --header --key id --value name --output data.cache`. For debugging existing caches, `inspect`
+//! prints a summary of a cache, `get` prints one value, `scan` prints a key range, and `verify` checks for corruption.
+//! `merge` combines several caches into one. Run `mmap-cache --help` for details.
 
+#[cfg(feature = "mmap")]
+mod advice;
+#[cfg(feature = "async")]
+mod async_cache;
+#[cfg(feature = "compression")]
+mod block;
 mod builder;
 mod cache;
+#[cfg(feature = "mmap")]
+mod cache_mut;
+mod cancel;
+mod checkpoint;
+mod checksum;
+#[cfg(feature = "mmap")]
+mod chunked;
+mod codec;
+mod compact;
+#[cfg(feature = "compression")]
+mod compression;
+mod cursor;
+mod decoded;
+mod diff;
 mod error;
+mod expiry;
+mod export;
+mod external;
+mod format;
+mod index_only;
+mod key;
+mod layered;
+#[cfg(feature = "mmap")]
+mod lsm;
+mod map;
+mod merge;
+mod metadata;
+mod multimap;
+mod observer;
+mod op;
+#[cfg(feature = "mmap")]
+mod options;
+#[cfg(feature = "parallel")]
+mod parallel;
+mod patch;
+mod pread;
+#[cfg(feature = "mmap")]
+mod reload;
+#[cfg(feature = "remote")]
+mod remote;
+#[cfg(all(feature = "mmap", unix))]
+mod residency;
+mod resident;
+mod reverse;
+mod ring;
+mod schema;
+mod segments;
+mod set;
+mod sharded;
+#[cfg(feature = "mmap")]
+mod shared;
+mod sizes;
+mod store;
+mod temp;
+mod typed;
+mod unsorted;
+mod versioned;
+mod warm;
 
+#[cfg(feature = "mmap")]
+pub use advice::*;
+#[cfg(feature = "async")]
+pub use async_cache::*;
+#[cfg(feature = "compression")]
+pub use block::*;
 pub use builder::*;
 pub use cache::*;
+#[cfg(feature = "mmap")]
+pub use cache_mut::*;
+pub use cancel::*;
+#[cfg(feature = "mmap")]
+pub use chunked::*;
+pub use codec::*;
+pub use compact::*;
+#[cfg(feature = "compression")]
+pub use compression::*;
+pub use cursor::*;
+pub use decoded::*;
+pub use diff::*;
 pub use error::*;
+pub use expiry::*;
+pub use external::*;
+pub use index_only::*;
+pub use key::*;
+pub use layered::*;
+#[cfg(feature = "mmap")]
+pub use lsm::*;
+pub use map::*;
+pub use merge::*;
+pub use metadata::*;
+pub use multimap::*;
+pub use observer::*;
+pub use op::*;
+#[cfg(feature = "mmap")]
+pub use options::*;
+#[cfg(feature = "parallel")]
+pub use parallel::*;
+pub use patch::*;
+pub use pread::*;
+#[cfg(feature = "mmap")]
+pub use reload::*;
+#[cfg(feature = "remote")]
+pub use remote::*;
+#[cfg(all(feature = "mmap", unix))]
+pub use residency::*;
+pub use resident::*;
+pub use reverse::*;
+pub use ring::*;
+pub use schema::*;
+pub use segments::*;
+pub use set::*;
+pub use sharded::*;
+#[cfg(feature = "mmap")]
+pub use shared::*;
+pub use sizes::*;
+pub use store::*;
+pub use typed::*;
+pub use unsorted::*;
+pub use versioned::*;
+pub use warm::*;
 
+pub use bytemuck;
 pub use fst;
+#[cfg(feature = "mmap")]
 pub use memmap2;
 
-#[cfg(test)]
+#[cfg(all(test, feature = "mmap"))]
 mod tests {
     use super::*;
 
     use bytemuck::cast_slice;
     use fst::{IntoStreamer, Streamer};
+    use memmap2::Mmap;
+    use std::collections::BTreeMap;
+    use std::ops::Bound;
+    use std::path::{Path, PathBuf};
+
+    macro_rules! collect_keys {
+        ($stream:expr) => {{
+            let mut stream = $stream;
+            let mut keys = Vec::new();
+            while let Some((key, _)) = stream.next() {
+                keys.push(key.to_vec());
+            }
+            keys
+        }};
+    }
 
     #[test]
     fn serialize_and_read_range() {
@@ -84,6 +262,192 @@ mod tests {
         );
     }
 
+    #[test]
+    fn serialize_and_read_range_values() {
+        let (index_path, values_path) = test_paths("serialize_and_read_range_values");
+        serialize_example_to(&index_path, &values_path);
+
+        let cache = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+        let dog: &[u8] = b"dog";
+        let mut stream = cache.range_values(dog..);
+        let mut key_values = Vec::new();
+        while let Some((key, value)) = stream.next() {
+            key_values.push((key.to_vec(), value));
+        }
+
+        let expected: Vec<_> = PAIRS[1..]
+            .iter()
+            .map(|(k, v)| (k.to_vec(), cast_slice::<i32, u8>(v)))
+            .collect();
+        assert_eq!(key_values, expected);
+    }
+
+    #[test]
+    fn prefix_scans() {
+        let cache = build_cache(
+            "prefix_scans",
+            &[
+                (b"a", b""),
+                (b"do", b""),
+                (b"dog", b""),
+                (b"doggy", b""),
+                (b"dp", b""),
+                (b"x\xff", b""),
+                (b"x\xff\xff", b""),
+            ],
+        );
+        assert_eq!(
+            collect_keys!(cache.prefix(b"dog").into_stream()),
+            [b"dog".to_vec(), b"doggy".to_vec()]
+        );
+        assert_eq!(collect_keys!(cache.prefix(b"dox").into_stream()).len(), 0);
+        assert_eq!(
+            collect_keys!(cache.prefix(b"x\xff").into_stream()),
+            [b"x\xff".to_vec(), b"x\xff\xff".to_vec()]
+        );
+        assert_eq!(collect_keys!(cache.prefix(b"").into_stream()).len(), 7);
+    }
+
+    #[test]
+    fn automaton_search() {
+        let (index_path, values_path) = test_paths("automaton_search");
+        serialize_example_to(&index_path, &values_path);
+        let cache = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+
+        let matches = collect_keys!(cache
+            .search(fst::automaton::Subsequence::new("og"))
+            .into_stream());
+        assert_eq!(
+            matches,
+            [b"dog".to_vec(), b"doggy".to_vec(), b"frog".to_vec()]
+        );
+
+        let frog: &[u8] = b"frog";
+        let mut stream = cache
+            .search_range(fst::automaton::Subsequence::new("og"), ..frog)
+            .into_stream();
+        assert_eq!(stream.next(), Some((&b"dog"[..], 12)));
+        assert_eq!(stream.next(), Some((&b"doggy"[..], 24)));
+        assert_eq!(stream.next(), None);
+    }
+
+    #[test]
+    fn reverse_ranges() {
+        let keys: [&[u8]; 7] = [b"", b"a", b"ab", b"abc", b"abd", b"b", b"ba"];
+        let pairs: Vec<(&[u8], &[u8])> = keys.iter().map(|&k| (k, &b"v"[..])).collect();
+        let cache = build_cache("reverse_ranges", &pairs);
+
+        let rev = |stream: RevStream<Mmap>| -> Vec<Vec<u8>> { collect_keys!(stream) };
+        let descending: Vec<_> = keys.iter().rev().map(|k| k.to_vec()).collect();
+        assert_eq!(rev(cache.range_rev::<&[u8], _>(..)), descending);
+
+        let mut forward = cache.range::<&[u8], _>(..).into_stream();
+        let mut backward = cache.range_rev::<&[u8], _>(..);
+        let mut offsets = Vec::new();
+        while let Some((_, offset)) = forward.next() {
+            offsets.push(offset);
+        }
+        while let Some((_, offset)) = backward.next() {
+            assert_eq!(Some(offset), offsets.pop());
+        }
+
+        let (a, ab, abd, b): (&[u8], &[u8], &[u8], &[u8]) = (b"a", b"ab", b"abd", b"b");
+        assert_eq!(
+            rev(cache.range_rev(ab..=abd)),
+            [b"abd".to_vec(), b"abc".to_vec(), b"ab".to_vec()]
+        );
+        assert_eq!(
+            rev(cache.range_rev(a..b)),
+            [
+                b"abd".to_vec(),
+                b"abc".to_vec(),
+                b"ab".to_vec(),
+                b"a".to_vec()
+            ]
+        );
+        assert_eq!(
+            rev(cache.range_rev(..=b"abb".as_slice())),
+            [b"ab".to_vec(), b"a".to_vec(), b"".to_vec()]
+        );
+        assert_eq!(
+            rev(cache.range_rev::<&[u8], _>((Bound::Excluded(a), Bound::Excluded(abd)))),
+            [b"abc".to_vec(), b"ab".to_vec()]
+        );
+        assert_eq!(rev(cache.range_rev(b"z".as_slice()..)).len(), 0);
+    }
+
+    #[test]
+    fn resumable_cursor() {
+        let (index_path, values_path) = test_paths("resumable_cursor");
+        serialize_example_to(&index_path, &values_path);
+        let cache = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+
+        let mut cursor = cache.cursor();
+        assert_eq!(cursor.next(), Some((&b"cat"[..], 0)));
+        assert_eq!(cursor.next(), Some((&b"dog"[..], 12)));
+        let token = cursor.position().to_bytes();
+
+        let position = CursorPosition::from_bytes(&token).unwrap();
+        assert_eq!(position, CursorPosition::After(b"dog".to_vec()));
+        let mut cursor = cache.cursor_at(position);
+        assert_eq!(cursor.next(), Some((&b"doggy"[..], 24)));
+        assert_eq!(cursor.next(), Some((&b"frog"[..], 36)));
+        assert_eq!(cursor.next(), Some((&b"goose"[..], 48)));
+        assert_eq!(cursor.next(), None);
+        assert_eq!(cursor.position(), &CursorPosition::After(b"goose".to_vec()));
+
+        cursor.seek_ge(b"dogg");
+        assert_eq!(
+            cursor.position(),
+            &CursorPosition::AtOrAfter(b"dogg".to_vec())
+        );
+        assert_eq!(cursor.next(), Some((&b"doggy"[..], 24)));
+        cursor.seek_ge(b"a");
+        assert_eq!(cursor.next(), Some((&b"cat"[..], 0)));
+        assert!(CursorPosition::from_bytes(b"").is_err());
+    }
+
+    #[test]
+    fn keys_and_value_offsets() {
+        let (index_path, values_path) = test_paths("keys_and_value_offsets");
+        serialize_example_to(&index_path, &values_path);
+        let cache = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+
+        let mut keys = Vec::new();
+        let mut stream = cache.keys();
+        while let Some(key) = stream.next() {
+            keys.push(key.to_vec());
+        }
+        let expected: Vec<_> = PAIRS.iter().map(|(k, _)| k.to_vec()).collect();
+        assert_eq!(keys, expected);
+
+        let mut offsets = Vec::new();
+        let mut stream = cache.value_offsets();
+        while let Some(offset) = stream.next() {
+            offsets.push(offset);
+        }
+        assert_eq!(offsets, [0, 12, 24, 36, 48]);
+    }
+
+    #[test]
+    fn typed_values() {
+        let (index_path, values_path) = test_paths("typed_values");
+        serialize_example_to(&index_path, &values_path);
+
+        let cache = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+        let typed = TypedCache::<[i32; 3], _, _>::new(cache);
+        assert_eq!(typed.get(b"frog"), Some(&[4, 5, 6]));
+        assert_eq!(typed.get(b"cow"), None);
+
+        let mut stream = typed.range::<&[u8], _>(..);
+        let mut key_values = Vec::new();
+        while let Some((key, value)) = stream.next() {
+            key_values.push((key.to_vec(), *value));
+        }
+        let expected: Vec<_> = PAIRS.iter().map(|(k, v)| (k.to_vec(), *v)).collect();
+        assert_eq!(key_values, expected);
+    }
+
     #[test]
     fn key_lookups() {
         serialize_example();
@@ -98,6 +462,34 @@ mod tests {
         assert_eq!(&last_key, b"goose");
         assert_eq!(last_offset, 48);
 
+        assert_eq!(
+            cache.get_many(&[&b"goose"[..], b"bird", b"cat", b"dog"]),
+            [Some(48), None, Some(0), Some(12)]
+        );
+        cache.prefetch_values([(0, 12), (48, 12)]).unwrap();
+        assert!(matches!(
+            cache.prefetch_value(48, 13),
+            Err(Error::OutOfBounds { available: 60, .. })
+        ));
+
+        assert_eq!(cache.try_value_at(48, 12).unwrap().len(), 12);
+        assert_eq!(cache.try_value_at(60, 0).unwrap(), b"");
+        assert!(matches!(
+            cache.try_value_at(50, 12),
+            Err(Error::OutOfBounds {
+                offset: 50,
+                len: 12,
+                available: 60
+            })
+        ));
+        assert!(matches!(
+            cache.try_value_at(u64::MAX, 2),
+            Err(Error::OutOfBounds { .. })
+        ));
+
+        assert_eq!(cache.first_key_value(), Some((b"cat".to_vec(), 0)));
+        assert_eq!(cache.last_key_value(), Some((b"goose".to_vec(), 48)));
+
         // Equal.
         let (le_key, le_offset) = cache.last_le::<4>(b"frog").unwrap();
         assert_eq!(&le_key, b"frog");
@@ -147,24 +539,2523 @@ mod tests {
         // No LE keys.
         let result = cache.last_le::<4>(b"candy");
         assert_eq!(result, None);
+
+        assert_eq!(cache.last_lt::<3>(b"dog"), Some((*b"cat", 0)));
+        assert_eq!(cache.last_lt::<5>(b"doggy"), Some((*b"dog\0\0", 12)));
+        assert_eq!(cache.last_lt::<5>(b"dogz"), Some((*b"doggy", 24)));
+        assert_eq!(cache.last_lt::<3>(b"cat"), None);
+        assert_eq!(cache.first_ge::<3>(b"dog"), Some((*b"dog", 12)));
+        assert_eq!(cache.first_ge::<4>(b"e"), Some((*b"frog", 36)));
+        assert_eq!(cache.first_gt::<5>(b"dog"), Some((*b"doggy", 24)));
+        assert_eq!(cache.first_gt::<5>(b"goose"), None);
+
+        assert_eq!(cache.try_first::<4>().unwrap(), Some((*b"cat\0", 0)));
+        assert_eq!(cache.try_last::<5>().unwrap(), Some((*b"goose", 48)));
+        assert!(matches!(
+            cache.try_last::<4>(),
+            Err(Error::KeyTooLong {
+                actual: 5,
+                capacity: 4
+            })
+        ));
+        assert_eq!(
+            cache.try_last_le::<4>(b"full").unwrap(),
+            Some((*b"frog", 36))
+        );
+        assert!(matches!(
+            cache.try_last_le::<4>(b"food"),
+            Err(Error::KeyTooLong { actual: 5, .. })
+        ));
+        assert_eq!(cache.try_last_le::<4>(b"candy").unwrap(), None);
     }
 
-    const INDEX_PATH: &str = "/tmp/mmap_cache_index";
-    const VALUES_PATH: &str = "/tmp/mmap_cache_values";
+    #[test]
+    fn empty_cache() {
+        let empty = build_cache("empty_cache", &[]);
+        assert!(empty.is_empty());
+        assert_eq!(empty.first_key_value(), None);
+        assert_eq!(empty.last_key_value(), None);
+    }
 
-    const PAIRS: [(&[u8], [i32; 3]); 5] = [
-        (b"cat", [1, 2, 3]),
-        (b"dog", [2, 3, 4]),
-        (b"doggy", [3, 4, 5]),
-        (b"frog", [4, 5, 6]),
-        (b"goose", [5, 6, 7]),
-    ];
+    #[test]
+    fn last_le_long_keys() {
+        let long_a = vec![b'a'; 50_000];
+        let mut long_b = long_a.clone();
+        long_b.push(b'b');
+        let cache = build_cache("last_le_long_keys", &[(&long_a, b"1"), (&long_b, b"2")]);
 
-    fn serialize_example() {
-        let mut builder = FileBuilder::create_files(INDEX_PATH, VALUES_PATH).unwrap();
+        let mut probe = long_a.clone();
+        probe.push(b'c');
+        let (key, offset) = cache.last_le::<50_001>(&probe).unwrap();
+        assert_eq!(key[..], long_b[..]);
+        assert_eq!(cache.value_at_offset(offset), Some(&b"2"[..]));
+        assert_eq!(cache.last_lt::<50_001>(&long_b).unwrap().1, 0);
+    }
+
+    #[test]
+    fn rank_and_select() {
+        let keys: Vec<_> = (0..3000u32).map(|i| format!("{:05}", i * 2)).collect();
+        let pairs: Vec<(&[u8], &[u8])> = keys.iter().map(|k| (k.as_bytes(), &b""[..])).collect();
+        let cache = build_cache("rank_and_select", &pairs);
+
+        for i in [0, 1, 1023, 1024, 1025, 2048, 2999] {
+            let (key, _) = cache.nth_key(i).unwrap().unwrap();
+            assert_eq!(key, keys[i].as_bytes());
+            assert_eq!(cache.rank(&key).unwrap(), i);
+            // Probe between keys.
+            let between = format!("{:05}", i * 2 + 1);
+            assert_eq!(cache.rank(between.as_bytes()).unwrap(), i + 1);
+        }
+        assert_eq!(cache.nth_key(3000).unwrap(), None);
+        assert_eq!(cache.rank(b"").unwrap(), 0);
+        assert_eq!(cache.rank(b"z").unwrap(), 3000);
+
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let xorshift = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        let samples = cache.sample(xorshift, 100).unwrap();
+        assert_eq!(samples.len(), 100);
+        assert!(samples.windows(2).all(|w| w[0].0 <= w[1].0));
+        assert!(samples.iter().all(|(k, _)| cache.contains_key(k)));
+        assert!(samples.first().unwrap().0 < b"01000".to_vec());
+        assert!(samples.last().unwrap().0 > b"05000".to_vec());
+
+        let (k100, k2500): (&[u8], &[u8]) = (keys[100].as_bytes(), keys[2500].as_bytes());
+        assert_eq!(cache.count_range(k100..k2500).unwrap(), 2400);
+        assert_eq!(cache.count_range(k100..=k2500).unwrap(), 2401);
+        assert_eq!(
+            cache
+                .count_range::<&[u8], _>((Bound::Excluded(k100), Bound::Unbounded))
+                .unwrap(),
+            2899
+        );
+        assert_eq!(cache.count_range::<&[u8], _>(..).unwrap(), 3000);
+        assert_eq!(cache.count_range(k2500..k100).unwrap(), 0);
+
+        // Without samples, the index is scanned from the start.
+        let raw = Cache::new(cache.index().as_fst().as_bytes(), &[][..]).unwrap();
+        assert_eq!(raw.nth_key(1500).unwrap().unwrap().0, keys[1500].as_bytes());
+        assert_eq!(raw.rank(keys[1500].as_bytes()).unwrap(), 1500);
+    }
+
+    #[test]
+    fn get_values() {
+        let (index_path, values_path) = test_paths("get_values");
+        serialize_example_to(&index_path, &values_path);
+
+        let cache = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+        for (key, value) in PAIRS {
+            assert_eq!(cache.get_value(key), Some(cast_slice(&value)));
+        }
+        assert_eq!(cache.get_value(b"cow"), None);
+    }
+
+    #[test]
+    fn value_lengths_are_recorded() {
+        let (index_path, values_path) = test_paths("value_lengths_are_recorded");
+        let mut builder = FileBuilder::create_files(&index_path, &values_path).unwrap();
+        builder.insert(b"a", b"xyz").unwrap();
+        builder.insert(b"b", b"").unwrap();
+        builder.insert(b"c", b"hello").unwrap();
+        builder.finish().unwrap();
+
+        let cache = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+        assert_eq!(cache.value_bytes().len(), 9);
+        assert_eq!(cache.get_value_offset(b"b"), Some(3));
+        assert_eq!(cache.get_value(b"a"), Some(&b"xyz"[..]));
+        assert_eq!(cache.get_value(b"b"), Some(&b""[..]));
+        assert_eq!(cache.get_value(b"c"), Some(&b"hello"[..]));
+        assert_eq!(cache.value_at_offset(4), Some(&b"hello"[..]));
+        assert_eq!(cache.value_at_offset(5), None);
+    }
+
+    #[test]
+    fn aligned_values() {
+        let (index_path, values_path) = test_paths("aligned_values");
+        let mut builder = FileBuilder::create_files(&index_path, &values_path)
+            .unwrap()
+            .with_value_alignment(8);
+        builder.insert(b"a", b"xyz").unwrap();
+        builder.insert(b"b", b"").unwrap();
+        builder.insert(b"c", b"hello").unwrap();
+        builder.finish().unwrap();
+
+        let cache = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+        assert_eq!(cache.get_value_offset(b"a"), Some(0));
+        assert_eq!(cache.get_value_offset(b"b"), Some(8));
+        assert_eq!(cache.get_value_offset(b"c"), Some(16));
+        assert_eq!(cache.get_value(b"a"), Some(&b"xyz"[..]));
+        assert_eq!(cache.get_value(b"b"), Some(&b""[..]));
+        assert_eq!(cache.get_value(b"c"), Some(&b"hello"[..]));
+    }
+
+    #[test]
+    fn checked_transmutes() {
+        let (index_path, values_path) = test_paths("checked_transmutes");
+        serialize_example_to(&index_path, &values_path);
+
+        let cache = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+        let value = unsafe { cache.try_transmuted_value::<[i32; 3]>(12) }.unwrap();
+        assert_eq!(value, &[2, 3, 4]);
+        assert!(matches!(
+            unsafe { cache.try_transmuted_value::<i32>(13) },
+            Err(Error::Misaligned {
+                offset: 13,
+                alignment: 4
+            })
+        ));
+        assert!(matches!(
+            unsafe { cache.try_transmuted_value::<[i32; 3]>(52) },
+            Err(Error::OutOfBounds {
+                offset: 52,
+                len: 12,
+                available: 60
+            })
+        ));
+
+        assert_eq!(cache.get_pod::<[i32; 3]>(b"dog").unwrap(), Some(&[2, 3, 4]));
+        assert_eq!(cache.get_pod::<[i32; 3]>(b"bird").unwrap(), None);
+        assert!(matches!(
+            cache.get_pod::<i32>(b"dog"),
+            Err(Error::ValueSize {
+                offset: 12,
+                expected: 4,
+                actual: 12
+            })
+        ));
+        let cache = build_cache("checked_pod", &[(b"a", b"x"), (b"b", b"1234")]);
+        assert!(matches!(
+            cache.get_pod::<u32>(b"b"),
+            Err(Error::Misaligned {
+                offset: 1,
+                alignment: 4
+            })
+        ));
+    }
+
+    #[test]
+    fn single_file_container() {
+        let path = std::env::temp_dir().join("mmap_cache_single_file_container");
+        let mut builder = FileBuilder::create_file(&path).unwrap();
         for (key, value) in PAIRS {
             builder.insert(key, cast_slice(&value)).unwrap();
         }
         builder.finish().unwrap();
+
+        let cache = unsafe { MmapCache::map_path(&path) }.unwrap();
+        assert_eq!(cache.value_bytes().len(), 60);
+        for (key, value) in PAIRS {
+            assert_eq!(cache.get_value(key), Some(cast_slice(&value)));
+        }
+        assert_eq!(cache.last::<5>(), Some((*b"goose", 48)));
+
+        let cache = Cache::from_container_bytes(std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(cache.value_bytes().len(), 60);
+        assert_eq!(cache.get_value(b"frog"), Some(cast_slice(&PAIRS[3].1)));
+        assert!(matches!(
+            Cache::from_container_bytes(vec![0; 8]),
+            Err(Error::InvalidFormat(_))
+        ));
+
+        let (index_path, values_path) = test_paths("single_file_container");
+        serialize_example_to(&index_path, &values_path);
+        let error = unsafe { MmapCache::map_path(&values_path) }.err().unwrap();
+        assert_eq!(error.path(), Some(values_path.as_path()));
+        assert!(matches!(error.root_cause(), Error::InvalidFormat(_)));
+    }
+
+    #[test]
+    fn map_with_options() {
+        let (index_path, values_path) = test_paths("map_with_options");
+        serialize_example_to(&index_path, &values_path);
+        let mut options = memmap2::MmapOptions::new();
+        options.populate().offset(7).len(1);
+        let index_file = std::fs::File::open(&index_path).unwrap();
+        let value_file = std::fs::File::open(&values_path).unwrap();
+        let cache =
+            unsafe { MmapCache::map_files_with_options(&index_file, &value_file, &options) }
+                .unwrap();
+        assert_eq!(cache.get_value(b"frog"), Some(cast_slice(&PAIRS[3].1)));
+
+        let path = std::env::temp_dir().join("mmap_cache_map_with_options_container");
+        let mut builder = FileBuilder::create_file(&path).unwrap();
+        builder.insert(b"k", b"v").unwrap();
+        builder.finish().unwrap();
+        let file = std::fs::File::open(&path).unwrap();
+        let cache = unsafe { MmapCache::map_file_with_options(&file, &options) }.unwrap();
+        assert_eq!(cache.get_value(b"k"), Some(&b"v"[..]));
+    }
+
+    #[test]
+    fn access_advice() {
+        let (index_path, values_path) = test_paths("access_advice");
+        serialize_example_to(&index_path, &values_path);
+        let cache = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+        cache.advise(Access::Random).unwrap();
+        assert_eq!(cache.get_value(b"dog"), Some(cast_slice(&PAIRS[1].1)));
+        let keys = collect_keys!(cache.range_sequential::<&[u8], _>(..).unwrap());
+        assert_eq!(keys.len(), PAIRS.len());
+    }
+
+    #[test]
+    fn resident_cache() {
+        let (index_path, values_path) = test_paths("resident_cache");
+        let mut builder = FileBuilder::create_files(&index_path, &values_path)
+            .unwrap()
+            .with_value_alignment(8);
+        builder.insert(b"a", b"x").unwrap();
+        builder.insert(b"b", &7u64.to_ne_bytes()).unwrap();
+        builder.finish().unwrap();
+
+        let cache = unsafe { MmapCache::map_paths(&index_path, &values_path) }
+            .unwrap()
+            .into_resident();
+        std::fs::remove_file(&index_path).unwrap();
+        std::fs::remove_file(&values_path).unwrap();
+        assert_eq!(cache.get_value(b"a"), Some(&b"x"[..]));
+        let offset = cache.get_value_offset(b"b").unwrap() as usize;
+        assert_eq!(
+            unsafe { cache.try_transmuted_value::<u64>(offset) }.unwrap(),
+            &7
+        );
+        cache.verify().unwrap();
+    }
+
+    #[test]
+    fn hash_index() {
+        let (index_path, values_path) = test_paths("hash_index");
+        let mut builder = FileBuilder::create_files(&index_path, &values_path)
+            .unwrap()
+            .with_hash_index();
+        let keys: Vec<_> = (0..500u32).map(|i| format!("key{i:03}")).collect();
+        for key in &keys {
+            builder.insert(key.as_bytes(), key.as_bytes()).unwrap();
+        }
+        builder.insert_tombstone(b"tomb").unwrap();
+        builder.finish().unwrap();
+
+        let cache = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+        for key in &keys {
+            assert_eq!(cache.get_value(key.as_bytes()), Some(key.as_bytes()));
+            assert_eq!(
+                cache.get_value_offset(key.as_bytes()),
+                cache.index().get(key.as_bytes())
+            );
+        }
+        assert_eq!(cache.get_value_offset(b"key500"), None);
+        assert_eq!(cache.get_value_offset(b""), None);
+        assert!(cache.is_tombstone(b"tomb"));
+        cache.verify().unwrap();
+    }
+
+    #[test]
+    fn hash_index_collisions() {
+        let mut builder = MemoryBuilder::in_memory().unwrap().with_hash_index();
+        for key in [b"a", b"b", b"c"] {
+            builder.insert(key, key).unwrap();
+        }
+        let (index, mut values) = builder.finish_into_inner().unwrap();
+        let cache = Cache::new(index.clone(), values.clone()).unwrap();
+        let offset = |key: &[u8]| cache.index().get(key).unwrap();
+        let table = cache.section_bytes(format::SECTION_HASH_INDEX).unwrap();
+        let table_start = table.as_ptr() as usize - cache.raw_value_bytes().as_ptr() as usize;
+
+        // Pretend that "c" has the same hash as "a", with "c" inserted first.
+        let (hash_a, hash_b) = (format::key_hash(b"a"), format::key_hash(b"b"));
+        let mut forged = Vec::new();
+        format::write_hash_index(
+            &mut forged,
+            &[
+                (hash_a, offset(b"c")),
+                (hash_a, offset(b"a")),
+                (hash_b, offset(b"b")),
+            ],
+        )
+        .unwrap();
+        assert_eq!(forged.len(), table.len());
+        assert_eq!(
+            format::lookup_hash_index(&forged, hash_a).unwrap(),
+            format::HashLookup::Colliding
+        );
+        values[table_start..table_start + forged.len()].copy_from_slice(&forged);
+
+        let cache = Cache::new(index.clone(), values.clone()).unwrap();
+        assert_eq!(cache.get_value(b"a"), Some(&b"a"[..]));
+        assert_eq!(cache.get_value(b"b"), Some(&b"b"[..]));
+
+        // Pretend that the missing key "d" has the hash of "c", so that it's found in the table.
+        let mut forged = Vec::new();
+        format::write_hash_index(
+            &mut forged,
+            &[
+                (hash_a, offset(b"a")),
+                (hash_b, offset(b"b")),
+                (format::key_hash(b"d"), offset(b"c")),
+            ],
+        )
+        .unwrap();
+        assert_eq!(forged.len(), table.len());
+        values[table_start..table_start + forged.len()].copy_from_slice(&forged);
+
+        let cache = Cache::new(index, values).unwrap();
+        assert_eq!(cache.get_value_offset(b"d"), None);
+        assert_eq!(cache.get_value(b"d"), None);
+        assert!(!cache.contains_key(b"d"));
+        assert_eq!(cache.get_value(b"a"), Some(&b"a"[..]));
+    }
+
+    #[test]
+    fn decoded_value_lru() {
+        let cache = build_cache(
+            "decoded_value_lru",
+            &[(b"a", b"1"), (b"b", b"22"), (b"c", b"333"), (b"x", b"nan")],
+        );
+        let decodes = std::cell::Cell::new(0);
+        let decoded = DecodedCache::new(cache, 2 * (1 + 8), |bytes| {
+            decodes.set(decodes.get() + 1);
+            std::str::from_utf8(bytes).unwrap().parse::<u64>()
+        });
+
+        assert_eq!(decoded.get(b"a").unwrap().as_deref(), Some(&1));
+        assert_eq!(decoded.get(b"b").unwrap().as_deref(), Some(&22));
+        assert_eq!(decoded.get(b"a").unwrap().as_deref(), Some(&1));
+        assert_eq!(decodes.get(), 2);
+        assert_eq!(decoded.weight(), 18);
+
+        // Evicts "b", the least recently used.
+        assert_eq!(decoded.get(b"c").unwrap().as_deref(), Some(&333));
+        assert_eq!(decoded.get(b"a").unwrap().as_deref(), Some(&1));
+        assert_eq!(decodes.get(), 3);
+        assert_eq!(decoded.get(b"b").unwrap().as_deref(), Some(&22));
+        assert_eq!(decodes.get(), 4);
+
+        assert!(decoded.get(b"x").is_err());
+        assert_eq!(decoded.get(b"z").unwrap(), None);
+        decoded.clear();
+        assert_eq!(decoded.weight(), 0);
+    }
+
+    #[test]
+    fn verify_checksums() {
+        let (index_path, values_path) = test_paths("verify_checksums");
+        serialize_example_to(&index_path, &values_path);
+
+        let index = std::fs::read(&index_path).unwrap();
+        let mut values = std::fs::read(&values_path).unwrap();
+        Cache::new(index.clone(), values.clone())
+            .unwrap()
+            .verify()
+            .unwrap();
+
+        values[13] ^= 1;
+        assert!(matches!(
+            Cache::new(index, values).unwrap().verify(),
+            Err(Error::ChecksumMismatch {
+                section: "values",
+                ..
+            })
+        ));
+
+        let path = std::env::temp_dir().join("mmap_cache_verify_checksums");
+        let mut builder = FileBuilder::create_file(&path).unwrap();
+        builder.insert(b"a", b"b").unwrap();
+        builder.finish().unwrap();
+        unsafe { MmapCache::map_path(&path) }
+            .unwrap()
+            .verify()
+            .unwrap();
+    }
+
+    #[test]
+    fn open_verified() {
+        let (index_path, values_path) = test_paths("open_verified");
+        serialize_example_to(&index_path, &values_path);
+        let cache = unsafe { MmapCache::open_verified(&index_path, &values_path) }.unwrap();
+        assert_eq!(cache.len(), 5);
+
+        let mut index = std::fs::read(&index_path).unwrap();
+        let middle = index.len() / 2;
+        index[middle] ^= 1;
+        std::fs::write(&index_path, index).unwrap();
+        let error = unsafe { MmapCache::open_verified(&index_path, &values_path) }
+            .err()
+            .unwrap();
+        assert_eq!(error.path(), Some(index_path.as_path()));
+        assert!(matches!(error.root_cause(), Error::Fst(_)));
+
+        let path = std::env::temp_dir().join("mmap_cache_open_verified");
+        let mut builder = FileBuilder::create_file(&path).unwrap();
+        builder.insert(b"a", b"b").unwrap();
+        builder.finish().unwrap();
+        unsafe { MmapCache::open_verified_path(&path) }.unwrap();
+    }
+
+    #[test]
+    fn verify_value_offsets() {
+        let (index_path, values_path) = test_paths("verify_value_offsets");
+        serialize_example_to(&index_path, &values_path);
+        let values = std::fs::read(&values_path).unwrap();
+        let cache = Cache::new(std::fs::read(&index_path).unwrap(), values.clone()).unwrap();
+        cache.verify_offsets().unwrap();
+
+        let index_with_offsets = |offsets: &[u64]| {
+            let mut index = fst::MapBuilder::memory();
+            for (key, &offset) in [b"a", b"b"].iter().zip(offsets) {
+                index.insert(key, offset).unwrap();
+            }
+            index.into_inner().unwrap()
+        };
+        // The length table has no entry for offset 1.
+        let cache = Cache::new(index_with_offsets(&[0, 1]), values).unwrap();
+        assert!(matches!(
+            cache.verify_offsets(),
+            Err(Error::InvalidFormat(_))
+        ));
+
+        // Raw value files have no length table.
+        let raw = b"xyzhello".to_vec();
+        let cache = Cache::new(index_with_offsets(&[0, 3]), raw.clone()).unwrap();
+        cache.verify_offsets().unwrap();
+        let cache = Cache::new(index_with_offsets(&[3, 0]), raw.clone()).unwrap();
+        assert!(matches!(
+            cache.verify_offsets(),
+            Err(Error::InvalidFormat(_))
+        ));
+        let cache = Cache::new(index_with_offsets(&[0, 9]), raw).unwrap();
+        assert!(matches!(
+            cache.verify_offsets(),
+            Err(Error::OutOfBounds { offset: 9, .. })
+        ));
+    }
+
+    #[test]
+    fn atomic_build() {
+        let (index_path, values_path) = test_paths("atomic_build");
+        serialize_example_to(&index_path, &values_path);
+
+        // Abandoning an atomic build leaves the existing files untouched.
+        let mut builder = FileBuilder::create_files_atomic(&index_path, &values_path).unwrap();
+        builder.insert(b"a", b"b").unwrap();
+        drop(builder);
+        let cache = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+        assert_eq!(cache.index().len(), PAIRS.len());
+        drop(cache);
+
+        let mut builder = FileBuilder::create_files_atomic(&index_path, &values_path).unwrap();
+        builder.insert(b"a", b"b").unwrap();
+        builder.finish().unwrap();
+        let cache = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+        assert_eq!(cache.index().len(), 1);
+        assert_eq!(cache.get_value(b"a"), Some(&b"b"[..]));
+
+        let path = std::env::temp_dir().join("mmap_cache_atomic_build");
+        let mut builder = FileBuilder::create_file_atomic(&path)
+            .unwrap()
+            .with_durability(Durability::SyncFilesAndDirectories);
+        builder.insert(b"a", b"b").unwrap();
+        builder.finish().unwrap();
+        let cache = unsafe { MmapCache::map_path(&path) }.unwrap();
+        assert_eq!(cache.get_value(b"a"), Some(&b"b"[..]));
+    }
+
+    #[test]
+    fn durable_build() {
+        for durability in [Durability::SyncFiles, Durability::SyncFilesAndDirectories] {
+            let (index_path, values_path) = test_paths("durable_build");
+            let mut builder = FileBuilder::create_files(&index_path, &values_path)
+                .unwrap()
+                .with_durability(durability);
+            builder.insert(b"a", b"1").unwrap();
+            builder.insert(b"b", b"2").unwrap();
+            builder.finish().unwrap();
+            let cache = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+            cache.verify().unwrap();
+            assert_eq!(cache.get_value(b"b"), Some(&b"2"[..]));
+
+            let path = std::env::temp_dir().join("mmap_cache_durable_build");
+            let mut builder = FileBuilder::create_file(&path)
+                .unwrap()
+                .with_durability(durability);
+            builder.insert(b"a", b"1").unwrap();
+            builder.finish().unwrap();
+            let cache = unsafe { MmapCache::map_path(&path) }.unwrap();
+            assert_eq!(cache.get_value(b"a"), Some(&b"1"[..]));
+
+            // Builders without output files have nothing to sync.
+            let mut builder = MemoryBuilder::in_memory()
+                .unwrap()
+                .with_durability(durability);
+            builder.insert(b"a", b"1").unwrap();
+            let cache = builder.finish_into_cache().unwrap();
+            assert_eq!(cache.get_value(b"a"), Some(&b"1"[..]));
+        }
+    }
+
+    #[test]
+    fn unsorted_build() {
+        let (index_path, values_path) = test_paths("unsorted_build");
+        let mut unsorted = UnsortedBuilder::new();
+        for (key, value) in PAIRS.iter().rev() {
+            unsorted.insert(key, cast_slice(value));
+        }
+        assert_eq!(unsorted.len(), PAIRS.len());
+        unsorted
+            .finish(FileBuilder::create_files(&index_path, &values_path).unwrap())
+            .unwrap();
+
+        let cache = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+        for (i, (key, value)) in PAIRS.iter().enumerate() {
+            assert_eq!(cache.get_value_offset(key), Some(12 * i as u64));
+            assert_eq!(cache.get_value(key), Some(cast_slice(value)));
+        }
+    }
+
+    #[test]
+    fn external_sort_build() {
+        let (index_path, values_path) = test_paths("external_sort_build");
+        let mut external = ExternalSortBuilder::new(4096);
+        for i in (0..1000u32).rev() {
+            external
+                .insert(&i.to_be_bytes(), &(2 * i).to_le_bytes())
+                .unwrap();
+        }
+        // Each buffered entry uses its 8 bytes plus its offset and lengths.
+        let entry_size = 8 + 3 * std::mem::size_of::<usize>();
+        assert!((2..=1000 * entry_size / 4096 + 1).contains(&external.num_runs()));
+        external
+            .finish(FileBuilder::create_files(&index_path, &values_path).unwrap())
+            .unwrap();
+
+        let cache = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+        assert_eq!(cache.index().len(), 1000);
+        let mut stream = cache.range_values::<&[u8], _>(..);
+        let mut i = 0u32;
+        while let Some((key, value)) = stream.next() {
+            assert_eq!(key, i.to_be_bytes());
+            assert_eq!(value, (2 * i).to_le_bytes());
+            i += 1;
+        }
+        assert_eq!(i, 1000);
+    }
+
+    #[test]
+    fn unsorted_memory_budget() {
+        let (index_path, values_path) = test_paths("unsorted_memory_budget");
+        let mut unsorted = UnsortedBuilder::new();
+        unsorted.insert(b"zzz", b"last");
+        let mut external = unsorted.with_memory_budget(256);
+        for i in (0..200u32).rev() {
+            external.insert(&i.to_be_bytes(), &i.to_le_bytes()).unwrap();
+        }
+        // Each buffered entry uses its 8 bytes plus its offset and lengths.
+        let entry_size = 8 + 3 * std::mem::size_of::<usize>();
+        assert!((2..=201 * entry_size / 256 + 1).contains(&external.num_runs()));
+        external
+            .finish(FileBuilder::create_files(&index_path, &values_path).unwrap())
+            .unwrap();
+
+        let cache = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+        assert_eq!(cache.len(), 201);
+        assert_eq!(
+            cache.get_value(&7u32.to_be_bytes()),
+            Some(&7u32.to_le_bytes()[..])
+        );
+        assert_eq!(cache.get_value(b"zzz"), Some(&b"last"[..]));
+    }
+
+    #[test]
+    fn merge_caches() {
+        let caches = [
+            build_cache("merge_caches_a", &[(b"a", b"1"), (b"c", b"1")]),
+            build_cache("merge_caches_b", &[(b"b", b"2"), (b"c", b"2")]),
+        ];
+
+        let (index_path, values_path) = test_paths("merge_caches_out");
+        let merge_with = |duplicates| {
+            let builder = FileBuilder::create_files(&index_path, &values_path).unwrap();
+            let options = MergeOptions {
+                duplicates,
+                ..Default::default()
+            };
+            merge(&caches, options, builder)
+        };
+
+        merge_with(DuplicatePolicy::KeepFirst).unwrap();
+        let merged = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+        assert_eq!(merged.index().len(), 3);
+        assert_eq!(merged.get_value(b"b"), Some(&b"2"[..]));
+        assert_eq!(merged.get_value(b"c"), Some(&b"1"[..]));
+
+        merge_with(DuplicatePolicy::KeepLast).unwrap();
+        let merged = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+        assert_eq!(merged.get_value(b"c"), Some(&b"2"[..]));
+
+        assert!(matches!(
+            merge_with(DuplicatePolicy::Error),
+            Err(Error::DuplicateKey { key }) if key == b"c"
+        ));
+    }
+
+    #[test]
+    fn merge_keeps_expiry_and_metadata() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let at = |seconds| UNIX_EPOCH + Duration::from_secs(seconds);
+        let (index_path, values_path) = test_paths("merge_expiry_a");
+        let mut builder = FileBuilder::create_files(&index_path, &values_path).unwrap();
+        builder.set_metadata("source", "a");
+        builder.set_metadata("version", "1");
+        builder.insert_with_expiry(b"a", b"1", at(100)).unwrap();
+        builder.insert(b"b", b"1").unwrap();
+        builder.finish().unwrap();
+        let a = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+        let (index_path, values_path) = test_paths("merge_expiry_b");
+        let mut builder = FileBuilder::create_files(&index_path, &values_path).unwrap();
+        builder.set_metadata("source", "b");
+        builder.insert_with_expiry(b"c", b"2", at(200)).unwrap();
+        builder.finish().unwrap();
+        let b = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+
+        let (index_path, values_path) = test_paths("merge_expiry_out");
+        let mut builder = FileBuilder::create_files(&index_path, &values_path).unwrap();
+        builder.set_metadata("version", "2");
+        merge(&[a, b], MergeOptions::default(), builder).unwrap();
+        let merged = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+        merged.verify().unwrap();
+        assert_eq!(merged.get_value(b"a"), Some(&b"1"[..]));
+        assert_eq!(merged.expiry(b"a"), Some(at(100)));
+        assert_eq!(merged.expiry(b"b"), None);
+        assert_eq!(merged.expiry(b"c"), Some(at(200)));
+        let expiring = ExpiringCache::new(merged).at_time(at(150));
+        assert_eq!(expiring.get_value(b"a"), None);
+        assert_eq!(expiring.get_value(b"c"), Some(&b"2"[..]));
+
+        let merged = expiring.into_cache();
+        let metadata = merged.metadata().unwrap();
+        assert_eq!(metadata.get_str("source"), Some("b"));
+        assert_eq!(metadata.get_str("version"), Some("2"));
+    }
+
+    #[test]
+    fn layered_reads() {
+        let base = build_cache(
+            "layered_reads_base",
+            &[(b"a", b"1"), (b"b", b"1"), (b"c", b"1")],
+        );
+        let delta = build_cache("layered_reads_delta", &[(b"b", b"2"), (b"d", b"2")]);
+        let layered = LayeredCache::new(vec![base, delta]);
+
+        assert_eq!(layered.get_value(b"a"), Some(&b"1"[..]));
+        assert_eq!(layered.get_value(b"b"), Some(&b"2"[..]));
+        assert_eq!(layered.get_value_offset(b"d"), Some((1, 1)));
+        assert_eq!(layered.get_value(b"e"), None);
+
+        let b: &[u8] = b"b";
+        let mut stream = layered.range(b..);
+        let mut key_values = Vec::new();
+        while let Some((key, value)) = stream.next() {
+            key_values.push((key.to_vec(), value.to_vec()));
+        }
+        assert_eq!(
+            key_values,
+            [
+                (b"b".to_vec(), b"2".to_vec()),
+                (b"c".to_vec(), b"1".to_vec()),
+                (b"d".to_vec(), b"2".to_vec())
+            ]
+        );
+    }
+
+    #[test]
+    fn tombstones() {
+        let base = build_cache(
+            "tombstones_base",
+            &[(b"a", b"1"), (b"b", b"1"), (b"c", b"1")],
+        );
+        let (index_path, values_path) = test_paths("tombstones_delta");
+        let mut builder = FileBuilder::create_files(&index_path, &values_path).unwrap();
+        builder.insert_tombstone(b"b").unwrap();
+        builder.insert(b"d", b"2").unwrap();
+        builder.finish().unwrap();
+        let delta = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+        assert!(delta.is_tombstone(b"b"));
+        assert_eq!(delta.get_value(b"b"), None);
+        assert!(delta.contains_key(b"b"));
+        assert!(!delta.contains_key(b"c"));
+        assert_eq!(delta.len(), 2);
+        assert!(!delta.is_empty());
+
+        let layered = LayeredCache::new(vec![base, delta]);
+        assert_eq!(layered.get_value(b"a"), Some(&b"1"[..]));
+        assert_eq!(layered.get_value(b"b"), None);
+        assert_eq!(layered.get_value_offset(b"b"), None);
+        let expected = [b"a".to_vec(), b"c".to_vec(), b"d".to_vec()];
+        assert_eq!(collect_keys!(layered.range::<&[u8], _>(..)), expected);
+
+        let (index_path, values_path) = test_paths("tombstones_merged");
+        let caches = layered.into_layers();
+        for drop_tombstones in [false, true] {
+            let builder = FileBuilder::create_files(&index_path, &values_path).unwrap();
+            let options = MergeOptions {
+                drop_tombstones,
+                ..Default::default()
+            };
+            merge(&caches, options, builder).unwrap();
+            let merged = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+            assert_eq!(merged.index().contains_key(b"b"), !drop_tombstones);
+            assert_eq!(merged.get_value(b"b"), None);
+            assert_eq!(collect_keys!(merged.range_values::<&[u8], _>(..)), expected);
+        }
+    }
+
+    #[test]
+    fn compact_layers() {
+        let base = build_cache("compact_layers_base", &[(b"a", b"1"), (b"b", b"1")]);
+        let (index_path, values_path) = test_paths("compact_layers_delta");
+        let mut builder = FileBuilder::create_files(&index_path, &values_path).unwrap();
+        builder.insert(b"a", b"2").unwrap();
+        builder.insert_tombstone(b"b").unwrap();
+        builder.finish().unwrap();
+        let delta = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+        let newest = build_cache("compact_layers_newest", &[(b"c", b"3")]);
+        let layered = LayeredCache::new(vec![base, delta, newest]);
+
+        // Compacting only the deltas must keep the tombstone.
+        let (index_path, values_path) = test_paths("compact_layers_deltas");
+        let builder = FileBuilder::create_files(&index_path, &values_path).unwrap();
+        layered.compact_layers(1..3, builder).unwrap();
+        let deltas = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+        assert!(deltas.is_tombstone(b"b"));
+
+        let (index_path, values_path) = test_paths("compact_layers_all");
+        let builder = FileBuilder::create_files(&index_path, &values_path).unwrap();
+        layered.compact(builder).unwrap();
+        let compacted = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+        assert_eq!(compacted.index().len(), 2);
+        assert_eq!(compacted.get_value(b"a"), Some(&b"2"[..]));
+        assert_eq!(compacted.get_value(b"c"), Some(&b"3"[..]));
+        assert_eq!(compacted.value_bytes().len(), 2);
+    }
+
+    #[test]
+    fn multimap() {
+        let (index_path, values_path) = test_paths("multimap");
+        let mut builder =
+            MultiMapBuilder::new(FileBuilder::create_files(&index_path, &values_path).unwrap());
+        builder.insert(b"a", [&b"1"[..], b"22", b""]).unwrap();
+        builder.insert(b"b", Vec::<&[u8]>::new()).unwrap();
+        builder.insert(b"c", [b"333"]).unwrap();
+        builder.finish().unwrap();
+
+        let cache =
+            MultiMapCache::new(unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap());
+        let all = |key: &[u8]| cache.get_all(key).map(|values| values.collect::<Vec<_>>());
+        assert_eq!(all(b"a"), Some(vec![&b"1"[..], b"22", b""]));
+        assert_eq!(all(b"b"), Some(vec![]));
+        assert_eq!(all(b"c"), Some(vec![&b"333"[..]]));
+        assert_eq!(all(b"d"), None);
+    }
+
+    #[test]
+    fn dedup_values() {
+        let (index_path, values_path) = test_paths("dedup_values");
+        let mut builder = FileBuilder::create_files(&index_path, &values_path)
+            .unwrap()
+            .with_value_dedup();
+        builder.insert(b"a", b"same").unwrap();
+        builder.insert(b"b", b"other").unwrap();
+        builder.append_value_bytes(b"sa").unwrap();
+        builder.append_value_bytes(b"me").unwrap();
+        builder.commit_entry(b"c").unwrap();
+        builder.finish().unwrap();
+
+        let cache = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+        assert_eq!(cache.value_bytes().len(), 9);
+        assert_eq!(cache.get_value_offset(b"c"), Some(0));
+        assert_eq!(cache.get_value(b"b"), Some(&b"other"[..]));
+        assert_eq!(cache.get_value(b"c"), Some(&b"same"[..]));
+    }
+
+    #[test]
+    fn raw_values_without_length_table() {
+        let index = fst::Map::from_iter([(b"a", 0), (b"b", 3)])
+            .unwrap()
+            .into_fst()
+            .into_inner();
+        let cache = Cache::new(index, b"xyzhello".to_vec()).unwrap();
+        assert_eq!(cache.get_value(b"a"), Some(&b"xyz"[..]));
+        assert_eq!(cache.get_value(b"b"), Some(&b"hello"[..]));
+        assert_eq!(cache.value_at_offset(0), None);
+    }
+
+    #[test]
+    fn pread_backend() {
+        let (index_path, values_path) = test_paths("pread_backend");
+        let mut builder = FileBuilder::create_files(&index_path, &values_path).unwrap();
+        builder.insert(b"a", b"first").unwrap();
+        builder.insert_tombstone(b"b").unwrap();
+        builder.insert(b"c", b"").unwrap();
+        builder.finish().unwrap();
+
+        let cache = PreadCache::open_paths(&index_path, &values_path).unwrap();
+        let mut buf = Vec::new();
+        assert!(cache.get_value(b"a", &mut buf).unwrap());
+        assert_eq!(buf, b"first");
+        assert!(!cache.get_value(b"b", &mut buf).unwrap());
+        assert!(cache.get_value(b"c", &mut buf).unwrap());
+        assert_eq!(buf, b"");
+        assert!(!cache.get_value(b"d", &mut buf).unwrap());
+        assert!(cache.read_value_at(0, &mut buf).unwrap());
+        assert_eq!(buf, b"first");
+        assert!(cache.read_value(4, 100, &mut buf).is_err());
+
+        let path = std::env::temp_dir().join("mmap_cache_pread_backend_container");
+        let mut builder = FileBuilder::create_file(&path).unwrap();
+        for (key, value) in PAIRS {
+            builder.insert(key, cast_slice(&value)).unwrap();
+        }
+        builder.finish().unwrap();
+        let cache = PreadCache::open_path(&path).unwrap();
+        assert!(cache.get_value(b"frog", &mut buf).unwrap());
+        assert_eq!(buf, cast_slice::<i32, u8>(&PAIRS[3].1));
+
+        // Raw value files have no length table.
+        let mut index = fst::MapBuilder::new(std::fs::File::create(&index_path).unwrap()).unwrap();
+        index.insert(b"a", 0).unwrap();
+        index.insert(b"b", 3).unwrap();
+        index.finish().unwrap();
+        std::fs::write(&values_path, b"xyzhello").unwrap();
+        let cache = PreadCache::open_paths(&index_path, &values_path).unwrap();
+        assert!(cache.get_value(b"b", &mut buf).unwrap());
+        assert_eq!(buf, b"hello");
+        assert!(!cache.read_value_at(0, &mut buf).unwrap());
+    }
+
+    #[test]
+    fn value_store_backends() {
+        let (index_path, values_path) = test_paths("value_store_backends");
+        let mut builder = FileBuilder::create_files(&index_path, &values_path).unwrap();
+        builder.insert(b"a", b"first").unwrap();
+        builder.insert_tombstone(b"b").unwrap();
+        builder.insert(b"c", b"third").unwrap();
+        builder.finish().unwrap();
+        let index = std::fs::read(&index_path).unwrap();
+
+        let cache =
+            Cache::from_store(index.clone(), FileStore::open(&values_path).unwrap()).unwrap();
+        assert_eq!(
+            cache.read_value(b"a").unwrap().as_deref(),
+            Some(&b"first"[..])
+        );
+        assert_eq!(cache.read_value(b"b").unwrap(), None);
+        assert_eq!(cache.read_value(b"d").unwrap(), None);
+        assert_eq!(
+            cache.read_value_at(6).unwrap().as_deref(),
+            Some(&b"third"[..])
+        );
+        assert_eq!(cache.read_value_at(5).unwrap(), None);
+        assert_eq!(cache.len(), 3);
+        assert_eq!(
+            collect_keys!(cache.range(b"b".as_slice()..).into_stream()),
+            [b"b".to_vec(), b"c".to_vec()]
+        );
+
+        // Stores in memory lend out borrowed bytes.
+        let values = std::fs::read(&values_path).unwrap();
+        let cache = Cache::from_store(index, values).unwrap();
+        assert!(matches!(
+            cache.read_value(b"c").unwrap(),
+            Some(std::borrow::Cow::Borrowed(b"third"))
+        ));
+        assert_eq!(cache.get_value(b"c"), Some(&b"third"[..]));
+        assert!(cache.store().read(u64::MAX, 1).is_err());
+    }
+
+    #[test]
+    fn chunked_mapping() {
+        let (index_path, values_path) = test_paths("chunked_mapping");
+        let mut builder = FileBuilder::create_files(&index_path, &values_path).unwrap();
+        let values: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i; 40_000]).collect();
+        for (i, value) in values.iter().enumerate() {
+            builder.insert(&[i as u8], value).unwrap();
+        }
+        builder.finish().unwrap();
+
+        let cache = unsafe { ChunkedMmapCache::map_paths(&index_path, &values_path) }.unwrap();
+        assert_eq!(cache.read_value(&[3]).unwrap().unwrap(), &values[3][..]);
+
+        // Values straddle window boundaries, and only two windows can be mapped at once.
+        let store = unsafe { ChunkedMmap::open(&values_path) }
+            .unwrap()
+            .with_window_size(CHUNK_ALIGNMENT)
+            .with_window_capacity(2);
+        let cache = Cache::from_store(std::fs::read(&index_path).unwrap(), store).unwrap();
+        for (i, value) in values.iter().enumerate().rev() {
+            assert_eq!(cache.read_value(&[i as u8]).unwrap().unwrap(), &value[..]);
+        }
+        assert_eq!(cache.read_value(&[5]).unwrap(), None);
+    }
+
+    #[test]
+    fn value_codecs() {
+        let (index_path, values_path) = test_paths("value_codecs");
+        let mut builder = FileBuilder::create_files(&index_path, &values_path).unwrap();
+        builder.insert_encoded(b"a", &Utf8Codec, "alpha").unwrap();
+        builder.insert_encoded(b"b", &RawCodec, b"\xff").unwrap();
+        builder.insert_encoded(b"c", &Utf8Codec, "gamma").unwrap();
+        builder
+            .insert_encoded(b"d", &PodCodec::new(), &[1u16, 2, 3])
+            .unwrap();
+        builder.finish().unwrap();
+
+        let cache = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+        let cache = CodecCache::new(cache, Utf8Codec);
+        assert_eq!(cache.get(b"a").unwrap(), Some("alpha"));
+        assert!(matches!(cache.get(b"b"), Err(Error::Decode(_))));
+        assert_eq!(cache.get(b"e").unwrap(), None);
+        let mut stream = cache.range(b"c".as_slice()..=b"c".as_slice());
+        assert!(matches!(stream.next(), Some((b"c", Ok("gamma")))));
+        assert!(stream.next().is_none());
+
+        let cache = CodecCache::new(cache.into_untyped(), PodCodec::<[u16; 3]>::new());
+        assert_eq!(cache.get(b"d").unwrap(), Some([1, 2, 3]));
+        assert!(cache.get(b"a").is_err());
+    }
+
+    #[test]
+    fn order_preserving_keys() {
+        fn assert_sorted<K: KeyEncode + Copy + std::fmt::Debug + PartialEq>(keys: &[K]) {
+            let encoded: Vec<_> = keys.iter().map(KeyEncode::to_key_bytes).collect();
+            assert!(encoded.windows(2).all(|w| w[0] < w[1]), "{keys:?}");
+            for (key, bytes) in keys.iter().zip(&encoded) {
+                assert_eq!(K::from_key_bytes(bytes), Some(*key));
+            }
+        }
+        assert_sorted(&[0u32, 1, 255, 256, u32::MAX]);
+        assert_sorted(&[0u64, 1, 1 << 40, u64::MAX]);
+        assert_sorted(&[i64::MIN, -256, -1, 0, 1, 255, i64::MAX]);
+        assert_sorted(&[
+            f64::NEG_INFINITY,
+            -1e10,
+            -1.5,
+            -f64::MIN_POSITIVE,
+            -0.0,
+            0.0,
+            f64::MIN_POSITIVE,
+            2.5,
+            f64::INFINITY,
+        ]);
+        assert_sorted(&[(0u32, -1i64), (0, 5), (1, i64::MIN), (1, 0)]);
+        assert_eq!(u32::from_key_bytes(&[0; 5]), None);
+        assert_eq!(<(u8, u8)>::from_key_bytes(&[0]), None);
+
+        let keys: Vec<Vec<u8>> = [-20i64, -3, 0, 7, 1000]
+            .iter()
+            .map(KeyEncode::to_key_bytes)
+            .collect();
+        let pairs: Vec<(&[u8], &[u8])> = keys.iter().map(|k| (k.as_slice(), &b"v"[..])).collect();
+        let cache = build_cache("order_preserving_keys", &pairs);
+        let in_range: Vec<i64> = collect_keys!(cache
+            .range((-5i64).to_key_bytes()..=7i64.to_key_bytes())
+            .into_stream())
+        .iter()
+        .map(|k| i64::from_key_bytes(k).unwrap())
+        .collect();
+        assert_eq!(in_range, [-3, 0, 7]);
+    }
+
+    #[test]
+    fn typed_map() {
+        let (index_path, values_path) = test_paths("typed_map");
+        let mut builder = FileBuilder::create_files(&index_path, &values_path).unwrap();
+        for (key, value) in [((-1i32, 0u8), 1.5f64), ((0, 0), 2.5), ((0, 1), -3.0)] {
+            builder
+                .insert_typed(&key, &PodCodec::new(), &value)
+                .unwrap();
+        }
+        builder
+            .insert_tombstone(&(0i32, 2u8).to_key_bytes())
+            .unwrap();
+        builder.insert(b"\xff", b"").unwrap();
+        builder.finish().unwrap();
+
+        let cache = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+        let map = Map::<(i32, u8), _, _, _>::new(cache, PodCodec::<f64>::new());
+        assert_eq!(map.get(&(0, 1)), Some(-3.0));
+        assert_eq!(map.get(&(1, 1)), None);
+        assert!(map.contains_key(&(0, 2)));
+        assert_eq!(map.try_get(&(0, 2)).unwrap(), None);
+
+        let pairs: Vec<_> = map.range((0, 0)..(1, 0)).map(Result::unwrap).collect();
+        assert_eq!(pairs, [((0, 0), 2.5), ((0, 1), -3.0)]);
+        let pairs: Vec<_> = map.range(..=(0, 0)).map(Result::unwrap).collect();
+        assert_eq!(pairs, [((-1, 0), 1.5), ((0, 0), 2.5)]);
+        // The untyped key sorts after every encoded key, and doesn't decode.
+        assert!(map.iter().last().unwrap().is_err());
+    }
+
+    #[test]
+    fn in_memory_writers() {
+        let mut builder = FileBuilder::new(Vec::new(), Vec::new()).unwrap();
+        builder.insert(b"a", b"first").unwrap();
+        builder.insert_tombstone(b"b").unwrap();
+        builder.insert_encoded(b"c", &Utf8Codec, "third").unwrap();
+        let (index, values) = builder.finish_into_inner().unwrap();
+
+        let cache = Cache::new(index, values).unwrap();
+        assert_eq!(cache.get_value(b"a"), Some(&b"first"[..]));
+        assert_eq!(cache.get_value(b"b"), None);
+        assert_eq!(cache.get_value(b"c"), Some(&b"third"[..]));
+        cache.verify().unwrap();
+    }
+
+    #[test]
+    fn memory_builder() {
+        let mut builder = MemoryBuilder::in_memory().unwrap().with_hash_index();
+        builder.insert(b"a", b"first").unwrap();
+        builder.insert(b"b", b"").unwrap();
+        let cache = builder.finish_into_cache().unwrap();
+        assert_eq!(cache.get_value(b"a"), Some(&b"first"[..]));
+        assert_eq!(cache.get_value(b"b"), Some(&b""[..]));
+        assert_eq!(cache.len(), 2);
+        cache.verify().unwrap();
+    }
+
+    #[test]
+    fn build_from_sorted_iter() {
+        let pairs = [(b"a".to_vec(), b"1".to_vec()), (b"b".to_vec(), vec![])];
+        let cache = Cache::from_sorted_iter(pairs.clone()).unwrap();
+        assert_eq!(cache.get_value(b"b"), Some(&b""[..]));
+
+        let (index_path, values_path) = test_paths("build_from_sorted_iter");
+        FileBuilder::build_from_sorted_iter(&index_path, &values_path, pairs).unwrap();
+        let cache = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+        assert_eq!(cache.get_value(b"a"), Some(&b"1"[..]));
+
+        assert!(Cache::from_sorted_iter([("b", ""), ("a", "")]).is_err());
+
+        let map = BTreeMap::from([(b"z".to_vec(), "last"), (b"y".to_vec(), "first")]);
+        FileBuilder::from_btree_map(&map, &index_path, &values_path).unwrap();
+        let cache = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+        assert_eq!(cache.first_key_value(), Some((b"y".to_vec(), 0)));
+        assert_eq!(cache.get_value(b"z"), Some(&b"last"[..]));
+    }
+
+    #[test]
+    fn append_segments() {
+        let manifest_path = Path::new("/tmp/mmap_cache_test_append_segments");
+        let _ = std::fs::remove_file(manifest_path);
+        let mut manifest = SegmentManifest::open(manifest_path).unwrap();
+        manifest
+            .append_segment(|builder| {
+                builder.insert(b"a", b"base")?;
+                builder.insert(b"b", b"base")
+            })
+            .unwrap();
+        manifest
+            .append_segment(|builder| {
+                builder.insert_tombstone(b"a")?;
+                builder.insert(b"c", b"delta")
+            })
+            .unwrap();
+        // An abandoned segment isn't committed.
+        let abandoned = manifest.append_segment(|builder| {
+            builder.insert(b"z", b"")?;
+            Err(Error::InvalidFormat("abandoned"))
+        });
+        assert!(abandoned.is_err());
+
+        let manifest = SegmentManifest::open(manifest_path).unwrap();
+        assert_eq!(manifest.segments().len(), 2);
+        assert!(manifest.segments()[1]
+            .value_path
+            .ends_with("mmap_cache_test_append_segments.1.values"));
+        let cache = unsafe { manifest.map_layers() }.unwrap();
+        assert_eq!(cache.get_value(b"a"), None);
+        assert_eq!(cache.get_value(b"b"), Some(&b"base"[..]));
+        assert_eq!(cache.get_value(b"c"), Some(&b"delta"[..]));
+
+        let mut manifest = manifest;
+        let missing = manifest.next_segment();
+        assert!(manifest.commit_segment(missing).is_err());
+        assert_eq!(manifest.segments().len(), 2);
+    }
+
+    #[test]
+    fn index_only_values() {
+        let path = Path::new("/tmp/mmap_cache_test_index_only_values");
+        let mut builder = IndexOnlyBuilder::create_file(path).unwrap();
+        builder.insert(b"a", 7).unwrap();
+        builder.insert(b"b", u64::MAX).unwrap();
+        builder.insert(b"c", 0).unwrap();
+        assert!(builder.insert(b"a", 1).is_err());
+        builder.finish().unwrap();
+
+        let cache = unsafe { MmapIndexOnlyCache::map_path(path) }.unwrap();
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.get(b"b"), Some(u64::MAX));
+        assert_eq!(cache.get(b"d"), None);
+        let pairs = cache
+            .range(b"b".as_slice()..)
+            .into_stream()
+            .into_str_vec()
+            .unwrap();
+        assert_eq!(pairs, [("b".to_owned(), u64::MAX), ("c".to_owned(), 0)]);
+
+        let mut builder = IndexOnlyBuilder::new(Vec::new()).unwrap();
+        builder.insert(b"x", 1).unwrap();
+        let cache = IndexOnlyCache::new(builder.finish_into_inner().unwrap()).unwrap();
+        assert_eq!(cache.get(b"x"), Some(1));
+    }
+
+    #[test]
+    fn keys_only_set() {
+        let path = Path::new("/tmp/mmap_cache_test_keys_only_set");
+        let mut builder = SetBuilder::create_file(path).unwrap();
+        for key in [b"a", b"b", b"c"] {
+            builder.insert(key).unwrap();
+        }
+        builder.finish().unwrap();
+
+        // The atomic builder leaves the existing set untouched until it's finished.
+        let mut builder = SetBuilder::create_file_atomic(path).unwrap();
+        builder.insert(b"z").unwrap();
+        let set = unsafe { MmapSet::map_path(path) }.unwrap();
+        assert_eq!(set.len(), 3);
+        assert!(set.contains(b"b"));
+        assert!(!set.contains(b"z"));
+        let keys = set
+            .range(b"b".as_slice()..)
+            .into_stream()
+            .into_strs()
+            .unwrap();
+        assert_eq!(keys, ["b", "c"]);
+        drop(set);
+
+        builder.finish().unwrap();
+        let set = unsafe { MmapSet::map_path(path) }.unwrap();
+        assert_eq!(set.len(), 1);
+        assert!(set.contains(b"z"));
+    }
+
+    #[test]
+    fn user_metadata() {
+        let path = Path::new("/tmp/mmap_cache_test_user_metadata");
+        let mut builder = FileBuilder::create_file(path).unwrap().with_hash_index();
+        builder.set_metadata("schema_version", "3");
+        builder.insert(b"a", b"first").unwrap();
+        builder.set_metadata("dataset", [0xff, 0]);
+        builder.set_metadata("schema_version", "4");
+        builder.finish().unwrap();
+
+        let cache = unsafe { MmapCache::map_path(path) }.unwrap();
+        cache.verify().unwrap();
+        assert_eq!(cache.get_value(b"a"), Some(&b"first"[..]));
+        let metadata = cache.metadata().unwrap();
+        assert_eq!(metadata.len(), 2);
+        assert_eq!(metadata.get_str("schema_version"), Some("4"));
+        assert_eq!(metadata.get("dataset"), Some(&[0xff, 0][..]));
+        assert_eq!(metadata.get_str("dataset"), None);
+        assert_eq!(metadata.get("missing"), None);
+        let keys: Vec<_> = metadata.iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, ["dataset", "schema_version"]);
+        let sections: Vec<_> = cache.sections().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(
+            sections,
+            [
+                "lengths",
+                "rank samples",
+                "hash index",
+                "metadata",
+                "checksums"
+            ]
+        );
+
+        let cache = Cache::from_sorted_iter([("a", "b")]).unwrap();
+        assert!(cache.metadata().unwrap().is_empty());
+        assert!(!cache.sections().iter().any(|&(name, _)| name == "metadata"));
+    }
+
+    #[test]
+    fn value_schema_validation() {
+        let path = Path::new("/tmp/mmap_cache_test_value_schema_validation");
+        let mut builder = FileBuilder::create_file(path)
+            .unwrap()
+            .with_value_alignment(8)
+            .with_value_schema(&ValueSchema::of::<u64>());
+        builder.insert(b"a", &7u64.to_ne_bytes()).unwrap();
+        builder.finish().unwrap();
+
+        let cache = unsafe { MmapCache::map_path(path) }.unwrap();
+        assert_eq!(
+            cache.value_schema().unwrap(),
+            Some(ValueSchema::of::<u64>())
+        );
+        let typed = TypedCache::<u64, _, _>::new_checked(cache).unwrap();
+        assert_eq!(typed.get(b"a"), Some(&7));
+
+        let cache = typed.into_untyped();
+        let err = cache
+            .check_value_schema(&ValueSchema::of::<i64>())
+            .unwrap_err();
+        assert!(matches!(err, Error::SchemaMismatch { .. }));
+        assert!(err
+            .to_string()
+            .contains("expected i64 (size 8, align 8, version 0)"));
+        let versioned = ValueSchema::of::<u64>().with_version(2);
+        assert!(TypedCache::<u64, _, _>::with_schema(cache, &versioned).is_err());
+
+        let cache = Cache::from_sorted_iter([("a", "b")]).unwrap();
+        assert_eq!(cache.value_schema().unwrap(), None);
+        let err = TypedCache::<u8, _, _>::new_checked(cache).err().unwrap();
+        assert!(err.to_string().ends_with("found no schema"));
+    }
+
+    #[test]
+    fn value_size_histogram() {
+        let mut builder = MemoryBuilder::in_memory().unwrap();
+        builder.insert(b"a", b"").unwrap();
+        builder.insert(b"b", b"xyz").unwrap();
+        builder.insert_tombstone(b"c").unwrap();
+        builder.insert(b"d", &[0; 100]).unwrap();
+        let cache = builder.finish_into_cache().unwrap();
+        let mut sizes = cache.value_sizes(b"b".as_slice()..);
+        assert_eq!(sizes.next(), Some((&b"b"[..], 3)));
+        assert_eq!(sizes.next(), Some((&b"d"[..], 100)));
+        assert_eq!(sizes.next(), None);
+
+        let histogram = cache.value_size_histogram();
+        assert_eq!((histogram.count, histogram.total_bytes), (3, 103));
+        assert_eq!((histogram.min, histogram.max), (Some(0), Some(100)));
+        let buckets: Vec<_> = histogram.buckets().filter(|&(_, n)| n > 0).collect();
+        assert_eq!(buckets, [(0..1, 1), (2..4, 1), (64..128, 1)]);
+
+        // Without a length table, sizes are derived from the offsets of the following keys.
+        let index = fst::Map::from_iter([(b"a", 0), (b"b", 3), (b"c", 8)])
+            .unwrap()
+            .into_fst()
+            .into_inner();
+        let cache = Cache::new(index, b"xyzhello!".to_vec()).unwrap();
+        let mut sizes = cache.value_sizes(..=b"b".as_slice());
+        assert_eq!(sizes.next(), Some((&b"a"[..], 3)));
+        assert_eq!(sizes.next(), Some((&b"b"[..], 5)));
+        assert_eq!(sizes.next(), None);
+        assert_eq!(cache.value_size_histogram().total_bytes, 9);
+    }
+
+    #[test]
+    fn cache_observer() {
+        use std::sync::atomic::{AtomicU64, Ordering};
+        use std::sync::Arc;
+
+        #[derive(Default)]
+        struct Counters([AtomicU64; 4]);
+
+        impl CacheObserver for Counters {
+            fn on_get_hit(&self, _key: &[u8]) {
+                self.0[0].fetch_add(1, Ordering::Relaxed);
+            }
+            fn on_get_miss(&self, _key: &[u8]) {
+                self.0[1].fetch_add(1, Ordering::Relaxed);
+            }
+            fn on_range_start(&self) {
+                self.0[2].fetch_add(1, Ordering::Relaxed);
+            }
+            fn on_bytes_read(&self, bytes: u64) {
+                self.0[3].fetch_add(bytes, Ordering::Relaxed);
+            }
+        }
+
+        let counters = Arc::new(Counters::default());
+        let cache = Cache::from_sorted_iter([("a", "first"), ("b", "second")])
+            .unwrap()
+            .with_observer(counters.clone());
+        assert!(cache.get_value(b"a").is_some());
+        assert!(cache.get_value(b"c").is_none());
+        let mut stream = cache.range_values::<&[u8], _>(..);
+        while stream.next().is_some() {}
+
+        let counts = counters.0.each_ref().map(|n| n.load(Ordering::Relaxed));
+        assert_eq!(counts, [1, 1, 1, 16]);
+    }
+
+    #[test]
+    fn reload_replaced_files() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+        use std::time::{Duration, Instant};
+
+        let path = Path::new("/tmp/mmap_cache_test_reload_replaced_files");
+        let build = |value: &[u8]| {
+            let mut builder = FileBuilder::create_file_atomic(path).unwrap();
+            builder.insert(b"key", value).unwrap();
+            builder.finish().unwrap();
+        };
+        build(b"v1");
+        let reloadable = Arc::new(unsafe { ReloadableCache::open_path(path) }.unwrap());
+        let old = reloadable.current();
+        assert!(!reloadable.reload().unwrap());
+
+        build(b"v2!");
+        assert!(reloadable.reload().unwrap());
+        assert!(!reloadable.reload().unwrap());
+        assert_eq!(reloadable.current().get_value(b"key"), Some(&b"v2!"[..]));
+        // Readers of the old cache are unaffected.
+        assert_eq!(old.get_value(b"key"), Some(&b"v1"[..]));
+
+        // A corrupt file is rejected, and the active cache is kept.
+        let mut bytes = std::fs::read(path).unwrap();
+        bytes[0] ^= 1;
+        std::fs::write(path.with_extension("tmp"), &bytes).unwrap();
+        std::fs::rename(path.with_extension("tmp"), path).unwrap();
+        assert!(matches!(
+            reloadable.reload(),
+            Err(Error::ChecksumMismatch { .. })
+        ));
+        assert_eq!(reloadable.current().get_value(b"key"), Some(&b"v2!"[..]));
+
+        let errors = Arc::new(AtomicUsize::new(0));
+        let watcher = {
+            let errors = errors.clone();
+            reloadable
+                .watch(Duration::from_millis(1), move |_| {
+                    errors.fetch_add(1, Ordering::Relaxed);
+                })
+                .unwrap()
+        };
+        let wait_until = |done: &dyn Fn() -> bool| {
+            let start = Instant::now();
+            while !done() {
+                assert!(start.elapsed() < Duration::from_secs(10));
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        };
+        wait_until(&|| errors.load(Ordering::Relaxed) > 0);
+        build(b"v3");
+        wait_until(&|| reloadable.current().get_value(b"key") == Some(&b"v3"[..]));
+        drop(watcher);
+    }
+
+    #[test]
+    fn mutate_values_in_place() {
+        let (index_path, values_path) = test_paths("mutate_values_in_place");
+        let mut builder = FileBuilder::create_files(&index_path, &values_path)
+            .unwrap()
+            .with_value_alignment(8);
+        builder.insert(b"a", &1u64.to_ne_bytes()).unwrap();
+        builder.insert(b"b", &[0; 3]).unwrap();
+        builder.insert_tombstone(b"c").unwrap();
+        builder.insert(b"d", &4u64.to_ne_bytes()).unwrap();
+        builder.finish().unwrap();
+
+        let mut cache = unsafe { CacheMut::map_paths(&index_path, &values_path) }.unwrap();
+        *cache.get_pod_mut::<u64>(b"a").unwrap().unwrap() += 10;
+        cache.get_value_mut(b"b").unwrap().copy_from_slice(b"xyz");
+        assert!(cache.get_value_mut(b"c").is_none());
+        assert!(cache.get_pod_mut::<u64>(b"missing").unwrap().is_none());
+        assert!(matches!(
+            cache.get_pod_mut::<u64>(b"b"),
+            Err(Error::Decode(_))
+        ));
+        assert!(matches!(
+            cache.get_pod_mut::<u128>(b"d"),
+            Err(Error::Decode(_))
+        ));
+        cache.flush().unwrap();
+        drop(cache);
+
+        let cache = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+        assert_eq!(cache.get_value(b"a"), Some(&11u64.to_ne_bytes()[..]));
+        assert_eq!(cache.get_value(b"b"), Some(&b"xyz"[..]));
+        assert_eq!(cache.get_value(b"d"), Some(&4u64.to_ne_bytes()[..]));
+        assert!(cache.verify().is_err());
+
+        let path = std::env::temp_dir().join("mmap_cache_mutate_values_in_place");
+        let mut builder = FileBuilder::create_file(&path).unwrap();
+        builder.insert(b"n", &[0u8; 2]).unwrap();
+        builder.finish().unwrap();
+        let mut cache = unsafe { CacheMut::map_path(&path) }.unwrap();
+        // Values are only aligned to 1 byte by default.
+        *cache.get_pod_mut::<[u8; 2]>(b"n").unwrap().unwrap() = [1, 2];
+        cache.flush().unwrap();
+        let cache = unsafe { MmapCache::map_path(&path) }.unwrap();
+        assert_eq!(cache.get_value(b"n"), Some(&[1, 2][..]));
+    }
+
+    #[test]
+    fn resume_interrupted_build() {
+        let (index_path, values_path) = test_paths("resume_interrupted_build");
+        let checkpoint_path = std::env::temp_dir().join("mmap_cache_resume_interrupted_build");
+        let mut builder =
+            FileBuilder::create_files_resumable(&index_path, &values_path, &checkpoint_path)
+                .unwrap()
+                .with_value_alignment(8)
+                .with_hash_index();
+        builder.set_metadata("source", "test");
+        builder.insert(b"a", b"first").unwrap();
+        builder.insert_tombstone(b"b").unwrap();
+        builder.checkpoint().unwrap();
+        builder.insert(b"c", b"third").unwrap();
+        builder.checkpoint().unwrap();
+        // These are lost in the interruption.
+        builder.insert(b"d", b"lost").unwrap();
+        builder.append_value_bytes(b"partial").unwrap();
+        assert!(matches!(builder.checkpoint(), Err(Error::Unsupported(_))));
+        drop(builder);
+
+        let mut builder =
+            FileBuilder::resume_files(&index_path, &values_path, &checkpoint_path).unwrap();
+        assert_eq!(builder.last_committed_key(), Some(&b"c"[..]));
+        builder.insert(b"d", b"fourth").unwrap();
+        builder.insert(b"e", b"fifth").unwrap();
+        builder.finish().unwrap();
+        assert!(!checkpoint_path.exists());
+
+        let cache = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+        cache.verify().unwrap();
+        let mut entries = Vec::new();
+        let mut stream = cache.range_values::<&[u8], _>(..);
+        while let Some((key, value)) = stream.next() {
+            entries.push((key.to_vec(), value.to_vec()));
+        }
+        let expected: Vec<(Vec<u8>, Vec<u8>)> = [
+            ("a", "first"),
+            ("c", "third"),
+            ("d", "fourth"),
+            ("e", "fifth"),
+        ]
+        .map(|(k, v)| (k.into(), v.into()))
+        .to_vec();
+        assert_eq!(entries, expected);
+        assert!(cache.is_tombstone(b"b"));
+        assert!(cache.get_value_offset(b"e").unwrap().is_multiple_of(8));
+        assert_eq!(cache.metadata().unwrap().get_str("source"), Some("test"));
+        assert!(cache
+            .sections()
+            .iter()
+            .any(|&(name, _)| name == "hash index"));
+
+        let mut builder = FileBuilder::create_files(&index_path, &values_path).unwrap();
+        assert!(matches!(builder.checkpoint(), Err(Error::Unsupported(_))));
+        assert!(FileBuilder::resume_files(&index_path, &values_path, &checkpoint_path).is_err());
+    }
+
+    #[test]
+    fn export_snapshot() {
+        let path = Path::new("/tmp/mmap_cache_test_export_snapshot");
+        let (index_path, values_path) = test_paths("export_snapshot");
+        let mut builder = FileBuilder::create_file_atomic(path).unwrap();
+        builder.insert(b"a", b"old").unwrap();
+        builder.finish().unwrap();
+        let cache = unsafe { MmapCache::map_path(path) }.unwrap();
+
+        // Replace the original while it's still mapped.
+        let mut builder = FileBuilder::create_file_atomic(path).unwrap();
+        builder.insert(b"a", b"new").unwrap();
+        builder.finish().unwrap();
+
+        cache.export_verified_to(&index_path, &values_path).unwrap();
+        let exported = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+        exported.verify().unwrap();
+        assert_eq!(exported.get_value(b"a"), Some(&b"old"[..]));
+
+        let (index, mut values) = {
+            let mut builder = MemoryBuilder::in_memory().unwrap();
+            builder.insert(b"a", b"corrupt").unwrap();
+            builder.finish_into_inner().unwrap()
+        };
+        values[0] ^= 1;
+        let corrupt = Cache::new(index, values).unwrap();
+        let (index_path, values_path) = test_paths("export_snapshot_corrupt");
+        let _ = std::fs::remove_file(&index_path);
+        assert!(corrupt
+            .export_verified_to(&index_path, &values_path)
+            .is_err());
+        assert!(!index_path.exists());
+        corrupt.export_to(&index_path, &values_path).unwrap();
+        assert!(index_path.exists());
+    }
+
+    #[test]
+    fn compact_unreachable_values() {
+        let (index_path, values_path) = test_paths("compact_unreachable_values");
+        let mut builder = FileBuilder::create_files(&index_path, &values_path).unwrap();
+        builder.insert(b"a", b"first").unwrap();
+        builder.append_value_bytes(&[7; 100]).unwrap();
+        builder.insert_tombstone(b"b").unwrap();
+        builder.insert(b"c", b"third").unwrap();
+        builder.finish().unwrap();
+        let cache = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+
+        for drop_tombstones in [false, true] {
+            let (out_index_path, out_values_path) = test_paths("compact_unreachable_values_out");
+            let builder = FileBuilder::create_files(&out_index_path, &out_values_path).unwrap();
+            let stats = compact(&cache, drop_tombstones, builder).unwrap();
+            assert_eq!(
+                stats,
+                CompactionStats {
+                    entries: 3 - drop_tombstones as u64,
+                    dropped_tombstones: drop_tombstones as u64,
+                    dropped_expired: 0,
+                    input_value_bytes: cache.value_bytes().len() as u64,
+                    live_value_bytes: 10,
+                }
+            );
+
+            let compacted =
+                unsafe { MmapCache::map_paths(&out_index_path, &out_values_path) }.unwrap();
+            compacted.verify().unwrap();
+            assert!(compacted.value_bytes().len() < 20);
+            assert_eq!(compacted.get_value(b"a"), Some(&b"first"[..]));
+            assert_eq!(compacted.get_value(b"c"), Some(&b"third"[..]));
+            assert_eq!(compacted.is_tombstone(b"b"), !drop_tombstones);
+            assert_eq!(compacted.contains_key(b"b"), !drop_tombstones);
+        }
+    }
+
+    #[test]
+    fn sharded_cache() {
+        for routing in [
+            ShardRouting::Range(vec![b"c".to_vec(), b"k2".to_vec()]),
+            ShardRouting::Hash {
+                shards: 3,
+                prefix_len: Some(1),
+            },
+        ] {
+            assert_eq!(
+                routing.to_string().parse::<ShardRouting>().unwrap(),
+                routing
+            );
+
+            let base_path = "/tmp/mmap_cache_sharded_cache";
+            let mut builder = ShardedBuilder::create(base_path, routing.clone()).unwrap();
+            let keys: [&[u8]; 6] = [b"a", b"c", b"k1", b"k2", b"k3", b"z"];
+            for key in keys {
+                if key == b"k3" {
+                    builder.insert_tombstone(key).unwrap();
+                } else {
+                    builder.insert(key, &[key[0], b'!']).unwrap();
+                }
+            }
+            builder.finish().unwrap();
+
+            let cache = unsafe { MmapShardedCache::map(base_path) }.unwrap();
+            assert_eq!(cache.routing(), &routing);
+            assert_eq!(cache.shards().len(), 3);
+            assert_eq!(cache.len(), 6);
+            assert_eq!(cache.get_value(b"k1"), Some(&b"k!"[..]));
+            assert_eq!(cache.get_value(b"k3"), None);
+            assert!(cache.contains_key(b"k3"));
+            assert!(!cache.contains_key(b"b"));
+            // Hashing a one-byte prefix keeps the "k" keys together.
+            if let ShardRouting::Hash { .. } = routing {
+                let shard = routing.shard_for(b"k1");
+                assert!([b"k2", b"k3"]
+                    .iter()
+                    .all(|key| routing.shard_for(*key) == shard));
+            }
+
+            let mut stream = cache.range(&b"b"[..]..&b"z"[..]);
+            let mut keys = Vec::new();
+            while let Some((key, value)) = stream.next() {
+                assert_eq!(value, [key[0], b'!']);
+                keys.push(key.to_vec());
+            }
+            assert_eq!(keys, [&b"c"[..], b"k1", b"k2"]);
+        }
+
+        assert!(ShardedBuilder::create(
+            "/tmp/mmap_cache_sharded_cache_invalid",
+            ShardRouting::Range(vec![b"b".to_vec(), b"a".to_vec()])
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn reshard_keeps_expiry() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let at = |seconds| UNIX_EPOCH + Duration::from_secs(seconds);
+        let routing = ShardRouting::Range(vec![b"b".to_vec()]);
+        let mut shards = Vec::new();
+        for entries in [&[(b"a", Some(100))][..], &[(b"b", None), (b"c", Some(200))]] {
+            let mut builder = MemoryBuilder::in_memory().unwrap();
+            for &(key, expiry) in entries {
+                match expiry {
+                    Some(seconds) => builder.insert_with_expiry(key, key, at(seconds)).unwrap(),
+                    None => builder.insert(key, key).unwrap(),
+                }
+            }
+            shards.push(builder.finish_into_cache().unwrap());
+        }
+        let cache = ShardedCache::new(routing, shards).unwrap();
+
+        let base_path = "/tmp/mmap_cache_reshard_keeps_expiry";
+        let routing = ShardRouting::Range(vec![b"c".to_vec()]);
+        cache
+            .reshard(ShardedBuilder::create(base_path, routing).unwrap())
+            .unwrap();
+        let resharded = unsafe { MmapShardedCache::map(base_path) }.unwrap();
+        assert_eq!(resharded.shards()[0].expiry(b"a"), Some(at(100)));
+        assert_eq!(resharded.shards()[0].expiry(b"b"), None);
+        assert_eq!(resharded.shards()[1].expiry(b"c"), Some(at(200)));
+        assert_eq!(resharded.get_value(b"c"), Some(&b"c"[..]));
+    }
+
+    #[test]
+    fn consistent_hash_router() {
+        let keys: Vec<Vec<u8>> = (0..1000u32).map(|i| i.to_be_bytes().to_vec()).collect();
+        let ring = HashRing::new(8, 64).unwrap();
+        let grown = HashRing::new(9, 64).unwrap();
+        // Growing the ring only moves keys to the new shard.
+        let moved: Vec<_> = keys
+            .iter()
+            .filter(|key| ring.shard_for(key) != grown.shard_for(key))
+            .collect();
+        assert!(moved.iter().all(|key| grown.shard_for(key) == 8));
+        assert!(!moved.is_empty() && moved.len() < 250);
+
+        let base_path = "/tmp/mmap_cache_consistent_hash_router";
+        let mut builder =
+            ShardedBuilder::create(base_path, ShardRouting::Ring(ring.clone())).unwrap();
+        for key in &keys {
+            builder.insert(key, key).unwrap();
+        }
+        builder.finish().unwrap();
+        let cache = unsafe { MmapShardedCache::map(base_path) }.unwrap();
+        assert_eq!(cache.routing(), &ShardRouting::Ring(ring.clone()));
+
+        let grown_path = "/tmp/mmap_cache_consistent_hash_router_grown";
+        let builder =
+            ShardedBuilder::create(grown_path, ShardRouting::Ring(grown.clone())).unwrap();
+        cache.reshard(builder).unwrap();
+        let resharded = unsafe { MmapShardedCache::map(grown_path) }.unwrap();
+        assert_eq!(resharded.len(), keys.len());
+        assert_eq!(resharded.shards()[8].len(), moved.len());
+        assert_eq!(resharded.get_value(&keys[7]), Some(&keys[7][..]));
+
+        let mut router = ConsistentHashRouter::new(ring, 32).unwrap();
+        assert_eq!(router.node_for(b"key"), None);
+        assert!(router.add_node("a"));
+        assert!(router.add_node("b"));
+        assert!(!router.add_node("b"));
+        let before = router.clone();
+        assert!(router.add_node("c"));
+        let moves = before.moves_to(&router);
+        assert!(moves.iter().all(|m| m.to.as_deref() == Some("c")));
+        assert_eq!(
+            moves.iter().map(|m| m.shard).collect::<Vec<_>>(),
+            router.shards_for_node("c")
+        );
+        let served: usize = router
+            .nodes()
+            .iter()
+            .map(|node| router.shards_for_node(node).len())
+            .sum();
+        assert_eq!(served, 8);
+
+        let node = router.node_for(&keys[3]).unwrap();
+        let paths = router.shard_paths_for_node(base_path, node);
+        let (shard, index_path, values_path) = &paths
+            .iter()
+            .find(|(shard, ..)| *shard == router.shard_for(&keys[3]))
+            .unwrap();
+        assert_eq!(
+            (index_path.clone(), values_path.clone()),
+            shard_paths(base_path, *shard)
+        );
+        let shard_cache = unsafe { MmapCache::map_paths(index_path, values_path) }.unwrap();
+        assert_eq!(shard_cache.get_value(&keys[3]), Some(&keys[3][..]));
+
+        assert!(router.remove_node("c"));
+        assert!(!router.remove_node("c"));
+        assert!(router.moves_to(&before).is_empty());
+    }
+
+    #[test]
+    fn set_operations() {
+        let a = build_cache(
+            "set_operations_a",
+            &[(b"a", &[1]), (b"b", &[2]), (b"c", &[3])],
+        );
+        let b = build_cache("set_operations_b", &[(b"b", &[20]), (b"d", &[40])]);
+        let (index_path, values_path) = test_paths("set_operations_delta");
+        let mut builder = FileBuilder::create_files(&index_path, &values_path).unwrap();
+        builder.insert_tombstone(b"c").unwrap();
+        builder.insert(b"e", &[50]).unwrap();
+        builder.finish().unwrap();
+        let c = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+
+        fn collect(mut stream: CacheOpStream<'_, Mmap, Mmap>) -> Vec<(Vec<u8>, Vec<OpValue<'_>>)> {
+            let mut entries = Vec::new();
+            while let Some((key, values)) = stream.next() {
+                entries.push((key.to_vec(), values.to_vec()));
+            }
+            entries
+        }
+        let union = collect(a.op().with_cache(&b).with_cache(&c).union());
+        assert_eq!(union.len(), 5);
+        assert_eq!(
+            union[1],
+            (
+                b"b".to_vec(),
+                vec![
+                    OpValue {
+                        index: 0,
+                        value: Some(&[2]),
+                        expires_at: None
+                    },
+                    OpValue {
+                        index: 1,
+                        value: Some(&[20]),
+                        expires_at: None
+                    }
+                ]
+            )
+        );
+        assert_eq!(
+            union[2].1[1],
+            OpValue {
+                index: 2,
+                value: None,
+                expires_at: None
+            }
+        );
+
+        let keys = |entries: Vec<(Vec<u8>, Vec<OpValue>)>| {
+            entries.into_iter().map(|(key, _)| key).collect::<Vec<_>>()
+        };
+        assert_eq!(keys(collect(a.op().with_cache(&b).intersection())), [b"b"]);
+        assert_eq!(
+            keys(collect(a.op().with_cache(&b).difference())),
+            [b"a", b"c"]
+        );
+        assert_eq!(
+            keys(collect(a.op().with_cache(&b).symmetric_difference())),
+            [b"a", b"c", b"d"]
+        );
+
+        // Sum the values of conflicting keys, and drop keys that were deleted.
+        let (index_path, values_path) = test_paths("set_operations_out");
+        let builder = FileBuilder::create_files(&index_path, &values_path).unwrap();
+        a.op()
+            .with_cache(&b)
+            .with_cache(&c)
+            .union()
+            .write_to(builder, |_key, values| {
+                if values.iter().any(|v| v.value.is_none()) {
+                    return Ok(None);
+                }
+                let sum = values.iter().map(|v| v.value.unwrap()[0]).sum::<u8>();
+                Ok(Some(vec![sum].into()))
+            })
+            .unwrap();
+        let out = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+        assert_eq!(out.len(), 4);
+        assert_eq!(out.get_value(b"b"), Some(&[22][..]));
+        assert!(!out.contains_key(b"c"));
+        assert_eq!(out.get_value(b"e"), Some(&[50][..]));
+    }
+
+    #[test]
+    fn op_write_to_keeps_expiry() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let at = |seconds| UNIX_EPOCH + Duration::from_secs(seconds);
+        let mut builder = MemoryBuilder::in_memory().unwrap();
+        builder.insert_with_expiry(b"a", b"1", at(100)).unwrap();
+        builder.insert_with_expiry(b"b", b"1", at(100)).unwrap();
+        let a = builder.finish_into_cache().unwrap();
+        let mut builder = MemoryBuilder::in_memory().unwrap();
+        builder.insert(b"b", b"2").unwrap();
+        builder.insert_with_expiry(b"c", b"2", at(200)).unwrap();
+        let b = builder.finish_into_cache().unwrap();
+
+        let (index_path, values_path) = test_paths("op_write_to_keeps_expiry");
+        let builder = FileBuilder::create_files(&index_path, &values_path).unwrap();
+        a.op()
+            .with_cache(&b)
+            .union()
+            .write_to(builder, |_key, values| {
+                assert_eq!(values[0].expires_at, Some(at(100)));
+                assert_eq!(values[1].expires_at, None);
+                Ok(values[1].value.map(std::borrow::Cow::Borrowed))
+            })
+            .unwrap();
+        let out = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+        assert_eq!(out.expiry(b"a"), Some(at(100)));
+        assert_eq!(out.get_value(b"b"), Some(&b"2"[..]));
+        assert_eq!(out.expiry(b"b"), None);
+        assert_eq!(out.expiry(b"c"), Some(at(200)));
+    }
+
+    #[test]
+    fn diff_versions() {
+        let old = build_cache(
+            "diff_versions_old",
+            &[(b"a", b"1"), (b"b", b"1"), (b"c", b"1"), (b"d", b"1")],
+        );
+        let (index_path, values_path) = test_paths("diff_versions_new");
+        let mut builder = FileBuilder::create_files(&index_path, &values_path).unwrap();
+        builder.insert(b"a", b"1").unwrap();
+        builder.insert(b"b", b"2").unwrap();
+        builder.insert_tombstone(b"c").unwrap();
+        builder.insert(b"d", b"11").unwrap();
+        builder.insert_tombstone(b"e").unwrap();
+        builder.insert(b"f", b"1").unwrap();
+        builder.finish().unwrap();
+        let new = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+
+        let mut changes = diff(&old, &new);
+        assert_eq!(changes.next(), Some(DiffEntry::Changed(b"b")));
+        assert_eq!(changes.next(), Some(DiffEntry::Removed(b"c")));
+        assert_eq!(changes.next(), Some(DiffEntry::Changed(b"d")));
+        assert_eq!(changes.next(), Some(DiffEntry::Added(b"f")));
+        assert_eq!(changes.next(), None);
+        assert_eq!(diff(&new, &new).next(), None);
+        assert_eq!(
+            diff(&new, &old).next().map(|change| change.key()),
+            Some(&b"b"[..])
+        );
+    }
+
+    #[test]
+    fn patch_versions() {
+        let old = build_cache(
+            "patch_versions_old",
+            &[(b"a", b"1"), (b"b", b"1"), (b"c", b"1"), (b"d", b"1")],
+        );
+        let (index_path, values_path) = test_paths("patch_versions_new");
+        let mut builder = FileBuilder::create_files(&index_path, &values_path).unwrap();
+        builder.set_metadata("version", "2");
+        builder.insert(b"a", b"1").unwrap();
+        builder.insert(b"b", b"2").unwrap();
+        builder.insert_tombstone(b"c").unwrap();
+        builder.insert(b"e", b"2").unwrap();
+        builder.finish().unwrap();
+        let new = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+
+        let mut patch = Vec::new();
+        let stats = create_patch(&old, &new, &mut patch).unwrap();
+        assert_eq!(
+            stats,
+            PatchStats {
+                puts: 2,
+                tombstones: 1,
+                deletes: 1
+            }
+        );
+
+        let (index_path, values_path) = test_paths("patch_versions_patched");
+        let builder = FileBuilder::create_files_atomic(&index_path, &values_path).unwrap();
+        apply_patch(&old, patch.as_slice(), builder)
+            .unwrap()
+            .finish()
+            .unwrap();
+        let patched = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+        patched.verify().unwrap();
+        assert_eq!(diff(&new, &patched).next(), None);
+        assert!(patched.is_tombstone(b"c"));
+        assert_eq!(patched.len(), new.len());
+        assert_eq!(patched.metadata().unwrap().get_str("version"), Some("2"));
+
+        // The patch only applies to the cache it was created from.
+        assert!(apply_patch(&new, patch.as_slice(), MemoryBuilder::in_memory().unwrap()).is_err());
+        let mut corrupt = patch.clone();
+        let len = corrupt.len();
+        corrupt[len - 5] ^= 1;
+        assert!(matches!(
+            apply_patch(
+                &old,
+                corrupt.as_slice(),
+                MemoryBuilder::in_memory().unwrap()
+            ),
+            Err(Error::ChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn patch_expiry() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let at = |seconds| UNIX_EPOCH + Duration::from_secs(seconds);
+        let mut builder = MemoryBuilder::in_memory().unwrap();
+        builder.insert_with_expiry(b"a", b"1", at(100)).unwrap();
+        builder.insert(b"b", b"1").unwrap();
+        builder.insert_with_expiry(b"c", b"1", at(100)).unwrap();
+        let old = builder.finish_into_cache().unwrap();
+        let mut builder = MemoryBuilder::in_memory().unwrap();
+        builder.insert_with_expiry(b"a", b"1", at(100)).unwrap();
+        builder.insert_with_expiry(b"b", b"1", at(200)).unwrap();
+        builder.insert(b"c", b"1").unwrap();
+        builder.insert_with_expiry(b"d", b"2", at(300)).unwrap();
+        let new = builder.finish_into_cache().unwrap();
+
+        let mut patch = Vec::new();
+        let stats = create_patch(&old, &new, &mut patch).unwrap();
+        assert_eq!(stats.puts, 3);
+        let patched = apply_patch(&old, patch.as_slice(), MemoryBuilder::in_memory().unwrap())
+            .unwrap()
+            .finish_into_cache()
+            .unwrap();
+        assert_eq!(patched.expiry(b"a"), Some(at(100)));
+        assert_eq!(patched.expiry(b"b"), Some(at(200)));
+        assert_eq!(patched.expiry(b"c"), None);
+        assert_eq!(patched.expiry(b"d"), Some(at(300)));
+        assert_eq!(patched.get_value(b"d"), Some(&b"2"[..]));
+    }
+
+    #[test]
+    fn expiring_entries() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let at = |seconds| UNIX_EPOCH + Duration::from_secs(seconds);
+        let (index_path, values_path) = test_paths("expiring_entries");
+        let mut builder = FileBuilder::create_files(&index_path, &values_path).unwrap();
+        builder.insert_with_expiry(b"a", b"1", at(100)).unwrap();
+        builder.insert(b"b", b"2").unwrap();
+        builder.insert_with_expiry(b"c", b"3", at(200)).unwrap();
+        builder.finish().unwrap();
+        let cache = unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap();
+        cache.verify().unwrap();
+        assert_eq!(cache.expiry(b"a"), Some(at(100)));
+        assert_eq!(cache.expiry(b"b"), None);
+        assert_eq!(cache.get_value(b"a"), Some(&b"1"[..]));
+        assert!(cache.sections().iter().any(|(name, _)| *name == "expiry"));
+
+        let expiring = ExpiringCache::new(cache).at_time(at(150));
+        assert_eq!(expiring.get_value(b"a"), None);
+        assert!(!expiring.contains_key(b"a"));
+        assert_eq!(expiring.get_value(b"b"), Some(&b"2"[..]));
+        assert_eq!(expiring.get_value(b"c"), Some(&b"3"[..]));
+        let mut stream = expiring.range::<&[u8], _>(..);
+        let mut keys = Vec::new();
+        while let Some((key, _)) = stream.next() {
+            keys.push(key.to_vec());
+        }
+        assert_eq!(keys, [b"b", b"c"]);
+        let cache = expiring.into_cache();
+
+        let (out_index_path, out_values_path) = test_paths("expiring_entries_out");
+        let builder = FileBuilder::create_files(&out_index_path, &out_values_path).unwrap();
+        let stats = compact_expired(&cache, at(150), false, builder).unwrap();
+        assert_eq!(stats.dropped_expired, 1);
+        assert_eq!(stats.entries, 2);
+        let compacted = unsafe { MmapCache::map_paths(&out_index_path, &out_values_path) }.unwrap();
+        assert!(!compacted.contains_key(b"a"));
+        assert_eq!(compacted.expiry(b"c"), Some(at(200)));
+
+        let mut builder = MemoryBuilder::in_memory().unwrap().with_value_dedup();
+        assert!(matches!(
+            builder.insert_with_expiry(b"a", b"1", at(100)),
+            Err(Error::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn versioned_values() {
+        let (index_path, values_path) = test_paths("versioned_values");
+        let mut builder =
+            VersionedBuilder::new(FileBuilder::create_files(&index_path, &values_path).unwrap());
+        builder.insert_version(b"a", 1, b"a1").unwrap();
+        builder.insert_version(b"a", 3, b"a3").unwrap();
+        assert!(matches!(
+            builder.insert_version(b"a", 3, b"again"),
+            Err(Error::VersionOrder {
+                version: 3,
+                previous: 3,
+                ..
+            })
+        ));
+        builder.delete_version(b"a", 5).unwrap();
+        builder.insert_version(b"b", 2, b"").unwrap();
+        assert!(matches!(
+            builder.insert_version(b"a", 6, b"late"),
+            Err(Error::KeyOrder { key, previous, .. }) if key == b"a" && previous == b"b"
+        ));
+        builder.insert_version(b"c", 4, b"c4").unwrap();
+        builder.finish().unwrap();
+        let cache = VersionedCache::new(
+            unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap(),
+        );
+
+        assert_eq!(
+            cache.versions(b"a").unwrap().collect::<Vec<_>>(),
+            [(1, Some(&b"a1"[..])), (3, Some(&b"a3"[..])), (5, None)]
+        );
+        assert_eq!(
+            cache.versions(b"c").unwrap().collect::<Vec<_>>(),
+            [(4, Some(&b"c4"[..]))]
+        );
+        assert!(cache.versions(b"d").is_none());
+        assert_eq!(cache.as_of(0).get_value(b"a"), None);
+        assert_eq!(cache.as_of(2).get_value(b"a"), Some(&b"a1"[..]));
+        assert_eq!(cache.as_of(4).get_value(b"a"), Some(&b"a3"[..]));
+        assert_eq!(cache.get_latest(b"a"), None);
+        assert_eq!(cache.get_latest(b"b"), Some(&b""[..]));
+
+        let keys_as_of = |version| {
+            let snapshot = cache.as_of(version);
+            let mut stream = snapshot.range::<&[u8], _>(..);
+            let mut keys = Vec::new();
+            while let Some((key, _)) = stream.next() {
+                keys.push(key.to_vec());
+            }
+            keys
+        };
+        assert_eq!(keys_as_of(2), [b"a", b"b"]);
+        assert_eq!(keys_as_of(4), [b"a", b"b", b"c"]);
+        assert_eq!(keys_as_of(5), [b"b", b"c"]);
+    }
+
+    #[test]
+    fn store_writes() {
+        use std::io::Write;
+
+        let dir = std::env::temp_dir().join("mmap_cache_store_writes");
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut store = Store::open(&dir).unwrap().with_flush_threshold(64);
+        store.put(b"a", b"1").unwrap();
+        store.put(b"b", b"1").unwrap();
+        store.flush().unwrap();
+        assert_eq!(store.segments().layers().len(), 1);
+        assert_eq!(store.unflushed_bytes(), 0);
+
+        store.put(b"a", b"2").unwrap();
+        store.delete(b"b").unwrap();
+        store.put(b"c", b"2").unwrap();
+        assert_eq!(store.get(b"a"), Some(&b"2"[..]));
+        assert_eq!(store.get(b"b"), None);
+        assert_eq!(store.unflushed_bytes(), 5);
+        drop(store);
+
+        // A write cut off by a crash is discarded when the log is replayed.
+        let mut wal = std::fs::OpenOptions::new()
+            .append(true)
+            .open(dir.join("wal"))
+            .unwrap();
+        wal.write_all(&[0; 10]).unwrap();
+        drop(wal);
+        let mut store = Store::open(&dir).unwrap().with_flush_threshold(64);
+        assert_eq!(store.get(b"a"), Some(&b"2"[..]));
+        assert_eq!(store.get(b"b"), None);
+        assert_eq!(store.get(b"c"), Some(&b"2"[..]));
+        assert_eq!(store.segments().layers().len(), 1);
+
+        // Reaching the threshold flushes the table.
+        store.put(b"d", &[3; 64]).unwrap();
+        assert_eq!(store.segments().layers().len(), 2);
+        assert_eq!(store.unflushed_bytes(), 0);
+        drop(store);
+        let store = Store::open(&dir).unwrap();
+        assert_eq!(store.unflushed_bytes(), 0);
+        assert_eq!(store.get(b"b"), None);
+        assert!(store.contains_key(b"c"));
+        assert_eq!(store.get(b"d"), Some(&[3; 64][..]));
+    }
+
+    #[test]
+    fn send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<MmapCache>();
+        assert_send_sync::<Cache<Vec<u8>, Vec<u8>>>();
+        assert_send_sync::<Cache<&[u8], &[u8]>>();
+
+        let cache = build_cache("send_sync", &[(b"apple", b"red"), (b"peach", b"pink")]);
+        let values: Vec<_> = std::thread::scope(|scope| {
+            let workers: Vec<_> = [&b"apple"[..], b"peach", b"plumb"]
+                .into_iter()
+                .map(|key| scope.spawn(|| cache.get_value(key)))
+                .collect();
+            workers
+                .into_iter()
+                .map(|worker| worker.join().unwrap())
+                .collect()
+        });
+        assert_eq!(values, [Some(&b"red"[..]), Some(&b"pink"[..]), None]);
+    }
+
+    #[test]
+    fn shared_cache() {
+        fn assert_send_sync<T: Clone + Send + Sync>() {}
+        assert_send_sync::<SharedCache>();
+
+        let cache = SharedCache::from(build_cache(
+            "shared",
+            &[(b"apple", b"red"), (b"peach", b"pink")],
+        ));
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                let cache = cache.clone();
+                std::thread::spawn(move || {
+                    let mut count = 0;
+                    let mut stream = cache.range_values::<&[u8], _>(..);
+                    while stream.next().is_some() {
+                        count += 1;
+                    }
+                    (cache.get_value(b"peach").map(<[u8]>::to_vec), count)
+                })
+            })
+            .collect();
+        drop(cache);
+        for worker in workers {
+            assert_eq!(worker.join().unwrap(), (Some(b"pink".to_vec()), 2));
+        }
+    }
+
+    #[test]
+    fn build_progress() {
+        let reports = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let (index_path, values_path) = test_paths("progress");
+        let mut builder = FileBuilder::create_files(&index_path, &values_path)
+            .unwrap()
+            .with_progress(2, {
+                let reports = reports.clone();
+                move |progress| {
+                    reports.lock().unwrap().push((
+                        progress.entries,
+                        progress.value_bytes,
+                        progress.key.to_vec(),
+                    ))
+                }
+            });
+        for key in [b"a", b"b", b"c", b"d", b"e"] {
+            builder.insert(key, b"123").unwrap();
+        }
+        builder.finish().unwrap();
+
+        assert_eq!(
+            *reports.lock().unwrap(),
+            [(2, 6, b"b".to_vec()), (4, 12, b"d".to_vec())]
+        );
+    }
+
+    #[test]
+    fn cancelled_builds() {
+        let dir = std::env::temp_dir().join("mmap_cache_cancelled_builds");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir(&dir).unwrap();
+        let is_empty = |dir: &Path| std::fs::read_dir(dir).unwrap().next().is_none();
+
+        let token = CancellationToken::new();
+        let mut builder = FileBuilder::create_files_atomic(dir.join("index"), dir.join("values"))
+            .unwrap()
+            .with_cancellation(token.clone());
+        builder.insert(b"a", b"1").unwrap();
+        token.cancel();
+        assert!(matches!(builder.insert(b"b", b"2"), Err(Error::Cancelled)));
+        assert!(matches!(builder.finish(), Err(Error::Cancelled)));
+        assert!(is_empty(&dir));
+
+        let token = CancellationToken::new();
+        let mut external = ExternalSortBuilder::new(64)
+            .with_spill_dir(&dir)
+            .with_cancellation(token.clone());
+        for i in 0..100u32 {
+            external.insert(&i.to_be_bytes(), b"value").unwrap();
+        }
+        assert!(!is_empty(&dir));
+        token.cancel();
+        assert!(matches!(
+            external.insert(b"key", b"value"),
+            Err(Error::Cancelled)
+        ));
+        drop(external);
+        assert!(is_empty(&dir));
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let caches = [build_cache("cancelled_merge", &[(b"a", b"1")])];
+        let builder = FileBuilder::create_files_atomic(dir.join("index"), dir.join("values"))
+            .unwrap()
+            .with_cancellation(token);
+        assert!(matches!(
+            merge(&caches, MergeOptions::default(), builder),
+            Err(Error::Cancelled)
+        ));
+        assert!(is_empty(&dir));
+    }
+
+    #[test]
+    fn builder_key_order() {
+        let mut builder = MemoryBuilder::in_memory().unwrap();
+        builder.insert(b"b", b"1").unwrap();
+        builder.insert(b"c", b"2").unwrap();
+        let error = builder.insert(b"a", b"3").unwrap_err();
+        assert!(matches!(
+            &error,
+            Error::KeyOrder { key, previous, index: 2 } if key == b"a" && previous == b"c"
+        ));
+        assert_eq!(
+            error.to_string(),
+            "entry 2 has key [97], which doesn't sort after the previous key [99]"
+        );
+        assert!(matches!(
+            builder.insert_tombstone(b"c"),
+            Err(Error::KeyOrder { index: 2, .. })
+        ));
+        builder.append_value_bytes(b"4").unwrap();
+        assert!(matches!(
+            builder.commit_entry(b"c"),
+            Err(Error::KeyOrder { .. })
+        ));
+        builder.commit_entry(b"d").unwrap();
+
+        let cache = builder.finish_into_cache().unwrap();
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.get_value(b"d"), Some(&b"4"[..]));
+
+        // Rejected keys leave no value bytes behind for the next entry.
+        let mut builder = MemoryBuilder::in_memory().unwrap();
+        builder.insert_encoded(b"b", &Utf8Codec, "1").unwrap();
+        assert!(matches!(
+            builder.insert_encoded(b"a", &Utf8Codec, "2"),
+            Err(Error::KeyOrder { .. })
+        ));
+        builder.insert_encoded(b"c", &Utf8Codec, "3").unwrap();
+        let cache = builder.finish_into_cache().unwrap();
+        assert_eq!(cache.get_value(b"c"), Some(&b"3"[..]));
+
+        let (index_path, values_path) = test_paths("builder_key_order_multimap");
+        let mut builder =
+            MultiMapBuilder::new(FileBuilder::create_files(&index_path, &values_path).unwrap());
+        builder.insert(b"b", [b"1"]).unwrap();
+        assert!(matches!(
+            builder.insert(b"a", [b"2", b"3"]),
+            Err(Error::KeyOrder { .. })
+        ));
+        builder.insert(b"c", [b"4"]).unwrap();
+        builder.finish().unwrap();
+        let cache =
+            MultiMapCache::new(unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap());
+        assert_eq!(
+            cache.get_all(b"c").unwrap().collect::<Vec<_>>(),
+            [&b"4"[..]]
+        );
+    }
+
+    #[test]
+    fn debug_summary() {
+        let long_key = [b'k'; 40];
+        let cache =
+            Cache::from_sorted_iter([(&b"a\n"[..], &b"1"[..]), (&long_key[..], &b"22"[..])])
+                .unwrap();
+        let debug = format!("{cache:?}");
+        let index_bytes = cache.index().as_fst().as_bytes().len();
+        assert_eq!(
+            debug,
+            format!(
+                "Cache {{ len: 2, index_bytes: {index_bytes}, value_bytes: 3, first_key: Some(\"a\\n\"), \
+                 last_key: Some(\"{}\"...), .. }}",
+                "k".repeat(32)
+            )
+        );
+
+        let empty = Cache::from_sorted_iter(std::iter::empty::<(&[u8], &[u8])>()).unwrap();
+        assert!(format!("{empty:?}").contains("len: 0"));
+        assert!(format!("{empty:?}").contains("first_key: None, last_key: None"));
+    }
+
+    #[test]
+    fn cache_options() {
+        let (index_path, values_path) = test_paths("cache_options");
+        serialize_example_to(&index_path, &values_path);
+        let options = CacheOptions::new()
+            .populate(true)
+            .lock(true)
+            .verify_index(true)
+            .verify_checksums(true)
+            .access(Access::Random);
+        let cache = unsafe { options.open(&index_path, &values_path) }.unwrap();
+        assert_eq!(cache.len(), 5);
+        assert_eq!(CacheOptions::new(), CacheOptions::default());
+
+        let path = std::env::temp_dir().join("mmap_cache_cache_options");
+        let mut builder = FileBuilder::create_file(&path).unwrap();
+        builder.insert(b"a", b"b").unwrap();
+        builder.finish().unwrap();
+        let cache = unsafe { options.open_path(&path) }.unwrap();
+        assert_eq!(cache.get_value(b"a"), Some(&b"b"[..]));
+
+        let mut values = std::fs::read(&values_path).unwrap();
+        values[0] ^= 1;
+        std::fs::write(&values_path, values).unwrap();
+        unsafe { CacheOptions::new().open(&index_path, &values_path) }.unwrap();
+        let error = unsafe { options.open(&index_path, &values_path) }
+            .err()
+            .unwrap();
+        assert_eq!(error.path(), Some(values_path.as_path()));
+        assert!(matches!(error.root_cause(), Error::ChecksumMismatch { .. }));
+    }
+
+    #[test]
+    fn evict() {
+        let pairs: Vec<_> = (0..1000u32)
+            .map(|i| (format!("{i:04}").into_bytes(), vec![i as u8; 100]))
+            .collect();
+        let pairs: Vec<_> = pairs
+            .iter()
+            .map(|(key, value)| (key.as_slice(), value.as_slice()))
+            .collect();
+        let cache = build_cache("evict", &pairs);
+        let mut stream = cache.range_values::<&[u8], _>(..);
+        while stream.next().is_some() {}
+
+        cache.evict().unwrap();
+        cache.evict_range(5000, 20_000).unwrap();
+        cache.evict_range(100_000, 0).unwrap();
+        assert!(matches!(
+            cache.evict_range(99_999, 2),
+            Err(Error::OutOfBounds {
+                available: 100_000,
+                ..
+            })
+        ));
+        assert_eq!(cache.get_value(b"0123"), Some(&[123; 100][..]));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn residency() {
+        let value = vec![7; 1 << 20];
+        let cache = build_cache("residency", &[(b"big", &value)]);
+        let before = cache.residency().unwrap();
+        let page_size = before.page_size as usize;
+        assert_eq!(
+            before.values.total_pages as usize,
+            cache.raw_value_bytes().len().div_ceil(page_size)
+        );
+        assert!(before.index.total_pages >= 1);
+
+        assert!(cache.get_value(b"big").unwrap().iter().all(|&b| b == 7));
+        let after = cache.residency().unwrap();
+        assert!(after.values.resident_pages as usize >= value.len() / page_size);
+        assert!(after.values.fraction() > 0.9);
+        assert_eq!(
+            after.total().total_pages,
+            after.index.total_pages + after.values.total_pages
+        );
+        assert_eq!(Residency::default().fraction(), 1.0);
+    }
+
+    #[test]
+    fn warm_up() {
+        let value = vec![7; 10_000];
+        let cache = build_cache("warm_up", &[(b"apple", b"red"), (b"big", &value)]);
+        let index_len = cache.index().as_fst().as_bytes().len() as u64;
+        assert_eq!(cache.warm_index().bytes, index_len);
+        assert_eq!(
+            cache.warm_all().bytes,
+            index_len + cache.raw_value_bytes().len() as u64
+        );
+        #[cfg(unix)]
+        assert_eq!(cache.residency().unwrap().index.fraction(), 1.0);
+    }
+
+    const INDEX_PATH: &str = "/tmp/mmap_cache_index";
+    const VALUES_PATH: &str = "/tmp/mmap_cache_values";
+
+    const PAIRS: [(&[u8], [i32; 3]); 5] = [
+        (b"cat", [1, 2, 3]),
+        (b"dog", [2, 3, 4]),
+        (b"doggy", [3, 4, 5]),
+        (b"frog", [4, 5, 6]),
+        (b"goose", [5, 6, 7]),
+    ];
+
+    fn test_paths(name: &str) -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir();
+        (
+            dir.join(format!("mmap_cache_{name}_index")),
+            dir.join(format!("mmap_cache_{name}_values")),
+        )
+    }
+
+    fn build_cache(name: &str, pairs: &[(&[u8], &[u8])]) -> MmapCache {
+        let (index_path, values_path) = test_paths(name);
+        let mut builder = FileBuilder::create_files(&index_path, &values_path).unwrap();
+        for (key, value) in pairs {
+            builder.insert(key, value).unwrap();
+        }
+        builder.finish().unwrap();
+        unsafe { MmapCache::map_paths(&index_path, &values_path) }.unwrap()
+    }
+
+    fn serialize_example() {
+        serialize_example_to(INDEX_PATH, VALUES_PATH);
+    }
+
+    fn serialize_example_to(index_path: impl AsRef<Path>, values_path: impl AsRef<Path>) {
+        let mut builder = FileBuilder::create_files(index_path, values_path).unwrap();
+        for (key, value) in PAIRS {
+            builder.insert(key, cast_slice(&value)).unwrap();
+        }
+        builder.finish().unwrap();
+    }
+}
+
+/// Tests that don't need memory mapping or the file system, so they also run without the `mmap` feature.
+#[cfg(test)]
+mod memory_tests {
+    use super::*;
+
+    use fst::Streamer;
+
+    fn build(pairs: &[(&[u8], &[u8])]) -> (Vec<u8>, Vec<u8>) {
+        let mut builder = MemoryBuilder::in_memory().unwrap();
+        for (key, value) in pairs {
+            builder.insert(key, value).unwrap();
+        }
+        builder.insert_tombstone(b"zzz").unwrap();
+        builder.set_metadata("source", "memory_tests");
+        builder.finish_into_inner().unwrap()
+    }
+
+    #[test]
+    fn in_memory_build() {
+        let (index, values) = build(&[(b"apple", b"red"), (b"peach", b"pink")]);
+        let cache = Cache::new(index, values).unwrap();
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.get_value(b"apple"), Some(&b"red"[..]));
+        assert_eq!(cache.get_value(b"plumb"), None);
+        assert!(cache.is_tombstone(b"zzz"));
+        assert_eq!(
+            cache.metadata().unwrap().get_str("source"),
+            Some("memory_tests")
+        );
+        cache.verify().unwrap();
+
+        let mut stream = cache.range_values::<&[u8], _>(..);
+        assert_eq!(stream.next(), Some((&b"apple"[..], &b"red"[..])));
+        assert_eq!(stream.next(), Some((&b"peach"[..], &b"pink"[..])));
+        assert_eq!(stream.next(), None);
+
+        let cache = Cache::from_sorted_iter([("a", "1"), ("b", "22")]).unwrap();
+        assert_eq!(cache.get_value(b"b"), Some(&b"22"[..]));
+    }
+
+    #[test]
+    fn container_bytes() {
+        let (index, mut container) = build(&[(b"apple", b"red"), (b"peach", b"pink")]);
+        let values_len = container.len() as u64;
+        container.extend_from_slice(&index);
+        format::write_container_footer(&mut container, values_len, index.len() as u64).unwrap();
+
+        let cache = Cache::from_container_bytes(container.clone()).unwrap();
+        assert_eq!(cache.get_value(b"peach"), Some(&b"pink"[..]));
+        assert!(cache.is_tombstone(b"zzz"));
+        cache.verify().unwrap();
+
+        let last = container.len() - 1;
+        container[last] ^= 1;
+        assert!(matches!(
+            Cache::from_container_bytes(container),
+            Err(Error::InvalidFormat(_))
+        ));
+        assert!(Cache::from_container_bytes(vec![0; 8]).is_err());
     }
 }
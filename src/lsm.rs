@@ -0,0 +1,311 @@
+use crate::checksum::{crc32, Crc32};
+use crate::{format, Error, LayeredCache, MmapCache, SegmentManifest};
+
+use memmap2::Mmap;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+
+// The write-ahead log is laid out as:
+//
+// [magic][version: u32][record]*
+//
+// Each record is `(crc: u32, tag: u8, key_len: u64, value_len: u64, key bytes, value bytes)`, where the CRC-32 covers the
+// rest of the record, and `DELETE` records have no value bytes. A record that is cut off or fails its checksum ends the log.
+//
+// All integers are little-endian.
+
+const WAL_MAGIC: [u8; 8] = *b"MMAPWAL_";
+const WAL_VERSION: u32 = 1;
+const WAL_HEADER_LEN: u64 = 12;
+
+const PUT: u8 = 1;
+const DELETE: u8 = 2;
+
+const MANIFEST_FILE: &str = "segments";
+const WAL_FILE: &str = "wal";
+
+/// The default [`Store::with_flush_threshold`].
+pub const DEFAULT_FLUSH_THRESHOLD: usize = 64 << 20;
+
+/// A read-mostly key-value store that accepts writes, built from immutable cache segments.
+///
+/// Writes are appended to a write-ahead log and applied to an in-memory table. Once the table grows past the flush
+/// threshold, it's frozen and written out as a new segment (see [`SegmentManifest`]), after which the log starts over.
+/// Reads consult the in-memory table first, then the segments from newest to oldest, like a [`LayeredCache`].
+///
+/// All files live in one directory: the segment manifest, the segments, and the log. Reopening the directory replays the
+/// log, so no acknowledged write is lost, though writes are only durable against power loss with
+/// [`with_synced_writes`](Self::with_synced_writes).
+///
+/// ```
+/// # use mmap_cache::Error;
+/// # fn example() -> Result<(), Error> {
+/// use mmap_cache::Store;
+///
+/// # let _ = std::fs::remove_dir_all("/tmp/mmap_cache_store_doc");
+/// let mut store = Store::open("/tmp/mmap_cache_store_doc")?;
+/// store.put(b"apple", b"red")?;
+/// store.flush()?;
+/// store.put(b"apple", b"green")?;
+/// assert_eq!(store.get(b"apple"), Some(&b"green"[..]));
+///
+/// let store = Store::open("/tmp/mmap_cache_store_doc")?;
+/// assert_eq!(store.get(b"apple"), Some(&b"green"[..]));
+/// # Ok(())
+/// # }
+/// # example().unwrap();
+/// ```
+pub struct Store {
+    dir: PathBuf,
+    manifest: SegmentManifest,
+    segments: LayeredCache<Mmap, Mmap>,
+    wal: Wal,
+    /// Unflushed writes, where `None` is a deletion.
+    memtable: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+    memtable_bytes: usize,
+    flush_threshold: usize,
+    sync_writes: bool,
+}
+
+impl Store {
+    /// Opens the store in the directory at `dir`, creating it if it doesn't exist, and replays any writes that were not
+    /// flushed yet.
+    ///
+    /// The segments are memory-mapped, so they must not be modified by other processes while the store is open.
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, Error> {
+        let dir = dir.as_ref().to_owned();
+        fs::create_dir_all(&dir)?;
+        let manifest = SegmentManifest::open(dir.join(MANIFEST_FILE))?;
+        // SAFETY: The segments are immutable once committed, and the store owns its directory.
+        let segments = unsafe { manifest.map_layers()? };
+        let mut store = Self {
+            wal: Wal::open(dir.join(WAL_FILE))?,
+            dir,
+            manifest,
+            segments,
+            memtable: BTreeMap::new(),
+            memtable_bytes: 0,
+            flush_threshold: DEFAULT_FLUSH_THRESHOLD,
+            sync_writes: false,
+        };
+        for (key, value) in store.wal.replay()? {
+            store.apply(key, value);
+        }
+        Ok(store)
+    }
+
+    /// Flushes the in-memory table to a new segment once it holds about `bytes` of keys and values.
+    pub fn with_flush_threshold(mut self, bytes: usize) -> Self {
+        self.flush_threshold = bytes;
+        self
+    }
+
+    /// Syncs the log to storage after every write, so acknowledged writes survive power loss, at the cost of much slower
+    /// writes. Otherwise writes are only flushed to the operating system.
+    pub fn with_synced_writes(mut self) -> Self {
+        self.sync_writes = true;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The flushed segments, from oldest to newest.
+    pub fn segments(&self) -> &LayeredCache<Mmap, Mmap> {
+        &self.segments
+    }
+
+    /// The approximate size of the keys and values that have not been flushed to a segment yet.
+    pub fn unflushed_bytes(&self) -> usize {
+        self.memtable_bytes
+    }
+
+    /// Returns the newest value of `key`, unless it doesn't exist or was deleted.
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        match self.memtable.get(key) {
+            Some(value) => value.as_deref(),
+            None => self.segments.get_value(key),
+        }
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Sets the value of `key`, flushing the in-memory table if it's full.
+    pub fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        self.write(key, Some(value))
+    }
+
+    /// Deletes `key`, flushing the in-memory table if it's full.
+    pub fn delete(&mut self, key: &[u8]) -> Result<(), Error> {
+        self.write(key, None)
+    }
+
+    fn write(&mut self, key: &[u8], value: Option<&[u8]>) -> Result<(), Error> {
+        self.wal.append(key, value)?;
+        if self.sync_writes {
+            self.wal.sync()?;
+        }
+        self.apply(key.to_vec(), value.map(<[u8]>::to_vec));
+        if self.memtable_bytes >= self.flush_threshold {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, key: Vec<u8>, value: Option<Vec<u8>>) {
+        let (key_len, value_len) = (key.len(), value.as_ref().map_or(0, Vec::len));
+        match self.memtable.insert(key, value) {
+            Some(old) => self.memtable_bytes -= old.map_or(0, |old| old.len()),
+            None => self.memtable_bytes += key_len,
+        }
+        self.memtable_bytes += value_len;
+    }
+
+    /// Writes the in-memory table to a new segment and starts a new log. Does nothing if there are no unflushed writes.
+    ///
+    /// If this fails, the unflushed writes stay in memory and in the log, so the flush can be retried.
+    pub fn flush(&mut self) -> Result<(), Error> {
+        if self.memtable.is_empty() {
+            return Ok(());
+        }
+        let frozen = mem::take(&mut self.memtable);
+        let result = self.manifest.append_segment(|builder| {
+            for (key, value) in &frozen {
+                match value {
+                    Some(value) => builder.insert(key, value)?,
+                    None => builder.insert_tombstone(key)?,
+                }
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            self.memtable = frozen;
+            return Err(e);
+        }
+        let segment = self.manifest.segments().last().unwrap();
+        // SAFETY: See `open`.
+        match unsafe { MmapCache::map_paths(&segment.index_path, &segment.value_path) } {
+            Ok(layer) => self.segments.push(layer),
+            Err(e) => {
+                self.memtable = frozen;
+                return Err(e);
+            }
+        }
+        self.memtable_bytes = 0;
+        // A crash before the log is reset only replays writes that are already in the segment.
+        self.wal.reset()?;
+        Ok(())
+    }
+}
+
+/// A logged write of a key, where a value of `None` is a deletion.
+type Record = (Vec<u8>, Option<Vec<u8>>);
+
+/// The write-ahead log of a [`Store`].
+struct Wal {
+    path: PathBuf,
+    writer: io::BufWriter<fs::File>,
+}
+
+impl Wal {
+    fn open(path: PathBuf) -> io::Result<Self> {
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        let mut wal = Self {
+            path,
+            writer: io::BufWriter::new(file),
+        };
+        if wal.writer.get_ref().metadata()?.len() < WAL_HEADER_LEN {
+            wal.reset()?;
+        }
+        Ok(wal)
+    }
+
+    /// Reads every intact record, then truncates the log after the last one so new records follow it.
+    fn replay(&mut self) -> Result<Vec<Record>, Error> {
+        let mut reader = io::BufReader::new(fs::File::open(&self.path)?);
+        let mut header = [0; WAL_HEADER_LEN as usize];
+        reader.read_exact(&mut header)?;
+        if header[..8] != WAL_MAGIC || format::read_u32(&header, 8) != WAL_VERSION {
+            return Err(Error::InvalidFormat("not a write-ahead log"));
+        }
+        let mut records = Vec::new();
+        let mut len = WAL_HEADER_LEN;
+        while let Ok(Some((record, record_len))) = read_record(&mut reader) {
+            records.push(record);
+            len += record_len;
+        }
+        let file = self.writer.get_mut();
+        file.set_len(len)?;
+        file.seek(SeekFrom::End(0))?;
+        Ok(records)
+    }
+
+    fn append(&mut self, key: &[u8], value: Option<&[u8]>) -> io::Result<()> {
+        let mut record = Vec::with_capacity(17 + key.len() + value.map_or(0, <[u8]>::len));
+        record.push(if value.is_some() { PUT } else { DELETE });
+        record.extend_from_slice(&(key.len() as u64).to_le_bytes());
+        record.extend_from_slice(&(value.map_or(0, <[u8]>::len) as u64).to_le_bytes());
+        record.extend_from_slice(key);
+        record.extend_from_slice(value.unwrap_or_default());
+        self.writer.write_all(&crc32(&record).to_le_bytes())?;
+        self.writer.write_all(&record)?;
+        self.writer.flush()
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        self.writer.get_ref().sync_data()
+    }
+
+    /// Discards every record.
+    fn reset(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        let file = self.writer.get_mut();
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(&WAL_MAGIC)?;
+        self.writer.write_all(&WAL_VERSION.to_le_bytes())?;
+        self.writer.flush()?;
+        self.sync()
+    }
+}
+
+/// Reads the next record and its length in bytes, or returns `None` at the end of `reader`.
+fn read_record(reader: &mut impl BufRead) -> io::Result<Option<(Record, u64)>> {
+    if reader.fill_buf()?.is_empty() {
+        return Ok(None);
+    }
+    let mut head = [0; 21];
+    reader.read_exact(&mut head)?;
+    let tag = head[4];
+    let key_len = format::read_u64(&head, 5);
+    let value_len = format::read_u64(&head, 13);
+    let mut body = Vec::new();
+    reader
+        .take(key_len.saturating_add(value_len))
+        .read_to_end(&mut body)?;
+    if body.len() as u64 != key_len.saturating_add(value_len) {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    let mut crc = Crc32::default();
+    crc.update(&head[4..]);
+    crc.update(&body);
+    if crc.finish() != format::read_u32(&head, 0) || !matches!(tag, PUT | DELETE) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "corrupt write-ahead log record",
+        ));
+    }
+    let value = (tag == PUT).then(|| body.split_off(key_len as usize));
+    Ok(Some(((body, value), 21 + key_len + value_len)))
+}
@@ -0,0 +1,146 @@
+use crate::{Cache, Error, FileBuilder, KeyEncode, ValueCodec, ValueStream};
+
+use fst::Streamer;
+use std::io;
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+
+/// A typed facade over a [`Cache`], with keys of type `K` encoded by [`KeyEncode`] and values decoded by the codec `V`.
+///
+/// Build the files with [`FileBuilder::insert_typed`] using the same key type and codec.
+///
+/// ```
+/// # use mmap_cache::Error;
+/// # #[cfg(feature = "mmap")]
+/// # fn example() -> Result<(), Error> {
+/// use mmap_cache::{FileBuilder, Map, MmapCache, Utf8Codec};
+///
+/// let mut builder = FileBuilder::create_files("/tmp/mmap_cache_map_index", "/tmp/mmap_cache_map_values")?;
+/// for (id, name) in [(1u64, "one"), (2, "two"), (10, "ten")] {
+///     builder.insert_typed(&id, &Utf8Codec, name)?;
+/// }
+/// builder.finish()?;
+///
+/// let cache = unsafe { MmapCache::map_paths("/tmp/mmap_cache_map_index", "/tmp/mmap_cache_map_values")? };
+/// let map = Map::<u64, _, _, _>::new(cache, Utf8Codec);
+/// assert_eq!(map.get(&10), Some("ten"));
+/// let names = map.range(2..).collect::<Result<Vec<_>, _>>()?;
+/// assert_eq!(names, [(2, "two"), (10, "ten")]);
+/// # Ok(())
+/// # }
+/// # #[cfg(feature = "mmap")]
+/// # example().unwrap();
+/// ```
+pub struct Map<K, V, DK, DV> {
+    cache: Cache<DK, DV>,
+    codec: V,
+    marker: PhantomData<fn() -> K>,
+}
+
+impl<K, V, DK, DV> Map<K, V, DK, DV>
+where
+    K: KeyEncode,
+    V: ValueCodec,
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    pub fn new(cache: Cache<DK, DV>, codec: V) -> Self {
+        Self {
+            cache,
+            codec,
+            marker: PhantomData,
+        }
+    }
+
+    /// Access the untyped [`Cache`].
+    pub fn untyped(&self) -> &Cache<DK, DV> {
+        &self.cache
+    }
+
+    pub fn into_untyped(self) -> Cache<DK, DV> {
+        self.cache
+    }
+
+    pub fn codec(&self) -> &V {
+        &self.codec
+    }
+
+    /// Returns the decoded value for `key`.
+    ///
+    /// Returns `None` if `key` doesn't exist, or if its value can't be decoded; see [`try_get`](Self::try_get).
+    pub fn get(&self, key: &K) -> Option<V::View<'_>> {
+        self.try_get(key).ok().flatten()
+    }
+
+    /// Returns the decoded value for `key`, if it exists.
+    pub fn try_get(&self, key: &K) -> Result<Option<V::View<'_>>, Error> {
+        self.cache
+            .get_value(&key.to_key_bytes())
+            .map(|bytes| self.codec.decode(bytes))
+            .transpose()
+    }
+
+    /// Returns `true` if `key` exists, even if it's a tombstone.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.cache.contains_key(&key.to_key_bytes())
+    }
+
+    /// Returns an iterator over the decoded (key, value) pairs in `key_range`, in key order. Tombstones are skipped.
+    pub fn range<R: RangeBounds<K>>(&self, key_range: R) -> MapIter<'_, K, V, DK, DV> {
+        let encode = |bound: Bound<&K>| match bound {
+            Bound::Unbounded => Bound::Unbounded,
+            Bound::Included(k) => Bound::Included(k.to_key_bytes()),
+            Bound::Excluded(k) => Bound::Excluded(k.to_key_bytes()),
+        };
+        let bounds = (
+            encode(key_range.start_bound()),
+            encode(key_range.end_bound()),
+        );
+        MapIter {
+            stream: self.cache.range_values::<Vec<u8>, _>(bounds),
+            codec: &self.codec,
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns an iterator over all decoded (key, value) pairs, in key order.
+    pub fn iter(&self) -> MapIter<'_, K, V, DK, DV> {
+        self.range(..)
+    }
+}
+
+/// An iterator over decoded (key, value) pairs, returned by [`Map::range`].
+pub struct MapIter<'c, K, V, DK, DV> {
+    stream: ValueStream<'c, DK, DV>,
+    codec: &'c V,
+    marker: PhantomData<fn() -> K>,
+}
+
+impl<'c, K, V, DK, DV> Iterator for MapIter<'c, K, V, DK, DV>
+where
+    K: KeyEncode,
+    V: ValueCodec,
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    type Item = Result<(K, V::View<'c>), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, value) = self.stream.next()?;
+        let key =
+            K::from_key_bytes(key).ok_or_else(|| Error::Decode("key has the wrong length".into()));
+        Some(key.and_then(|key| Ok((key, self.codec.decode(value)?))))
+    }
+}
+
+impl<WI: io::Write, WV: io::Write> FileBuilder<WI, WV> {
+    /// Encodes `key` with [`KeyEncode`] and `value` with `codec`, and commits the entry. Keys must be inserted in order.
+    pub fn insert_typed<K: KeyEncode, C: ValueCodec>(
+        &mut self,
+        key: &K,
+        codec: &C,
+        value: &C::Value,
+    ) -> Result<(), Error> {
+        self.insert_encoded(&key.to_key_bytes(), codec, value)
+    }
+}
@@ -0,0 +1,84 @@
+use crate::{Cache, Error, FileBuilder};
+
+use fst::Streamer;
+
+/// Decides which value to keep when a key exists in more than one input of [`merge`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum DuplicatePolicy {
+    /// Keep the value from the first input (in slice order) containing the key.
+    KeepFirst,
+    /// Keep the value from the last input (in slice order) containing the key.
+    #[default]
+    KeepLast,
+    /// Fail with [`Error::DuplicateKey`].
+    Error,
+}
+
+/// Options for [`merge`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct MergeOptions {
+    /// How to resolve keys found in multiple inputs.
+    pub duplicates: DuplicatePolicy,
+    /// If `true`, keys resolved to a tombstone are left out of the output entirely. Otherwise the tombstone is written to
+    /// the output, so it can continue to hide the key when the output is layered on top of older caches.
+    pub drop_tombstones: bool,
+}
+
+/// Streams the entries of all `caches` in key order into `builder`, then finishes `builder`.
+///
+/// Keys found in multiple caches are resolved according to `options`. Entries keep their expiry, and the output gets the
+/// metadata of every input, with later inputs taking precedence for keys they share; metadata already set on `builder`
+/// is kept. Apart from the metadata, only constant memory is required, regardless of the size of the inputs. Fails with
+/// [`Error::Cancelled`] once the cancellation token of `builder` (see [`FileBuilder::with_cancellation`]) is cancelled.
+pub fn merge<DK, DV>(
+    caches: &[Cache<DK, DV>],
+    options: MergeOptions,
+    mut builder: FileBuilder,
+) -> Result<(), Error>
+where
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    for cache in caches.iter().rev() {
+        for (key, value) in cache.metadata()?.iter() {
+            builder.set_metadata_if_unset(key, value);
+        }
+    }
+    let mut union = caches
+        .iter()
+        .fold(fst::map::OpBuilder::new(), |op, cache| {
+            op.add(cache.index())
+        })
+        .union();
+    while let Some((key, sources)) = union.next() {
+        builder.check_cancelled()?;
+        let source = match options.duplicates {
+            DuplicatePolicy::KeepFirst => sources.iter().min_by_key(|s| s.index),
+            DuplicatePolicy::KeepLast => sources.iter().max_by_key(|s| s.index),
+            DuplicatePolicy::Error => {
+                if sources.len() > 1 {
+                    return Err(Error::DuplicateKey { key: key.to_vec() });
+                }
+                sources.first()
+            }
+        }
+        .unwrap();
+        let cache = &caches[source.index];
+        if cache.is_tombstone_at(source.value) {
+            if !options.drop_tombstones {
+                builder.insert_tombstone(key)?;
+            }
+            continue;
+        }
+        let value = cache
+            .resolve_value(key, source.value)
+            .ok_or(Error::InvalidFormat(
+                "value offset is missing from the length table",
+            ))?;
+        match cache.expiry_at(source.value) {
+            Some(expires_at) => builder.insert_with_expiry(key, value, expires_at)?,
+            None => builder.insert(key, value)?,
+        }
+    }
+    builder.finish()
+}
@@ -0,0 +1,54 @@
+use crate::format;
+use crate::{Cache, Error};
+
+/// User metadata attached to a cache with [`FileBuilder::set_metadata`](crate::FileBuilder::set_metadata), returned by
+/// [`Cache::metadata`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Metadata<'a> {
+    /// Sorted by key.
+    entries: Vec<(&'a str, &'a [u8])>,
+}
+
+impl<'a> Metadata<'a> {
+    /// Returns the value for `key`, if it was set.
+    pub fn get(&self, key: &str) -> Option<&'a [u8]> {
+        self.entries
+            .binary_search_by_key(&key, |&(k, _)| k)
+            .ok()
+            .map(|i| self.entries[i].1)
+    }
+
+    /// Returns the value for `key`, if it was set and is valid UTF-8.
+    pub fn get_str(&self, key: &str) -> Option<&'a str> {
+        self.get(key)
+            .and_then(|value| std::str::from_utf8(value).ok())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns an iterator over all (key, value) pairs, sorted by key.
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, &'a [u8])> + '_ {
+        self.entries.iter().copied()
+    }
+}
+
+impl<DK, DV> Cache<DK, DV>
+where
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    /// Reads the metadata attached by the [`FileBuilder`](crate::FileBuilder). It's empty if none was set.
+    pub fn metadata(&self) -> Result<Metadata<'_>, Error> {
+        let entries = match self.section_bytes(format::SECTION_METADATA) {
+            Some(section) => format::parse_metadata(section)?,
+            None => Vec::new(),
+        };
+        Ok(Metadata { entries })
+    }
+}
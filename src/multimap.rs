@@ -0,0 +1,130 @@
+use crate::{Cache, Error, FileBuilder};
+
+use std::iter::FusedIterator;
+
+// Each key's value is a packed list: `(count: u64, (len: u64, bytes)*)`, with little-endian integers.
+
+/// Serializes keys that each map to a list of values, for reading with [`MultiMapCache`].
+///
+/// Like [`FileBuilder`], keys must be inserted in sorted order.
+pub struct MultiMapBuilder {
+    builder: FileBuilder,
+}
+
+impl MultiMapBuilder {
+    pub fn new(builder: FileBuilder) -> Self {
+        Self { builder }
+    }
+
+    /// Writes all of `values` as a packed list and associates it with `key`.
+    pub fn insert<I, V>(&mut self, key: &[u8], values: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = V>,
+        I::IntoIter: ExactSizeIterator,
+        V: AsRef<[u8]>,
+    {
+        // Check before appending, so a rejected key doesn't leave its values pending.
+        self.builder.check_key_order(key)?;
+        let values = values.into_iter();
+        self.builder
+            .append_value_bytes(&(values.len() as u64).to_le_bytes())?;
+        for value in values {
+            let value = value.as_ref();
+            self.builder
+                .append_value_bytes(&(value.len() as u64).to_le_bytes())?;
+            self.builder.append_value_bytes(value)?;
+        }
+        self.builder.commit_entry(key)
+    }
+
+    /// Completes the serialization. See [`FileBuilder::finish`].
+    pub fn finish(self) -> Result<(), Error> {
+        self.builder.finish()
+    }
+}
+
+/// A [`Cache`] where each key maps to a list of values, as written by a [`MultiMapBuilder`].
+pub struct MultiMapCache<DK, DV> {
+    cache: Cache<DK, DV>,
+}
+
+impl<DK, DV> MultiMapCache<DK, DV>
+where
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    pub fn new(cache: Cache<DK, DV>) -> Self {
+        Self { cache }
+    }
+
+    /// Access the underlying [`Cache`], whose values are the packed lists.
+    pub fn packed(&self) -> &Cache<DK, DV> {
+        &self.cache
+    }
+
+    pub fn into_packed(self) -> Cache<DK, DV> {
+        self.cache
+    }
+
+    /// Returns an iterator over all values for `key`, if it exists.
+    pub fn get_all(&self, key: &[u8]) -> Option<MultiValues<'_>> {
+        self.cache.get_value(key).map(MultiValues::new)
+    }
+}
+
+/// An iterator over the values of one key in a [`MultiMapCache`].
+///
+/// Iteration stops early if the packed list is malformed.
+#[derive(Clone, Debug)]
+pub struct MultiValues<'c> {
+    remaining: usize,
+    bytes: &'c [u8],
+}
+
+impl<'c> MultiValues<'c> {
+    fn new(packed: &'c [u8]) -> Self {
+        match split_u64(packed) {
+            Some((count, bytes)) => Self {
+                remaining: usize::try_from(count).unwrap_or(0),
+                bytes,
+            },
+            None => Self {
+                remaining: 0,
+                bytes: &[],
+            },
+        }
+    }
+}
+
+impl<'c> Iterator for MultiValues<'c> {
+    type Item = &'c [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let value = split_u64(self.bytes).and_then(|(len, rest)| {
+            let len = usize::try_from(len).ok().filter(|&len| len <= rest.len())?;
+            let (value, rest) = rest.split_at(len);
+            self.bytes = rest;
+            Some(value)
+        });
+        self.remaining = if value.is_some() {
+            self.remaining - 1
+        } else {
+            0
+        };
+        value
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.remaining))
+    }
+}
+
+impl FusedIterator for MultiValues<'_> {}
+
+fn split_u64(bytes: &[u8]) -> Option<(u64, &[u8])> {
+    let (int, rest) = bytes.split_first_chunk::<8>()?;
+    Some((u64::from_le_bytes(*int), rest))
+}
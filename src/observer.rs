@@ -0,0 +1,18 @@
+/// Callbacks that a [`Cache`](crate::Cache) invokes on lookups and scans, e.g. to update metrics counters. Attach one with
+/// [`Cache::with_observer`](crate::Cache::with_observer).
+///
+/// Every method does nothing by default, and they're called synchronously on the reading thread, so implementations should
+/// be cheap, like incrementing atomic counters.
+pub trait CacheObserver: Send + Sync {
+    /// A key lookup, like [`Cache::get_value_offset`](crate::Cache::get_value_offset), found `key`. Tombstones count as hits.
+    fn on_get_hit(&self, _key: &[u8]) {}
+
+    /// A key lookup didn't find `key`.
+    fn on_get_miss(&self, _key: &[u8]) {}
+
+    /// A range scan started, like [`Cache::range`](crate::Cache::range).
+    fn on_range_start(&self) {}
+
+    /// Value bytes were returned from a lookup or scan.
+    fn on_bytes_read(&self, _bytes: u64) {}
+}
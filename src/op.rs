@@ -0,0 +1,172 @@
+use crate::{Cache, Error, FileBuilder};
+
+use fst::map::IndexedValue;
+use fst::Streamer;
+use std::borrow::Cow;
+use std::time::SystemTime;
+
+impl<DK, DV> Cache<DK, DV>
+where
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    /// Starts a set operation over the keys of this cache and any caches [added](CacheOpBuilder::with_cache) to it.
+    ///
+    /// ```
+    /// # use mmap_cache::{Cache, Error};
+    /// # fn example() -> Result<(), Error> {
+    /// use mmap_cache::FileBuilder;
+    /// use std::borrow::Cow;
+    ///
+    /// let a = Cache::from_sorted_iter([(b"apple", b"1"), (b"peach", b"2")])?;
+    /// let b = Cache::from_sorted_iter([(b"peach", b"3"), (b"plumb", b"4")])?;
+    ///
+    /// let builder = FileBuilder::create_files("/tmp/mmap_cache_op_doc_index", "/tmp/mmap_cache_op_doc_values")?;
+    /// a.op().with_cache(&b).intersection().write_to(builder, |_key, values| {
+    ///     Ok(values.last().unwrap().value.map(Cow::Borrowed))
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// # example().unwrap();
+    /// ```
+    pub fn op(&self) -> CacheOpBuilder<'_, DK, DV> {
+        CacheOpBuilder {
+            caches: Vec::new(),
+            op: fst::map::OpBuilder::new(),
+        }
+        .with_cache(self)
+    }
+}
+
+/// Combines the keys of several caches with a set operation; see [`Cache::op`].
+///
+/// Inputs are numbered in the order they were added, starting with the cache `op` was called on.
+pub struct CacheOpBuilder<'c, DK, DV> {
+    caches: Vec<&'c Cache<DK, DV>>,
+    op: fst::map::OpBuilder<'c>,
+}
+
+impl<'c, DK, DV> CacheOpBuilder<'c, DK, DV>
+where
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    /// Adds `cache` as the next input.
+    pub fn with_cache(mut self, cache: &'c Cache<DK, DV>) -> Self {
+        self.caches.push(cache);
+        self.op.push(cache.index());
+        self
+    }
+
+    /// Streams every key found in any input.
+    pub fn union(self) -> CacheOpStream<'c, DK, DV> {
+        self.stream(|op| Box::new(op.union()))
+    }
+
+    /// Streams the keys found in every input.
+    pub fn intersection(self) -> CacheOpStream<'c, DK, DV> {
+        self.stream(|op| Box::new(op.intersection()))
+    }
+
+    /// Streams the keys of the first input that are not found in any other input, with only the values of the first input.
+    pub fn difference(self) -> CacheOpStream<'c, DK, DV> {
+        self.stream(|op| Box::new(op.difference()))
+    }
+
+    /// Streams the keys found in an odd number of inputs.
+    pub fn symmetric_difference(self) -> CacheOpStream<'c, DK, DV> {
+        self.stream(|op| Box::new(op.symmetric_difference()))
+    }
+
+    fn stream(
+        self,
+        f: impl FnOnce(fst::map::OpBuilder<'c>) -> Box<dyn IndexedStreamer + 'c>,
+    ) -> CacheOpStream<'c, DK, DV> {
+        CacheOpStream {
+            caches: self.caches,
+            stream: f(self.op),
+            values: Vec::new(),
+        }
+    }
+}
+
+trait IndexedStreamer: for<'a> Streamer<'a, Item = (&'a [u8], &'a [IndexedValue])> {}
+
+impl<S> IndexedStreamer for S where S: for<'a> Streamer<'a, Item = (&'a [u8], &'a [IndexedValue])> {}
+
+/// The value of a key in one input of a set operation.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct OpValue<'c> {
+    /// The number of the input, in the order the inputs were added.
+    pub index: usize,
+    /// The bytes of the value, or `None` if the input has a tombstone for the key.
+    ///
+    /// If a value can't be resolved (e.g. the length table is missing an entry), it is an empty slice.
+    pub value: Option<&'c [u8]>,
+    /// When the entry expires, if it was inserted with [`FileBuilder::insert_with_expiry`].
+    pub expires_at: Option<SystemTime>,
+}
+
+/// A streaming iterator over the keys produced by a set operation, along with the value of each key in every input that
+/// contains it, ordered by input.
+pub struct CacheOpStream<'c, DK, DV> {
+    caches: Vec<&'c Cache<DK, DV>>,
+    stream: Box<dyn IndexedStreamer + 'c>,
+    values: Vec<OpValue<'c>>,
+}
+
+impl<'c, DK, DV> CacheOpStream<'c, DK, DV>
+where
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    /// Writes every key into `builder`, then finishes `builder`.
+    ///
+    /// A key with a value in only one input is copied as is, including tombstones and expiry times. For a key with values
+    /// in several inputs, `resolve` is called with the key and its values, and returns the value to write, or `None` to
+    /// leave the key out. Resolved values are written without an expiry.
+    pub fn write_to<F>(mut self, mut builder: FileBuilder, mut resolve: F) -> Result<(), Error>
+    where
+        F: FnMut(&[u8], &[OpValue<'c>]) -> Result<Option<Cow<'c, [u8]>>, Error>,
+    {
+        while let Some((key, values)) = self.next() {
+            if let [single] = values {
+                match (single.value, single.expires_at) {
+                    (Some(value), Some(expires_at)) => {
+                        builder.insert_with_expiry(key, value, expires_at)?
+                    }
+                    (Some(value), None) => builder.insert(key, value)?,
+                    (None, _) => builder.insert_tombstone(key)?,
+                }
+            } else if let Some(value) = resolve(key, values)? {
+                builder.insert(key, &value)?;
+            }
+        }
+        builder.finish()
+    }
+}
+
+impl<'a, 'c: 'a, DK, DV> Streamer<'a> for CacheOpStream<'c, DK, DV>
+where
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    type Item = (&'a [u8], &'a [OpValue<'c>]);
+
+    fn next(&'a mut self) -> Option<Self::Item> {
+        let (key, sources) = self.stream.next()?;
+        self.values.clear();
+        self.values.extend(sources.iter().map(|source| {
+            let cache = self.caches[source.index];
+            let value = (!cache.is_tombstone_at(source.value))
+                .then(|| cache.resolve_value(key, source.value).unwrap_or(&[]));
+            OpValue {
+                index: source.index,
+                value,
+                expires_at: cache.expiry_at(source.value),
+            }
+        }));
+        self.values.sort_unstable_by_key(|value| value.index);
+        Some((key, &self.values))
+    }
+}
@@ -0,0 +1,167 @@
+use crate::cache::map_section;
+use crate::error::PathContext;
+use crate::format::ContainerLayout;
+use crate::{Access, Error, MmapCache};
+
+use memmap2::{Mmap, MmapOptions};
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
+/// Options for opening a [`MmapCache`], as an alternative to the `map_*` constructors when more than the defaults are
+/// needed.
+///
+/// ```
+/// # use mmap_cache::Error;
+/// # fn example() -> Result<(), Error> {
+/// use mmap_cache::{Access, CacheOptions, FileBuilder};
+///
+/// let (index_path, value_path) = ("/tmp/mmap_cache_options_doc_index", "/tmp/mmap_cache_options_doc_values");
+/// let mut builder = FileBuilder::create_files(index_path, value_path)?;
+/// builder.insert(b"apple", b"red")?;
+/// builder.finish()?;
+///
+/// let cache = unsafe {
+///     CacheOptions::new()
+///         .populate(true)
+///         .access(Access::Random)
+///         .verify_checksums(true)
+///         .open(index_path, value_path)?
+/// };
+/// assert_eq!(cache.get_value(b"apple"), Some(&b"red"[..]));
+/// # Ok(())
+/// # }
+/// # example().unwrap();
+/// ```
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CacheOptions {
+    populate: bool,
+    lock: bool,
+    verify_checksums: bool,
+    verify_index: bool,
+    access: Access,
+}
+
+impl CacheOptions {
+    /// The options used by [`MmapCache::map_paths`]: nothing is read up front, locked or verified.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads both files into the page cache while mapping them ([`MmapOptions::populate`]), so the first lookups don't
+    /// block on page faults.
+    pub fn populate(mut self, populate: bool) -> Self {
+        self.populate = populate;
+        self
+    }
+
+    /// Locks both mappings into RAM (`mlock`), so their pages are never evicted, e.g. for latency-sensitive lookups. The
+    /// pages are unlocked when the cache is dropped.
+    ///
+    /// Opening fails if the process may not lock that much memory (see `RLIMIT_MEMLOCK`), or on platforms other than Unix.
+    pub fn lock(mut self, lock: bool) -> Self {
+        self.lock = lock;
+        self
+    }
+
+    /// Checks both files against their checksums with [`Cache::verify`](crate::Cache::verify), which reads every byte.
+    pub fn verify_checksums(mut self, verify: bool) -> Self {
+        self.verify_checksums = verify;
+        self
+    }
+
+    /// Checks the structure of the index with [`Cache::verify_index`](crate::Cache::verify_index).
+    pub fn verify_index(mut self, verify: bool) -> Self {
+        self.verify_index = verify;
+        self
+    }
+
+    /// Applies `access` advice to both mappings once they're open; see [`MmapCache::advise`].
+    pub fn access(mut self, access: Access) -> Self {
+        self.access = access;
+        self
+    }
+
+    /// Maps the files at `index_path` and `value_path` with these options.
+    ///
+    /// # Safety
+    ///
+    /// See [`Mmap`].
+    pub unsafe fn open(
+        &self,
+        index_path: impl AsRef<Path>,
+        value_path: impl AsRef<Path>,
+    ) -> Result<MmapCache, Error> {
+        let (index_path, value_path) = (index_path.as_ref(), value_path.as_ref());
+        let index_mmap = self.map_file(index_path)?;
+        let value_mmap = self.map_file(value_path)?;
+        let cache = MmapCache::new(index_mmap, value_mmap)
+            .map_err(|e| e.at_cache_paths(index_path, value_path))?;
+        if self.verify_index {
+            cache.verify_index().at_path("verify", index_path)?;
+        }
+        if self.verify_checksums {
+            cache.verify().at_path("verify", value_path)?;
+        }
+        if self.access != Access::Normal {
+            cache.advise(self.access).at_path("advise", value_path)?;
+        }
+        Ok(cache)
+    }
+
+    /// Maps the index and value sections of the single-file container at `path` with these options.
+    ///
+    /// # Safety
+    ///
+    /// See [`Mmap`].
+    pub unsafe fn open_path(&self, path: impl AsRef<Path>) -> Result<MmapCache, Error> {
+        let path = path.as_ref();
+        let file = fs::File::open(path).at_path("map", path)?;
+        let layout = ContainerLayout::read(&file).at_path("read", path)?;
+        let index_mmap = self.map_section(&file, layout.index).at_path("map", path)?;
+        let value_mmap = self
+            .map_section(&file, layout.values)
+            .at_path("map", path)?;
+        let cache = MmapCache::new(index_mmap, value_mmap).at_path("read", path)?;
+        if self.verify_index {
+            cache.verify_index().at_path("verify", path)?;
+        }
+        if self.verify_checksums {
+            cache.verify().at_path("verify", path)?;
+        }
+        if self.access != Access::Normal {
+            cache.advise(self.access).at_path("advise", path)?;
+        }
+        Ok(cache)
+    }
+
+    unsafe fn map_file(&self, path: &Path) -> Result<Mmap, Error> {
+        let file = fs::File::open(path).at_path("map", path)?;
+        let len = file.metadata().at_path("map", path)?.len();
+        self.map_section(&file, 0..len).at_path("map", path)
+    }
+
+    unsafe fn map_section(&self, file: &fs::File, section: Range<u64>) -> Result<Mmap, Error> {
+        let mut options = MmapOptions::new();
+        if self.populate {
+            options.populate();
+        }
+        let mmap = map_section(file, section, &options)?;
+        if self.lock {
+            return Ok(lock(mmap)?);
+        }
+        Ok(mmap)
+    }
+}
+
+#[cfg(unix)]
+fn lock(mut mmap: Mmap) -> io::Result<Mmap> {
+    mmap.lock()?;
+    Ok(mmap)
+}
+
+#[cfg(not(unix))]
+fn lock(_mmap: Mmap) -> io::Result<Mmap> {
+    Err(io::ErrorKind::Unsupported.into())
+}
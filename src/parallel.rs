@@ -0,0 +1,378 @@
+use crate::{merge, Cache, DuplicatePolicy, Error, FileBuilder, MergeOptions, UnsortedBuilder};
+
+use fst::Streamer;
+use std::ops::{Bound, RangeBounds};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// The number of sub-ranges per thread that [`Cache::par_range`] aims for, so threads that finish early can take over
+/// work from the others.
+const SPLITS_PER_THREAD: usize = 4;
+
+/// How many bytes of key prefix [`Cache::par_range`] looks at to find split points.
+const MAX_SPLIT_DEPTH: usize = 3;
+
+impl<DK, DV> Cache<DK, DV>
+where
+    DK: AsRef<[u8]> + Sync,
+    DV: AsRef<[u8]> + Sync,
+{
+    /// Calls `f` with every (key, value) pair in `key_range`, like [`range_values`](Self::range_values), but on one thread
+    /// per available CPU.
+    ///
+    /// The range is split into sub-ranges at the key prefixes found near the root of the index, and threads take turns
+    /// scanning the next unscanned sub-range. Pairs are delivered in key order within a sub-range, but in no particular
+    /// order overall. Tombstones are skipped.
+    ///
+    /// ```
+    /// # use mmap_cache::{Cache, Error};
+    /// # fn example() -> Result<(), Error> {
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// let cache = Cache::from_sorted_iter([("apple", "red"), ("peach", "pink"), ("plumb", "purple")])?;
+    /// let value_bytes = AtomicUsize::new(0);
+    /// cache.par_range(&b"b"[..].., |_key, value| {
+    ///     value_bytes.fetch_add(value.len(), Ordering::Relaxed);
+    /// });
+    /// assert_eq!(value_bytes.into_inner(), 10);
+    /// # Ok(())
+    /// # }
+    /// # example().unwrap();
+    /// ```
+    pub fn par_range<K, R, F>(&self, key_range: R, f: F)
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+        F: Fn(&[u8], &[u8]) + Sync,
+    {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        let start = owned_bound(key_range.start_bound());
+        let end = owned_bound(key_range.end_bound());
+        let mut splits = self.split_points(threads * SPLITS_PER_THREAD);
+        splits.retain(|split| in_bounds(split, &start, &end));
+
+        // Sub-range `i` ends where sub-range `i + 1` starts.
+        let mut starts = vec![start];
+        starts.extend(splits.iter().cloned().map(Bound::Included));
+        let mut ends: Vec<_> = splits.into_iter().map(Bound::Excluded).collect();
+        ends.push(end);
+        let sub_ranges: Vec<_> = starts.into_iter().zip(ends).collect();
+
+        let next = AtomicUsize::new(0);
+        thread::scope(|scope| {
+            for _ in 0..threads.min(sub_ranges.len()) {
+                scope.spawn(|| {
+                    while let Some(sub_range) = sub_ranges.get(next.fetch_add(1, Ordering::Relaxed))
+                    {
+                        let mut stream = self.range_values(sub_range.clone());
+                        while let Some((key, value)) = stream.next() {
+                            f(key, value);
+                        }
+                    }
+                });
+            }
+        });
+    }
+
+    /// Looks up the value offsets of many keys at once, like [`get_many`](Self::get_many), but on one thread per available
+    /// CPU. The results are in the same order as `keys`.
+    ///
+    /// The keys are sorted and split into contiguous runs, one per thread, so each thread probes a narrow part of the index.
+    /// This pays off for large batches against a cold cache, where each thread can block on a different page fault.
+    ///
+    /// ```
+    /// # use mmap_cache::{Cache, Error};
+    /// # fn example() -> Result<(), Error> {
+    /// let cache = Cache::from_sorted_iter([("apple", "red"), ("peach", "pink")])?;
+    /// let offsets = cache.par_get_many(&["peach", "plumb", "apple"]);
+    /// assert_eq!(offsets, [Some(3), None, Some(0)]);
+    /// # Ok(())
+    /// # }
+    /// # example().unwrap();
+    /// ```
+    pub fn par_get_many<K>(&self, keys: &[K]) -> Vec<Option<u64>>
+    where
+        K: AsRef<[u8]> + Sync,
+    {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        let mut order: Vec<usize> = (0..keys.len()).collect();
+        order.sort_unstable_by_key(|&i| keys[i].as_ref());
+
+        let mut offsets = vec![None; keys.len()];
+        let run_len = keys.len().div_ceil(threads).max(1);
+        thread::scope(|scope| {
+            let workers: Vec<_> = order
+                .chunks(run_len)
+                .map(|run| {
+                    scope.spawn(move || {
+                        run.iter()
+                            .map(|&i| (i, self.get_value_offset(keys[i].as_ref())))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            for worker in workers {
+                for (i, offset) in worker.join().unwrap() {
+                    offsets[i] = offset;
+                }
+            }
+        });
+        offsets
+    }
+
+    /// Finds about `parts` sorted keys that split the index into ranges of keys with different prefixes, by walking the
+    /// index breadth-first from the root.
+    fn split_points(&self, parts: usize) -> Vec<Vec<u8>> {
+        let fst = self.index().as_fst();
+        let mut frontier = vec![(Vec::new(), fst.root().addr())];
+        for _ in 0..MAX_SPLIT_DEPTH {
+            if frontier.len() >= parts {
+                break;
+            }
+            let mut next = Vec::new();
+            for (prefix, addr) in &frontier {
+                for transition in fst.node(*addr).transitions() {
+                    let mut child = prefix.clone();
+                    child.push(transition.inp);
+                    next.push((child, transition.addr));
+                }
+            }
+            if next.is_empty() {
+                break;
+            }
+            frontier = next;
+        }
+        // Keys before the first prefix belong to the first sub-range anyway.
+        frontier
+            .into_iter()
+            .skip(1)
+            .map(|(prefix, _)| prefix)
+            .collect()
+    }
+}
+
+/// Serializes key-value pairs that arrive in any order, sorting and indexing them on several threads.
+///
+/// Entries are buffered in memory in chunks of about `chunk_size` bytes. When finishing, the chunks are sorted and built
+/// into in-memory segments concurrently, and the segments are then merged in key order into a [`FileBuilder`]. Like
+/// [`UnsortedBuilder`], the whole dataset must fit in memory, and duplicate keys are not supported.
+///
+/// ```
+/// # use mmap_cache::Error;
+/// # fn example() -> Result<(), Error> {
+/// use mmap_cache::{FileBuilder, MmapCache, ParallelBuilder};
+///
+/// let (index_path, value_path) = ("/tmp/mmap_cache_parallel_doc_index", "/tmp/mmap_cache_parallel_doc_values");
+/// let mut parallel = ParallelBuilder::new(1 << 20);
+/// parallel.insert(b"foo", b"bar");
+/// parallel.insert(b"abc", b"def");
+/// parallel.finish(FileBuilder::create_files(index_path, value_path)?)?;
+///
+/// let cache = unsafe { MmapCache::map_paths(index_path, value_path)? };
+/// assert_eq!(cache.get_value(b"abc"), Some(&b"def"[..]));
+/// # Ok(())
+/// # }
+/// # example().unwrap();
+/// ```
+pub struct ParallelBuilder {
+    chunks: Vec<UnsortedBuilder>,
+    chunk_size: usize,
+    threads: usize,
+}
+
+impl ParallelBuilder {
+    /// Creates a builder that starts a new chunk whenever the current one uses more than `chunk_size` bytes of memory.
+    ///
+    /// Chunks are built on one thread per available CPU.
+    pub fn new(chunk_size: usize) -> Self {
+        Self {
+            chunks: vec![UnsortedBuilder::new()],
+            chunk_size,
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        }
+    }
+
+    /// Builds chunks on `threads` threads instead of one per available CPU.
+    ///
+    /// # Panics
+    ///
+    /// If `threads` is zero.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        assert!(threads > 0);
+        self.threads = threads;
+        self
+    }
+
+    /// The number of chunks, including the one being filled.
+    pub fn num_chunks(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Buffers a copy of `key` and `value`, starting a new chunk if the current one is full.
+    pub fn insert(&mut self, key: &[u8], value: &[u8]) {
+        let chunk = self.chunks.last_mut().unwrap();
+        chunk.insert(key, value);
+        if chunk.memory_usage() > self.chunk_size {
+            self.chunks.push(UnsortedBuilder::new());
+        }
+    }
+
+    /// Sorts and indexes every chunk concurrently, then merges them in key order and serializes them with `builder`, and
+    /// finishes `builder`.
+    ///
+    /// Fails with [`Error::KeyOrder`] if a chunk has a duplicate key, or with [`Error::DuplicateKey`] if chunks share a key.
+    pub fn finish(mut self, builder: FileBuilder) -> Result<(), Error> {
+        self.chunks.retain(|chunk| !chunk.is_empty());
+        if self.chunks.len() <= 1 {
+            return self.chunks.pop().unwrap_or_default().finish(builder);
+        }
+
+        let chunks: Vec<_> = self.chunks.into_iter().map(Mutex::new).collect();
+        let next = AtomicUsize::new(0);
+        let segments: Vec<_> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..self.threads.min(chunks.len()))
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut built = Vec::new();
+                        while let Some(chunk) = chunks.get(next.fetch_add(1, Ordering::Relaxed)) {
+                            // Take the chunk, so its memory is freed once its segment is built.
+                            let mut chunk = std::mem::take(&mut *chunk.lock().unwrap());
+                            built.push(Cache::from_sorted_iter(chunk.sorted()));
+                        }
+                        built
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap())
+                .collect()
+        });
+        // The segments don't share keys, so their order doesn't matter.
+        let segments = segments.into_iter().collect::<Result<Vec<_>, _>>()?;
+        merge(
+            &segments,
+            MergeOptions {
+                duplicates: DuplicatePolicy::Error,
+                drop_tombstones: false,
+            },
+            builder,
+        )
+    }
+}
+
+fn owned_bound<K: AsRef<[u8]>>(bound: Bound<&K>) -> Bound<Vec<u8>> {
+    match bound {
+        Bound::Included(key) => Bound::Included(key.as_ref().to_vec()),
+        Bound::Excluded(key) => Bound::Excluded(key.as_ref().to_vec()),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+fn in_bounds(key: &[u8], start: &Bound<Vec<u8>>, end: &Bound<Vec<u8>>) -> bool {
+    let after_start = match start {
+        Bound::Included(start) => key >= start.as_slice(),
+        Bound::Excluded(start) => key > start.as_slice(),
+        Bound::Unbounded => true,
+    };
+    let before_end = match end {
+        Bound::Included(end) => key <= end.as_slice(),
+        Bound::Excluded(end) => key < end.as_slice(),
+        Bound::Unbounded => true,
+    };
+    after_start && before_end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Mutex;
+
+    #[test]
+    fn parallel_get_many() {
+        let pairs: Vec<_> = (0..1000)
+            .map(|i| (format!("{i:04}"), i.to_string()))
+            .collect();
+        let cache = Cache::from_sorted_iter(pairs).unwrap();
+
+        let keys: Vec<_> = (0..1200).rev().map(|i| format!("{i:04}")).collect();
+        assert_eq!(cache.par_get_many(&keys), cache.get_many(&keys));
+        assert!(cache.par_get_many::<&[u8]>(&[]).is_empty());
+    }
+
+    #[test]
+    fn parallel_range() {
+        let mut pairs: Vec<_> = (0..2000)
+            .map(|i| (format!("{}/{i:04}", i % 7), i.to_string()))
+            .collect();
+        pairs.sort();
+        let cache = Cache::from_sorted_iter(pairs).unwrap();
+
+        let expected = |start: &[u8], end: &[u8]| {
+            let mut pairs = Vec::new();
+            let mut stream = cache.range_values(start..end);
+            while let Some((key, value)) = stream.next() {
+                pairs.push((key.to_vec(), value.to_vec()));
+            }
+            pairs
+        };
+        let scan = |key_range: (Bound<&[u8]>, Bound<&[u8]>)| {
+            let pairs = Mutex::new(Vec::new());
+            let key_range = (
+                key_range.0.map(<[u8]>::to_vec),
+                key_range.1.map(<[u8]>::to_vec),
+            );
+            cache.par_range(key_range, |key, value| {
+                pairs.lock().unwrap().push((key.to_vec(), value.to_vec()));
+            });
+            let mut pairs = pairs.into_inner().unwrap();
+            pairs.sort();
+            pairs
+        };
+
+        assert!(cache.split_points(8).len() >= 7);
+        let all = scan((Bound::Unbounded, Bound::Unbounded));
+        assert_eq!(all.len(), 2000);
+        assert_eq!(all, expected(b"", b"9"));
+        assert_eq!(
+            scan((Bound::Included(b"2/"), Bound::Excluded(b"5/0100"))),
+            expected(b"2/", b"5/0100")
+        );
+        assert!(scan((Bound::Included(b"x"), Bound::Unbounded)).is_empty());
+    }
+
+    #[test]
+    fn parallel_build() {
+        let (index_path, values_path) = (
+            std::env::temp_dir().join("mmap_cache_parallel_build_index"),
+            std::env::temp_dir().join("mmap_cache_parallel_build_values"),
+        );
+        let mut parallel = ParallelBuilder::new(1024).with_threads(3);
+        for i in (0..1000).rev() {
+            parallel.insert(format!("{i:04}").as_bytes(), i.to_string().as_bytes());
+        }
+        assert!(parallel.num_chunks() > 3);
+        parallel
+            .finish(FileBuilder::create_files(&index_path, &values_path).unwrap())
+            .unwrap();
+        let cache = Cache::new(
+            std::fs::read(&index_path).unwrap(),
+            std::fs::read(&values_path).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(cache.len(), 1000);
+        assert_eq!(cache.get_value(b"0042"), Some(&b"42"[..]));
+        assert_eq!(cache.first_key_value().unwrap().0, b"0000");
+
+        let mut parallel = ParallelBuilder::new(16);
+        parallel.insert(b"a", b"1");
+        parallel.insert(b"b", b"2");
+        parallel.insert(b"a", b"3");
+        assert!(matches!(
+            parallel.finish(FileBuilder::create_files(&index_path, &values_path).unwrap()),
+            Err(Error::DuplicateKey { .. })
+        ));
+    }
+}
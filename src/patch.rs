@@ -0,0 +1,305 @@
+use crate::checksum::{ChecksumReader, ChecksumWriter};
+use crate::{format, Cache, Error, FileBuilder};
+
+use fst::Streamer;
+use std::io::{self, Read, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// A patch is laid out as:
+//
+// [magic][version: u32][base checksums: (values_crc: u32, index_crc: u32)][record]*[end record][crc: u32]
+//
+// The base checksums are copied from the checksum section of the old cache, or are all zeros if it has none. Each record
+// starts with a tag byte, and records are sorted by key:
+//
+// - `PUT`: `(key_len: u64, key bytes, expires_at: u64, value_len: u64, value bytes)`, where `expires_at` is in seconds
+//   since the Unix epoch, or `NO_EXPIRY`. Version 1 patches have no `expires_at`.
+// - `TOMBSTONE`: `(key_len: u64, key bytes)`
+// - `DELETE`: `(key_len: u64, key bytes)`
+// - `END`: `(metadata_len: u64, metadata section)`, where the metadata section is laid out like the metadata section of a
+//   value file.
+//
+// The trailing CRC-32 covers every byte of the patch before it.
+//
+// All integers are little-endian.
+
+const MAGIC: [u8; 8] = *b"MMAPPTCH";
+const VERSION: u32 = 2;
+
+const NO_EXPIRY: u64 = u64::MAX;
+
+const PUT: u8 = 1;
+const TOMBSTONE: u8 = 2;
+const DELETE: u8 = 3;
+const END: u8 = 4;
+
+/// What a patch written by [`create_patch`] contains.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PatchStats {
+    /// The number of keys that were added or whose value changed.
+    pub puts: u64,
+    /// The number of keys that became tombstones.
+    pub tombstones: u64,
+    /// The number of keys that were removed entirely.
+    pub deletes: u64,
+}
+
+/// Writes a patch to `writer` that turns `old` into `new` when applied with [`apply_patch`].
+///
+/// Only the entries whose value or expiry differ are encoded, along with the metadata of `new`, so the patch is usually
+/// much smaller than `new` when few keys changed. The patch records checksums of `old` (if it has them), so it can't be applied to a
+/// different cache by mistake.
+///
+/// ```
+/// # use mmap_cache::{Cache, Error};
+/// # fn example() -> Result<(), Error> {
+/// use mmap_cache::{apply_patch, create_patch, FileBuilder};
+///
+/// let old = Cache::from_sorted_iter([("apple", "red"), ("peach", "pink")])?;
+/// let new = Cache::from_sorted_iter([("apple", "green"), ("peach", "pink")])?;
+///
+/// let mut patch = Vec::new();
+/// create_patch(&old, &new, &mut patch)?;
+///
+/// let patched = apply_patch(&old, patch.as_slice(), FileBuilder::in_memory()?)?.finish_into_cache()?;
+/// assert_eq!(patched.get_value(b"apple"), Some(&b"green"[..]));
+/// # Ok(())
+/// # }
+/// # example().unwrap();
+/// ```
+pub fn create_patch<DK, DV>(
+    old: &Cache<DK, DV>,
+    new: &Cache<DK, DV>,
+    writer: impl Write,
+) -> Result<PatchStats, Error>
+where
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    let mut writer = ChecksumWriter::new(writer);
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&base_checksums(old))?;
+
+    let mut stats = PatchStats::default();
+    let mut union = fst::map::OpBuilder::new()
+        .add(old.index())
+        .add(new.index())
+        .union();
+    while let Some((key, sources)) = union.next() {
+        let mut offsets = [None; 2];
+        for source in sources {
+            offsets[source.index] = Some(source.value);
+        }
+        match offsets {
+            [Some(_), None] => {
+                write_key_record(&mut writer, DELETE, key)?;
+                stats.deletes += 1;
+            }
+            [old_offset, Some(new_offset)] => {
+                let old_entry = match old_offset {
+                    Some(offset) => Some((entry(old, key, offset)?, old.expiry_at(offset))),
+                    None => None,
+                };
+                let new_entry = (entry(new, key, new_offset)?, new.expiry_at(new_offset));
+                if old_entry == Some(new_entry) {
+                    continue;
+                }
+                match new_entry {
+                    (Some(value), expires_at) => {
+                        write_key_record(&mut writer, PUT, key)?;
+                        let expires_at = expires_at.map_or(NO_EXPIRY, format::unix_seconds);
+                        writer.write_all(&expires_at.to_le_bytes())?;
+                        writer.write_all(&(value.len() as u64).to_le_bytes())?;
+                        writer.write_all(value)?;
+                        stats.puts += 1;
+                    }
+                    (None, _) => {
+                        write_key_record(&mut writer, TOMBSTONE, key)?;
+                        stats.tombstones += 1;
+                    }
+                }
+            }
+            [None, None] => unreachable!(),
+        }
+    }
+
+    let metadata = new.section_bytes(format::SECTION_METADATA).unwrap_or(&[]);
+    writer.write_all(&[END])?;
+    writer.write_all(&(metadata.len() as u64).to_le_bytes())?;
+    writer.write_all(metadata)?;
+    let crc = writer.checksum();
+    let mut writer = writer.into_inner();
+    writer.write_all(&crc.to_le_bytes())?;
+    writer.flush()?;
+    Ok(stats)
+}
+
+/// Rebuilds the new cache from `old` and a patch written by [`create_patch`], writing it with `builder`.
+///
+/// The returned builder has received every entry and the metadata of the new cache, but is not finished, so the caller
+/// decides how to finish it. The patch is only known to be intact once it has been read to the end, so use an
+/// [atomic](FileBuilder::create_files_atomic) builder when writing files, which leaves existing files untouched if this
+/// fails.
+///
+/// The new cache has the same entries, expiry times and metadata as the one the patch was created from, but not
+/// necessarily the same bytes, since those also depend on the options of `builder`.
+pub fn apply_patch<DK, DV, WI, WV>(
+    old: &Cache<DK, DV>,
+    patch: impl Read,
+    mut builder: FileBuilder<WI, WV>,
+) -> Result<FileBuilder<WI, WV>, Error>
+where
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+    WI: Write,
+    WV: Write,
+{
+    let mut reader = ChecksumReader::new(io::BufReader::new(patch));
+    let mut header = [0; 20];
+    reader.read_exact(&mut header)?;
+    let version = format::read_u32(&header, 8);
+    if header[..8] != MAGIC || !(1..=VERSION).contains(&version) {
+        return Err(Error::InvalidFormat("not a patch"));
+    }
+    let expected_base = &header[12..];
+    if expected_base != [0; 8] && expected_base != base_checksums(old) {
+        return Err(Error::InvalidFormat(
+            "patch was created for a different cache",
+        ));
+    }
+
+    let mut old_stream = old.index().stream();
+    let mut old_entry = old_stream.next();
+    loop {
+        let (tag, key) = read_record_key(&mut reader)?;
+        // Copy the old entries before the next patched key.
+        while let Some((old_key, offset)) = old_entry {
+            if tag != END && old_key >= key.as_slice() {
+                break;
+            }
+            match entry(old, old_key, offset)? {
+                Some(value) => insert(&mut builder, old_key, value, old.expiry_at(offset))?,
+                None => builder.insert_tombstone(old_key)?,
+            }
+            old_entry = old_stream.next();
+        }
+        let replaces_old = old_entry.is_some_and(|(old_key, _)| old_key == key.as_slice());
+        match tag {
+            PUT => {
+                let expires_at = if version >= 2 {
+                    let mut expires_at = [0; 8];
+                    reader.read_exact(&mut expires_at)?;
+                    Some(u64::from_le_bytes(expires_at)).filter(|&seconds| seconds != NO_EXPIRY)
+                } else {
+                    None
+                };
+                let value = read_bytes(&mut reader)?;
+                let expires_at =
+                    expires_at.map(|seconds| UNIX_EPOCH + Duration::from_secs(seconds));
+                insert(&mut builder, &key, &value, expires_at)?;
+            }
+            TOMBSTONE => builder.insert_tombstone(&key)?,
+            DELETE if replaces_old => {}
+            DELETE => return Err(Error::InvalidFormat("patch deletes a missing key")),
+            END => break,
+            _ => return Err(Error::InvalidFormat("corrupt patch")),
+        }
+        if replaces_old {
+            old_entry = old_stream.next();
+        }
+    }
+
+    let metadata = read_bytes(&mut reader)?;
+    let actual = reader.checksum();
+    let mut crc = [0; 4];
+    reader.get_mut().read_exact(&mut crc)?;
+    let expected = u32::from_le_bytes(crc);
+    if actual != expected {
+        return Err(Error::ChecksumMismatch {
+            section: "patch",
+            expected,
+            actual,
+        });
+    }
+    if !metadata.is_empty() {
+        for (key, value) in format::parse_metadata(&metadata)? {
+            builder.set_metadata(key, value);
+        }
+    }
+    Ok(builder)
+}
+
+fn insert<WI: Write, WV: Write>(
+    builder: &mut FileBuilder<WI, WV>,
+    key: &[u8],
+    value: &[u8],
+    expires_at: Option<SystemTime>,
+) -> Result<(), Error> {
+    match expires_at {
+        Some(expires_at) => builder.insert_with_expiry(key, value, expires_at),
+        None => builder.insert(key, value),
+    }
+}
+
+/// The value of the entry at `offset`, or `None` for a tombstone.
+fn entry<'c, DK, DV>(
+    cache: &'c Cache<DK, DV>,
+    key: &[u8],
+    offset: u64,
+) -> Result<Option<&'c [u8]>, Error>
+where
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    if cache.is_tombstone_at(offset) {
+        return Ok(None);
+    }
+    cache
+        .resolve_value(key, offset)
+        .map(Some)
+        .ok_or(Error::InvalidFormat(
+            "value offset is missing from the length table",
+        ))
+}
+
+fn base_checksums<DK, DV>(cache: &Cache<DK, DV>) -> [u8; 8]
+where
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    cache
+        .section_bytes(format::SECTION_CHECKSUMS)
+        .and_then(|section| section.try_into().ok())
+        .unwrap_or([0; 8])
+}
+
+fn write_key_record(writer: &mut impl Write, tag: u8, key: &[u8]) -> io::Result<()> {
+    writer.write_all(&[tag])?;
+    writer.write_all(&(key.len() as u64).to_le_bytes())?;
+    writer.write_all(key)
+}
+
+/// Reads the tag of the next record, and its key unless it's the end record.
+fn read_record_key(reader: &mut impl Read) -> io::Result<(u8, Vec<u8>)> {
+    let mut tag = [0];
+    reader.read_exact(&mut tag)?;
+    let key = if tag[0] == END {
+        Vec::new()
+    } else {
+        read_bytes(reader)?
+    };
+    Ok((tag[0], key))
+}
+
+fn read_bytes(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut len = [0; 8];
+    reader.read_exact(&mut len)?;
+    let len = u64::from_le_bytes(len);
+    let mut bytes = Vec::new();
+    reader.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(bytes)
+}
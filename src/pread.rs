@@ -0,0 +1,101 @@
+use crate::error::PathContext;
+use crate::format::{read_exact_at, ContainerLayout};
+use crate::{Cache, Error, FileStore, ValueStore};
+
+use std::fs;
+use std::path::Path;
+
+/// A cache that reads values with positional reads (`pread`) instead of memory-mapping the value file.
+///
+/// This is useful where memory mapping is undesirable, e.g. on some network file systems, or to keep page faults off of
+/// latency-sensitive threads. The index is read into memory, and each value lookup reads exactly the value's bytes into a
+/// caller-provided buffer. For files with a length table, the length of a value is found by binary searching the table
+/// with small positional reads.
+///
+/// This is a [`Cache`] over a [`FileStore`] that reads into reusable buffers instead of allocating one per value.
+pub struct PreadCache {
+    cache: Cache<Vec<u8>, FileStore>,
+}
+
+impl PreadCache {
+    /// Opens the files at `index_path` and `value_path`, as written by a [`FileBuilder`](crate::FileBuilder).
+    pub fn open_paths(
+        index_path: impl AsRef<Path>,
+        value_path: impl AsRef<Path>,
+    ) -> Result<Self, Error> {
+        let (index_path, value_path) = (index_path.as_ref(), value_path.as_ref());
+        let index = fs::read(index_path).at_path("read", index_path)?;
+        let store = FileStore::open(value_path)?;
+        let cache = Cache::from_store(index, store)
+            .map_err(|e| e.at_cache_paths(index_path, value_path))?;
+        Ok(Self { cache })
+    }
+
+    /// Opens the single-file container at `path`.
+    pub fn open_path(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::open_container(path.as_ref()).at_path("read", path)
+    }
+
+    fn open_container(path: &Path) -> Result<Self, Error> {
+        let file = fs::File::open(path)?;
+        let layout = ContainerLayout::read(&file)?;
+        let index_len = usize::try_from(layout.index.end - layout.index.start)
+            .map_err(|_| Error::InvalidFormat("index is too large"))?;
+        let mut index = vec![0; index_len];
+        read_exact_at(&file, &mut index, layout.index.start)?;
+        // The value section comes first, so the store ends where it does.
+        let store = FileStore::with_len(file, layout.values.end);
+        Ok(Self {
+            cache: Cache::from_store(index, store)?,
+        })
+    }
+
+    pub fn index(&self) -> &fst::Map<Vec<u8>> {
+        self.cache.index()
+    }
+
+    /// Access the underlying [`Cache`], e.g. to read values into owned buffers with [`Cache::read_value`].
+    pub fn cache(&self) -> &Cache<Vec<u8>, FileStore> {
+        &self.cache
+    }
+
+    /// Returns the byte offset of the value for `key`, if it exists.
+    pub fn get_value_offset(&self, key: &[u8]) -> Option<u64> {
+        self.cache.index().get(key)
+    }
+
+    /// Reads the value for `key` into `buf`, replacing its contents. Returns `false` if `key` doesn't exist or is a
+    /// tombstone.
+    pub fn get_value(&self, key: &[u8], buf: &mut Vec<u8>) -> Result<bool, Error> {
+        let Some(offset) = self.get_value_offset(key) else {
+            return Ok(false);
+        };
+        match self.cache.read_value_len(key, offset)? {
+            Some(len) => {
+                self.read_value(offset, len, buf)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Reads the value starting at `offset` into `buf`, replacing its contents, if the value file has a length table with
+    /// an entry for `offset`. Returns `false` otherwise, or if the entry is a tombstone.
+    pub fn read_value_at(&self, offset: u64, buf: &mut Vec<u8>) -> Result<bool, Error> {
+        match self.cache.read_recorded_len(offset)? {
+            Some(len) => {
+                self.read_value(offset, len, buf)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Reads the `len` value bytes at `offset` into `buf`, replacing its contents.
+    pub fn read_value(&self, offset: u64, len: u64, buf: &mut Vec<u8>) -> Result<(), Error> {
+        self.cache.check_store_values(offset, len)?;
+        buf.clear();
+        buf.resize(len as usize, 0);
+        self.cache.store().read_into(offset, buf)
+    }
+}
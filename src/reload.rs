@@ -0,0 +1,204 @@
+use crate::{Error, MmapCache};
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+type Validator = Box<dyn Fn(&MmapCache) -> Result<(), Error> + Send + Sync>;
+
+/// A [`MmapCache`] that is replaced whenever its files are replaced, for serving new builds without a restart.
+///
+/// Readers call [`current`](Self::current) to get the active cache. A reload maps the new files, validates them, and then
+/// swaps them in, so lookups never see a partially written cache. Readers that still hold the previous cache keep reading
+/// its mapping, which is unmapped once the last of them drops it.
+///
+/// Files are expected to be replaced by renaming a new file over the old path, as the atomic builders do (e.g.
+/// [`FileBuilder::create_file_atomic`](crate::FileBuilder::create_file_atomic)). A change is detected by comparing the
+/// inode (on Unix), length, and modification time of each path with the files that are mapped, either on demand with
+/// [`reload`](Self::reload) or periodically with [`watch`](Self::watch).
+pub struct ReloadableCache {
+    source: Source,
+    validator: Validator,
+    current: RwLock<Arc<MmapCache>>,
+    /// The identities of the files that `current` was mapped from.
+    stamps: Mutex<Vec<FileStamp>>,
+}
+
+enum Source {
+    Container(PathBuf),
+    Files(PathBuf, PathBuf),
+}
+
+impl ReloadableCache {
+    /// Maps the single-file container at `path`, which must pass [`Cache::verify`](crate::Cache::verify).
+    ///
+    /// # Safety
+    ///
+    /// See [`Mmap`](memmap2::Mmap). Files must be replaced rather than modified in place.
+    pub unsafe fn open_path(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::open(Source::Container(path.as_ref().to_owned()))
+    }
+
+    /// Maps the files at `index_path` and `value_path`, which must pass [`Cache::verify`](crate::Cache::verify).
+    ///
+    /// The two files are replaced separately, so a reload may see a new index with the old values. The checksums recorded
+    /// in the value file cover the index, so the default validation rejects the mismatched pair, and the next reload
+    /// retries once both files are replaced.
+    ///
+    /// # Safety
+    ///
+    /// See [`Mmap`](memmap2::Mmap). Files must be replaced rather than modified in place.
+    pub unsafe fn open_paths(
+        index_path: impl AsRef<Path>,
+        value_path: impl AsRef<Path>,
+    ) -> Result<Self, Error> {
+        Self::open(Source::Files(
+            index_path.as_ref().to_owned(),
+            value_path.as_ref().to_owned(),
+        ))
+    }
+
+    unsafe fn open(source: Source) -> Result<Self, Error> {
+        let validator: Validator = Box::new(MmapCache::verify);
+        let (cache, stamps) = source.map()?;
+        validator(&cache)?;
+        Ok(Self {
+            source,
+            validator,
+            current: RwLock::new(Arc::new(cache)),
+            stamps: Mutex::new(stamps),
+        })
+    }
+
+    /// Replaces the check that new files must pass before they are swapped in, which is [`Cache::verify`] by default.
+    ///
+    /// [`Cache::verify`]: crate::Cache::verify
+    pub fn with_validator(
+        mut self,
+        validator: impl Fn(&MmapCache) -> Result<(), Error> + Send + Sync + 'static,
+    ) -> Self {
+        self.validator = Box::new(validator);
+        self
+    }
+
+    /// The active cache.
+    pub fn current(&self) -> Arc<MmapCache> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Maps and swaps in the files if they were replaced since they were last mapped. Returns `true` if the cache was
+    /// swapped.
+    ///
+    /// If the new files can't be mapped or fail validation, the active cache is kept and the error is returned. The files
+    /// are tried again on the next reload.
+    pub fn reload(&self) -> Result<bool, Error> {
+        let mut stamps = self.stamps.lock().unwrap();
+        if self.source.stamps()? == *stamps {
+            return Ok(false);
+        }
+        // SAFETY: The caller of `open_path` or `open_paths` promised that files are only replaced.
+        let (cache, new_stamps) = unsafe { self.source.map()? };
+        (self.validator)(&cache)?;
+        *self.current.write().unwrap() = Arc::new(cache);
+        *stamps = new_stamps;
+        Ok(true)
+    }
+
+    /// Spawns a thread that calls [`reload`](Self::reload) every `interval`, passing any errors to `on_error`. The thread
+    /// stops when the returned [`ReloadWatcher`] is dropped.
+    pub fn watch(
+        self: &Arc<Self>,
+        interval: Duration,
+        mut on_error: impl FnMut(Error) + Send + 'static,
+    ) -> io::Result<ReloadWatcher> {
+        let cache = self.clone();
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = thread::Builder::new()
+            .name("mmap-cache-reload".to_owned())
+            .spawn(move || {
+                while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                    if let Err(e) = cache.reload() {
+                        on_error(e);
+                    }
+                }
+            })?;
+        Ok(ReloadWatcher {
+            stop: Some(stop),
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Source {
+    fn stamps(&self) -> io::Result<Vec<FileStamp>> {
+        match self {
+            Self::Container(path) => Ok(vec![FileStamp::new(&fs::metadata(path)?)]),
+            Self::Files(index_path, value_path) => Ok(vec![
+                FileStamp::new(&fs::metadata(index_path)?),
+                FileStamp::new(&fs::metadata(value_path)?),
+            ]),
+        }
+    }
+
+    /// Maps the files, returning the identities of the files that were actually opened, in case a path is replaced again
+    /// while this runs.
+    unsafe fn map(&self) -> Result<(MmapCache, Vec<FileStamp>), Error> {
+        match self {
+            Self::Container(path) => {
+                let file = fs::File::open(path)?;
+                let stamps = vec![FileStamp::new(&file.metadata()?)];
+                Ok((MmapCache::map_file(&file)?, stamps))
+            }
+            Self::Files(index_path, value_path) => {
+                let index_file = fs::File::open(index_path)?;
+                let value_file = fs::File::open(value_path)?;
+                let stamps = vec![
+                    FileStamp::new(&index_file.metadata()?),
+                    FileStamp::new(&value_file.metadata()?),
+                ];
+                Ok((MmapCache::map_files(&index_file, &value_file)?, stamps))
+            }
+        }
+    }
+}
+
+/// Identifies a version of a file, so that a replaced file can be detected.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct FileStamp {
+    inode: u64,
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl FileStamp {
+    fn new(metadata: &fs::Metadata) -> Self {
+        #[cfg(unix)]
+        let inode = std::os::unix::fs::MetadataExt::ino(metadata);
+        #[cfg(not(unix))]
+        let inode = 0;
+        Self {
+            inode,
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        }
+    }
+}
+
+/// Stops the thread started by [`ReloadableCache::watch`] when dropped.
+pub struct ReloadWatcher {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Drop for ReloadWatcher {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
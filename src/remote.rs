@@ -0,0 +1,582 @@
+use crate::{Error, ValueStore};
+
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Fetches byte ranges of a remote object, e.g. with HTTP range requests or S3 `GetObject` calls with a `Range`.
+///
+/// Implement this to plug in any transport, like an HTTPS client or an S3 SDK with request signing. [`HttpFetcher`] is a
+/// minimal built-in implementation for plain HTTP.
+pub trait RangeFetcher {
+    /// The total size of the object in bytes.
+    fn size(&self) -> io::Result<u64>;
+
+    /// Returns exactly the bytes in `range`.
+    fn fetch(&self, range: Range<u64>) -> io::Result<Vec<u8>>;
+}
+
+/// How a [`RemoteStore`] retries failed fetches, with exponential backoff.
+///
+/// Errors of kind [`NotFound`](io::ErrorKind::NotFound), [`PermissionDenied`](io::ErrorKind::PermissionDenied),
+/// [`InvalidInput`](io::ErrorKind::InvalidInput), [`InvalidData`](io::ErrorKind::InvalidData) and
+/// [`Unsupported`](io::ErrorKind::Unsupported) are considered permanent and never retried.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts per fetch, including the first one.
+    pub max_attempts: u32,
+    /// The delay before the first retry, which doubles for every following retry.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Never retries.
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    fn run<T>(&self, mut f: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+        let mut backoff = self.initial_backoff;
+        let mut attempt = 1;
+        loop {
+            match f() {
+                Err(e) if attempt < self.max_attempts && is_transient(&e) => {
+                    thread::sleep(backoff);
+                    backoff = (backoff * 2).min(self.max_backoff);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+fn is_transient(error: &io::Error) -> bool {
+    !matches!(
+        error.kind(),
+        io::ErrorKind::NotFound
+            | io::ErrorKind::PermissionDenied
+            | io::ErrorKind::InvalidInput
+            | io::ErrorKind::InvalidData
+            | io::ErrorKind::Unsupported
+    )
+}
+
+/// A [`ValueStore`] for a value file in remote storage, like an object store, so that only the (small) index needs to be
+/// kept locally.
+///
+/// Reads are rounded out to aligned blocks, which are fetched with a [`RangeFetcher`] and kept in an LRU cache. Contiguous
+/// missing blocks are fetched with a single request.
+///
+/// ```no_run
+/// # use mmap_cache::Error;
+/// # fn example() -> Result<(), Error> {
+/// use mmap_cache::{Cache, HttpFetcher, RemoteStore};
+///
+/// let index = std::fs::read("/data/index")?;
+/// let store = RemoteStore::new(HttpFetcher::new("http://storage.local/bucket/values")?)?;
+/// let cache = Cache::from_store(index, store)?;
+/// let value = cache.read_value(b"foo")?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct RemoteStore<F> {
+    fetcher: F,
+    len: u64,
+    retry: RetryPolicy,
+    block_size: u64,
+    blocks: Mutex<RemoteBlockLru>,
+}
+
+struct RemoteBlockLru {
+    capacity: usize,
+    /// Least recently used first.
+    blocks: VecDeque<(u64, Arc<[u8]>)>,
+}
+
+impl<F: RangeFetcher> RemoteStore<F> {
+    /// Wraps `fetcher`, fetching the size of the object with the default [`RetryPolicy`].
+    pub fn new(fetcher: F) -> Result<Self, Error> {
+        let retry = RetryPolicy::default();
+        let len = retry.run(|| fetcher.size())?;
+        Ok(Self {
+            fetcher,
+            len,
+            retry,
+            block_size: 64 * 1024,
+            blocks: Mutex::new(RemoteBlockLru {
+                capacity: 256,
+                blocks: VecDeque::new(),
+            }),
+        })
+    }
+
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Reads are rounded out to multiples of `block_size` bytes. Defaults to 64 KiB.
+    ///
+    /// # Panics
+    ///
+    /// If `block_size` is zero.
+    pub fn with_block_size(mut self, block_size: u64) -> Self {
+        assert!(block_size > 0);
+        self.block_size = block_size;
+        self.blocks.get_mut().unwrap().blocks.clear();
+        self
+    }
+
+    /// The maximum number of fetched blocks kept in memory. Defaults to 256; zero disables caching.
+    pub fn with_block_cache_capacity(mut self, capacity: usize) -> Self {
+        let lru = self.blocks.get_mut().unwrap();
+        lru.capacity = capacity;
+        lru.blocks.clear();
+        self
+    }
+
+    pub fn fetcher(&self) -> &F {
+        &self.fetcher
+    }
+
+    /// Fetches blocks `first..end` with one request.
+    fn fetch_blocks(&self, first: u64, end: u64) -> Result<Vec<Arc<[u8]>>, Error> {
+        let range = first * self.block_size..(end * self.block_size).min(self.len);
+        let expected = range.end - range.start;
+        let bytes = self.retry.run(|| {
+            let bytes = self.fetcher.fetch(range.clone())?;
+            if bytes.len() as u64 != expected {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("fetched {} of {expected} bytes", bytes.len()),
+                ));
+            }
+            Ok(bytes)
+        })?;
+        Ok(bytes
+            .chunks(self.block_size as usize)
+            .map(Arc::from)
+            .collect())
+    }
+}
+
+impl<F: RangeFetcher> ValueStore for RemoteStore<F> {
+    fn size(&self) -> u64 {
+        self.len
+    }
+
+    fn read(&self, offset: u64, len: u64) -> Result<Cow<'_, [u8]>, Error> {
+        let end = offset
+            .checked_add(len)
+            .filter(|&end| end <= self.len)
+            .ok_or(Error::OutOfBounds {
+                offset,
+                len,
+                available: self.len,
+            })?;
+        if len == 0 {
+            return Ok(Cow::Owned(Vec::new()));
+        }
+
+        let first = offset / self.block_size;
+        let blocks_end = end.div_ceil(self.block_size);
+        let mut blocks: Vec<Option<Arc<[u8]>>> = {
+            let mut lru = self.blocks.lock().unwrap();
+            (first..blocks_end).map(|b| lru.get(b)).collect()
+        };
+        // Fetch each run of missing blocks without holding the lock.
+        let mut i = 0;
+        while i < blocks.len() {
+            if blocks[i].is_some() {
+                i += 1;
+                continue;
+            }
+            let run_end = blocks[i..]
+                .iter()
+                .position(Option::is_some)
+                .map_or(blocks.len(), |n| i + n);
+            let fetched = self.fetch_blocks(first + i as u64, first + run_end as u64)?;
+            let mut lru = self.blocks.lock().unwrap();
+            for (j, block) in (i..run_end).zip(fetched) {
+                lru.insert(first + j as u64, block.clone());
+                blocks[j] = Some(block);
+            }
+            i = run_end;
+        }
+
+        let mut bytes = Vec::with_capacity(len as usize);
+        let mut position = offset;
+        for block in blocks.into_iter().flatten() {
+            let start = (position % self.block_size) as usize;
+            let take = (block.len() - start).min((end - position) as usize);
+            bytes.extend_from_slice(&block[start..start + take]);
+            position += take as u64;
+        }
+        Ok(Cow::Owned(bytes))
+    }
+}
+
+impl RemoteBlockLru {
+    fn get(&mut self, index: u64) -> Option<Arc<[u8]>> {
+        let i = self.blocks.iter().position(|(b, _)| *b == index)?;
+        let entry = self.blocks.remove(i).unwrap();
+        let block = entry.1.clone();
+        self.blocks.push_back(entry);
+        Some(block)
+    }
+
+    fn insert(&mut self, index: u64, block: Arc<[u8]>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.blocks.len() == self.capacity {
+            self.blocks.pop_front();
+        }
+        self.blocks.push_back((index, block));
+    }
+}
+
+/// A minimal [`RangeFetcher`] that sends HTTP/1.1 range requests over plain TCP, one connection per request.
+///
+/// This works with any HTTP server or object store that supports `Range` requests, including presigned S3 URLs. HTTPS
+/// isn't supported; for TLS or signed requests, implement [`RangeFetcher`] with a full client.
+#[derive(Clone, Debug)]
+pub struct HttpFetcher {
+    /// The `host[:port]` used for the `Host` header.
+    host: String,
+    /// The `host:port` to connect to.
+    address: String,
+    /// The path and query of the object.
+    target: String,
+    headers: Vec<(String, String)>,
+    timeout: Option<Duration>,
+}
+
+impl HttpFetcher {
+    /// Fetches from `url`, which must look like `http://host[:port]/path[?query]`.
+    pub fn new(url: &str) -> Result<Self, Error> {
+        let rest = url.strip_prefix("http://").ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "only http:// URLs are supported",
+            )
+        })?;
+        let (host, target) = match rest.find(['/', '?']) {
+            Some(i) if rest[i..].starts_with('/') => (&rest[..i], rest[i..].to_string()),
+            Some(i) => (&rest[..i], format!("/{}", &rest[i..])),
+            None => (rest, "/".to_string()),
+        };
+        if host.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "URL has no host").into());
+        }
+        let address = if host.contains(':') {
+            host.to_string()
+        } else {
+            format!("{host}:80")
+        };
+        Ok(Self {
+            host: host.to_string(),
+            address,
+            target,
+            headers: Vec::new(),
+            timeout: Some(Duration::from_secs(30)),
+        })
+    }
+
+    /// Adds a header to every request, e.g. for authorization.
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// The read and write timeout of each request. Defaults to 30 seconds.
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn request(&self, range: &str) -> io::Result<HttpResponse> {
+        let mut stream = TcpStream::connect(&self.address)?;
+        stream.set_read_timeout(self.timeout)?;
+        stream.set_write_timeout(self.timeout)?;
+        let mut request = format!(
+            "GET {} HTTP/1.1\r\nHost: {}\r\nRange: bytes={range}\r\nConnection: close\r\n",
+            self.target, self.host
+        );
+        for (name, value) in &self.headers {
+            request.push_str(&format!("{name}: {value}\r\n"));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes())?;
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response)?;
+        HttpResponse::parse(response)
+    }
+}
+
+impl RangeFetcher for HttpFetcher {
+    fn size(&self) -> io::Result<u64> {
+        let response = self.request("0-0")?;
+        match response.status {
+            // "Content-Range: bytes 0-0/<size>", or "bytes */0" for an empty object.
+            206 | 416 => response
+                .header("content-range")
+                .and_then(|range| range.rsplit_once('/'))
+                .and_then(|(_, size)| size.trim().parse().ok())
+                .ok_or_else(|| invalid_data("missing Content-Range")),
+            200 => Ok(response.body.len() as u64),
+            status => Err(status_error(status)),
+        }
+    }
+
+    fn fetch(&self, range: Range<u64>) -> io::Result<Vec<u8>> {
+        if range.is_empty() {
+            return Ok(Vec::new());
+        }
+        let response = self.request(&format!("{}-{}", range.start, range.end - 1))?;
+        match response.status {
+            206 => Ok(response.body),
+            // The server ignored the range and sent the whole object.
+            200 => response
+                .body
+                .get(range.start as usize..range.end as usize)
+                .map(<[u8]>::to_vec)
+                .ok_or_else(|| invalid_data("object is shorter than the requested range")),
+            status => Err(status_error(status)),
+        }
+    }
+}
+
+struct HttpResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl HttpResponse {
+    fn parse(mut response: Vec<u8>) -> io::Result<Self> {
+        let head_len = response
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .ok_or_else(|| invalid_data("truncated HTTP response"))?;
+        let head = std::str::from_utf8(&response[..head_len])
+            .map_err(|_| invalid_data("non-UTF-8 HTTP headers"))?;
+        let mut lines = head.split("\r\n");
+        let status = lines
+            .next()
+            .and_then(|line| line.split(' ').nth(1))
+            .and_then(|status| status.parse().ok())
+            .ok_or_else(|| invalid_data("malformed HTTP status line"))?;
+        let headers: Vec<(String, String)> = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+            .collect();
+        let mut body = response.split_off(head_len + 4);
+
+        let mut this = Self {
+            status,
+            headers,
+            body: Vec::new(),
+        };
+        if this
+            .header("transfer-encoding")
+            .is_some_and(|encoding| encoding.eq_ignore_ascii_case("chunked"))
+        {
+            body = decode_chunked(&body)?;
+        } else if let Some(len) = this.header("content-length") {
+            let len: usize = len
+                .parse()
+                .map_err(|_| invalid_data("malformed Content-Length"))?;
+            if body.len() < len {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "truncated HTTP body",
+                ));
+            }
+            body.truncate(len);
+        }
+        this.body = body;
+        Ok(this)
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn decode_chunked(mut body: &[u8]) -> io::Result<Vec<u8>> {
+    let mut decoded = Vec::new();
+    loop {
+        let line_end = body
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or_else(|| invalid_data("truncated chunk"))?;
+        let size = std::str::from_utf8(&body[..line_end])
+            .ok()
+            .and_then(|line| line.split(';').next())
+            .and_then(|size| usize::from_str_radix(size.trim(), 16).ok())
+            .ok_or_else(|| invalid_data("malformed chunk size"))?;
+        body = &body[line_end + 2..];
+        if size == 0 {
+            return Ok(decoded);
+        }
+        let chunk = body
+            .get(..size)
+            .ok_or_else(|| invalid_data("truncated chunk"))?;
+        decoded.extend_from_slice(chunk);
+        body = body.get(size + 2..).unwrap_or_default();
+    }
+}
+
+fn invalid_data(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn status_error(status: u16) -> io::Error {
+    let kind = match status {
+        401 | 403 => io::ErrorKind::PermissionDenied,
+        404 => io::ErrorKind::NotFound,
+        400..=499 => io::ErrorKind::InvalidInput,
+        _ => io::ErrorKind::Other,
+    };
+    io::Error::new(kind, format!("HTTP status {status}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Cache, FileBuilder};
+
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Serves `object` with range support until the test process exits.
+    fn serve(object: Vec<u8>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = Vec::new();
+                let mut byte = [0];
+                while !request.ends_with(b"\r\n\r\n") {
+                    stream.read_exact(&mut byte).unwrap();
+                    request.push(byte[0]);
+                }
+                let request = String::from_utf8(request).unwrap();
+                let range = request
+                    .lines()
+                    .find_map(|line| line.strip_prefix("Range: bytes="))
+                    .unwrap();
+                let (start, end) = range.split_once('-').unwrap();
+                let start: usize = start.parse().unwrap();
+                let end = (end.parse::<usize>().unwrap() + 1).min(object.len());
+                let body = &object[start..end];
+                write!(
+                    stream,
+                    "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {start}-{}/{}\r\nContent-Length: {}\r\n\r\n",
+                    end - 1,
+                    object.len(),
+                    body.len()
+                )
+                .unwrap();
+                stream.write_all(body).unwrap();
+            }
+        });
+        format!("http://{address}/values")
+    }
+
+    #[test]
+    fn http_backend() {
+        let dir = std::env::temp_dir();
+        let index_path = dir.join("mmap_cache_http_backend_index");
+        let values_path = dir.join("mmap_cache_http_backend_values");
+        let mut builder = FileBuilder::create_files(&index_path, &values_path).unwrap();
+        builder.insert(b"a", b"first").unwrap();
+        builder.insert(b"b", &[7; 300]).unwrap();
+        builder.insert(b"c", b"third").unwrap();
+        builder.finish().unwrap();
+
+        let url = serve(std::fs::read(&values_path).unwrap());
+        let store = RemoteStore::new(HttpFetcher::new(&url).unwrap())
+            .unwrap()
+            .with_block_size(64);
+        let cache = Cache::from_store(std::fs::read(&index_path).unwrap(), store).unwrap();
+        assert_eq!(cache.read_value(b"a").unwrap().unwrap(), &b"first"[..]);
+        assert_eq!(cache.read_value(b"b").unwrap().unwrap(), &[7; 300][..]);
+        assert_eq!(cache.read_value(b"c").unwrap().unwrap(), &b"third"[..]);
+        assert_eq!(cache.read_value(b"d").unwrap(), None);
+    }
+
+    struct FlakyFetcher {
+        object: Vec<u8>,
+        failures: AtomicUsize,
+        fetches: AtomicUsize,
+    }
+
+    impl RangeFetcher for FlakyFetcher {
+        fn size(&self) -> io::Result<u64> {
+            Ok(self.object.len() as u64)
+        }
+
+        fn fetch(&self, range: Range<u64>) -> io::Result<Vec<u8>> {
+            self.fetches.fetch_add(1, Ordering::SeqCst);
+            if self.failures.load(Ordering::SeqCst) > 0 {
+                self.failures.fetch_sub(1, Ordering::SeqCst);
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "flaky"));
+            }
+            Ok(self.object[range.start as usize..range.end as usize].to_vec())
+        }
+    }
+
+    #[test]
+    fn retries_and_caches_blocks() {
+        let fetcher = FlakyFetcher {
+            object: (0..100).collect(),
+            failures: AtomicUsize::new(2),
+            fetches: AtomicUsize::new(0),
+        };
+        let store = RemoteStore::new(fetcher)
+            .unwrap()
+            .with_block_size(16)
+            .with_retry_policy(RetryPolicy {
+                initial_backoff: Duration::ZERO,
+                ..RetryPolicy::default()
+            });
+        let expected: Vec<u8> = (10..40).collect();
+        assert_eq!(store.read(10, 30).unwrap(), &expected[..]);
+        assert_eq!(store.fetcher().fetches.load(Ordering::SeqCst), 3);
+
+        // Blocks 1 and 2 are cached, so only block 3 is fetched.
+        let expected: Vec<u8> = (20..60).collect();
+        assert_eq!(store.read(20, 40).unwrap(), &expected[..]);
+        assert_eq!(store.fetcher().fetches.load(Ordering::SeqCst), 4);
+        assert!(store.read(90, 11).is_err());
+
+        store.fetcher().failures.store(3, Ordering::SeqCst);
+        assert!(store.read(96, 4).is_err());
+    }
+}
@@ -0,0 +1,87 @@
+use crate::{Error, MmapCache};
+
+use std::io;
+
+/// How many pages of a memory mapping are resident in RAM, as reported by [`MmapCache::residency`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Residency {
+    pub resident_pages: u64,
+    pub total_pages: u64,
+}
+
+impl Residency {
+    /// The fraction of pages that are resident, from 0 to 1. An empty mapping counts as fully resident.
+    pub fn fraction(&self) -> f64 {
+        if self.total_pages == 0 {
+            return 1.0;
+        }
+        self.resident_pages as f64 / self.total_pages as f64
+    }
+}
+
+/// The page residency of the index and value mappings of a [`MmapCache`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CacheResidency {
+    pub index: Residency,
+    pub values: Residency,
+    /// The size of a page in bytes.
+    pub page_size: u64,
+}
+
+impl CacheResidency {
+    /// The combined residency of both mappings.
+    pub fn total(&self) -> Residency {
+        Residency {
+            resident_pages: self.index.resident_pages + self.values.resident_pages,
+            total_pages: self.index.total_pages + self.values.total_pages,
+        }
+    }
+}
+
+impl MmapCache {
+    /// Reports how many pages of the index and value mappings are currently resident in RAM (`mincore`), e.g. for
+    /// capacity planning or to export as a metric.
+    ///
+    /// This is a snapshot: pages may be read in or evicted at any time. A page counts as resident if it's in the page
+    /// cache, even if this process never touched it. Only supported on Unix.
+    pub fn residency(&self) -> Result<CacheResidency, Error> {
+        let page_size = page_size()?;
+        Ok(CacheResidency {
+            index: mapping_residency(self.index().as_fst().as_bytes(), page_size)?,
+            values: mapping_residency(self.raw_value_bytes(), page_size)?,
+            page_size: page_size as u64,
+        })
+    }
+}
+
+fn page_size() -> io::Result<usize> {
+    // SAFETY: `sysconf` has no preconditions.
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    usize::try_from(page_size).map_err(|_| io::Error::last_os_error())
+}
+
+fn mapping_residency(bytes: &[u8], page_size: usize) -> io::Result<Residency> {
+    if bytes.is_empty() {
+        return Ok(Residency::default());
+    }
+    // `mincore` requires a page-aligned start, so include the start of the first page.
+    let misalignment = bytes.as_ptr() as usize % page_size;
+    let len = bytes.len() + misalignment;
+    let mut pages = vec![0u8; len.div_ceil(page_size)];
+    // SAFETY: Everything from the start of the first page to the end of `bytes` is mapped, and `pages` has a byte per page.
+    let result = unsafe {
+        libc::mincore(
+            bytes.as_ptr().sub(misalignment) as *mut libc::c_void,
+            len,
+            pages.as_mut_ptr().cast(),
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(Residency {
+        // The lowest bit is set for resident pages; the others are reserved.
+        resident_pages: pages.iter().filter(|&&page| page & 1 != 0).count() as u64,
+        total_pages: pages.len() as u64,
+    })
+}
@@ -0,0 +1,65 @@
+use crate::Cache;
+
+/// The alignment of [`AlignedBytes`]. This is enough for any primitive type or SIMD vector, and matches a cache line.
+pub const RESIDENT_ALIGNMENT: usize = 64;
+
+#[derive(Clone, Copy)]
+#[repr(C, align(64))]
+struct Chunk([u8; RESIDENT_ALIGNMENT]);
+
+/// An immutable heap buffer whose start is aligned to [`RESIDENT_ALIGNMENT`] bytes.
+///
+/// Values that were aligned within a memory-mapped file stay aligned when copied into this buffer.
+#[derive(Clone)]
+pub struct AlignedBytes {
+    chunks: Vec<Chunk>,
+    len: usize,
+}
+
+impl AlignedBytes {
+    pub fn copy_from_slice(bytes: &[u8]) -> Self {
+        let mut chunks =
+            vec![Chunk([0; RESIDENT_ALIGNMENT]); bytes.len().div_ceil(RESIDENT_ALIGNMENT)];
+        for (chunk, src) in chunks.iter_mut().zip(bytes.chunks(RESIDENT_ALIGNMENT)) {
+            chunk.0[..src.len()].copy_from_slice(src);
+        }
+        Self {
+            chunks,
+            len: bytes.len(),
+        }
+    }
+}
+
+impl AsRef<[u8]> for AlignedBytes {
+    fn as_ref(&self) -> &[u8] {
+        // SAFETY: `Chunk` is a `repr(C)` byte array with no padding, and the first `len` bytes are initialized.
+        unsafe { std::slice::from_raw_parts(self.chunks.as_ptr().cast(), self.len) }
+    }
+}
+
+impl std::fmt::Debug for AlignedBytes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AlignedBytes")
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+/// A [`Cache`] held entirely in heap memory, created by [`Cache::into_resident`].
+pub type ResidentCache = Cache<AlignedBytes, AlignedBytes>;
+
+impl<DK, DV> Cache<DK, DV>
+where
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    /// Copies the index and value storage into aligned heap buffers, so that reads never page fault on a file.
+    ///
+    /// This lets the same files be shipped and mapped as usual, but served purely from RAM. Value offsets and alignment are
+    /// preserved.
+    pub fn into_resident(self) -> ResidentCache {
+        let index = AlignedBytes::copy_from_slice(self.index().as_fst().as_bytes());
+        let values = AlignedBytes::copy_from_slice(self.raw_value_bytes().as_ref());
+        Cache::new(index, values).expect("cache was already validated")
+    }
+}
@@ -0,0 +1,122 @@
+use fst::raw::{CompiledAddr, Fst};
+use fst::Streamer;
+use std::ops::Bound;
+
+/// A streaming iterator over (key, value offset) pairs in descending key order, returned by
+/// [`Cache::range_rev`](crate::Cache::range_rev).
+///
+/// The FST is walked in reverse post-order, so no more than one key is buffered at a time.
+pub struct RevStream<'f, DK> {
+    fst: &'f Fst<DK>,
+    /// The path from the root to the current node. `key[..i]` is the key of `stack[i]`.
+    stack: Vec<RevFrame>,
+    key: Vec<u8>,
+    lower: Bound<Vec<u8>>,
+}
+
+struct RevFrame {
+    addr: CompiledAddr,
+    /// Transitions with index less than this are yet to be visited.
+    remaining: usize,
+    /// Whether the key of this node is within the upper bound.
+    in_range: bool,
+    output: u64,
+}
+
+impl<'f, DK: AsRef<[u8]>> RevStream<'f, DK> {
+    pub(crate) fn new(fst: &'f Fst<DK>, lower: Bound<Vec<u8>>, upper: Bound<&[u8]>) -> Self {
+        let mut stream = Self {
+            fst,
+            stack: Vec::new(),
+            key: Vec::new(),
+            lower,
+        };
+        let root = fst.root();
+        let upper_key = match upper {
+            Bound::Unbounded => {
+                stream.stack.push(RevFrame {
+                    addr: root.addr(),
+                    remaining: root.len(),
+                    in_range: true,
+                    output: 0,
+                });
+                return stream;
+            }
+            Bound::Included(k) | Bound::Excluded(k) => k,
+        };
+
+        // Descend along the upper bound, leaving only the transitions below it to visit.
+        let mut node = root;
+        let mut output = 0;
+        for &byte in upper_key {
+            let below = (0..node.len())
+                .position(|t| node.transition(t).inp >= byte)
+                .unwrap_or(node.len());
+            stream.stack.push(RevFrame {
+                addr: node.addr(),
+                remaining: below,
+                in_range: true,
+                output,
+            });
+            match node.find_input(byte) {
+                Some(t) => {
+                    let t = node.transition(t);
+                    stream.key.push(t.inp);
+                    output += t.out.value();
+                    node = fst.node(t.addr);
+                }
+                None => return stream,
+            }
+        }
+        stream.stack.push(RevFrame {
+            addr: node.addr(),
+            remaining: 0,
+            in_range: matches!(upper, Bound::Included(_)),
+            output,
+        });
+        stream
+    }
+}
+
+impl<'a, 'f: 'a, DK: AsRef<[u8]>> Streamer<'a> for RevStream<'f, DK> {
+    type Item = (&'a [u8], u64);
+
+    fn next(&'a mut self) -> Option<Self::Item> {
+        while let Some(frame) = self.stack.last_mut() {
+            let node = self.fst.node(frame.addr);
+            if frame.remaining > 0 {
+                frame.remaining -= 1;
+                let t = node.transition(frame.remaining);
+                let child = self.fst.node(t.addr);
+                let output = frame.output + t.out.value();
+                self.key.truncate(self.stack.len() - 1);
+                self.key.push(t.inp);
+                self.stack.push(RevFrame {
+                    addr: t.addr,
+                    remaining: child.len(),
+                    in_range: true,
+                    output,
+                });
+                continue;
+            }
+
+            // All greater keys have been visited, so this node's own key is next.
+            let frame = self.stack.pop().unwrap();
+            self.key.truncate(self.stack.len());
+            if !(frame.in_range && node.is_final()) {
+                continue;
+            }
+            let below_lower = match &self.lower {
+                Bound::Unbounded => false,
+                Bound::Included(lower) => self.key < *lower,
+                Bound::Excluded(lower) => self.key <= *lower,
+            };
+            if below_lower {
+                self.stack.clear();
+                return None;
+            }
+            return Some((&self.key, frame.output + node.final_output().value()));
+        }
+        None
+    }
+}
@@ -0,0 +1,245 @@
+use crate::{format, shard_paths, Error};
+
+use std::path::{Path, PathBuf};
+
+/// A consistent hash ring that assigns keys to shards `0..shard_count`.
+///
+/// Every shard owns `virtual_nodes` points on the ring, and a key belongs to the shard that owns the first point at or after
+/// the hash of the key. The points of a shard only depend on its number, so growing the ring from `n` to `n + 1` shards only
+/// moves the keys that now belong to the new shard (about `1 / (n + 1)` of them), unlike
+/// [`ShardRouting::Hash`](crate::ShardRouting::Hash), which moves almost every key.
+///
+/// Use [`ShardRouting::Ring`](crate::ShardRouting::Ring) to write and read a [`ShardedCache`](crate::ShardedCache) with a
+/// ring.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HashRing {
+    shard_count: usize,
+    virtual_nodes: usize,
+    points: Vec<(u64, usize)>,
+}
+
+impl HashRing {
+    pub fn new(shard_count: usize, virtual_nodes: usize) -> Result<Self, Error> {
+        if shard_count == 0 || virtual_nodes == 0 {
+            return Err(Error::InvalidFormat(
+                "hash ring needs at least one shard and virtual node",
+            ));
+        }
+        let points = build_ring(
+            (0..shard_count).map(|shard| {
+                let id = (shard as u64).to_le_bytes();
+                (id, shard)
+            }),
+            virtual_nodes,
+        );
+        Ok(Self {
+            shard_count,
+            virtual_nodes,
+            points,
+        })
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shard_count
+    }
+
+    pub fn virtual_nodes(&self) -> usize {
+        self.virtual_nodes
+    }
+
+    /// The shard that holds `key`.
+    pub fn shard_for(&self, key: &[u8]) -> usize {
+        ring_lookup(&self.points, format::key_hash(key))
+    }
+}
+
+/// Assigns the shards of a [`HashRing`] to the nodes of a distributed deployment, so that each node only maps the shard
+/// files it serves.
+///
+/// Shards are placed on nodes with a second consistent hash ring, so adding or removing a node only moves the shards that
+/// the node gains or loses. Use [`moves_to`](Self::moves_to) to find the shard files that must be copied before switching
+/// to a new set of nodes.
+///
+/// ```
+/// # use mmap_cache::Error;
+/// # fn example() -> Result<(), Error> {
+/// use mmap_cache::{ConsistentHashRouter, HashRing};
+///
+/// let mut router = ConsistentHashRouter::new(HashRing::new(16, 64)?, 64)?;
+/// router.add_node("node-a");
+/// router.add_node("node-b");
+///
+/// let node = router.node_for(b"apple").unwrap();
+/// assert!(router.shards_for_node(node).contains(&router.shard_for(b"apple")));
+///
+/// let mut grown = router.clone();
+/// grown.add_node("node-c");
+/// assert!(router.moves_to(&grown).iter().all(|m| m.to.as_deref() == Some("node-c")));
+/// # Ok(())
+/// # }
+/// # example().unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct ConsistentHashRouter {
+    ring: HashRing,
+    virtual_nodes: usize,
+    nodes: Vec<String>,
+    node_points: Vec<(u64, usize)>,
+}
+
+/// A shard that is served by a different node after rebalancing, returned by [`ConsistentHashRouter::moves_to`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ShardMove {
+    pub shard: usize,
+    /// The node that served the shard before, or `None` if there were no nodes or the shard is new.
+    pub from: Option<String>,
+    /// The node that serves the shard after, or `None` if there are no nodes or the shard was removed.
+    pub to: Option<String>,
+}
+
+impl ConsistentHashRouter {
+    /// Routes keys with `ring`, placing each node at `virtual_nodes` points on the node ring. There are no nodes at first.
+    pub fn new(ring: HashRing, virtual_nodes: usize) -> Result<Self, Error> {
+        if virtual_nodes == 0 {
+            return Err(Error::InvalidFormat(
+                "hash ring needs at least one virtual node",
+            ));
+        }
+        Ok(Self {
+            ring,
+            virtual_nodes,
+            nodes: Vec::new(),
+            node_points: Vec::new(),
+        })
+    }
+
+    pub fn ring(&self) -> &HashRing {
+        &self.ring
+    }
+
+    /// The names of all nodes, sorted.
+    pub fn nodes(&self) -> &[String] {
+        &self.nodes
+    }
+
+    /// Adds a node, returning `false` if it already exists.
+    pub fn add_node(&mut self, name: &str) -> bool {
+        match self.nodes.binary_search_by(|node| node.as_str().cmp(name)) {
+            Ok(_) => false,
+            Err(i) => {
+                self.nodes.insert(i, name.to_owned());
+                self.rebuild_node_ring();
+                true
+            }
+        }
+    }
+
+    /// Removes a node, returning `false` if it doesn't exist.
+    pub fn remove_node(&mut self, name: &str) -> bool {
+        match self.nodes.binary_search_by(|node| node.as_str().cmp(name)) {
+            Ok(i) => {
+                self.nodes.remove(i);
+                self.rebuild_node_ring();
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn rebuild_node_ring(&mut self) {
+        self.node_points = build_ring(
+            self.nodes
+                .iter()
+                .enumerate()
+                .map(|(i, name)| (name.as_bytes(), i)),
+            self.virtual_nodes,
+        );
+    }
+
+    /// The shard that holds `key`.
+    pub fn shard_for(&self, key: &[u8]) -> usize {
+        self.ring.shard_for(key)
+    }
+
+    /// The node that serves `shard`, or `None` if there are no nodes.
+    pub fn node_for_shard(&self, shard: usize) -> Option<&str> {
+        if self.node_points.is_empty() {
+            return None;
+        }
+        let hash = format::key_hash(&(shard as u64).to_le_bytes());
+        Some(&self.nodes[ring_lookup(&self.node_points, hash)])
+    }
+
+    /// The node that serves the shard holding `key`, or `None` if there are no nodes.
+    pub fn node_for(&self, key: &[u8]) -> Option<&str> {
+        self.node_for_shard(self.shard_for(key))
+    }
+
+    /// The shards served by `node`, in order.
+    pub fn shards_for_node(&self, node: &str) -> Vec<usize> {
+        (0..self.ring.shard_count())
+            .filter(|&shard| self.node_for_shard(shard) == Some(node))
+            .collect()
+    }
+
+    /// The index and value paths (see [`shard_paths`]) of the shards that `node` must map, for a sharded cache written at
+    /// `base_path`.
+    pub fn shard_paths_for_node(
+        &self,
+        base_path: impl AsRef<Path>,
+        node: &str,
+    ) -> Vec<(usize, PathBuf, PathBuf)> {
+        self.shards_for_node(node)
+            .into_iter()
+            .map(|shard| {
+                let (index_path, value_path) = shard_paths(&base_path, shard);
+                (shard, index_path, value_path)
+            })
+            .collect()
+    }
+
+    /// The shards that are served by a different node in `other`, e.g. after adding or removing nodes.
+    ///
+    /// If the rings differ, keys move between shards as well, so the shards must be rebuilt with
+    /// [`ShardedCache::reshard`](crate::ShardedCache::reshard) before they are moved.
+    pub fn moves_to(&self, other: &Self) -> Vec<ShardMove> {
+        let shard_count = self.ring.shard_count().max(other.ring.shard_count());
+        (0..shard_count)
+            .filter_map(|shard| {
+                let node = |router: &Self| {
+                    (shard < router.ring.shard_count())
+                        .then(|| router.node_for_shard(shard))
+                        .flatten()
+                        .map(str::to_owned)
+                };
+                let (from, to) = (node(self), node(other));
+                (from != to).then_some(ShardMove { shard, from, to })
+            })
+            .collect()
+    }
+}
+
+/// Places `virtual_nodes` points on the ring for each `(id, member)`, sorted by hash.
+fn build_ring<I: AsRef<[u8]>>(
+    members: impl Iterator<Item = (I, usize)>,
+    virtual_nodes: usize,
+) -> Vec<(u64, usize)> {
+    let mut points = Vec::new();
+    let mut point_id = Vec::new();
+    for (id, member) in members {
+        for v in 0..virtual_nodes as u64 {
+            point_id.clear();
+            point_id.extend_from_slice(id.as_ref());
+            point_id.extend_from_slice(&v.to_le_bytes());
+            points.push((format::key_hash(&point_id), member));
+        }
+    }
+    points.sort_unstable();
+    points
+}
+
+/// The member owning the first point at or after `hash`, wrapping around to the first point.
+fn ring_lookup(points: &[(u64, usize)], hash: u64) -> usize {
+    let i = points.partition_point(|&(point, _)| point < hash);
+    points[i % points.len()].1
+}
@@ -0,0 +1,129 @@
+use crate::format::read_u64;
+use crate::{Cache, Error, FileBuilder, TypedCache};
+
+use bytemuck::Pod;
+use std::fmt;
+use std::io::Write;
+
+/// The metadata key under which [`FileBuilder::with_value_schema`] records the [`ValueSchema`].
+pub const VALUE_SCHEMA_METADATA_KEY: &str = "mmap_cache.value_schema";
+
+/// A fingerprint of the layout of the values in a cache, recorded by the writer and checked by readers before values are
+/// cast or transmuted, so that a reader whose struct has diverged from the writer's fails loudly instead of misreading.
+///
+/// The fingerprint of a type is its name, size, and alignment, plus a user-chosen `version`, which can be bumped (or set
+/// to a hash of the field layout) when a struct changes without changing its size. Type names come from
+/// [`std::any::type_name`], which isn't guaranteed to be stable across compiler versions.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct ValueSchema {
+    pub type_name: String,
+    pub size: u64,
+    pub align: u64,
+    pub version: u64,
+}
+
+impl ValueSchema {
+    /// The fingerprint of `T`, with version 0.
+    pub fn of<T>() -> Self {
+        Self {
+            type_name: std::any::type_name::<T>().to_owned(),
+            size: std::mem::size_of::<T>() as u64,
+            align: std::mem::align_of::<T>() as u64,
+            version: 0,
+        }
+    }
+
+    pub fn with_version(mut self, version: u64) -> Self {
+        self.version = version;
+        self
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(24 + self.type_name.len());
+        for n in [self.size, self.align, self.version] {
+            bytes.write_all(&n.to_le_bytes()).unwrap();
+        }
+        bytes.extend_from_slice(self.type_name.as_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        const CORRUPT: Error = Error::InvalidFormat("corrupt value schema");
+        if bytes.len() < 24 {
+            return Err(CORRUPT);
+        }
+        Ok(Self {
+            size: read_u64(bytes, 0),
+            align: read_u64(bytes, 8),
+            version: read_u64(bytes, 16),
+            type_name: std::str::from_utf8(&bytes[24..])
+                .map_err(|_| CORRUPT)?
+                .to_owned(),
+        })
+    }
+}
+
+impl fmt::Display for ValueSchema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (size {}, align {}, version {})",
+            self.type_name, self.size, self.align, self.version
+        )
+    }
+}
+
+impl<WI: Write, WV: Write> FileBuilder<WI, WV> {
+    /// Records `schema` in the [metadata](Self::set_metadata), for readers to check with
+    /// [`Cache::check_value_schema`] or [`TypedCache::with_schema`].
+    pub fn with_value_schema(mut self, schema: &ValueSchema) -> Self {
+        self.set_metadata(VALUE_SCHEMA_METADATA_KEY, schema.to_bytes());
+        self
+    }
+}
+
+impl<DK, DV> Cache<DK, DV>
+where
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    /// Returns the [`ValueSchema`] recorded by the writer, if any.
+    pub fn value_schema(&self) -> Result<Option<ValueSchema>, Error> {
+        self.metadata()?
+            .get(VALUE_SCHEMA_METADATA_KEY)
+            .map(ValueSchema::from_bytes)
+            .transpose()
+    }
+
+    /// Fails with [`Error::SchemaMismatch`] unless the writer recorded exactly `expected`.
+    ///
+    /// Call this before using the transmuting accessors, like [`get_transmuted_value`](Self::get_transmuted_value).
+    pub fn check_value_schema(&self, expected: &ValueSchema) -> Result<(), Error> {
+        match self.value_schema()? {
+            Some(found) if found == *expected => Ok(()),
+            found => Err(Error::SchemaMismatch {
+                expected: expected.to_string(),
+                found: found.map_or_else(|| "no schema".to_owned(), |s| s.to_string()),
+            }),
+        }
+    }
+}
+
+impl<T, DK, DV> TypedCache<T, DK, DV>
+where
+    T: Pod,
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    /// Like [`new`](Self::new), but first checks that the writer recorded the schema of `T`; see
+    /// [`ValueSchema::of`].
+    pub fn new_checked(cache: Cache<DK, DV>) -> Result<Self, Error> {
+        Self::with_schema(cache, &ValueSchema::of::<T>())
+    }
+
+    /// Like [`new`](Self::new), but first checks that the writer recorded `schema`, e.g. one with a version.
+    pub fn with_schema(cache: Cache<DK, DV>, schema: &ValueSchema) -> Result<Self, Error> {
+        cache.check_value_schema(schema)?;
+        Ok(Self::new(cache))
+    }
+}
@@ -0,0 +1,193 @@
+use crate::temp::TempFile;
+use crate::{Error, FileBuilder};
+#[cfg(feature = "mmap")]
+use crate::{LayeredCache, MmapCache};
+
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const MANIFEST_HEADER: &str = "mmap-cache segments 1";
+
+/// The index and value files of one segment listed in a [`SegmentManifest`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Segment {
+    pub index_path: PathBuf,
+    pub value_path: PathBuf,
+}
+
+/// A small text file listing the segments of a cache that grows by appending delta segments, from oldest to newest.
+///
+/// Instead of rebuilding the whole cache when new records arrive, build a segment with only the new (or changed) keys and
+/// append it to the manifest. Readers open all listed segments together as a [`LayeredCache`](crate::LayeredCache), where
+/// newer segments shadow older ones. The segments can later be merged with
+/// [`LayeredCache::compact`](crate::LayeredCache::compact).
+///
+/// Segment paths are recorded relative to the directory containing the manifest, so the whole directory can be moved. The
+/// manifest is replaced atomically, so readers never observe a segment that isn't finished.
+///
+/// ```
+/// # use mmap_cache::Error;
+/// # #[cfg(feature = "mmap")]
+/// # fn example() -> Result<(), Error> {
+/// use mmap_cache::SegmentManifest;
+///
+/// # let _ = std::fs::remove_file("/tmp/mmap_cache_segments_doc");
+/// let mut manifest = SegmentManifest::open("/tmp/mmap_cache_segments_doc")?;
+/// manifest.append_segment(|builder| builder.insert(b"apple", b"red"))?;
+/// manifest.append_segment(|builder| builder.insert(b"apple", b"green"))?;
+///
+/// let cache = unsafe { SegmentManifest::open("/tmp/mmap_cache_segments_doc")?.map_layers()? };
+/// assert_eq!(cache.get_value(b"apple"), Some(&b"green"[..]));
+/// # Ok(())
+/// # }
+/// # #[cfg(feature = "mmap")]
+/// # example().unwrap();
+/// ```
+pub struct SegmentManifest {
+    path: PathBuf,
+    segments: Vec<Segment>,
+}
+
+impl SegmentManifest {
+    /// Reads the manifest at `path`, or starts an empty one if the file doesn't exist yet.
+    ///
+    /// An empty manifest isn't written until the first segment is committed.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref().to_owned();
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(Self {
+                    path,
+                    segments: Vec::new(),
+                })
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let mut lines = contents.lines();
+        if lines.next() != Some(MANIFEST_HEADER) {
+            return Err(Error::InvalidFormat("bad segment manifest header"));
+        }
+        let dir = manifest_dir(&path);
+        let segments = lines
+            .map(|line| {
+                let (index, values) = line
+                    .split_once('\t')
+                    .ok_or(Error::InvalidFormat("bad segment manifest entry"))?;
+                Ok(Segment {
+                    index_path: dir.join(index),
+                    value_path: dir.join(values),
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+        Ok(Self { path, segments })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The committed segments, ordered from oldest to newest.
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Returns the paths for the next segment, next to the manifest. Build the segment there and then call
+    /// [`commit_segment`](Self::commit_segment).
+    pub fn next_segment(&self) -> Segment {
+        let name = self
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let n = self.segments.len();
+        let dir = manifest_dir(&self.path);
+        Segment {
+            index_path: dir.join(format!("{name}.{n}.index")),
+            value_path: dir.join(format!("{name}.{n}.values")),
+        }
+    }
+
+    /// Appends the finished `segment` as the newest segment and atomically replaces the manifest file.
+    pub fn commit_segment(&mut self, segment: Segment) -> Result<(), Error> {
+        for path in [&segment.index_path, &segment.value_path] {
+            if !path.is_file() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("segment file {} doesn't exist", path.display()),
+                )
+                .into());
+            }
+        }
+        self.segments.push(segment);
+        if let Err(e) = self.save() {
+            self.segments.pop();
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Builds the next segment with `fill`, which inserts the new entries in key order, then commits it.
+    ///
+    /// If `fill` or the build fails, the manifest and any existing segments are left untouched.
+    pub fn append_segment(
+        &mut self,
+        fill: impl FnOnce(&mut FileBuilder) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let segment = self.next_segment();
+        let mut builder =
+            FileBuilder::create_files_atomic(&segment.index_path, &segment.value_path)?;
+        fill(&mut builder)?;
+        builder.finish()?;
+        self.commit_segment(segment)
+    }
+
+    /// Maps all segments into a [`LayeredCache`], with the newest segment on top.
+    ///
+    /// # Safety
+    ///
+    /// See [`Mmap`].
+    #[cfg(feature = "mmap")]
+    pub unsafe fn map_layers(&self) -> Result<LayeredCache<Mmap, Mmap>, Error> {
+        let layers = self
+            .segments
+            .iter()
+            .map(|segment| MmapCache::map_paths(&segment.index_path, &segment.value_path))
+            .collect::<Result<_, _>>()?;
+        Ok(LayeredCache::new(layers))
+    }
+
+    fn save(&self) -> Result<(), Error> {
+        let dir = manifest_dir(&self.path);
+        let relative = |path: &Path| {
+            let path = path.strip_prefix(&dir).unwrap_or(path);
+            match path.to_str() {
+                Some(s) if !s.contains(['\t', '\n']) => Ok(s.to_owned()),
+                _ => Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("unsupported segment path {}", path.display()),
+                )),
+            }
+        };
+        let mut file = TempFile::new_beside(&self.path)?;
+        writeln!(file, "{MANIFEST_HEADER}")?;
+        for segment in &self.segments {
+            let index = relative(&segment.index_path)?;
+            let values = relative(&segment.value_path)?;
+            writeln!(file, "{index}\t{values}")?;
+        }
+        file.try_clone_file()?.sync_all()?;
+        file.persist(&self.path)?;
+        Ok(())
+    }
+}
+
+fn manifest_dir(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_owned(),
+        _ => PathBuf::from("."),
+    }
+}
@@ -0,0 +1,156 @@
+use crate::temp::TempFile;
+use crate::Error;
+
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+use std::fs;
+use std::io::{self, Write};
+use std::ops::{Bound, RangeBounds};
+use std::path::{Path, PathBuf};
+
+/// Serializes a sorted stream of keys, without values, into an [`fst::Set`]. Read the result with a [`Set`].
+///
+/// ```
+/// # use mmap_cache::Error;
+/// # #[cfg(feature = "mmap")]
+/// # fn example() -> Result<(), Error> {
+/// use mmap_cache::{MmapSet, SetBuilder};
+///
+/// let mut builder = SetBuilder::create_file("/tmp/mmap_cache_set")?;
+/// builder.insert(b"apple")?;
+/// builder.insert(b"banana")?;
+/// builder.finish()?;
+///
+/// let set = unsafe { MmapSet::map_path("/tmp/mmap_cache_set")? };
+/// assert!(set.contains(b"banana"));
+/// assert!(!set.contains(b"cherry"));
+/// # Ok(())
+/// # }
+/// # #[cfg(feature = "mmap")]
+/// # example().unwrap();
+/// ```
+pub struct SetBuilder<W = io::BufWriter<fs::File>> {
+    set_builder: fst::SetBuilder<W>,
+    /// A temporary file to rename into place when building atomically.
+    pending_rename: Option<(TempFile, PathBuf)>,
+}
+
+impl<W: Write> SetBuilder<W> {
+    pub fn new(writer: W) -> Result<Self, Error> {
+        Ok(Self {
+            set_builder: fst::SetBuilder::new(writer)?,
+            pending_rename: None,
+        })
+    }
+
+    /// Inserts `key`. Keys must be inserted in order.
+    pub fn insert(&mut self, key: &[u8]) -> Result<(), Error> {
+        Ok(self.set_builder.insert(key)?)
+    }
+
+    /// Completes the serialization and flushes any outstanding IO. When building atomically, the finished file is then
+    /// renamed into place.
+    pub fn finish(self) -> Result<(), Error> {
+        self.finish_into_inner().map(drop)
+    }
+
+    /// Like [`finish`](Self::finish), but returns the flushed writer.
+    pub fn finish_into_inner(self) -> Result<W, Error> {
+        let mut writer = self.set_builder.into_inner()?;
+        writer.flush()?;
+        if let Some((file, path)) = self.pending_rename {
+            file.persist(path)?;
+        }
+        Ok(writer)
+    }
+}
+
+impl SetBuilder {
+    /// Creates a new [`SetBuilder`] writing to the file at `path`. This always overwrites the given file.
+    pub fn create_file(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::new(io::BufWriter::new(fs::File::create(path)?))
+    }
+
+    /// Like [`create_file`](Self::create_file), but the existing file is only replaced once `finish` succeeds.
+    pub fn create_file_atomic(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let file = TempFile::new_beside(&path)?;
+        let mut builder = Self::new(io::BufWriter::new(file.try_clone_file()?))?;
+        builder.pending_rename = Some((file, path.as_ref().to_owned()));
+        Ok(builder)
+    }
+}
+
+/// A set of `[u8]` keys stored in an [`fst::Set`], as written by a [`SetBuilder`], supporting membership and range
+/// queries.
+pub struct Set<D> {
+    set: fst::Set<D>,
+}
+
+impl<D: AsRef<[u8]>> Set<D> {
+    pub fn new(bytes: D) -> Result<Self, Error> {
+        Ok(Self {
+            set: fst::Set::new(bytes)?,
+        })
+    }
+
+    /// Access the underlying [`fst::Set`].
+    pub fn index(&self) -> &fst::Set<D> {
+        &self.set
+    }
+
+    pub fn len(&self) -> usize {
+        self.set.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.set.is_empty()
+    }
+
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.set.contains(key)
+    }
+
+    /// Returns a streaming iterator over the keys in `key_range`, in order.
+    pub fn range<K, R>(&self, key_range: R) -> fst::set::StreamBuilder<'_>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        let builder = self.set.range();
+        let builder = match key_range.start_bound() {
+            Bound::Unbounded => builder,
+            Bound::Excluded(b) => builder.gt(b),
+            Bound::Included(b) => builder.ge(b),
+        };
+        match key_range.end_bound() {
+            Bound::Unbounded => builder,
+            Bound::Excluded(b) => builder.lt(b),
+            Bound::Included(b) => builder.le(b),
+        }
+    }
+}
+
+#[cfg(feature = "mmap")]
+pub type MmapSet = Set<Mmap>;
+
+#[cfg(feature = "mmap")]
+impl MmapSet {
+    /// Maps the file at `path` to a read-only virtual memory range.
+    ///
+    /// # Safety
+    ///
+    /// See [`Mmap`].
+    pub unsafe fn map_path(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let file = fs::File::open(path)?;
+        Self::map_file(&file)
+    }
+
+    /// Maps `file` to a read-only virtual memory range.
+    ///
+    /// # Safety
+    ///
+    /// See [`Mmap`].
+    pub unsafe fn map_file(file: &fs::File) -> Result<Self, Error> {
+        Self::new(Mmap::map(file)?)
+    }
+}
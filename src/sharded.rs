@@ -0,0 +1,432 @@
+#[cfg(feature = "mmap")]
+use crate::MmapCache;
+use crate::{format, Cache, Error, FileBuilder, HashRing};
+
+use fst::Streamer;
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+use std::fmt;
+use std::ops::RangeBounds;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// The [metadata](crate::Cache::metadata) key under which every shard written by a [`ShardedBuilder`] records its
+/// [`ShardRouting`].
+pub const SHARD_ROUTING_METADATA_KEY: &str = "mmap_cache.shard_routing";
+
+/// The metadata key under which every shard written by a [`ShardedBuilder`] records its shard number.
+pub const SHARD_INDEX_METADATA_KEY: &str = "mmap_cache.shard_index";
+
+/// How keys are assigned to the shards of a [`ShardedCache`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ShardRouting {
+    /// Shard `i` holds the keys from `split_keys[i - 1]` (inclusive) to `split_keys[i]` (exclusive), so there is one more
+    /// shard than there are split keys. The split keys must be sorted.
+    ///
+    /// Each shard holds a contiguous key range, so a range scan only touches the shards that overlap it.
+    Range(Vec<Vec<u8>>),
+    /// A key is assigned to shard `hash(key[..prefix_len]) % shards`, or `hash(key) % shards` if `prefix_len` is `None`.
+    ///
+    /// Hashing a prefix keeps keys with the same prefix (e.g. all records of one tenant) on the same shard.
+    Hash {
+        shards: usize,
+        prefix_len: Option<usize>,
+    },
+    /// Keys are assigned with a consistent [`HashRing`], so the number of shards can grow without moving most keys.
+    Ring(HashRing),
+}
+
+impl ShardRouting {
+    pub fn shard_count(&self) -> usize {
+        match self {
+            Self::Range(split_keys) => split_keys.len() + 1,
+            Self::Hash { shards, .. } => *shards,
+            Self::Ring(ring) => ring.shard_count(),
+        }
+    }
+
+    /// The shard that holds `key`.
+    pub fn shard_for(&self, key: &[u8]) -> usize {
+        match self {
+            Self::Range(split_keys) => split_keys.partition_point(|split| split.as_slice() <= key),
+            Self::Hash { shards, prefix_len } => {
+                let prefix = &key[..prefix_len.map_or(key.len(), |len| len.min(key.len()))];
+                (format::key_hash(prefix) % *shards as u64) as usize
+            }
+            Self::Ring(ring) => ring.shard_for(key),
+        }
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        match self {
+            Self::Range(split_keys) if !split_keys.windows(2).all(|w| w[0] < w[1]) => Err(
+                Error::InvalidFormat("shard split keys must be sorted and distinct"),
+            ),
+            Self::Hash { shards: 0, .. } => Err(Error::InvalidFormat("no shards")),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Formats the routing as recorded in shard metadata: `range` followed by hex split keys, `hash <shards>` with an optional
+/// `prefix <len>`, or `ring <shards> <virtual nodes>`.
+impl fmt::Display for ShardRouting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Range(split_keys) => {
+                f.write_str("range")?;
+                for key in split_keys {
+                    f.write_str(" ")?;
+                    for byte in key {
+                        write!(f, "{byte:02x}")?;
+                    }
+                }
+                Ok(())
+            }
+            Self::Hash { shards, prefix_len } => {
+                write!(f, "hash {shards}")?;
+                if let Some(len) = prefix_len {
+                    write!(f, " prefix {len}")?;
+                }
+                Ok(())
+            }
+            Self::Ring(ring) => write!(f, "ring {} {}", ring.shard_count(), ring.virtual_nodes()),
+        }
+    }
+}
+
+impl FromStr for ShardRouting {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        const BAD: Error = Error::InvalidFormat("bad shard routing");
+        let mut words = s.split(' ');
+        match words.next() {
+            Some("range") => words
+                .map(|hex| {
+                    if !hex.len().is_multiple_of(2) {
+                        return Err(BAD);
+                    }
+                    (0..hex.len())
+                        .step_by(2)
+                        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| BAD))
+                        .collect()
+                })
+                .collect::<Result<_, _>>()
+                .map(Self::Range),
+            Some("hash") => {
+                let shards = words.next().and_then(|n| n.parse().ok()).ok_or(BAD)?;
+                let prefix_len = match (words.next(), words.next()) {
+                    (None, _) => None,
+                    (Some("prefix"), Some(len)) => Some(len.parse().map_err(|_| BAD)?),
+                    _ => return Err(BAD),
+                };
+                if words.next().is_some() {
+                    return Err(BAD);
+                }
+                Ok(Self::Hash { shards, prefix_len })
+            }
+            Some("ring") => {
+                let mut number = || words.next().and_then(|n| n.parse().ok()).ok_or(BAD);
+                let (shards, virtual_nodes) = (number()?, number()?);
+                if words.next().is_some() {
+                    return Err(BAD);
+                }
+                HashRing::new(shards, virtual_nodes).map(Self::Ring)
+            }
+            _ => Err(BAD),
+        }
+    }
+}
+
+/// The index and value paths of shard `i` of a sharded cache at `base_path`: `{base_path}.{i}.index` and
+/// `{base_path}.{i}.values`.
+pub fn shard_paths(base_path: impl AsRef<Path>, i: usize) -> (PathBuf, PathBuf) {
+    let base = base_path.as_ref().as_os_str().to_string_lossy();
+    (
+        PathBuf::from(format!("{base}.{i}.index")),
+        PathBuf::from(format!("{base}.{i}.values")),
+    )
+}
+
+/// A cache whose keys are partitioned across several smaller caches (shards) by a [`ShardRouting`], so that no single file
+/// has to hold the whole data set.
+///
+/// Lookups go straight to the shard that holds the key, and range scans merge the shards in key order.
+///
+/// ```
+/// # use mmap_cache::Error;
+/// # #[cfg(feature = "mmap")]
+/// # fn example() -> Result<(), Error> {
+/// use mmap_cache::{MmapShardedCache, ShardRouting, ShardedBuilder};
+///
+/// let routing = ShardRouting::Hash { shards: 4, prefix_len: None };
+/// let mut builder = ShardedBuilder::create("/tmp/mmap_cache_sharded_doc", routing)?;
+/// builder.insert(b"apple", b"red")?;
+/// builder.insert(b"banana", b"yellow")?;
+/// builder.finish()?;
+///
+/// let cache = unsafe { MmapShardedCache::map("/tmp/mmap_cache_sharded_doc")? };
+/// assert_eq!(cache.get_value(b"banana"), Some(&b"yellow"[..]));
+/// # Ok(())
+/// # }
+/// # #[cfg(feature = "mmap")]
+/// # example().unwrap();
+/// ```
+pub struct ShardedCache<DK, DV> {
+    routing: ShardRouting,
+    shards: Vec<Cache<DK, DV>>,
+}
+
+#[cfg(feature = "mmap")]
+pub type MmapShardedCache = ShardedCache<Mmap, Mmap>;
+
+impl<DK, DV> ShardedCache<DK, DV>
+where
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    /// Combines `shards`, where `shards[i]` holds the keys that `routing` assigns to shard `i`.
+    pub fn new(routing: ShardRouting, shards: Vec<Cache<DK, DV>>) -> Result<Self, Error> {
+        routing.validate()?;
+        if shards.len() != routing.shard_count() {
+            return Err(Error::InvalidFormat(
+                "number of shards doesn't match the routing",
+            ));
+        }
+        Ok(Self { routing, shards })
+    }
+
+    pub fn routing(&self) -> &ShardRouting {
+        &self.routing
+    }
+
+    pub fn shards(&self) -> &[Cache<DK, DV>] {
+        &self.shards
+    }
+
+    pub fn into_shards(self) -> Vec<Cache<DK, DV>> {
+        self.shards
+    }
+
+    /// The shard that `key` is routed to.
+    pub fn shard(&self, key: &[u8]) -> &Cache<DK, DV> {
+        &self.shards[self.routing.shard_for(key)]
+    }
+
+    /// The total number of keys in all shards.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(Cache::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(Cache::is_empty)
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.shard(key).contains_key(key)
+    }
+
+    /// Returns the bytes of the value for `key`; see [`Cache::get_value`].
+    pub fn get_value(&self, key: &[u8]) -> Option<&[u8]> {
+        self.shard(key).get_value(key)
+    }
+
+    /// Returns a streaming iterator over the (key, value) pairs of all shards in `key_range`, in key order.
+    ///
+    /// Tombstones are skipped.
+    pub fn range<K, R>(&self, key_range: R) -> ShardedStream<'_, DK, DV>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K> + Clone,
+    {
+        let union = self
+            .shards
+            .iter()
+            .fold(fst::map::OpBuilder::new(), |op, shard| {
+                op.add(shard.range(key_range.clone()))
+            })
+            .union();
+        ShardedStream {
+            shards: &self.shards,
+            union,
+            key: Vec::new(),
+        }
+    }
+
+    /// Copies every key, including tombstones and expiry times, into `builder`, which routes them to its own shards, then
+    /// finishes it.
+    ///
+    /// Use this to change the routing of a cache, e.g. to grow a [`ShardRouting::Ring`] by a shard.
+    pub fn reshard(&self, mut builder: ShardedBuilder) -> Result<(), Error> {
+        let mut union = self
+            .shards
+            .iter()
+            .fold(fst::map::OpBuilder::new(), |op, shard| {
+                op.add(shard.index())
+            })
+            .union();
+        while let Some((key, sources)) = union.next() {
+            let source = sources[0];
+            let shard = &self.shards[source.index];
+            if shard.is_tombstone_at(source.value) {
+                builder.insert_tombstone(key)?;
+            } else {
+                let value = shard
+                    .resolve_value(key, source.value)
+                    .ok_or(Error::InvalidFormat(
+                        "value offset is missing from the length table",
+                    ))?;
+                match shard.expiry_at(source.value) {
+                    Some(expires_at) => builder.insert_with_expiry(key, value, expires_at)?,
+                    None => builder.insert(key, value)?,
+                }
+            }
+        }
+        builder.finish()
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl MmapShardedCache {
+    /// Maps the shards written by [`ShardedBuilder::create`] with the same `base_path`, reading the routing from the
+    /// metadata of the first shard.
+    ///
+    /// # Safety
+    ///
+    /// See [`Mmap`].
+    pub unsafe fn map(base_path: impl AsRef<Path>) -> Result<Self, Error> {
+        let (index_path, value_path) = shard_paths(&base_path, 0);
+        let first = MmapCache::map_paths(index_path, value_path)?;
+        let routing: ShardRouting = first
+            .metadata()?
+            .get_str(SHARD_ROUTING_METADATA_KEY)
+            .ok_or(Error::InvalidFormat("shard has no routing metadata"))?
+            .parse()?;
+        let mut shards = vec![first];
+        for i in 1..routing.shard_count() {
+            let (index_path, value_path) = shard_paths(&base_path, i);
+            let shard = MmapCache::map_paths(index_path, value_path)?;
+            let metadata = shard.metadata()?;
+            let expected_index = i.to_string();
+            if metadata.get_str(SHARD_ROUTING_METADATA_KEY) != Some(&routing.to_string())
+                || metadata.get_str(SHARD_INDEX_METADATA_KEY) != Some(&expected_index)
+            {
+                return Err(Error::InvalidFormat(
+                    "shard belongs to a different sharded cache",
+                ));
+            }
+            shards.push(shard);
+        }
+        Self::new(routing, shards)
+    }
+}
+
+/// A streaming iterator over (key, value bytes) pairs, returned by [`ShardedCache::range`].
+///
+/// If a value can't be resolved (e.g. the length table is missing an entry), it is yielded as an empty slice.
+pub struct ShardedStream<'c, DK, DV> {
+    shards: &'c [Cache<DK, DV>],
+    union: fst::map::Union<'c>,
+    key: Vec<u8>,
+}
+
+impl<'a, 'c: 'a, DK, DV> Streamer<'a> for ShardedStream<'c, DK, DV>
+where
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    type Item = (&'a [u8], &'c [u8]);
+
+    fn next(&'a mut self) -> Option<Self::Item> {
+        let value = loop {
+            let (key, sources) = self.union.next()?;
+            // Each key is held by exactly one shard.
+            let source = sources[0];
+            let shard = &self.shards[source.index];
+            if !shard.is_tombstone_at(source.value) {
+                self.key.clear();
+                self.key.extend_from_slice(key);
+                break shard.resolve_value(key, source.value).unwrap_or(&[]);
+            }
+        };
+        Some((&self.key, value))
+    }
+}
+
+/// Writes every shard of a [`ShardedCache`] in one pass over sorted input.
+///
+/// Each shard receives a sorted subsequence of the input, so every shard is written as it goes, in constant memory.
+pub struct ShardedBuilder {
+    routing: ShardRouting,
+    builders: Vec<FileBuilder>,
+}
+
+impl ShardedBuilder {
+    /// Writes shard `i` with `builders[i]`.
+    pub fn new(routing: ShardRouting, builders: Vec<FileBuilder>) -> Result<Self, Error> {
+        routing.validate()?;
+        if builders.len() != routing.shard_count() {
+            return Err(Error::InvalidFormat(
+                "number of builders doesn't match the routing",
+            ));
+        }
+        Ok(Self { routing, builders })
+    }
+
+    /// Writes each shard to the [`shard_paths`] for `base_path`, recording the routing in the metadata of every shard so
+    /// that [`MmapShardedCache::map`] can open them.
+    ///
+    /// Each shard file is only replaced once `finish` succeeds.
+    pub fn create(base_path: impl AsRef<Path>, routing: ShardRouting) -> Result<Self, Error> {
+        routing.validate()?;
+        let builders = (0..routing.shard_count())
+            .map(|i| {
+                let (index_path, value_path) = shard_paths(&base_path, i);
+                let mut builder = FileBuilder::create_files_atomic(index_path, value_path)?;
+                builder.set_metadata(SHARD_ROUTING_METADATA_KEY, routing.to_string());
+                builder.set_metadata(SHARD_INDEX_METADATA_KEY, i.to_string());
+                Ok(builder)
+            })
+            .collect::<Result<_, Error>>()?;
+        Self::new(routing, builders)
+    }
+
+    pub fn routing(&self) -> &ShardRouting {
+        &self.routing
+    }
+
+    /// The builder for each shard, e.g. to set metadata.
+    pub fn builders_mut(&mut self) -> &mut [FileBuilder] {
+        &mut self.builders
+    }
+
+    /// Inserts into the shard that holds `key`. Keys must be inserted in sorted order.
+    pub fn insert(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        self.builders[self.routing.shard_for(key)].insert(key, value)
+    }
+
+    /// Like [`insert`](Self::insert), but the entry expires at `expires_at`; see [`FileBuilder::insert_with_expiry`].
+    pub fn insert_with_expiry(
+        &mut self,
+        key: &[u8],
+        value: &[u8],
+        expires_at: SystemTime,
+    ) -> Result<(), Error> {
+        self.builders[self.routing.shard_for(key)].insert_with_expiry(key, value, expires_at)
+    }
+
+    /// Commits a tombstone for `key` to the shard that holds it.
+    pub fn insert_tombstone(&mut self, key: &[u8]) -> Result<(), Error> {
+        self.builders[self.routing.shard_for(key)].insert_tombstone(key)
+    }
+
+    /// Finishes every shard.
+    pub fn finish(self) -> Result<(), Error> {
+        for builder in self.builders {
+            builder.finish()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,108 @@
+use crate::{Cache, Error, MmapCache, ValueStore};
+
+use memmap2::Mmap;
+use std::borrow::Cow;
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::Arc;
+
+/// A read-only memory map that is shared by all of its clones, so cloning it is cheap.
+#[derive(Clone, Debug)]
+pub struct SharedMmap(Arc<Mmap>);
+
+impl SharedMmap {
+    pub fn new(mmap: Mmap) -> Self {
+        Self(Arc::new(mmap))
+    }
+}
+
+impl From<Mmap> for SharedMmap {
+    fn from(mmap: Mmap) -> Self {
+        Self::new(mmap)
+    }
+}
+
+impl Deref for SharedMmap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for SharedMmap {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl ValueStore for SharedMmap {
+    fn size(&self) -> u64 {
+        self.0.size()
+    }
+
+    fn read(&self, offset: u64, len: u64) -> Result<Cow<'_, [u8]>, Error> {
+        self.0.read(offset, len)
+    }
+}
+
+/// A [`MmapCache`] that can be cloned cheaply, e.g. to hand a copy to each worker thread.
+///
+/// Every clone shares the same memory maps, which are unmapped once the last clone is dropped. Since this is just a
+/// [`Cache`], each clone has the full read API.
+///
+/// ```
+/// # use mmap_cache::Error;
+/// # fn example() -> Result<(), Error> {
+/// use mmap_cache::{FileBuilder, SharedCache};
+/// use std::thread;
+///
+/// let (index_path, value_path) = ("/tmp/mmap_cache_shared_doc_index", "/tmp/mmap_cache_shared_doc_values");
+/// let mut builder = FileBuilder::create_files(index_path, value_path)?;
+/// builder.insert(b"apple", b"red")?;
+/// builder.finish()?;
+///
+/// let cache = unsafe { SharedCache::map_paths(index_path, value_path)? };
+/// let workers: Vec<_> = (0..4)
+///     .map(|_| {
+///         let cache = cache.clone();
+///         thread::spawn(move || cache.get_value(b"apple").map(<[u8]>::to_vec))
+///     })
+///     .collect();
+/// for worker in workers {
+///     assert_eq!(worker.join().unwrap(), Some(b"red".to_vec()));
+/// }
+/// # Ok(())
+/// # }
+/// # example().unwrap();
+/// ```
+pub type SharedCache = Cache<SharedMmap, SharedMmap>;
+
+impl SharedCache {
+    /// Like [`MmapCache::map_paths`].
+    ///
+    /// # Safety
+    ///
+    /// See [`Mmap`].
+    pub unsafe fn map_paths(
+        index_path: impl AsRef<Path>,
+        value_path: impl AsRef<Path>,
+    ) -> Result<Self, Error> {
+        MmapCache::map_paths(index_path, value_path).map(Self::from)
+    }
+
+    /// Like [`MmapCache::map_path`].
+    ///
+    /// # Safety
+    ///
+    /// See [`Mmap`].
+    pub unsafe fn map_path(path: impl AsRef<Path>) -> Result<Self, Error> {
+        MmapCache::map_path(path).map(Self::from)
+    }
+}
+
+impl From<MmapCache> for SharedCache {
+    fn from(cache: MmapCache) -> Self {
+        cache.map_storage(SharedMmap::new, SharedMmap::new)
+    }
+}
@@ -0,0 +1,149 @@
+use crate::format;
+use crate::Cache;
+
+use fst::{IntoStreamer, Streamer};
+use std::ops::{Range, RangeBounds};
+
+impl<DK, DV> Cache<DK, DV>
+where
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    /// Returns a streaming iterator over (key, value length) pairs in `key_range`, without touching the value bytes.
+    ///
+    /// Lengths come from the length table if there is one. Otherwise they are derived from the offsets of consecutive keys,
+    /// like [`get_value`](Self::get_value), so any padding is included. Tombstones are skipped.
+    pub fn value_sizes<K, R>(&self, key_range: R) -> ValueSizeStream<'_, DK, DV>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        let mut stream = self.range(key_range).into_stream();
+        let has_lengths = self.section_bytes(format::SECTION_LENGTHS).is_some();
+        let mut next_key = Vec::new();
+        let next_offset = if has_lengths {
+            None
+        } else {
+            stream.next().map(|(key, offset)| {
+                next_key.extend_from_slice(key);
+                offset
+            })
+        };
+        ValueSizeStream {
+            cache: self,
+            stream,
+            has_lengths,
+            key: Vec::new(),
+            next_key,
+            next_offset,
+        }
+    }
+
+    /// Buckets the lengths of all values by powers of two; see [`value_sizes`](Self::value_sizes).
+    pub fn value_size_histogram(&self) -> SizeHistogram {
+        let mut histogram = SizeHistogram::default();
+        let mut sizes = self.value_sizes::<&[u8], _>(..);
+        while let Some((_, size)) = sizes.next() {
+            histogram.record(size);
+        }
+        histogram
+    }
+}
+
+/// A streaming iterator over (key, value length) pairs, returned by [`Cache::value_sizes`].
+pub struct ValueSizeStream<'c, DK, DV> {
+    cache: &'c Cache<DK, DV>,
+    stream: fst::map::Stream<'c>,
+    has_lengths: bool,
+    key: Vec<u8>,
+    /// Without a length table, the next entry is read ahead, since its offset is where the current value ends.
+    next_key: Vec<u8>,
+    next_offset: Option<u64>,
+}
+
+impl<'a, DK, DV> Streamer<'a> for ValueSizeStream<'_, DK, DV>
+where
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    type Item = (&'a [u8], u64);
+
+    fn next(&'a mut self) -> Option<Self::Item> {
+        if self.has_lengths {
+            let len = loop {
+                let (key, offset) = self.stream.next()?;
+                let len = self.cache.recorded_len(offset).unwrap_or(0);
+                if len != format::TOMBSTONE_LEN {
+                    self.key.clear();
+                    self.key.extend_from_slice(key);
+                    break len;
+                }
+            };
+            return Some((&self.key, len));
+        }
+
+        let offset = self.next_offset?;
+        std::mem::swap(&mut self.key, &mut self.next_key);
+        self.next_key.clear();
+        self.next_offset = self.stream.next().map(|(key, offset)| {
+            self.next_key.extend_from_slice(key);
+            offset
+        });
+        // The last value in the range ends where the value of the next key outside of the range begins.
+        let end = self.next_offset.unwrap_or_else(|| {
+            self.cache
+                .index()
+                .range()
+                .gt(&self.key)
+                .into_stream()
+                .next()
+                .map_or(self.cache.value_bytes().len() as u64, |(_, offset)| offset)
+        });
+        Some((&self.key, end.saturating_sub(offset)))
+    }
+}
+
+/// A histogram of value lengths with power-of-two buckets, returned by [`Cache::value_size_histogram`].
+///
+/// Bucket 0 counts empty values, and bucket `i > 0` counts lengths in `2^(i - 1)..2^i`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SizeHistogram {
+    /// The number of values recorded.
+    pub count: u64,
+    /// The sum of all recorded lengths.
+    pub total_bytes: u64,
+    pub min: Option<u64>,
+    pub max: Option<u64>,
+    counts: Vec<u64>,
+}
+
+impl SizeHistogram {
+    pub fn record(&mut self, len: u64) {
+        let bucket = (u64::BITS - len.leading_zeros()) as usize;
+        if self.counts.len() <= bucket {
+            self.counts.resize(bucket + 1, 0);
+        }
+        self.counts[bucket] += 1;
+        self.count += 1;
+        self.total_bytes += len;
+        self.min = Some(self.min.map_or(len, |min| min.min(len)));
+        self.max = Some(self.max.map_or(len, |max| max.max(len)));
+    }
+
+    /// The mean length, or `None` if nothing was recorded.
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.total_bytes as f64 / self.count as f64)
+    }
+
+    /// Returns an iterator over (length range, count) for every bucket up to the largest recorded length.
+    pub fn buckets(&self) -> impl Iterator<Item = (Range<u64>, u64)> + '_ {
+        self.counts.iter().enumerate().map(|(i, &count)| {
+            let range = match i {
+                0 => 0..1,
+                64 => 1 << 63..u64::MAX,
+                _ => 1 << (i - 1)..1 << i,
+            };
+            (range, count)
+        })
+    }
+}
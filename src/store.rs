@@ -0,0 +1,139 @@
+use crate::error::PathContext;
+use crate::format::read_exact_at;
+use crate::{AlignedBytes, Error};
+
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+use std::borrow::Cow;
+use std::fs;
+use std::path::Path;
+
+/// Random-access storage for the value file of a [`Cache`](crate::Cache).
+///
+/// Storage that is addressable in memory, like [`Mmap`], can lend out borrowed bytes. Other storage, like a [`FileStore`]
+/// or a remote object, fetches the requested range into an owned buffer. Use
+/// [`Cache::from_store`](crate::Cache::from_store) and [`Cache::read_value`](crate::Cache::read_value) to read values from
+/// any store.
+pub trait ValueStore {
+    /// The total number of bytes in the store.
+    fn size(&self) -> u64;
+
+    /// Returns the `len` bytes starting at `offset`, or [`Error::OutOfBounds`] if they aren't all in the store.
+    fn read(&self, offset: u64, len: u64) -> Result<Cow<'_, [u8]>, Error>;
+
+    /// Fills `buf` with the bytes starting at `offset`, like [`read`](Self::read).
+    ///
+    /// Stores that fetch into owned buffers can override this to read into `buf` directly, without allocating.
+    fn read_into(&self, offset: u64, buf: &mut [u8]) -> Result<(), Error> {
+        buf.copy_from_slice(&self.read(offset, buf.len() as u64)?);
+        Ok(())
+    }
+}
+
+/// Borrows `len` bytes at `offset` from an in-memory store.
+fn read_slice(bytes: &[u8], offset: u64, len: u64) -> Result<Cow<'_, [u8]>, Error> {
+    offset
+        .checked_add(len)
+        .and_then(|end| bytes.get(usize::try_from(offset).ok()?..usize::try_from(end).ok()?))
+        .map(Cow::Borrowed)
+        .ok_or(Error::OutOfBounds {
+            offset,
+            len,
+            available: bytes.len() as u64,
+        })
+}
+
+#[cfg(feature = "mmap")]
+impl ValueStore for Mmap {
+    fn size(&self) -> u64 {
+        self.len() as u64
+    }
+
+    fn read(&self, offset: u64, len: u64) -> Result<Cow<'_, [u8]>, Error> {
+        read_slice(self, offset, len)
+    }
+}
+
+impl ValueStore for Vec<u8> {
+    fn size(&self) -> u64 {
+        self.len() as u64
+    }
+
+    fn read(&self, offset: u64, len: u64) -> Result<Cow<'_, [u8]>, Error> {
+        read_slice(self, offset, len)
+    }
+}
+
+impl ValueStore for AlignedBytes {
+    fn size(&self) -> u64 {
+        self.as_ref().len() as u64
+    }
+
+    fn read(&self, offset: u64, len: u64) -> Result<Cow<'_, [u8]>, Error> {
+        read_slice(self.as_ref(), offset, len)
+    }
+}
+
+/// A [`ValueStore`] that reads a file with positional reads (`pread`), so nothing is memory-mapped.
+///
+/// Every [`read`](ValueStore::read) allocates a buffer. To read into reusable buffers instead, see
+/// [`PreadCache`](crate::PreadCache).
+#[derive(Debug)]
+pub struct FileStore {
+    file: fs::File,
+    len: u64,
+}
+
+impl FileStore {
+    /// Wraps `file`. The length is read once, so the file must not be modified afterwards.
+    pub fn new(file: fs::File) -> Result<Self, Error> {
+        let len = file.metadata()?.len();
+        Ok(Self { file, len })
+    }
+
+    /// Wraps the first `len` bytes of `file`, e.g. the value section of a single-file container.
+    pub(crate) fn with_len(file: fs::File, len: u64) -> Self {
+        Self { file, len }
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        fs::File::open(&path)
+            .map_err(Error::from)
+            .and_then(Self::new)
+            .at_path("open", path)
+    }
+
+    pub fn file(&self) -> &fs::File {
+        &self.file
+    }
+
+    fn check_range(&self, offset: u64, len: u64) -> Result<(), Error> {
+        if offset.checked_add(len).is_none_or(|end| end > self.len) {
+            return Err(Error::OutOfBounds {
+                offset,
+                len,
+                available: self.len,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl ValueStore for FileStore {
+    fn size(&self) -> u64 {
+        self.len
+    }
+
+    fn read(&self, offset: u64, len: u64) -> Result<Cow<'_, [u8]>, Error> {
+        self.check_range(offset, len)?;
+        let mut buf = vec![0; len as usize];
+        read_exact_at(&self.file, &mut buf, offset)?;
+        Ok(Cow::Owned(buf))
+    }
+
+    fn read_into(&self, offset: u64, buf: &mut [u8]) -> Result<(), Error> {
+        self.check_range(offset, buf.len() as u64)?;
+        read_exact_at(&self.file, buf, offset)?;
+        Ok(())
+    }
+}
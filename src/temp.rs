@@ -0,0 +1,97 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A scratch file that is removed when dropped.
+pub(crate) struct TempFile {
+    path: PathBuf,
+    file: fs::File,
+    persisted: bool,
+}
+
+impl TempFile {
+    /// Creates a new, empty file in [`std::env::temp_dir`].
+    pub fn new() -> io::Result<Self> {
+        Self::new_in(std::env::temp_dir())
+    }
+
+    /// Creates a new, empty file in `dir`.
+    pub fn new_in(dir: impl AsRef<Path>) -> io::Result<Self> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        loop {
+            let name = format!(
+                ".mmap-cache-{}-{}.tmp",
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed)
+            );
+            let path = dir.as_ref().join(name);
+            match fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(file) => {
+                    return Ok(Self {
+                        path,
+                        file,
+                        persisted: false,
+                    })
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Creates a new, empty file in the same directory as `path`, so that it can later be renamed to `path`.
+    pub fn new_beside(path: impl AsRef<Path>) -> io::Result<Self> {
+        match path.as_ref().parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => Self::new_in(dir),
+            _ => Self::new_in("."),
+        }
+    }
+
+    /// Atomically renames the file to `path`, replacing any existing file, instead of removing it on drop.
+    pub fn persist(mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::rename(&self.path, path)?;
+        self.persisted = true;
+        Ok(())
+    }
+
+    /// Opens another handle to the same file.
+    pub fn try_clone_file(&self) -> io::Result<fs::File> {
+        self.file.try_clone()
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if !self.persisted {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+impl io::Write for TempFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl io::Read for TempFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+impl io::Seek for TempFile {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
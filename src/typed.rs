@@ -0,0 +1,85 @@
+use crate::{Cache, ValueStream};
+
+use bytemuck::Pod;
+use fst::Streamer;
+use std::marker::PhantomData;
+use std::ops::RangeBounds;
+
+/// A [`Cache`] whose values are all a single [`Pod`] type `T`.
+///
+/// Values are cast with [`bytemuck`], which checks both the size and alignment of each value, so no `unsafe` is required to
+/// read them.
+pub struct TypedCache<T, DK, DV> {
+    cache: Cache<DK, DV>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T, DK, DV> TypedCache<T, DK, DV>
+where
+    T: Pod,
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    pub fn new(cache: Cache<DK, DV>) -> Self {
+        Self {
+            cache,
+            marker: PhantomData,
+        }
+    }
+
+    /// Access the untyped [`Cache`].
+    pub fn untyped(&self) -> &Cache<DK, DV> {
+        &self.cache
+    }
+
+    pub fn into_untyped(self) -> Cache<DK, DV> {
+        self.cache
+    }
+
+    /// Returns a reference to the value for `key`.
+    ///
+    /// Returns `None` if `key` doesn't exist, or if its value is not a correctly sized and aligned `T`.
+    pub fn get(&self, key: &[u8]) -> Option<&T> {
+        cast_value(self.cache.get_value(key)?)
+    }
+
+    /// Returns a streaming iterator over (key, `&T`) pairs.
+    ///
+    /// # Panics
+    ///
+    /// While streaming, if a value is not a correctly sized and aligned `T`.
+    pub fn range<K, R>(&self, key_range: R) -> TypedStream<'_, T, DK, DV>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        TypedStream {
+            stream: self.cache.range_values(key_range),
+            marker: PhantomData,
+        }
+    }
+}
+
+/// A streaming iterator over (key, `&T`) pairs, returned by [`TypedCache::range`].
+pub struct TypedStream<'c, T, DK, DV> {
+    stream: ValueStream<'c, DK, DV>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<'a, 'c: 'a, T, DK, DV> Streamer<'a> for TypedStream<'c, T, DK, DV>
+where
+    T: Pod,
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    type Item = (&'a [u8], &'c T);
+
+    fn next(&'a mut self) -> Option<Self::Item> {
+        let (key, value) = self.stream.next()?;
+        Some((key, bytemuck::from_bytes(value)))
+    }
+}
+
+fn cast_value<T: Pod>(bytes: &[u8]) -> Option<&T> {
+    bytemuck::try_from_bytes(bytes).ok()
+}
@@ -0,0 +1,115 @@
+use crate::{Error, ExternalSortBuilder, FileBuilder};
+
+/// Buffers key-value pairs in memory, in any order, then sorts them and serializes them with a [`FileBuilder`].
+///
+/// This is convenient when the whole dataset fits in memory. Otherwise, set a [memory budget](Self::with_memory_budget).
+/// Like [`FileBuilder`], duplicate keys are not supported.
+///
+/// ```
+/// # use mmap_cache::Error;
+/// # #[cfg(feature = "mmap")]
+/// # fn example() -> Result<(), Error> {
+/// use mmap_cache::{FileBuilder, MmapCache, UnsortedBuilder};
+///
+/// let mut unsorted = UnsortedBuilder::new();
+/// unsorted.insert(b"foo", b"bar");
+/// unsorted.insert(b"abc", b"def");
+/// unsorted.finish(FileBuilder::create_files("/tmp/mmap_cache_unsorted_index", "/tmp/mmap_cache_unsorted_values")?)?;
+///
+/// let cache = unsafe { MmapCache::map_paths("/tmp/mmap_cache_unsorted_index", "/tmp/mmap_cache_unsorted_values")? };
+/// assert_eq!(cache.get_value(b"abc"), Some(&b"def"[..]));
+/// # Ok(())
+/// # }
+/// # #[cfg(feature = "mmap")]
+/// # example().unwrap();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct UnsortedBuilder {
+    /// Every key immediately followed by its value.
+    bytes: Vec<u8>,
+    entries: Vec<Entry>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Entry {
+    start: usize,
+    key_len: usize,
+    value_len: usize,
+}
+
+impl Entry {
+    fn key<'a>(&self, bytes: &'a [u8]) -> &'a [u8] {
+        &bytes[self.start..self.start + self.key_len]
+    }
+
+    fn value<'a>(&self, bytes: &'a [u8]) -> &'a [u8] {
+        let value_start = self.start + self.key_len;
+        &bytes[value_start..value_start + self.value_len]
+    }
+}
+
+impl UnsortedBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Continues as an [`ExternalSortBuilder`] that spills a sorted run to disk whenever the buffered entries use more than
+    /// `budget` bytes of memory, so inputs of any size can be built with the same code. While everything fits in the budget,
+    /// nothing is spilled.
+    ///
+    /// The entries buffered so far are kept, and are spilled by the next insert if they already exceed `budget`.
+    pub fn with_memory_budget(self, budget: usize) -> ExternalSortBuilder {
+        ExternalSortBuilder::from_buffer(self, budget)
+    }
+
+    /// The number of buffered entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The approximate number of bytes of memory used by the buffered entries.
+    ///
+    /// This counts the bytes in use rather than the allocated capacity, which is kept when a spilled run is cleared.
+    pub fn memory_usage(&self) -> usize {
+        self.bytes.len() + self.entries.len() * std::mem::size_of::<Entry>()
+    }
+
+    /// Buffers a copy of `key` and `value`.
+    pub fn insert(&mut self, key: &[u8], value: &[u8]) {
+        self.entries.push(Entry {
+            start: self.bytes.len(),
+            key_len: key.len(),
+            value_len: value.len(),
+        });
+        self.bytes.extend_from_slice(key);
+        self.bytes.extend_from_slice(value);
+    }
+
+    /// Sorts the buffered entries by key and serializes them with `builder`, then finishes `builder`.
+    pub fn finish(mut self, mut builder: FileBuilder) -> Result<(), Error> {
+        for (key, value) in self.sorted() {
+            builder.insert(key, value)?;
+        }
+        builder.finish()
+    }
+
+    /// Sorts the buffered entries by key and returns them in order.
+    pub(crate) fn sorted(&mut self) -> impl Iterator<Item = (&[u8], &[u8])> {
+        let bytes = &self.bytes;
+        self.entries
+            .sort_unstable_by(|a, b| a.key(bytes).cmp(b.key(bytes)));
+        self.entries
+            .iter()
+            .map(move |entry| (entry.key(bytes), entry.value(bytes)))
+    }
+
+    /// Removes all entries, keeping the allocated memory.
+    pub(crate) fn clear(&mut self) {
+        self.bytes.clear();
+        self.entries.clear();
+    }
+}
@@ -0,0 +1,272 @@
+use crate::{Cache, Error, FileBuilder};
+
+use fst::{IntoStreamer, Streamer};
+use std::iter::FusedIterator;
+use std::ops::RangeBounds;
+
+// Each key's value is a list of records sorted by version: `(version: u64, len: u64, bytes)*`, with little-endian integers.
+// A record with a `len` of `DELETED` has no bytes and marks the key as deleted as of its version. The list has no count, so
+// it relies on the length table for its exact length.
+
+const DELETED: u64 = u64::MAX;
+
+/// Serializes keys that each map to several versions of a value, for reading with [`VersionedCache`].
+///
+/// Insert all versions of a key, in increasing version order, before moving on to the next key. Like [`FileBuilder`], keys
+/// must be inserted in sorted order.
+pub struct VersionedBuilder {
+    builder: FileBuilder,
+    /// The key whose versions are being written, and its last version.
+    current: Option<(Vec<u8>, u64)>,
+}
+
+impl VersionedBuilder {
+    pub fn new(builder: FileBuilder) -> Self {
+        Self {
+            builder,
+            current: None,
+        }
+    }
+
+    /// Adds `value` as the value of `key` as of `version`.
+    ///
+    /// Fails with [`Error::KeyOrder`] if `key` starts a new key that doesn't sort after the previous key, or with
+    /// [`Error::VersionOrder`] if `version` doesn't follow the last version of `key`. Nothing is written in either case.
+    pub fn insert_version(&mut self, key: &[u8], version: u64, value: &[u8]) -> Result<(), Error> {
+        self.start_record(key, version)?;
+        self.builder
+            .append_value_bytes(&(value.len() as u64).to_le_bytes())?;
+        self.builder.append_value_bytes(value)
+    }
+
+    /// Marks `key` as deleted as of `version`, until a later version is inserted.
+    pub fn delete_version(&mut self, key: &[u8], version: u64) -> Result<(), Error> {
+        self.start_record(key, version)?;
+        self.builder.append_value_bytes(&DELETED.to_le_bytes())
+    }
+
+    fn start_record(&mut self, key: &[u8], version: u64) -> Result<(), Error> {
+        match &mut self.current {
+            Some((current_key, last_version)) if current_key.as_slice() == key => {
+                if version <= *last_version {
+                    return Err(Error::VersionOrder {
+                        key: key.to_vec(),
+                        version,
+                        previous: *last_version,
+                    });
+                }
+                *last_version = version;
+            }
+            _ => {
+                self.commit_current()?;
+                self.builder.check_key_order(key)?;
+                self.current = Some((key.to_vec(), version));
+            }
+        }
+        self.builder.append_value_bytes(&version.to_le_bytes())
+    }
+
+    fn commit_current(&mut self) -> Result<(), Error> {
+        match self.current.take() {
+            Some((key, _)) => self.builder.commit_entry(&key),
+            None => Ok(()),
+        }
+    }
+
+    /// Completes the serialization. See [`FileBuilder::finish`].
+    pub fn finish(mut self) -> Result<(), Error> {
+        self.commit_current()?;
+        self.builder.finish()
+    }
+}
+
+/// A [`Cache`] where each key maps to several versions of a value, as written by a [`VersionedBuilder`].
+///
+/// Reading [`as_of`](Self::as_of) a version gives a consistent snapshot of the data set at that version, so lookups against
+/// time-stamped data can be reproduced without keeping a separate cache per version.
+///
+/// ```
+/// # use mmap_cache::Error;
+/// # #[cfg(feature = "mmap")]
+/// # fn example() -> Result<(), Error> {
+/// use mmap_cache::{FileBuilder, MmapCache, VersionedBuilder, VersionedCache};
+///
+/// let (index_path, value_path) = ("/tmp/mmap_cache_versioned_doc_index", "/tmp/mmap_cache_versioned_doc_values");
+/// let mut builder = VersionedBuilder::new(FileBuilder::create_files(index_path, value_path)?);
+/// builder.insert_version(b"apple", 1, b"green")?;
+/// builder.insert_version(b"apple", 5, b"red")?;
+/// builder.finish()?;
+///
+/// let cache = VersionedCache::new(unsafe { MmapCache::map_paths(index_path, value_path)? });
+/// assert_eq!(cache.as_of(3).get_value(b"apple"), Some(&b"green"[..]));
+/// assert_eq!(cache.as_of(7).get_value(b"apple"), Some(&b"red"[..]));
+/// assert_eq!(cache.as_of(0).get_value(b"apple"), None);
+/// # Ok(())
+/// # }
+/// # #[cfg(feature = "mmap")]
+/// # example().unwrap();
+/// ```
+pub struct VersionedCache<DK, DV> {
+    cache: Cache<DK, DV>,
+}
+
+impl<DK, DV> VersionedCache<DK, DV>
+where
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    pub fn new(cache: Cache<DK, DV>) -> Self {
+        Self { cache }
+    }
+
+    /// Access the underlying [`Cache`], whose values are the packed version lists.
+    pub fn packed(&self) -> &Cache<DK, DV> {
+        &self.cache
+    }
+
+    pub fn into_packed(self) -> Cache<DK, DV> {
+        self.cache
+    }
+
+    /// Returns an iterator over every `(version, value)` record of `key`, in increasing version order, if it exists. The
+    /// value is `None` for versions where the key was deleted.
+    pub fn versions(&self, key: &[u8]) -> Option<Versions<'_>> {
+        self.cache.get_value(key).map(|bytes| Versions { bytes })
+    }
+
+    /// Returns the value of `key` in the newest version, unless it was deleted.
+    pub fn get_latest(&self, key: &[u8]) -> Option<&[u8]> {
+        self.as_of(u64::MAX).get_value(key)
+    }
+
+    /// A view of the cache as of `version`, where each key has its value from the newest version at or before `version`.
+    pub fn as_of(&self, version: u64) -> Snapshot<'_, DK, DV> {
+        Snapshot {
+            cache: &self.cache,
+            version,
+        }
+    }
+}
+
+/// A view of a [`VersionedCache`] as of one version; see [`VersionedCache::as_of`].
+pub struct Snapshot<'c, DK, DV> {
+    cache: &'c Cache<DK, DV>,
+    version: u64,
+}
+
+impl<'c, DK, DV> Snapshot<'c, DK, DV>
+where
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Returns the value of `key` as of this version, unless it didn't exist yet or was deleted.
+    pub fn get_value(&self, key: &[u8]) -> Option<&'c [u8]> {
+        value_as_of(self.cache.get_value(key)?, self.version)
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.get_value(key).is_some()
+    }
+
+    /// Returns a streaming iterator over the (key, value) pairs in `key_range` that exist as of this version.
+    pub fn range<K, R>(&self, key_range: R) -> SnapshotStream<'c, DK, DV>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        SnapshotStream {
+            cache: self.cache,
+            stream: self.cache.range(key_range).into_stream(),
+            version: self.version,
+            key: Vec::new(),
+        }
+    }
+}
+
+/// A streaming iterator over (key, value bytes) pairs, returned by [`Snapshot::range`].
+pub struct SnapshotStream<'c, DK, DV> {
+    cache: &'c Cache<DK, DV>,
+    stream: fst::map::Stream<'c>,
+    version: u64,
+    key: Vec<u8>,
+}
+
+impl<'a, 'c: 'a, DK, DV> Streamer<'a> for SnapshotStream<'c, DK, DV>
+where
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    type Item = (&'a [u8], &'c [u8]);
+
+    fn next(&'a mut self) -> Option<Self::Item> {
+        let value = loop {
+            let (key, offset) = self.stream.next()?;
+            if self.cache.is_tombstone_at(offset) {
+                continue;
+            }
+            let Some(packed) = self.cache.resolve_value(key, offset) else {
+                continue;
+            };
+            if let Some(value) = value_as_of(packed, self.version) {
+                self.key.clear();
+                self.key.extend_from_slice(key);
+                break value;
+            }
+        };
+        Some((&self.key, value))
+    }
+}
+
+/// The value of the newest record at or before `version` in a packed version list.
+fn value_as_of(packed: &[u8], version: u64) -> Option<&[u8]> {
+    Versions { bytes: packed }
+        .take_while(|&(v, _)| v <= version)
+        .last()?
+        .1
+}
+
+/// An iterator over the `(version, value)` records of one key in a [`VersionedCache`].
+///
+/// Iteration stops early if the packed list is malformed.
+#[derive(Clone, Debug)]
+pub struct Versions<'c> {
+    bytes: &'c [u8],
+}
+
+impl<'c> Iterator for Versions<'c> {
+    type Item = (u64, Option<&'c [u8]>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (version, rest) = split_u64(self.bytes)?;
+        let Some((len, rest)) = split_u64(rest) else {
+            self.bytes = &[];
+            return None;
+        };
+        if len == DELETED {
+            self.bytes = rest;
+            return Some((version, None));
+        }
+        match usize::try_from(len).ok().filter(|&len| len <= rest.len()) {
+            Some(len) => {
+                let (value, rest) = rest.split_at(len);
+                self.bytes = rest;
+                Some((version, Some(value)))
+            }
+            None => {
+                self.bytes = &[];
+                None
+            }
+        }
+    }
+}
+
+impl FusedIterator for Versions<'_> {}
+
+fn split_u64(bytes: &[u8]) -> Option<(u64, &[u8])> {
+    let (int, rest) = bytes.split_first_chunk::<8>()?;
+    Some((u64::from_le_bytes(*int), rest))
+}
@@ -0,0 +1,59 @@
+use crate::Cache;
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+/// The stride between touched bytes. Pages are at least this large on common platforms, so every page is touched.
+const TOUCH_STRIDE: usize = 4096;
+
+/// What a warm-up pass over a cache did, as returned by [`Cache::warm_index`] and [`Cache::warm_all`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct WarmUp {
+    /// The number of bytes whose pages were touched.
+    pub bytes: u64,
+    pub elapsed: Duration,
+}
+
+impl<DK, DV> Cache<DK, DV>
+where
+    DK: AsRef<[u8]>,
+    DV: AsRef<[u8]>,
+{
+    /// Reads one byte of every page of the index, in order, so that the first lookups after a cold start don't stall on
+    /// page faults.
+    ///
+    /// For a memory-mapped cache, touching the pages in order lets the operating system read ahead, which is much faster
+    /// than faulting them in one by one from random lookups. Whether the pages stay resident is up to the operating
+    /// system, unless they are locked with `CacheOptions::lock`.
+    pub fn warm_index(&self) -> WarmUp {
+        let start = Instant::now();
+        let bytes = touch_pages(self.index().as_fst().as_bytes());
+        WarmUp {
+            bytes,
+            elapsed: start.elapsed(),
+        }
+    }
+
+    /// Like [`warm_index`](Self::warm_index), but also touches every page of the value storage, including the sections
+    /// after the values.
+    pub fn warm_all(&self) -> WarmUp {
+        let start = Instant::now();
+        let bytes = touch_pages(self.index().as_fst().as_bytes())
+            + touch_pages(self.raw_value_bytes().as_ref());
+        WarmUp {
+            bytes,
+            elapsed: start.elapsed(),
+        }
+    }
+}
+
+/// Reads a byte from every page of `bytes`, returning the length of `bytes`.
+fn touch_pages(bytes: &[u8]) -> u64 {
+    let mut checksum = 0u8;
+    for page in bytes.chunks(TOUCH_STRIDE) {
+        checksum ^= page[0];
+    }
+    // Keeps the reads from being optimized away.
+    black_box(checksum);
+    bytes.len() as u64
+}